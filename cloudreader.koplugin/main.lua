@@ -83,6 +83,7 @@ function CloudReader:autoSyncLibrary()
     local code = socket.skip(1, http.request{
         url = api_url,
         method = "GET",
+        headers = self:authHeaders(),
         sink = ltn12.sink.table(response_body),
     })
     socketutil:reset_timeout()
@@ -456,6 +457,14 @@ function CloudReader:getBaseUrl()
     return url:gsub("/$", "")
 end
 
+-- Authorization header when logged in (private libraries need it)
+function CloudReader:authHeaders()
+    if self.token and self.token ~= "" then
+        return { ["Authorization"] = "Bearer " .. self.token }
+    end
+    return {}
+end
+
 -- Menu
 
 function CloudReader:addToMainMenu(menu_items)
@@ -932,6 +941,7 @@ function CloudReader:createPlaceholder(book)
     local code = socket.skip(1, http.request{
         url = placeholder_url,
         method = "GET",
+        headers = self:authHeaders(),
         sink = ltn12.sink.file(file),
     })
     socketutil:reset_timeout()
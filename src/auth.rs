@@ -4,8 +4,21 @@ use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, Salt, SaltString},
 };
-use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
 use rand::{TryRng, rngs::SysRng};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::time::{Duration, Instant};
+
+/// How long verified HTTP Basic credentials skip Argon2 verification.
+const BASIC_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Argon2 hash verified for unknown usernames, so they take as long to
+/// reject as a wrong password.
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$ByHAivCMpuEF949fp6MNWQ$8kF91lVA/UwEYGab1eK9OzK5xwvGbSbV8rRwcUD2Mzc";
 
 /// Fill a buffer with cryptographically secure random bytes from the OS.
 fn fill_random(buf: &mut [u8]) {
@@ -38,6 +51,27 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
         .is_ok())
 }
 
+/// Compute the kosync key for a password.
+///
+/// KOReader's progress sync plugin never sends the plain password, only its
+/// hex-encoded MD5 digest in the `x-auth-key` header.
+pub fn kosync_key(password: &str) -> String {
+    format!("{:x}", md5::compute(password.as_bytes()))
+}
+
+/// Decode an `Authorization: Basic` header value into username and password.
+pub fn decode_basic_auth(value: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+
+    let decoded = STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
 /// Generate a secure random token.
 pub fn generate_token() -> String {
     let mut bytes = [0u8; 32];
@@ -50,6 +84,10 @@ pub struct AuthService {
     db: Database,
     session_duration_days: u32,
     registration_enabled: bool,
+    /// Recently verified Basic credentials: keyed hash -> (user ID, verified at).
+    basic_cache: parking_lot::Mutex<HashMap<u64, (String, Instant)>>,
+    /// Per-process random keys, so cached hashes reveal nothing about passwords.
+    basic_hasher: RandomState,
 }
 
 impl AuthService {
@@ -59,6 +97,8 @@ impl AuthService {
             db,
             session_duration_days,
             registration_enabled,
+            basic_cache: parking_lot::Mutex::new(HashMap::new()),
+            basic_hasher: RandomState::new(),
         }
     }
 
@@ -73,8 +113,91 @@ impl AuthService {
         self.create_user(username, password, "user")
     }
 
+    /// Register a new user through the kosync protocol.
+    ///
+    /// The MD5 key is used as the account password, so the user can log in
+    /// with it on the JSON API as well.
+    pub fn register_kosync(&self, username: &str, key: &str) -> Result<User> {
+        if !self.registration_enabled {
+            return Err(AppError::InvalidFormat(
+                "Registration is disabled".to_string(),
+            ));
+        }
+
+        let user = self.insert_user(username, key, "user")?;
+        self.db.set_kosync_key(&user.id, &hash_password(key)?)?;
+        Ok(user)
+    }
+
+    /// Authenticate a kosync client from its `x-auth-user`/`x-auth-key` headers.
+    pub fn authenticate_kosync(&self, username: &str, key: &str) -> Result<Option<User>> {
+        let Some(user) = self.db.get_user_by_username(username)? else {
+            return Ok(None);
+        };
+
+        let key_hash = self
+            .db
+            .get_kosync_key(&user.id)?
+            .unwrap_or_else(|| user.password_hash.clone());
+
+        if verify_password(key, &key_hash)? {
+            Ok(Some(user))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Authenticate HTTP Basic credentials.
+    ///
+    /// OPDS clients send credentials with every request (feeds, covers,
+    /// thumbnails), so successful verifications are cached for a few
+    /// minutes instead of running Argon2 each time.
+    pub fn authenticate_basic(&self, username: &str, password: &str) -> Result<Option<User>> {
+        let key = self.basic_hasher.hash_one((username, password));
+
+        let cached = self
+            .basic_cache
+            .lock()
+            .get(&key)
+            .filter(|(_, verified_at)| verified_at.elapsed() < BASIC_CACHE_TTL)
+            .map(|(user_id, _)| user_id.clone());
+        if let Some(user_id) = cached
+            && let Some(user) = self.db.get_user_by_id(&user_id)?
+            && user.username == username
+        {
+            return Ok(Some(user));
+        }
+
+        let Some(user) = self.db.get_user_by_username(username)? else {
+            verify_password(password, DUMMY_PASSWORD_HASH)?;
+            return Ok(None);
+        };
+        if !verify_password(password, &user.password_hash)? {
+            return Ok(None);
+        }
+
+        let mut cache = self.basic_cache.lock();
+        cache.retain(|_, (_, verified_at)| verified_at.elapsed() < BASIC_CACHE_TTL);
+        cache.insert(key, (user.id.clone(), Instant::now()));
+
+        Ok(Some(user))
+    }
+
+    /// Store the kosync key derived from a plain password.
+    fn save_kosync_key(&self, user_id: &str, password: &str) -> Result<()> {
+        let key_hash = hash_password(&kosync_key(password))?;
+        self.db.set_kosync_key(user_id, &key_hash)
+    }
+
     /// Create a new user (admin function).
     pub fn create_user(&self, username: &str, password: &str, role: &str) -> Result<User> {
+        let user = self.insert_user(username, password, role)?;
+        self.save_kosync_key(&user.id, password)?;
+        Ok(user)
+    }
+
+    /// Validate and insert a new user.
+    fn insert_user(&self, username: &str, password: &str, role: &str) -> Result<User> {
         // Validate username
         if username.is_empty() || username.len() > 64 {
             return Err(AppError::InvalidFormat(
@@ -115,6 +238,7 @@ impl AuthService {
             role: role.to_string(),
             created_at: now_timestamp(),
             last_login: None,
+            device_profile: None,
         };
 
         self.db.create_user(&user)?;
@@ -142,6 +266,11 @@ impl AuthService {
         // Update last login
         self.db.update_user_last_login(&user.id)?;
 
+        // Users created before kosync support get their key on next login
+        if self.db.get_kosync_key(&user.id)?.is_none() {
+            self.save_kosync_key(&user.id, password)?;
+        }
+
         // Create session
         let token = generate_token();
         let expires_at = now_timestamp() + (self.session_duration_days as i64 * 24 * 60 * 60);
@@ -187,12 +316,20 @@ impl AuthService {
             ));
         }
 
+        let Some(user) = self.db.get_user_by_username(username)? else {
+            return Ok(false);
+        };
+
         let password_hash = hash_password(new_password)?;
-        self.db.update_user_password(username, &password_hash)
+        self.db.update_user_password(username, &password_hash)?;
+        self.save_kosync_key(&user.id, new_password)?;
+        self.basic_cache.lock().clear();
+        Ok(true)
     }
 
     /// Delete a user.
     pub fn delete_user(&self, username: &str) -> Result<bool> {
+        self.basic_cache.lock().clear();
         self.db.delete_user(username)
     }
 
@@ -228,4 +365,21 @@ mod tests {
         assert_eq!(token1.len(), 43); // Base64 of 32 bytes
         assert_ne!(token1, token2);
     }
+
+    #[test]
+    fn test_decode_basic_auth() {
+        // Only the first ':' separates username and password
+        let header = format!("Basic {}", STANDARD.encode("alice:open:sesame"));
+        assert_eq!(
+            decode_basic_auth(&header),
+            Some(("alice".to_string(), "open:sesame".to_string()))
+        );
+
+        assert_eq!(decode_basic_auth("Bearer abc"), None);
+        assert_eq!(decode_basic_auth("Basic !!!"), None);
+        assert_eq!(
+            decode_basic_auth(&format!("Basic {}", STANDARD.encode("nocolon"))),
+            None
+        );
+    }
 }
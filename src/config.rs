@@ -100,6 +100,9 @@ pub enum LibraryCommand {
         /// Make library public (accessible to all users).
         #[arg(long, default_value = "true")]
         public: bool,
+        /// Index the text of its books for content search (slower scans).
+        #[arg(long)]
+        content_index: bool,
     },
 
     /// Remove a library.
@@ -111,6 +114,22 @@ pub enum LibraryCommand {
     /// List all libraries.
     List,
 
+    /// Give a user access to a private library.
+    Grant {
+        /// Library name.
+        name: String,
+        /// Username.
+        username: String,
+    },
+
+    /// Remove a user's access to a library.
+    Revoke {
+        /// Library name.
+        name: String,
+        /// Username.
+        username: String,
+    },
+
     /// Scan libraries for new books.
     Scan {
         /// Scan all libraries.
@@ -151,6 +170,10 @@ pub struct Config {
     /// Libraries to serve.
     #[serde(default)]
     pub libraries: Vec<LibraryConfig>,
+
+    /// Device profiles for optimised comic downloads.
+    #[serde(default)]
+    pub devices: Vec<DeviceProfile>,
 }
 
 /// Library configuration.
@@ -165,12 +188,60 @@ pub struct LibraryConfig {
     /// Whether library is public (accessible to all users).
     #[serde(default = "default_public")]
     pub public: bool,
+
+    /// Whether the text of its books is indexed for content search.
+    #[serde(default)]
+    pub content_index: bool,
 }
 
 fn default_public() -> bool {
     true
 }
 
+/// Reading device profile, used to re-encode comic pages for its screen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    /// Profile name, as selected with `?profile=`.
+    pub name: String,
+
+    /// Screen width in pixels.
+    pub width: u32,
+
+    /// Screen height in pixels.
+    pub height: u32,
+
+    /// Convert pages to grayscale (e-ink screens).
+    #[serde(default)]
+    pub grayscale: bool,
+
+    /// Image format of the pages: "jpeg" or "png".
+    #[serde(default)]
+    pub format: PageFormat,
+
+    /// JPEG quality (1-100).
+    #[serde(default = "default_page_quality")]
+    pub quality: u8,
+
+    /// Maximum size of an optimised book in megabytes.
+    #[serde(default)]
+    pub max_file_size_mb: Option<u64>,
+}
+
+fn default_page_quality() -> u8 {
+    85
+}
+
+/// Image format of re-encoded comic pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFormat {
+    /// JPEG, smallest for scanned pages.
+    #[default]
+    Jpeg,
+    /// PNG, lossless.
+    Png,
+}
+
 /// Server configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
@@ -181,6 +252,14 @@ pub struct ServerConfig {
     /// Catalog title.
     #[serde(default = "default_title")]
     pub title: String,
+
+    /// Number of books per page in acquisition feeds.
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+
+    /// Number of books in the recent feeds.
+    #[serde(default = "default_recent_books")]
+    pub recent_books: usize,
 }
 
 impl Default for ServerConfig {
@@ -188,6 +267,8 @@ impl Default for ServerConfig {
         Self {
             bind: default_bind(),
             title: default_title(),
+            page_size: default_page_size(),
+            recent_books: default_recent_books(),
         }
     }
 }
@@ -203,6 +284,14 @@ fn default_title() -> String {
     "My Library".to_string()
 }
 
+fn default_page_size() -> usize {
+    50
+}
+
+fn default_recent_books() -> usize {
+    50
+}
+
 /// Database configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
@@ -233,6 +322,11 @@ pub struct AuthConfig {
     /// Session token duration in days.
     #[serde(default = "default_session_days")]
     pub session_days: u32,
+
+    /// Require authentication (Bearer token or HTTP Basic) for the catalog
+    /// and downloads, instead of serving public libraries anonymously.
+    #[serde(default)]
+    pub require_auth: bool,
 }
 
 impl Default for AuthConfig {
@@ -240,6 +334,7 @@ impl Default for AuthConfig {
         Self {
             registration: default_registration(),
             session_days: default_session_days(),
+            require_auth: false,
         }
     }
 }
@@ -260,25 +355,52 @@ impl AuthConfig {
 }
 
 /// Sync configuration.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SyncConfig {
     /// Merge strategy: "latest", "furthest", "per_device".
-    #[serde(default = "default_merge_strategy")]
-    pub merge_strategy: String,
-}
+    #[serde(default)]
+    pub merge_strategy: MergeStrategy,
+}
+
+/// How reading progress from several devices is merged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Most recent update wins.
+    Latest,
+    /// Furthest reading position wins, progress never moves backwards.
+    #[default]
+    Furthest,
+    /// Each device keeps its own position.
+    PerDevice,
+}
+
+impl MergeStrategy {
+    /// Pick the winning position among a user's device rows for a book.
+    ///
+    /// `device_id` selects the requesting device's own row for `PerDevice`.
+    pub fn select<'a>(
+        &self,
+        rows: &'a [crate::db::ReadingProgress],
+        device_id: Option<&str>,
+    ) -> Option<&'a crate::db::ReadingProgress> {
+        let latest = || rows.iter().max_by_key(|p| (p.updated_at, p.id));
 
-impl Default for SyncConfig {
-    fn default() -> Self {
-        Self {
-            merge_strategy: default_merge_strategy(),
+        match self {
+            MergeStrategy::Latest => latest(),
+            MergeStrategy::Furthest => rows.iter().max_by(|a, b| {
+                a.position()
+                    .total_cmp(&b.position())
+                    .then(a.updated_at.cmp(&b.updated_at))
+                    .then(a.id.cmp(&b.id))
+            }),
+            MergeStrategy::PerDevice => device_id
+                .and_then(|d| rows.iter().find(|p| p.device_id.as_deref() == Some(d)))
+                .or_else(latest),
         }
     }
 }
 
-fn default_merge_strategy() -> String {
-    "furthest".to_string()
-}
-
 /// Scan configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScanConfig {
@@ -316,6 +438,16 @@ pub struct CacheConfig {
     #[serde(default = "default_cache_dir")]
     pub covers_dir: PathBuf,
 
+    /// Directory for books converted to EPUB.
+    #[serde(default = "default_converted_dir")]
+    pub converted_dir: PathBuf,
+
+    /// Size limit of `converted_dir` in megabytes (0 for no limit).
+    ///
+    /// The least recently used conversions are removed past the limit.
+    #[serde(default = "default_converted_max_mb")]
+    pub converted_max_mb: u64,
+
     /// Thumbnail size in pixels.
     #[serde(default = "default_thumbnail_size")]
     pub thumbnail_size: u32,
@@ -325,6 +457,8 @@ impl Default for CacheConfig {
     fn default() -> Self {
         Self {
             covers_dir: default_cache_dir(),
+            converted_dir: default_converted_dir(),
+            converted_max_mb: default_converted_max_mb(),
             thumbnail_size: default_thumbnail_size(),
         }
     }
@@ -334,6 +468,14 @@ fn default_cache_dir() -> PathBuf {
     PathBuf::from("data/covers")
 }
 
+fn default_converted_dir() -> PathBuf {
+    PathBuf::from("data/converted")
+}
+
+fn default_converted_max_mb() -> u64 {
+    2048
+}
+
 fn default_thumbnail_size() -> u32 {
     200
 }
@@ -350,6 +492,13 @@ impl Config {
         })
     }
 
+    /// Find a device profile by name (case-insensitive).
+    pub fn device_profile(&self, name: &str) -> Option<&DeviceProfile> {
+        self.devices
+            .iter()
+            .find(|d| d.name.eq_ignore_ascii_case(name))
+    }
+
     /// Find config file in default locations.
     pub fn find_config_file() -> Option<PathBuf> {
         let candidates = [
@@ -371,6 +520,10 @@ impl Config {
 [server]
 bind = "0.0.0.0:8080"
 title = "My Library"
+# Books per page in OPDS acquisition feeds
+page_size = 50
+# Books listed in the "Recent" feeds
+recent_books = 50
 
 [database]
 # path = "/var/lib/ebook-rs/library.db"
@@ -380,6 +533,8 @@ title = "My Library"
 registration = "open"
 # Session duration in days
 session_days = 30
+# Require login (HTTP Basic or Bearer token) for the catalog and downloads
+require_auth = false
 
 [sync]
 # Merge strategy: "latest", "furthest", "per_device"
@@ -391,6 +546,8 @@ interval_seconds = 300
 
 [cache]
 # covers_dir = "/var/lib/ebook-rs/covers"
+# converted_dir = "/var/lib/ebook-rs/converted"
+converted_max_mb = 2048
 thumbnail_size = 200
 
 # Libraries to serve (optional - can also use CLI)
@@ -402,6 +559,17 @@ thumbnail_size = 200
 # [[libraries]]
 # name = "Romans"
 # path = "/mnt/nas/Ebook/Romans"
+# content_index = true  # Index book text for content search (slower scans)
+
+# Device profiles for optimised comic downloads (optional)
+# [[devices]]
+# name = "kindle"
+# width = 1072
+# height = 1448
+# grayscale = true
+# format = "jpeg"        # "jpeg" or "png"
+# quality = 80
+# max_file_size_mb = 100
 "#
         .to_string()
     }
@@ -467,6 +635,32 @@ impl BookFormat {
         }
     }
 
+    /// Detect format from a file path, including double extensions
+    /// such as `.fb2.zip`.
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".fb2.zip") {
+            return Some(BookFormat::Fb2);
+        }
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    /// Canonical file extension, accepted back by [`Self::from_extension`].
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BookFormat::Epub => "epub",
+            BookFormat::Pdf => "pdf",
+            BookFormat::Cbz => "cbz",
+            BookFormat::Cbr => "cbr",
+            BookFormat::Cb7 => "cb7",
+            BookFormat::Mobi => "mobi",
+            BookFormat::Fb2 => "fb2",
+            BookFormat::Txt => "txt",
+            BookFormat::Html => "html",
+            BookFormat::Md => "md",
+        }
+    }
+
     /// Check if this format is a comic book archive.
     pub fn is_comic(&self) -> bool {
         matches!(self, BookFormat::Cbz | BookFormat::Cbr | BookFormat::Cb7)
@@ -2,6 +2,7 @@ mod schema;
 
 pub use schema::Database;
 
+use crate::formats::TextLocation;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -23,6 +24,9 @@ pub struct User {
     pub created_at: i64,
     /// Last login timestamp.
     pub last_login: Option<i64>,
+    /// Default device profile for optimised comic downloads.
+    #[serde(default)]
+    pub device_profile: Option<String>,
 }
 
 /// Authentication session.
@@ -53,6 +57,9 @@ pub struct Library {
     pub owner_id: Option<String>,
     /// Creation timestamp.
     pub created_at: i64,
+    /// Whether the text of its books is indexed for content search.
+    #[serde(default)]
+    pub content_index: bool,
 }
 
 /// Reading progress for a book.
@@ -86,6 +93,23 @@ pub struct ReadingProgress {
     pub updated_at: i64,
 }
 
+impl ReadingProgress {
+    /// Reading position as a percentage, for comparing devices.
+    ///
+    /// Falls back to the page ratio, then the raw page number, when the
+    /// client did not report a percentage.
+    pub fn position(&self) -> f64 {
+        if let Some(percentage) = self.percentage {
+            return percentage;
+        }
+        match (self.current_page, self.total_pages) {
+            (Some(page), Some(total)) if total > 0 => page as f64 / total as f64 * 100.0,
+            (Some(page), _) => page as f64,
+            _ => 0.0,
+        }
+    }
+}
+
 /// Highlight/annotation in a book.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Highlight {
@@ -183,6 +207,8 @@ pub struct StoredBook {
     pub author: Option<String>,
     /// All authors (JSON array).
     pub authors_json: Option<String>,
+    /// Other contributors (JSON array of name/role objects).
+    pub contributors_json: Option<String>,
     /// Book description.
     pub description: Option<String>,
     /// Publisher.
@@ -193,6 +219,8 @@ pub struct StoredBook {
     pub language: Option<String>,
     /// ISBN.
     pub isbn: Option<String>,
+    /// All identifiers (JSON array of scheme/value objects).
+    pub identifiers_json: Option<String>,
     /// Series name.
     pub series: Option<String>,
     /// Series index.
@@ -211,12 +239,54 @@ pub struct StoredBook {
     pub page_count: Option<i64>,
     /// Whether cover is available.
     pub cover_cached: bool,
+    /// Page progression ("ltr" or "rtl").
+    pub reading_direction: Option<String>,
     /// Creation timestamp.
     pub created_at: i64,
     /// Last update timestamp.
     pub updated_at: i64,
 }
 
+/// Searchable text of a book in the full-text index.
+#[derive(Debug, Clone, Default)]
+pub struct SearchEntry {
+    /// Book ID.
+    pub book_id: String,
+    /// Book title.
+    pub title: String,
+    /// Authors, one per line.
+    pub authors: String,
+    /// Series name.
+    pub series: String,
+    /// Tags, one per line.
+    pub tags: String,
+    /// Publisher.
+    pub publisher: String,
+    /// Book description.
+    pub description: String,
+    /// ISBNs without separators, one per line.
+    pub isbn: String,
+}
+
+/// A passage of book content matching a content search.
+#[derive(Debug, Clone)]
+pub struct ContentMatch {
+    /// Book ID.
+    pub book_id: String,
+    /// Where the passage is in the book.
+    pub location: TextLocation,
+    /// Chapter title, if known.
+    pub title: Option<String>,
+    /// Text around the match, with matched words between
+    /// [`MATCH_START`] and [`MATCH_END`].
+    pub snippet: String,
+}
+
+/// Marks the start of a matched word in content snippets.
+pub const MATCH_START: char = '\u{2}';
+/// Marks the end of a matched word in content snippets.
+pub const MATCH_END: char = '\u{3}';
+
 /// SDR backup (KOReader .sdr folder).
 #[derive(Debug, Clone)]
 pub struct SdrBackup {
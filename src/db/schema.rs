@@ -1,7 +1,10 @@
+use crate::config::MergeStrategy;
 use crate::db::*;
 use crate::error::{AppError, Result};
+use crate::formats::TextSection;
 use parking_lot::Mutex;
 use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -112,6 +115,9 @@ impl Database {
                 mtime INTEGER NOT NULL DEFAULT 0,
                 page_count INTEGER,
                 cover_cached INTEGER NOT NULL DEFAULT 0,
+                reading_direction TEXT,
+                contributors_json TEXT,
+                identifiers_json TEXT,
                 created_at INTEGER NOT NULL,
                 updated_at INTEGER NOT NULL,
                 FOREIGN KEY (library_id) REFERENCES libraries(id) ON DELETE CASCADE
@@ -205,6 +211,13 @@ impl Database {
                 FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE
             );
 
+            -- KOReader progress sync keys (Argon2 hash of the MD5 key sent by kosync clients)
+            CREATE TABLE IF NOT EXISTS kosync_keys (
+                user_id TEXT PRIMARY KEY,
+                key_hash TEXT NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+            );
+
             -- Indexes
             CREATE INDEX IF NOT EXISTS idx_books_library ON books(library_id);
             CREATE INDEX IF NOT EXISTS idx_books_hash ON books(file_hash);
@@ -213,10 +226,105 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id);
             CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
             CREATE INDEX IF NOT EXISTS idx_sdr_user ON sdr_backups(user_id);
+
+            -- Full-text search index, keyed by the rowid of the book
+            CREATE VIRTUAL TABLE IF NOT EXISTS books_fts USING fts5(
+                book_id UNINDEXED,
+                title,
+                authors,
+                series,
+                tags,
+                publisher,
+                description,
+                isbn,
+                tokenize = 'unicode61 remove_diacritics 2',
+                prefix = '2 3'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS books_fts_delete AFTER DELETE ON books BEGIN
+                DELETE FROM books_fts WHERE rowid = old.rowid;
+            END;
+
+            -- Book text for content search, in libraries that opt in
+            CREATE TABLE IF NOT EXISTS book_content (
+                id INTEGER PRIMARY KEY,
+                book_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                number INTEGER NOT NULL,
+                title TEXT,
+                text TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_book_content_book ON book_content(book_id);
+
+            CREATE VIRTUAL TABLE IF NOT EXISTS book_content_fts USING fts5(
+                text,
+                content = 'book_content',
+                content_rowid = 'id',
+                tokenize = 'unicode61 remove_diacritics 2'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS book_content_insert AFTER INSERT ON book_content BEGIN
+                INSERT INTO book_content_fts (rowid, text) VALUES (new.id, new.text);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS book_content_delete AFTER DELETE ON book_content BEGIN
+                INSERT INTO book_content_fts (book_content_fts, rowid, text)
+                VALUES ('delete', old.id, old.text);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS books_content_delete AFTER DELETE ON books BEGIN
+                DELETE FROM book_content WHERE book_id = old.id;
+            END;
             "#,
         )
         .map_err(|e| AppError::Internal(format!("Failed to initialize schema: {}", e)))?;
 
+        // Columns added after the initial schema
+        Self::add_column_if_missing(&conn, "books", "reading_direction", "TEXT")?;
+        Self::add_column_if_missing(&conn, "books", "contributors_json", "TEXT")?;
+        Self::add_column_if_missing(&conn, "books", "identifiers_json", "TEXT")?;
+        Self::add_column_if_missing(&conn, "users", "device_profile", "TEXT")?;
+        Self::add_column_if_missing(
+            &conn,
+            "libraries",
+            "content_index",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        Self::add_column_if_missing(
+            &conn,
+            "books",
+            "content_indexed",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+
+        Ok(())
+    }
+
+    /// Add a column to an existing table unless it is already there.
+    fn add_column_if_missing(
+        conn: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<()> {
+        let exists = conn
+            .prepare(&format!(
+                "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+                table
+            ))
+            .and_then(|mut stmt| stmt.exists(params![column]))
+            .map_err(|e| AppError::Internal(format!("Failed to inspect {}: {}", table, e)))?;
+
+        if !exists {
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to add {}.{}: {}", table, column, e))
+            })?;
+        }
         Ok(())
     }
 
@@ -226,8 +334,8 @@ impl Database {
     pub fn create_user(&self, user: &User) -> Result<()> {
         let conn = self.conn.lock();
         conn.execute(
-            "INSERT INTO users (id, username, password_hash, display_name, role, created_at, last_login)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO users (id, username, password_hash, display_name, role, created_at, last_login, device_profile)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
                 user.id,
                 user.username,
@@ -236,6 +344,7 @@ impl Database {
                 user.role,
                 user.created_at,
                 user.last_login,
+                user.device_profile,
             ],
         )
         .map_err(|e| {
@@ -252,7 +361,8 @@ impl Database {
     pub fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
         let conn = self.conn.lock();
         conn.query_row(
-            "SELECT id, username, password_hash, display_name, role, created_at, last_login
+            "SELECT id, username, password_hash, display_name, role, created_at, last_login,
+                    device_profile
              FROM users WHERE username = ?1",
             params![username],
             |row| {
@@ -264,6 +374,7 @@ impl Database {
                     role: row.get(4)?,
                     created_at: row.get(5)?,
                     last_login: row.get(6)?,
+                    device_profile: row.get(7)?,
                 })
             },
         )
@@ -275,7 +386,8 @@ impl Database {
     pub fn get_user_by_id(&self, id: &str) -> Result<Option<User>> {
         let conn = self.conn.lock();
         conn.query_row(
-            "SELECT id, username, password_hash, display_name, role, created_at, last_login
+            "SELECT id, username, password_hash, display_name, role, created_at, last_login,
+                    device_profile
              FROM users WHERE id = ?1",
             params![id],
             |row| {
@@ -287,6 +399,7 @@ impl Database {
                     role: row.get(4)?,
                     created_at: row.get(5)?,
                     last_login: row.get(6)?,
+                    device_profile: row.get(7)?,
                 })
             },
         )
@@ -299,7 +412,8 @@ impl Database {
         let conn = self.conn.lock();
         let mut stmt = conn
             .prepare(
-                "SELECT id, username, password_hash, display_name, role, created_at, last_login
+                "SELECT id, username, password_hash, display_name, role, created_at, last_login,
+                        device_profile
                  FROM users ORDER BY username",
             )
             .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
@@ -314,6 +428,7 @@ impl Database {
                     role: row.get(4)?,
                     created_at: row.get(5)?,
                     last_login: row.get(6)?,
+                    device_profile: row.get(7)?,
                 })
             })
             .map_err(|e| AppError::Internal(format!("Failed to list users: {}", e)))?
@@ -346,6 +461,17 @@ impl Database {
         Ok(())
     }
 
+    /// Set or clear a user's default device profile.
+    pub fn set_user_device_profile(&self, user_id: &str, profile: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE users SET device_profile = ?1 WHERE id = ?2",
+            params![profile, user_id],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to update device profile: {}", e)))?;
+        Ok(())
+    }
+
     /// Delete user.
     pub fn delete_user(&self, username: &str) -> Result<bool> {
         let conn = self.conn.lock();
@@ -355,6 +481,30 @@ impl Database {
         Ok(rows > 0)
     }
 
+    /// Set the kosync key hash for a user.
+    pub fn set_kosync_key(&self, user_id: &str, key_hash: &str) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT INTO kosync_keys (user_id, key_hash) VALUES (?1, ?2)
+             ON CONFLICT (user_id) DO UPDATE SET key_hash = excluded.key_hash",
+            params![user_id, key_hash],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to save kosync key: {}", e)))?;
+        Ok(())
+    }
+
+    /// Get the kosync key hash for a user.
+    pub fn get_kosync_key(&self, user_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            "SELECT key_hash FROM kosync_keys WHERE user_id = ?1",
+            params![user_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| AppError::Internal(format!("Failed to get kosync key: {}", e)))
+    }
+
     // ========== SESSION OPERATIONS ==========
 
     /// Create session.
@@ -419,8 +569,8 @@ impl Database {
     pub fn create_library(&self, library: &Library) -> Result<()> {
         let conn = self.conn.lock();
         conn.execute(
-            "INSERT INTO libraries (id, name, path, is_public, owner_id, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO libraries (id, name, path, is_public, owner_id, created_at, content_index)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 library.id,
                 library.name,
@@ -428,6 +578,7 @@ impl Database {
                 library.is_public,
                 library.owner_id,
                 library.created_at,
+                library.content_index,
             ],
         )
         .map_err(|e| AppError::Internal(format!("Failed to create library: {}", e)))?;
@@ -438,7 +589,7 @@ impl Database {
     pub fn get_library_by_name(&self, name: &str) -> Result<Option<Library>> {
         let conn = self.conn.lock();
         conn.query_row(
-            "SELECT id, name, path, is_public, owner_id, created_at
+            "SELECT id, name, path, is_public, owner_id, created_at, content_index
              FROM libraries WHERE name = ?1",
             params![name],
             |row| {
@@ -449,6 +600,7 @@ impl Database {
                     is_public: row.get(3)?,
                     owner_id: row.get(4)?,
                     created_at: row.get(5)?,
+                    content_index: row.get(6)?,
                 })
             },
         )
@@ -461,7 +613,7 @@ impl Database {
         let conn = self.conn.lock();
         let mut stmt = conn
             .prepare(
-                "SELECT id, name, path, is_public, owner_id, created_at
+                "SELECT id, name, path, is_public, owner_id, created_at, content_index
                  FROM libraries ORDER BY name",
             )
             .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
@@ -475,6 +627,7 @@ impl Database {
                     is_public: row.get(3)?,
                     owner_id: row.get(4)?,
                     created_at: row.get(5)?,
+                    content_index: row.get(6)?,
                 })
             })
             .map_err(|e| AppError::Internal(format!("Failed to list libraries: {}", e)))?
@@ -489,7 +642,8 @@ impl Database {
         let conn = self.conn.lock();
         let mut stmt = conn
             .prepare(
-                "SELECT DISTINCT l.id, l.name, l.path, l.is_public, l.owner_id, l.created_at
+                "SELECT DISTINCT l.id, l.name, l.path, l.is_public, l.owner_id, l.created_at,
+                        l.content_index
                  FROM libraries l
                  LEFT JOIN library_access la ON l.id = la.library_id
                  WHERE l.is_public = 1 OR l.owner_id = ?1 OR la.user_id = ?1
@@ -506,6 +660,7 @@ impl Database {
                     is_public: row.get(3)?,
                     owner_id: row.get(4)?,
                     created_at: row.get(5)?,
+                    content_index: row.get(6)?,
                 })
             })
             .map_err(|e| AppError::Internal(format!("Failed to get libraries: {}", e)))?
@@ -524,6 +679,20 @@ impl Database {
         Ok(rows > 0)
     }
 
+    /// Turn content indexing of a library on or off.
+    pub fn set_library_content_index(&self, name: &str, enabled: bool) -> Result<bool> {
+        let conn = self.conn.lock();
+        let rows = conn
+            .execute(
+                "UPDATE libraries SET content_index = ?1 WHERE name = ?2",
+                params![enabled, name],
+            )
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to update library content index: {}", e))
+            })?;
+        Ok(rows > 0)
+    }
+
     /// Update library path.
     pub fn update_library_path(&self, name: &str, path: &str) -> Result<bool> {
         let conn = self.conn.lock();
@@ -536,11 +705,79 @@ impl Database {
         Ok(rows > 0)
     }
 
+    /// Grant a user access to a private library.
+    pub fn grant_library_access(
+        &self,
+        user_id: &str,
+        library_id: &str,
+        can_write: bool,
+    ) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT OR REPLACE INTO library_access (user_id, library_id, can_write)
+             VALUES (?1, ?2, ?3)",
+            params![user_id, library_id, can_write],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to grant library access: {}", e)))?;
+        Ok(())
+    }
+
+    /// Revoke a user's access to a library.
+    pub fn revoke_library_access(&self, user_id: &str, library_id: &str) -> Result<bool> {
+        let conn = self.conn.lock();
+        let rows = conn
+            .execute(
+                "DELETE FROM library_access WHERE user_id = ?1 AND library_id = ?2",
+                params![user_id, library_id],
+            )
+            .map_err(|e| AppError::Internal(format!("Failed to revoke library access: {}", e)))?;
+        Ok(rows > 0)
+    }
+
     // ========== PROGRESS OPERATIONS ==========
 
     /// Save or update reading progress.
     pub fn save_progress(&self, progress: &ReadingProgress) -> Result<()> {
         let conn = self.conn.lock();
+        Self::upsert_progress(&conn, progress)
+    }
+
+    /// Save reading progress of a device according to a merge strategy.
+    ///
+    /// With `Furthest`, an update behind the device's saved position is
+    /// ignored so the merged position never moves backwards. Returns whether
+    /// the update was saved.
+    pub fn save_merged_progress(
+        &self,
+        progress: &ReadingProgress,
+        strategy: MergeStrategy,
+    ) -> Result<bool> {
+        let conn = self.conn.lock();
+
+        if strategy == MergeStrategy::Furthest {
+            let saved = conn
+                .query_row(
+                    "SELECT id, user_id, book_id, device_id, current_page, total_pages, percentage,
+                            current_chapter, position_data, status, started_at, finished_at,
+                            updated_at
+                     FROM reading_progress
+                     WHERE user_id = ?1 AND book_id = ?2 AND device_id = ?3",
+                    params![progress.user_id, progress.book_id, progress.device_id],
+                    Self::row_to_progress,
+                )
+                .optional()
+                .map_err(|e| AppError::Internal(format!("Failed to get progress: {}", e)))?;
+            if saved.is_some_and(|saved| saved.position() > progress.position()) {
+                return Ok(false);
+            }
+        }
+
+        Self::upsert_progress(&conn, progress)?;
+        Ok(true)
+    }
+
+    /// Insert or replace the progress row of a device.
+    fn upsert_progress(conn: &Connection, progress: &ReadingProgress) -> Result<()> {
         conn.execute(
             "INSERT INTO reading_progress 
              (user_id, book_id, device_id, current_page, total_pages, percentage, 
@@ -575,6 +812,86 @@ impl Database {
         Ok(())
     }
 
+    /// Get reading progress rows of every device for a book.
+    pub fn get_device_progress(
+        &self,
+        user_id: &str,
+        book_id: &str,
+    ) -> Result<Vec<ReadingProgress>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, user_id, book_id, device_id, current_page, total_pages, percentage,
+                        current_chapter, position_data, status, started_at, finished_at, updated_at
+                 FROM reading_progress
+                 WHERE user_id = ?1 AND book_id = ?2
+                 ORDER BY updated_at DESC, id DESC",
+            )
+            .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
+
+        let rows = stmt
+            .query_map(params![user_id, book_id], Self::row_to_progress)
+            .map_err(|e| AppError::Internal(format!("Failed to get progress: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| AppError::Internal(format!("Failed to collect progress: {}", e)))?;
+
+        Ok(rows)
+    }
+
+    /// Get the merged reading progress for a book according to a merge strategy.
+    pub fn get_merged_progress(
+        &self,
+        user_id: &str,
+        book_id: &str,
+        strategy: MergeStrategy,
+        device_id: Option<&str>,
+    ) -> Result<Option<ReadingProgress>> {
+        let rows = self.get_device_progress(user_id, book_id)?;
+        Ok(strategy.select(&rows, device_id).cloned())
+    }
+
+    /// Helper to convert a row to ReadingProgress.
+    fn row_to_progress(row: &rusqlite::Row<'_>) -> rusqlite::Result<ReadingProgress> {
+        Ok(ReadingProgress {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            book_id: row.get(2)?,
+            device_id: row.get(3)?,
+            current_page: row.get(4)?,
+            total_pages: row.get(5)?,
+            percentage: row.get(6)?,
+            current_chapter: row.get(7)?,
+            position_data: row.get(8)?,
+            status: row.get(9)?,
+            started_at: row.get(10)?,
+            finished_at: row.get(11)?,
+            updated_at: row.get(12)?,
+        })
+    }
+
+    /// Latest reading status of every book a user has progress for.
+    pub fn reading_statuses(&self, user_id: &str) -> Result<HashMap<String, String>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare(
+                "SELECT book_id, status FROM reading_progress
+                 WHERE user_id = ?1
+                 ORDER BY updated_at, id",
+            )
+            .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
+
+        // Later rows (other devices) overwrite earlier ones
+        let statuses = stmt
+            .query_map(params![user_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| AppError::Internal(format!("Failed to get reading statuses: {}", e)))?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to collect reading statuses: {}", e))
+            })?;
+
+        Ok(statuses)
+    }
+
     /// Get reading progress for a book.
     pub fn get_progress(&self, user_id: &str, book_id: &str) -> Result<Option<ReadingProgress>> {
         let conn = self.conn.lock();
@@ -607,6 +924,50 @@ impl Database {
         .map_err(|e| AppError::Internal(format!("Failed to get progress: {}", e)))
     }
 
+    // ========== DEVICE OPERATIONS ==========
+
+    /// Save or update a device.
+    pub fn save_device(&self, device: &Device) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT INTO devices (id, user_id, name, model, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id) DO UPDATE SET
+                name = COALESCE(excluded.name, devices.name),
+                model = COALESCE(excluded.model, devices.model),
+                last_seen = excluded.last_seen",
+            params![
+                device.id,
+                device.user_id,
+                device.name,
+                device.model,
+                device.last_seen,
+            ],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to save device: {}", e)))?;
+        Ok(())
+    }
+
+    /// Get device by ID.
+    pub fn get_device(&self, id: &str) -> Result<Option<Device>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            "SELECT id, user_id, name, model, last_seen FROM devices WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(Device {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    name: row.get(2)?,
+                    model: row.get(3)?,
+                    last_seen: row.get(4)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| AppError::Internal(format!("Failed to get device: {}", e)))
+    }
+
     // ========== HIGHLIGHT OPERATIONS ==========
 
     /// Save a highlight.
@@ -763,8 +1124,9 @@ impl Database {
             "INSERT INTO books 
              (id, library_id, file_hash, title, author, authors_json, description, publisher, 
               published, language, isbn, series, series_index, tags_json, path, format, 
-              file_size, mtime, page_count, cover_cached, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)
+              file_size, mtime, page_count, cover_cached, reading_direction, created_at, updated_at,
+              contributors_json, identifiers_json)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)
              ON CONFLICT (id) DO UPDATE SET
                 file_hash = excluded.file_hash,
                 title = excluded.title,
@@ -783,6 +1145,9 @@ impl Database {
                 mtime = excluded.mtime,
                 page_count = excluded.page_count,
                 cover_cached = excluded.cover_cached,
+                reading_direction = excluded.reading_direction,
+                contributors_json = excluded.contributors_json,
+                identifiers_json = excluded.identifiers_json,
                 updated_at = excluded.updated_at",
             params![
                 book.id,
@@ -805,8 +1170,11 @@ impl Database {
                 book.mtime,
                 book.page_count,
                 book.cover_cached,
+                book.reading_direction,
                 book.created_at,
                 book.updated_at,
+                book.contributors_json,
+                book.identifiers_json,
             ],
         )
         .map_err(|e| AppError::Internal(format!("Failed to save book: {}", e)))?;
@@ -819,7 +1187,8 @@ impl Database {
         conn.query_row(
             "SELECT id, library_id, file_hash, title, author, authors_json, description, publisher,
                     published, language, isbn, series, series_index, tags_json, path, format,
-                    file_size, mtime, page_count, cover_cached, created_at, updated_at
+                    file_size, mtime, page_count, cover_cached, reading_direction,
+                    created_at, updated_at, contributors_json, identifiers_json
              FROM books WHERE id = ?1",
             params![id],
             Self::row_to_stored_book,
@@ -834,7 +1203,8 @@ impl Database {
         conn.query_row(
             "SELECT id, library_id, file_hash, title, author, authors_json, description, publisher,
                     published, language, isbn, series, series_index, tags_json, path, format,
-                    file_size, mtime, page_count, cover_cached, created_at, updated_at
+                    file_size, mtime, page_count, cover_cached, reading_direction,
+                    created_at, updated_at, contributors_json, identifiers_json
              FROM books WHERE file_hash = ?1",
             params![hash],
             Self::row_to_stored_book,
@@ -850,7 +1220,8 @@ impl Database {
             .prepare(
                 "SELECT id, library_id, file_hash, title, author, authors_json, description, publisher,
                         published, language, isbn, series, series_index, tags_json, path, format,
-                        file_size, mtime, page_count, cover_cached, created_at, updated_at
+                        file_size, mtime, page_count, cover_cached, reading_direction,
+                        created_at, updated_at, contributors_json, identifiers_json
                  FROM books WHERE library_id = ?1
                  ORDER BY title",
             )
@@ -872,7 +1243,8 @@ impl Database {
             .prepare(
                 "SELECT id, library_id, file_hash, title, author, authors_json, description, publisher,
                         published, language, isbn, series, series_index, tags_json, path, format,
-                        file_size, mtime, page_count, cover_cached, created_at, updated_at
+                        file_size, mtime, page_count, cover_cached, reading_direction,
+                        created_at, updated_at, contributors_json, identifiers_json
                  FROM books ORDER BY title",
             )
             .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
@@ -909,11 +1281,26 @@ impl Database {
             mtime: row.get(17)?,
             page_count: row.get(18)?,
             cover_cached: row.get(19)?,
-            created_at: row.get(20)?,
-            updated_at: row.get(21)?,
+            reading_direction: row.get(20)?,
+            created_at: row.get(21)?,
+            updated_at: row.get(22)?,
+            contributors_json: row.get(23)?,
+            identifiers_json: row.get(24)?,
         })
     }
 
+    /// Update the file hash of a book.
+    pub fn update_book_hash(&self, id: &str, hash: &str) -> Result<bool> {
+        let conn = self.conn.lock();
+        let rows = conn
+            .execute(
+                "UPDATE books SET file_hash = ?1 WHERE id = ?2",
+                params![hash, id],
+            )
+            .map_err(|e| AppError::Internal(format!("Failed to update book hash: {}", e)))?;
+        Ok(rows > 0)
+    }
+
     /// Delete books not in the given list of IDs (cleanup removed files).
     pub fn delete_books_not_in(&self, library_id: &str, keep_ids: &[String]) -> Result<usize> {
         if keep_ids.is_empty() {
@@ -948,6 +1335,237 @@ impl Database {
         Ok(rows > 0)
     }
 
+    // ========== SEARCH INDEX OPERATIONS ==========
+
+    /// Add or refresh a book in the full-text index.
+    ///
+    /// The book must already be saved; it is removed from the index when
+    /// its row is deleted.
+    pub fn index_book(&self, entry: &SearchEntry) -> Result<()> {
+        let conn = self.conn.lock();
+        Self::insert_search_entry(&conn, entry)
+            .map_err(|e| AppError::Internal(format!("Failed to index book: {}", e)))
+    }
+
+    /// Replace the whole full-text index.
+    pub fn rebuild_search_index(&self, entries: &[SearchEntry]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn
+            .transaction()
+            .map_err(|e| AppError::Internal(format!("Failed to start transaction: {}", e)))?;
+        tx.execute("DELETE FROM books_fts", [])
+            .and_then(|_| {
+                entries
+                    .iter()
+                    .try_for_each(|entry| Self::insert_search_entry(&tx, entry))
+            })
+            .and_then(|()| tx.commit())
+            .map_err(|e| AppError::Internal(format!("Failed to rebuild search index: {}", e)))
+    }
+
+    fn insert_search_entry(conn: &Connection, entry: &SearchEntry) -> rusqlite::Result<()> {
+        let rowid: Option<i64> = conn
+            .query_row(
+                "SELECT rowid FROM books WHERE id = ?1",
+                params![entry.book_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(rowid) = rowid else {
+            return Ok(());
+        };
+
+        conn.execute("DELETE FROM books_fts WHERE rowid = ?1", params![rowid])?;
+        conn.execute(
+            "INSERT INTO books_fts
+             (rowid, book_id, title, authors, series, tags, publisher, description, isbn)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                rowid,
+                entry.book_id,
+                entry.title,
+                entry.authors,
+                entry.series,
+                entry.tags,
+                entry.publisher,
+                entry.description,
+                entry.isbn,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Number of books in the full-text index.
+    pub fn search_index_len(&self) -> Result<usize> {
+        let conn = self.conn.lock();
+        conn.query_row("SELECT COUNT(*) FROM books_fts", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map(|count| count as usize)
+        .map_err(|e| AppError::Internal(format!("Failed to count search index: {}", e)))
+    }
+
+    /// Find book IDs matching an FTS5 expression, best matches first.
+    ///
+    /// Titles and authors weigh more than series and tags, which weigh more
+    /// than the publisher and the description.
+    pub fn search_books(&self, expression: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare(
+                "SELECT book_id FROM books_fts WHERE books_fts MATCH ?1
+                 ORDER BY bm25(books_fts, 0.0, 10.0, 8.0, 5.0, 4.0, 2.0, 1.0, 10.0)",
+            )
+            .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
+
+        let ids = stmt
+            .query_map(params![expression], |row| row.get(0))
+            .map_err(|e| AppError::Internal(format!("Failed to search books: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| AppError::Internal(format!("Failed to search books: {}", e)))?;
+
+        Ok(ids)
+    }
+
+    // ========== CONTENT INDEX OPERATIONS ==========
+
+    /// Replace the indexed text of a book.
+    ///
+    /// An empty list still marks the book as indexed, so books without
+    /// extractable text are not read again on every scan.
+    pub fn index_book_content(&self, book_id: &str, sections: &[TextSection]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn
+            .transaction()
+            .map_err(|e| AppError::Internal(format!("Failed to start transaction: {}", e)))?;
+        let result = tx
+            .execute(
+                "DELETE FROM book_content WHERE book_id = ?1",
+                params![book_id],
+            )
+            .and_then(|_| {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO book_content (book_id, kind, number, title, text)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                )?;
+                for section in sections {
+                    stmt.execute(params![
+                        book_id,
+                        section.location.kind(),
+                        section.location.number(),
+                        section.title,
+                        section.text,
+                    ])?;
+                }
+                Ok(())
+            })
+            .and_then(|()| {
+                tx.execute(
+                    "UPDATE books SET content_indexed = 1 WHERE id = ?1",
+                    params![book_id],
+                )
+            })
+            .and_then(|_| tx.commit());
+        result.map_err(|e| AppError::Internal(format!("Failed to index book content: {}", e)))
+    }
+
+    /// IDs of the books of a library whose content is indexed.
+    pub fn content_indexed_books(&self, library_id: &str) -> Result<HashSet<String>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare("SELECT id FROM books WHERE library_id = ?1 AND content_indexed = 1")
+            .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
+
+        let ids = stmt
+            .query_map(params![library_id], |row| row.get(0))
+            .map_err(|e| AppError::Internal(format!("Failed to get indexed books: {}", e)))?
+            .collect::<std::result::Result<HashSet<_>, _>>()
+            .map_err(|e| AppError::Internal(format!("Failed to collect indexed books: {}", e)))?;
+
+        Ok(ids)
+    }
+
+    /// Remove the indexed text of every book in a library.
+    pub fn clear_library_content(&self, library_id: &str) -> Result<usize> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "DELETE FROM book_content
+             WHERE book_id IN (SELECT id FROM books WHERE library_id = ?1)",
+            params![library_id],
+        )
+        .and_then(|_| {
+            conn.execute(
+                "UPDATE books SET content_indexed = 0
+                 WHERE library_id = ?1 AND content_indexed = 1",
+                params![library_id],
+            )
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to clear book content: {}", e)))
+    }
+
+    /// Find passages matching an FTS5 expression in the given libraries,
+    /// best matches first.
+    pub fn search_content(
+        &self,
+        expression: &str,
+        library_ids: &[String],
+        limit: usize,
+    ) -> Result<Vec<ContentMatch>> {
+        if library_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn.lock();
+        let placeholders: Vec<String> = library_ids.iter().map(|_| "?".to_string()).collect();
+        let sql = format!(
+            "SELECT c.book_id, c.kind, c.number, c.title,
+                    snippet(book_content_fts, 0, char(2), char(3), '…', 24)
+             FROM book_content_fts
+             JOIN book_content c ON c.id = book_content_fts.rowid
+             JOIN books b ON b.id = c.book_id
+             WHERE book_content_fts MATCH ? AND b.library_id IN ({})
+             ORDER BY rank
+             LIMIT ?",
+            placeholders.join(",")
+        );
+
+        let limit = limit as i64;
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&expression];
+        for id in library_ids {
+            params.push(id);
+        }
+        params.push(&limit);
+
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| AppError::Internal(format!("Failed to prepare query: {}", e)))?;
+        let matches = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                let kind: String = row.get(1)?;
+                Ok((
+                    row.get(0)?,
+                    TextLocation::new(&kind, row.get(2)?),
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .map_err(|e| AppError::Internal(format!("Failed to search content: {}", e)))?
+            .filter_map(|row| match row {
+                Ok((book_id, Some(location), title, snippet)) => Some(Ok(ContentMatch {
+                    book_id,
+                    location,
+                    title,
+                    snippet,
+                })),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| AppError::Internal(format!("Failed to search content: {}", e)))?;
+
+        Ok(matches)
+    }
+
     // ========== SDR BACKUP OPERATIONS ==========
 
     /// Save or update an SDR backup.
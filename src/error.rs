@@ -1,4 +1,4 @@
-use axum::http::StatusCode;
+use axum::http::{StatusCode, header};
 use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 
@@ -21,6 +21,10 @@ pub enum AppError {
     #[error("ZIP error: {0}")]
     Zip(#[from] zip::result::ZipError),
 
+    /// 7z archive error.
+    #[error("7z error: {0}")]
+    SevenZip(#[from] sevenz_rust::Error),
+
     /// PDF processing error.
     #[error("PDF error: {0}")]
     Pdf(String),
@@ -33,6 +37,14 @@ pub enum AppError {
     #[error("Image processing error: {0}")]
     Image(#[from] image::ImageError),
 
+    /// Missing or invalid credentials.
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// Authenticated user lacks permission.
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     /// Configuration error.
     #[error("Configuration error: {0}")]
     Config(String),
@@ -42,15 +54,29 @@ pub enum AppError {
     Internal(String),
 }
 
+/// Challenge sent with 401 responses so OPDS clients prompt for credentials.
+const BASIC_CHALLENGE: &str = r#"Basic realm="ebook-rs", charset="UTF-8""#;
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let status = match &self {
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::InvalidFormat(_) => StatusCode::BAD_REQUEST,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
             AppError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
+        if let AppError::Unauthorized(_) = &self {
+            tracing::debug!(error = %self, "Unauthorized request");
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, BASIC_CHALLENGE)],
+                self.to_string(),
+            )
+                .into_response();
+        }
+
         tracing::error!(error = %self, "Request error");
 
         (status, self.to_string()).into_response()
@@ -1,12 +1,27 @@
+mod cb7;
+mod cbr;
 mod cbz;
+mod comicinfo;
+pub mod convert;
+mod cover;
 mod epub;
+mod fb2;
 pub mod jxl;
+mod mobi;
 mod pdf;
 pub mod placeholder;
+#[cfg(test)]
+mod test_util;
+mod text;
 
+pub use cb7::Cb7Handler;
+pub use cbr::CbrHandler;
 pub use cbz::CbzHandler;
 pub use epub::EpubHandler;
+pub use fb2::Fb2Handler;
+pub use mobi::MobiHandler;
 pub use pdf::PdfHandler;
+pub use text::{HtmlHandler, MarkdownHandler, TxtHandler};
 
 use crate::error::Result;
 use crate::library::book::Book;
@@ -22,36 +37,75 @@ pub trait FormatHandler: Send + Sync {
 
     /// Get the number of pages (if applicable).
     fn page_count(&self, path: &Path) -> Result<Option<u32>>;
+
+    /// Extract the plain text of the book for content search.
+    ///
+    /// Formats without extractable text return no sections.
+    fn extract_content(&self, _path: &Path) -> Result<Vec<TextSection>> {
+        Ok(Vec::new())
+    }
 }
 
-/// Get the appropriate handler for a book format.
-pub fn get_handler(format: crate::config::BookFormat) -> Box<dyn FormatHandler> {
-    use crate::config::BookFormat;
+/// Plain text of a chapter or page, for content search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSection {
+    /// Where the text is in the book.
+    pub location: TextLocation,
+    /// Chapter title, if known.
+    pub title: Option<String>,
+    /// Text with whitespace collapsed.
+    pub text: String,
+}
 
-    match format {
-        BookFormat::Epub => Box::new(EpubHandler),
-        BookFormat::Pdf => Box::new(PdfHandler),
-        BookFormat::Cbz => Box::new(CbzHandler),
-        // Fallback to CBZ handler for other comic formats (they're similar)
-        BookFormat::Cbr | BookFormat::Cb7 => Box::new(CbzHandler),
-        // For text formats, use a minimal handler
-        _ => Box::new(MinimalHandler),
-    }
+/// Position of a text section in its book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextLocation {
+    /// Document in the reading order (1-based).
+    Chapter(u32),
+    /// Page number (1-based).
+    Page(u32),
 }
 
-/// Minimal handler for formats without special metadata.
-struct MinimalHandler;
+impl TextLocation {
+    /// Kind of location ("chapter" or "page").
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TextLocation::Chapter(_) => "chapter",
+            TextLocation::Page(_) => "page",
+        }
+    }
 
-impl FormatHandler for MinimalHandler {
-    fn extract_metadata(&self, _book: &mut Book) -> Result<()> {
-        Ok(())
+    /// Chapter or page number.
+    pub fn number(&self) -> u32 {
+        match self {
+            TextLocation::Chapter(n) | TextLocation::Page(n) => *n,
+        }
     }
 
-    fn extract_cover(&self, _path: &Path) -> Result<Option<Vec<u8>>> {
-        Ok(None)
+    /// Build a location from its kind and number.
+    pub fn new(kind: &str, number: u32) -> Option<Self> {
+        match kind {
+            "chapter" => Some(TextLocation::Chapter(number)),
+            "page" => Some(TextLocation::Page(number)),
+            _ => None,
+        }
     }
+}
+
+/// Get the appropriate handler for a book format.
+pub fn get_handler(format: crate::config::BookFormat) -> Box<dyn FormatHandler> {
+    use crate::config::BookFormat;
 
-    fn page_count(&self, _path: &Path) -> Result<Option<u32>> {
-        Ok(None)
+    match format {
+        BookFormat::Epub => Box::new(EpubHandler),
+        BookFormat::Pdf => Box::new(PdfHandler),
+        BookFormat::Cbz => Box::new(CbzHandler),
+        BookFormat::Mobi => Box::new(MobiHandler),
+        BookFormat::Fb2 => Box::new(Fb2Handler),
+        BookFormat::Cbr => Box::new(CbrHandler),
+        BookFormat::Cb7 => Box::new(Cb7Handler),
+        BookFormat::Txt => Box::new(TxtHandler),
+        BookFormat::Md => Box::new(MarkdownHandler),
+        BookFormat::Html => Box::new(HtmlHandler),
     }
 }
@@ -0,0 +1,232 @@
+use crate::error::Result;
+use crate::formats::FormatHandler;
+use crate::formats::cbz::{CbzHandler, natord_compare, parse_comic_filename, read_entry_data};
+use crate::formats::comicinfo::ComicInfo;
+use crate::library::book::Book;
+use sevenz_rust::{Password, SevenZReader};
+use std::fs::File;
+use std::path::Path;
+
+/// Handler for CB7 files (7-Zip comic book archives).
+pub struct Cb7Handler;
+
+impl Cb7Handler {
+    /// Open a 7z archive (unencrypted).
+    fn open(path: &Path) -> Result<SevenZReader<File>> {
+        Ok(SevenZReader::open(path, Password::empty())?)
+    }
+
+    /// Get sorted list of image files in archive.
+    fn get_image_files(archive: &SevenZReader<File>) -> Vec<String> {
+        let mut images: Vec<String> = archive
+            .archive()
+            .files
+            .iter()
+            .filter(|entry| !entry.is_directory() && entry.has_stream())
+            .map(|entry| entry.name())
+            .filter(|name| CbzHandler::is_image_file(name))
+            .filter(|name| !name.contains("__MACOSX")) // Skip macOS metadata
+            .map(String::from)
+            .collect();
+
+        // Sort naturally (so page2 comes before page10)
+        images.sort_by(|a, b| natord_compare(a, b));
+
+        images
+    }
+
+    /// Read and parse `ComicInfo.xml`, if the archive has one.
+    fn read_comic_info(archive: &mut SevenZReader<File>) -> Option<ComicInfo> {
+        let name = archive
+            .archive()
+            .files
+            .iter()
+            .filter(|entry| !entry.is_directory() && ComicInfo::is_comic_info(entry.name()))
+            .map(|entry| entry.name().to_string())
+            .min_by_key(|name| name.len())?;
+
+        let data = Self::read_entry(archive, &name).ok()??;
+        ComicInfo::parse(&data)
+            .inspect_err(|e| tracing::debug!(error = %e, "Invalid ComicInfo.xml"))
+            .ok()
+    }
+
+    /// Visit the pages and `ComicInfo.xml` of an archive, in archive order.
+    pub(super) fn for_each_file(
+        path: &Path,
+        visit: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let mut archive = Self::open(path)?;
+        let mut result = Ok(());
+
+        archive.for_each_entries(|entry, reader| {
+            let name = entry.name();
+            let wanted = !entry.is_directory()
+                && !name.contains("__MACOSX")
+                && (CbzHandler::is_image_file(name) || ComicInfo::is_comic_info(name));
+            if !wanted {
+                std::io::copy(reader, &mut std::io::sink())?;
+                return Ok(true);
+            }
+
+            let content = read_entry_data(entry.size(), reader)?;
+            // Stop at the first failure and report it once decoding is done
+            result = visit(name, content);
+            Ok(result.is_ok())
+        })?;
+
+        result
+    }
+
+    /// Decompress a single entry.
+    ///
+    /// Solid archives must be decoded in order, so preceding entries are
+    /// read and discarded.
+    fn read_entry(archive: &mut SevenZReader<File>, name: &str) -> Result<Option<Vec<u8>>> {
+        let mut data = None;
+
+        archive.for_each_entries(|entry, reader| {
+            if entry.name() == name {
+                data = Some(read_entry_data(entry.size(), reader)?);
+                return Ok(false);
+            }
+            std::io::copy(reader, &mut std::io::sink())?;
+            Ok(true)
+        })?;
+
+        Ok(data)
+    }
+}
+
+impl FormatHandler for Cb7Handler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        let mut archive = Self::open(&book.path)?;
+
+        let images = Self::get_image_files(&archive);
+        book.page_count = Some(images.len() as u32);
+        book.has_cover = !images.is_empty();
+
+        let filename = book.title.clone();
+        if let Some((series, index)) = parse_comic_filename(&filename) {
+            book.series = Some(series);
+            book.series_index = Some(index);
+        }
+
+        // ComicInfo.xml takes precedence over the filename
+        if let Some(info) = Self::read_comic_info(&mut archive) {
+            info.apply(book);
+        }
+
+        Ok(())
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let mut archive = Self::open(path)?;
+
+        let images = Self::get_image_files(&archive);
+        let info = Self::read_comic_info(&mut archive);
+        let cover_image = match images
+            .get(ComicInfo::cover_index(info.as_ref()))
+            .or_else(|| images.first())
+        {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        match Self::read_entry(&mut archive, cover_image)? {
+            Some(data) => Ok(Some(CbzHandler::to_png(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
+        let archive = Self::open(path)?;
+
+        let count = Self::get_image_files(&archive).len();
+        Ok(Some(count as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::test_util::PNG_1X1;
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter, SourceReader};
+
+    fn entry(name: &str) -> SevenZArchiveEntry {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry.has_stream = true;
+        entry
+    }
+
+    /// Write a solid 7z archive with the given files.
+    fn write_cb7(path: &Path, files: &[(&str, &[u8])]) {
+        let mut writer = SevenZWriter::create(path).unwrap();
+        let entries = files.iter().map(|(name, _)| entry(name)).collect();
+        let readers = files
+            .iter()
+            .map(|(_, data)| SourceReader::new(*data))
+            .collect();
+        writer
+            .push_archive_entries(entries, sevenz_rust::SeqReader::new(readers))
+            .unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_cb7_pages_and_cover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Saga #12.cb7");
+        // The cover is stored after other entries in the solid block
+        write_cb7(
+            &path,
+            &[
+                (
+                    "ComicInfo.xml",
+                    b"<ComicInfo><Series>Saga</Series><Number>12.5</Number></ComicInfo>",
+                ),
+                ("page10.jxl", b"not decoded"),
+                ("page2.webp", b"not decoded"),
+                ("__MACOSX/._page1.png", b"junk"),
+                ("page1.png", PNG_1X1),
+            ],
+        );
+
+        let archive = Cb7Handler::open(&path).unwrap();
+        assert_eq!(
+            Cb7Handler::get_image_files(&archive),
+            vec!["page1.png", "page2.webp", "page10.jxl"]
+        );
+
+        let mut book = Book::new(path.clone(), crate::config::BookFormat::Cb7);
+        Cb7Handler.extract_metadata(&mut book).unwrap();
+        assert_eq!(book.page_count, Some(3));
+        assert!(book.has_cover);
+        assert_eq!(book.series.as_deref(), Some("Saga"));
+        // ComicInfo.xml wins over the "#12" filename
+        assert_eq!(book.series_index, Some(12.5));
+
+        let cover = Cb7Handler.extract_cover(&path).unwrap().unwrap();
+        assert!(cover.starts_with(&[0x89, b'P', b'N', b'G']));
+    }
+
+    #[test]
+    fn test_cb7_without_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.cb7");
+        write_cb7(&path, &[("readme.txt", b"no pages")]);
+
+        assert_eq!(Cb7Handler.page_count(&path).unwrap(), Some(0));
+        assert_eq!(Cb7Handler.extract_cover(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_rejects_non_7z() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.cb7");
+        std::fs::write(&path, b"not an archive").unwrap();
+
+        assert!(Cb7Handler.page_count(&path).is_err());
+    }
+}
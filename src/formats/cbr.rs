@@ -0,0 +1,738 @@
+use crate::error::{AppError, Result};
+use crate::formats::FormatHandler;
+use crate::formats::cbz::{CbzHandler, natord_compare, parse_comic_filename};
+use crate::formats::comicinfo::ComicInfo;
+use crate::library::book::Book;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Handler for CBR files (RAR comic book archives).
+///
+/// Archives are read with a minimal RAR 4 / RAR 5 header parser: every
+/// entry can be listed, but only stored (uncompressed) entries can be
+/// extracted. Comic archives are usually stored since images are already
+/// compressed. ZIP files misnamed as `.cbr` are handed to [`CbzHandler`].
+pub struct CbrHandler;
+
+/// RAR 1.5 - 4.x signature.
+const RAR4_SIGNATURE: &[u8] = b"Rar!\x1A\x07\x00";
+
+/// RAR 5.0 signature.
+const RAR5_SIGNATURE: &[u8] = b"Rar!\x1A\x07\x01\x00";
+
+/// ZIP local file header signature.
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Upper bound for a single RAR 5 header (as per the format).
+const RAR5_MAX_HEADER: u64 = 2 * 1024 * 1024;
+
+/// A file stored in a RAR archive.
+#[derive(Debug, Clone)]
+struct RarEntry {
+    /// Path inside the archive.
+    name: String,
+    /// Offset of the packed data in the archive.
+    offset: u64,
+    /// Size of the packed data.
+    packed_size: u64,
+    /// Size once extracted.
+    unpacked_size: u64,
+    /// Whether the entry uses the "store" method (no compression).
+    stored: bool,
+    /// Whether the entry is encrypted.
+    encrypted: bool,
+    /// Whether the entry spans several volumes.
+    split: bool,
+    /// Whether the entry is a directory.
+    is_dir: bool,
+}
+
+impl RarEntry {
+    /// Whether the entry data can be read back as-is.
+    fn is_extractable(&self) -> bool {
+        self.stored && !self.encrypted && !self.split && self.packed_size == self.unpacked_size
+    }
+}
+
+/// Parsed RAR archive headers.
+struct RarArchive<R> {
+    reader: R,
+    entries: Vec<RarEntry>,
+    file_len: u64,
+}
+
+impl<R: Read + Seek> RarArchive<R> {
+    /// Parse the archive headers.
+    fn parse(mut reader: R) -> Result<Self> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut signature = [0u8; 8];
+        let read = read_up_to(&mut reader, &mut signature)?;
+        let signature = &signature[..read];
+
+        let mut archive = Self {
+            reader,
+            entries: Vec::new(),
+            file_len,
+        };
+
+        if signature.starts_with(RAR5_SIGNATURE) {
+            archive.parse_rar5(RAR5_SIGNATURE.len() as u64)?;
+        } else if signature.starts_with(RAR4_SIGNATURE) {
+            archive.parse_rar4(RAR4_SIGNATURE.len() as u64)?;
+        } else {
+            return Err(AppError::InvalidFormat("Not a RAR archive".into()));
+        }
+
+        Ok(archive)
+    }
+
+    /// Walk RAR 4 blocks, collecting file headers.
+    fn parse_rar4(&mut self, mut pos: u64) -> Result<()> {
+        const MAIN_HEAD: u8 = 0x73;
+        const FILE_HEAD: u8 = 0x74;
+        const END_HEAD: u8 = 0x7B;
+
+        while self.file_len.saturating_sub(pos) >= 7 {
+            self.reader.seek(SeekFrom::Start(pos))?;
+            let mut base = [0u8; 7];
+            self.reader.read_exact(&mut base)?;
+
+            let head_type = base[2];
+            let flags = u16::from_le_bytes([base[3], base[4]]);
+            let head_size = u16::from_le_bytes([base[5], base[6]]) as u64;
+            if head_size < 7 {
+                return Err(AppError::InvalidFormat("Invalid RAR block header".into()));
+            }
+
+            let mut header = vec![0u8; head_size as usize - 7];
+            self.reader.read_exact(&mut header)?;
+
+            let mut data_size = if flags & 0x8000 != 0 || head_type == FILE_HEAD {
+                le_u32(&header, 0) as u64
+            } else {
+                0
+            };
+
+            match head_type {
+                MAIN_HEAD if flags & 0x0080 != 0 => {
+                    return Err(AppError::InvalidFormat(
+                        "Encrypted RAR headers are not supported".into(),
+                    ));
+                }
+                FILE_HEAD => {
+                    let offset = checked_offset(pos, &[head_size])?;
+                    let entry = parse_rar4_file(flags, &header, offset)
+                        .ok_or_else(|| AppError::InvalidFormat("Invalid RAR file header".into()))?;
+                    data_size = entry.packed_size;
+                    self.entries.push(entry);
+                }
+                END_HEAD => break,
+                _ => {}
+            }
+
+            pos = advance(pos, checked_offset(pos, &[head_size, data_size])?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Walk RAR 5 headers, collecting file headers.
+    fn parse_rar5(&mut self, mut pos: u64) -> Result<()> {
+        const FILE_HEAD: u64 = 2;
+        const ENCRYPTION_HEAD: u64 = 4;
+        const END_HEAD: u64 = 5;
+
+        while self.file_len.saturating_sub(pos) > 4 {
+            // Skip the header CRC32
+            self.reader.seek(SeekFrom::Start(pos + 4))?;
+            let (head_size, size_len) = read_vint(&mut self.reader)?;
+            if head_size == 0 || head_size > RAR5_MAX_HEADER {
+                return Err(AppError::InvalidFormat("Invalid RAR header size".into()));
+            }
+
+            let mut header = vec![0u8; head_size as usize];
+            self.reader.read_exact(&mut header)?;
+            let data_offset = checked_offset(pos, &[4, size_len, head_size])?;
+
+            let invalid = || AppError::InvalidFormat("Invalid RAR header".into());
+            let mut cursor = 0;
+            let head_type = vint(&header, &mut cursor).ok_or_else(invalid)?;
+            let flags = vint(&header, &mut cursor).ok_or_else(invalid)?;
+            let extra_size = if flags & 0x01 != 0 {
+                vint(&header, &mut cursor).ok_or_else(invalid)?
+            } else {
+                0
+            };
+            let data_size = if flags & 0x02 != 0 {
+                vint(&header, &mut cursor).ok_or_else(invalid)?
+            } else {
+                0
+            };
+
+            match head_type {
+                FILE_HEAD => {
+                    let extra_start = header
+                        .len()
+                        .checked_sub(extra_size as usize)
+                        .filter(|&start| start >= cursor)
+                        .ok_or_else(invalid)?;
+                    let mut entry = parse_rar5_file(&header[cursor..extra_start], data_offset)
+                        .ok_or_else(invalid)?;
+                    entry.packed_size = data_size;
+                    entry.split = flags & (0x08 | 0x10) != 0;
+                    entry.encrypted = rar5_extra_encrypted(&header[extra_start..]);
+                    self.entries.push(entry);
+                }
+                ENCRYPTION_HEAD => {
+                    return Err(AppError::InvalidFormat(
+                        "Encrypted RAR headers are not supported".into(),
+                    ));
+                }
+                END_HEAD => break,
+                _ => {}
+            }
+
+            pos = advance(pos, checked_offset(data_offset, &[data_size])?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the content of a stored entry.
+    fn read_entry(&mut self, entry: &RarEntry) -> Result<Vec<u8>> {
+        if !entry.is_extractable() {
+            return Err(AppError::InvalidFormat(format!(
+                "Cannot extract compressed or encrypted RAR entry: {}",
+                entry.name
+            )));
+        }
+        let end = entry.offset.checked_add(entry.packed_size);
+        if end.is_none_or(|end| end > self.file_len) {
+            return Err(AppError::InvalidFormat(format!(
+                "Truncated RAR entry: {}",
+                entry.name
+            )));
+        }
+
+        let mut data = vec![0u8; entry.packed_size as usize];
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Image entries, sorted naturally (so page2 comes before page10).
+    fn image_entries(&self) -> Vec<RarEntry> {
+        let mut images: Vec<RarEntry> = self
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir && CbzHandler::is_image_file(&e.name))
+            .filter(|e| !e.name.contains("__MACOSX")) // Skip macOS metadata
+            .cloned()
+            .collect();
+
+        images.sort_by(|a, b| natord_compare(&a.name, &b.name));
+        images
+    }
+
+    /// Read and parse `ComicInfo.xml`, if stored in the archive.
+    fn comic_info(&mut self) -> Option<ComicInfo> {
+        let entry = self
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir && ComicInfo::is_comic_info(&e.name))
+            .min_by_key(|e| e.name.len())?
+            .clone();
+
+        let data = self.read_entry(&entry).ok()?;
+        ComicInfo::parse(&data)
+            .inspect_err(|e| tracing::debug!(error = %e, "Invalid ComicInfo.xml"))
+            .ok()
+    }
+
+    /// The cover page: the ComicInfo front cover, or the first page.
+    fn cover_entry(&mut self) -> Option<RarEntry> {
+        let images = self.image_entries();
+        let info = self.comic_info();
+        images
+            .get(ComicInfo::cover_index(info.as_ref()))
+            .or_else(|| images.first())
+            .cloned()
+    }
+}
+
+impl CbrHandler {
+    /// Check whether the file is actually a ZIP archive.
+    fn is_zip(path: &Path) -> Result<bool> {
+        let mut magic = [0u8; 4];
+        let read = read_up_to(&mut File::open(path)?, &mut magic)?;
+        Ok(&magic[..read] == ZIP_SIGNATURE)
+    }
+
+    /// Open and parse a RAR archive.
+    fn open(path: &Path) -> Result<RarArchive<BufReader<File>>> {
+        RarArchive::parse(BufReader::new(File::open(path)?))
+    }
+}
+
+impl CbrHandler {
+    /// Visit the pages and `ComicInfo.xml` of an archive, in archive order.
+    ///
+    /// Compressed entries cannot be read and are skipped.
+    pub(super) fn for_each_file(
+        path: &Path,
+        visit: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        if Self::is_zip(path)? {
+            return CbzHandler::for_each_file(path, visit);
+        }
+
+        let mut archive = Self::open(path)?;
+        let entries: Vec<RarEntry> = archive
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir && !e.name.contains("__MACOSX"))
+            .filter(|e| CbzHandler::is_image_file(&e.name) || ComicInfo::is_comic_info(&e.name))
+            .cloned()
+            .collect();
+
+        for entry in entries {
+            if !entry.is_extractable() {
+                tracing::warn!(entry = %entry.name, "Skipping compressed RAR entry");
+                continue;
+            }
+            let data = archive.read_entry(&entry)?;
+            visit(&entry.name, data)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FormatHandler for CbrHandler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        if Self::is_zip(&book.path)? {
+            return CbzHandler.extract_metadata(book);
+        }
+
+        let mut archive = Self::open(&book.path)?;
+        book.page_count = Some(archive.image_entries().len() as u32);
+        book.has_cover = archive.cover_entry().is_some_and(|e| e.is_extractable());
+
+        let filename = book.title.clone();
+        if let Some((series, index)) = parse_comic_filename(&filename) {
+            book.series = Some(series);
+            book.series_index = Some(index);
+        }
+
+        // ComicInfo.xml takes precedence over the filename
+        if let Some(info) = archive.comic_info() {
+            info.apply(book);
+        }
+
+        Ok(())
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        if Self::is_zip(path)? {
+            return CbzHandler.extract_cover(path);
+        }
+
+        let mut archive = Self::open(path)?;
+        let cover = match archive.cover_entry() {
+            Some(entry) if entry.is_extractable() => entry,
+            _ => return Ok(None),
+        };
+
+        let data = archive.read_entry(&cover)?;
+        Ok(Some(CbzHandler::to_png(&data)?))
+    }
+
+    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
+        if Self::is_zip(path)? {
+            return CbzHandler.page_count(path);
+        }
+
+        let archive = Self::open(path)?;
+        Ok(Some(archive.image_entries().len() as u32))
+    }
+}
+
+/// Parse the fields of a RAR 4 file header (after the 7-byte block header).
+fn parse_rar4_file(flags: u16, header: &[u8], offset: u64) -> Option<RarEntry> {
+    const LHD_SPLIT_BEFORE: u16 = 0x0001;
+    const LHD_SPLIT_AFTER: u16 = 0x0002;
+    const LHD_PASSWORD: u16 = 0x0004;
+    const LHD_DIRECTORY: u16 = 0x00E0;
+    const LHD_LARGE: u16 = 0x0100;
+    const METHOD_STORE: u8 = 0x30;
+
+    if header.len() < 25 {
+        return None;
+    }
+
+    let mut packed_size = le_u32(header, 0) as u64;
+    let mut unpacked_size = le_u32(header, 4) as u64;
+    let method = header[18];
+    let name_size = u16::from_le_bytes([header[19], header[20]]) as usize;
+
+    let mut name_start = 25;
+    if flags & LHD_LARGE != 0 {
+        packed_size |= (le_u32(header, 25) as u64) << 32;
+        unpacked_size |= (le_u32(header, 29) as u64) << 32;
+        name_start += 8;
+    }
+
+    // Unicode names store an ASCII version, a NUL, then encoded data
+    let name = header.get(name_start..name_start + name_size)?;
+    let name = name.split(|&b| b == 0).next().unwrap_or_default();
+
+    Some(RarEntry {
+        name: String::from_utf8_lossy(name).replace('\\', "/"),
+        offset,
+        packed_size,
+        unpacked_size,
+        stored: method == METHOD_STORE,
+        encrypted: flags & LHD_PASSWORD != 0,
+        split: flags & (LHD_SPLIT_BEFORE | LHD_SPLIT_AFTER) != 0,
+        is_dir: flags & LHD_DIRECTORY == LHD_DIRECTORY,
+    })
+}
+
+/// Parse the type-specific fields of a RAR 5 file header.
+fn parse_rar5_file(fields: &[u8], offset: u64) -> Option<RarEntry> {
+    const FILE_DIRECTORY: u64 = 0x01;
+    const FILE_MTIME: u64 = 0x02;
+    const FILE_CRC: u64 = 0x04;
+
+    let mut cursor = 0;
+    let file_flags = vint(fields, &mut cursor)?;
+    let unpacked_size = vint(fields, &mut cursor)?;
+    let _attributes = vint(fields, &mut cursor)?;
+    if file_flags & FILE_MTIME != 0 {
+        cursor += 4;
+    }
+    if file_flags & FILE_CRC != 0 {
+        cursor += 4;
+    }
+    let compression = vint(fields, &mut cursor)?;
+    let _host_os = vint(fields, &mut cursor)?;
+    let name_len = vint(fields, &mut cursor)? as usize;
+    let name = fields.get(cursor..cursor.checked_add(name_len)?)?;
+
+    Some(RarEntry {
+        name: String::from_utf8_lossy(name).into_owned(),
+        offset,
+        packed_size: 0,
+        unpacked_size,
+        // Bits 7-9 hold the method, 0 meaning "store"
+        stored: (compression >> 7) & 0x07 == 0,
+        encrypted: false,
+        split: false,
+        is_dir: file_flags & FILE_DIRECTORY != 0,
+    })
+}
+
+/// Check a RAR 5 extra area for a file encryption record.
+fn rar5_extra_encrypted(extra: &[u8]) -> bool {
+    const FILE_ENCRYPTION: u64 = 0x01;
+
+    let mut cursor = 0;
+    while cursor < extra.len() {
+        let Some(size) = vint(extra, &mut cursor) else {
+            return false;
+        };
+        let record_end = cursor.saturating_add(size as usize);
+        let mut type_cursor = cursor;
+        if vint(extra, &mut type_cursor) == Some(FILE_ENCRYPTION) {
+            return true;
+        }
+        cursor = record_end;
+    }
+    false
+}
+
+/// Decode a RAR 5 variable-length integer from a buffer.
+fn vint(data: &[u8], cursor: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*cursor)?;
+        *cursor += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Read a RAR 5 variable-length integer, returning it and its encoded size.
+fn read_vint<R: Read>(reader: &mut R) -> Result<(u64, u64)> {
+    let mut bytes = Vec::with_capacity(3);
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        bytes.push(byte[0]);
+        if byte[0] & 0x80 == 0 || bytes.len() == 10 {
+            break;
+        }
+    }
+
+    let value = vint(&bytes, &mut 0)
+        .ok_or_else(|| AppError::InvalidFormat("Invalid RAR integer".into()))?;
+    Ok((value, bytes.len() as u64))
+}
+
+/// Read as many bytes as available into `buf`, returning the count.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Offset after skipping header-declared sizes from `pos`.
+///
+/// Sizes come from the archive, so overflowing offsets are rejected.
+fn checked_offset(pos: u64, sizes: &[u64]) -> Result<u64> {
+    sizes
+        .iter()
+        .try_fold(pos, |pos, &size| pos.checked_add(size))
+        .ok_or_else(|| AppError::InvalidFormat("Invalid RAR header offset".into()))
+}
+
+/// Move the header walk to `next`, which must be past `pos`.
+fn advance(pos: u64, next: u64) -> Result<u64> {
+    if next <= pos {
+        return Err(AppError::InvalidFormat("Invalid RAR header offset".into()));
+    }
+    Ok(next)
+}
+
+/// Read a little-endian u32 (0 when out of bounds).
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::test_util::PNG_1X1;
+    use std::io::{Cursor, Write};
+
+    /// Archive entry: name, data, stored (vs compressed), directory.
+    type Entry<'a> = (&'a str, &'a [u8], bool, bool);
+
+    /// Build a RAR 4 archive (CRCs are left at zero).
+    fn build_rar4(entries: &[Entry]) -> Vec<u8> {
+        let mut out = RAR4_SIGNATURE.to_vec();
+        // Main header: 7-byte block header + 6 reserved bytes
+        out.extend_from_slice(&[0, 0, 0x73, 0, 0, 13, 0]);
+        out.extend_from_slice(&[0; 6]);
+
+        for (name, data, stored, is_dir) in entries {
+            let flags: u16 = if *is_dir { 0x00E0 } else { 0 } | 0x8000;
+            let head_size = (7 + 25 + name.len()) as u16;
+            out.extend_from_slice(&[0, 0, 0x74]);
+            out.extend_from_slice(&flags.to_le_bytes());
+            out.extend_from_slice(&head_size.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes()); // packed
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes()); // unpacked
+            out.push(2); // host OS
+            out.extend_from_slice(&[0; 4]); // file CRC
+            out.extend_from_slice(&[0; 4]); // time
+            out.push(29); // version
+            out.push(if *stored { 0x30 } else { 0x33 });
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0; 4]); // attributes
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+        }
+
+        out.extend_from_slice(&[0, 0, 0x7B, 0, 0x40, 7, 0]);
+        out
+    }
+
+    fn push_vint(out: &mut Vec<u8>, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn push_rar5_header(out: &mut Vec<u8>, header: &[u8]) {
+        out.extend_from_slice(&[0; 4]); // CRC32
+        push_vint(out, header.len() as u64);
+        out.extend_from_slice(header);
+    }
+
+    /// Build a RAR 5 archive (CRCs are left at zero).
+    fn build_rar5(entries: &[Entry]) -> Vec<u8> {
+        let mut out = RAR5_SIGNATURE.to_vec();
+        push_rar5_header(&mut out, &[1, 0, 0]);
+
+        for (name, data, stored, is_dir) in entries {
+            let mut header = Vec::new();
+            push_vint(&mut header, 2); // file header
+            push_vint(&mut header, 0x02); // data area present
+            push_vint(&mut header, data.len() as u64);
+            push_vint(&mut header, if *is_dir { 0x01 } else { 0 });
+            push_vint(&mut header, data.len() as u64);
+            push_vint(&mut header, 0); // attributes
+            push_vint(&mut header, if *stored { 0 } else { 3 << 7 });
+            push_vint(&mut header, 0); // host OS
+            push_vint(&mut header, name.len() as u64);
+            header.extend_from_slice(name.as_bytes());
+
+            push_rar5_header(&mut out, &header);
+            out.extend_from_slice(data);
+        }
+
+        push_rar5_header(&mut out, &[5, 0, 0]);
+        out
+    }
+
+    fn sample_entries() -> Vec<Entry<'static>> {
+        vec![
+            ("pages", b"", true, true),
+            ("pages/page10.jpg", b"ten", true, false),
+            ("pages/page2.jpg", b"two", true, false),
+            ("pages/page1.png", PNG_1X1, true, false),
+            ("notes.txt", b"not a page", true, false),
+            ("__MACOSX/pages/._page1.png", b"junk", true, false),
+        ]
+    }
+
+    fn check_archive(data: Vec<u8>) {
+        let mut archive = RarArchive::parse(Cursor::new(data)).unwrap();
+        assert_eq!(archive.entries.len(), 6);
+        assert!(archive.entries[0].is_dir);
+
+        let images = archive.image_entries();
+        let names: Vec<&str> = images.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["pages/page1.png", "pages/page2.jpg", "pages/page10.jpg"]
+        );
+
+        assert_eq!(archive.read_entry(&images[0]).unwrap(), PNG_1X1);
+        assert_eq!(archive.read_entry(&images[2]).unwrap(), b"ten");
+    }
+
+    #[test]
+    fn test_rar4_entries() {
+        check_archive(build_rar4(&sample_entries()));
+    }
+
+    #[test]
+    fn test_rar5_entries() {
+        check_archive(build_rar5(&sample_entries()));
+    }
+
+    #[test]
+    fn test_compressed_entries_not_extracted() {
+        for data in [
+            build_rar4(&[("01.jpg", b"packed", false, false)]),
+            build_rar5(&[("01.jpg", b"packed", false, false)]),
+        ] {
+            let mut archive = RarArchive::parse(Cursor::new(data)).unwrap();
+            let images = archive.image_entries();
+            assert_eq!(images.len(), 1);
+            assert!(!images[0].is_extractable());
+            assert!(archive.read_entry(&images[0]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_comic_info_front_cover() {
+        let comic_info = br#"<ComicInfo>
+            <Title>Chapter 1</Title>
+            <Pages><Page Image="1" Type="FrontCover"/></Pages>
+        </ComicInfo>"#;
+        let data = build_rar4(&[
+            ("ComicInfo.xml", comic_info, true, false),
+            ("01.jpg", b"packed", false, false),
+            ("02.png", PNG_1X1, true, false),
+        ]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbr");
+        std::fs::write(&path, data).unwrap();
+
+        let mut book = Book::new(path.clone(), crate::config::BookFormat::Cbr);
+        CbrHandler.extract_metadata(&mut book).unwrap();
+        assert_eq!(book.title, "Chapter 1");
+        assert_eq!(book.page_count, Some(2));
+        // The compressed first page is skipped for the stored front cover
+        assert!(book.has_cover);
+        assert!(CbrHandler.extract_cover(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_rejects_non_rar() {
+        assert!(RarArchive::parse(Cursor::new(b"not an archive".to_vec())).is_err());
+        assert!(RarArchive::parse(Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_rejects_malformed_headers() {
+        // Extra area larger than the rest of the file header
+        let mut data = RAR5_SIGNATURE.to_vec();
+        push_rar5_header(&mut data, &[2, 0x01, 9, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(RarArchive::parse(Cursor::new(data)).is_err());
+
+        // Data size that would wrap the header walk around
+        let mut header = Vec::new();
+        push_vint(&mut header, 3); // service header
+        push_vint(&mut header, 0x02); // data area present
+        push_vint(&mut header, u64::MAX);
+        let mut data = RAR5_SIGNATURE.to_vec();
+        push_rar5_header(&mut data, &header);
+        data.extend_from_slice(&[0; 16]);
+        assert!(RarArchive::parse(Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn test_handler_rar_and_misnamed_zip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let rar_path = dir.path().join("Saga v03.cbr");
+        std::fs::write(&rar_path, build_rar5(&sample_entries())).unwrap();
+
+        let mut book = Book::new(rar_path.clone(), crate::config::BookFormat::Cbr);
+        CbrHandler.extract_metadata(&mut book).unwrap();
+        assert_eq!(book.page_count, Some(3));
+        assert!(book.has_cover);
+        assert_eq!(book.series.as_deref(), Some("Saga"));
+        assert_eq!(book.series_index, Some(3.0));
+        let cover = CbrHandler.extract_cover(&rar_path).unwrap().unwrap();
+        assert!(cover.starts_with(&[0x89, b'P', b'N', b'G']));
+
+        let zip_path = dir.path().join("misnamed.cbr");
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+            for name in ["p2.png", "p1.png"] {
+                writer
+                    .start_file(name, zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(PNG_1X1).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert_eq!(CbrHandler.page_count(&zip_path).unwrap(), Some(2));
+        assert!(CbrHandler.extract_cover(&zip_path).unwrap().is_some());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::error::Result;
 use crate::formats::FormatHandler;
+use crate::formats::comicinfo::ComicInfo;
 use crate::formats::jxl as jxl_decoder;
 use crate::library::book::Book;
 use std::fs::File;
@@ -12,7 +13,7 @@ pub struct CbzHandler;
 
 impl CbzHandler {
     /// Check if a filename is an image.
-    fn is_image_file(name: &str) -> bool {
+    pub(super) fn is_image_file(name: &str) -> bool {
         let lower = name.to_lowercase();
         lower.ends_with(".jpg")
             || lower.ends_with(".jpeg")
@@ -23,7 +24,7 @@ impl CbzHandler {
     }
 
     /// Get sorted list of image files in archive.
-    fn get_image_files(archive: &ZipArchive<File>) -> Vec<String> {
+    pub(super) fn get_image_files(archive: &ZipArchive<File>) -> Vec<String> {
         let mut images: Vec<String> = archive
             .file_names()
             .filter(|name| Self::is_image_file(name))
@@ -37,8 +38,49 @@ impl CbzHandler {
         images
     }
 
+    /// Read and parse `ComicInfo.xml`, if the archive has one.
+    pub(super) fn read_comic_info(archive: &mut ZipArchive<File>) -> Option<ComicInfo> {
+        let name = archive
+            .file_names()
+            .filter(|name| ComicInfo::is_comic_info(name))
+            .min_by_key(|name| name.len())?
+            .to_string();
+
+        let mut entry = archive.by_name(&name).ok()?;
+        let data = read_entry_data(entry.size(), &mut entry).ok()?;
+
+        ComicInfo::parse(&data)
+            .inspect_err(|e| tracing::debug!(error = %e, "Invalid ComicInfo.xml"))
+            .ok()
+    }
+
+    /// Visit the pages and `ComicInfo.xml` of an archive, in archive order.
+    pub(super) fn for_each_file(
+        path: &Path,
+        visit: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let name = file.name().to_string();
+            if file.is_dir()
+                || name.contains("__MACOSX")
+                || !(Self::is_image_file(&name) || ComicInfo::is_comic_info(&name))
+            {
+                continue;
+            }
+
+            let data = read_entry_data(file.size(), &mut file)?;
+            drop(file);
+            visit(&name, data)?;
+        }
+
+        Ok(())
+    }
+
     /// Convert image data to PNG, with JXL support.
-    fn to_png(data: &[u8]) -> Result<Vec<u8>> {
+    pub(super) fn to_png(data: &[u8]) -> Result<Vec<u8>> {
         let img = if jxl_decoder::is_jxl(data) {
             jxl_decoder::decode_to_image(data)?
         } else {
@@ -58,7 +100,7 @@ impl CbzHandler {
 impl FormatHandler for CbzHandler {
     fn extract_metadata(&self, book: &mut Book) -> Result<()> {
         let file = File::open(&book.path)?;
-        let archive = ZipArchive::new(file)?;
+        let mut archive = ZipArchive::new(file)?;
 
         let images = Self::get_image_files(&archive);
         book.page_count = Some(images.len() as u32);
@@ -72,6 +114,11 @@ impl FormatHandler for CbzHandler {
             book.series_index = Some(index);
         }
 
+        // ComicInfo.xml takes precedence over the filename
+        if let Some(info) = Self::read_comic_info(&mut archive) {
+            info.apply(book);
+        }
+
         Ok(())
     }
 
@@ -80,13 +127,17 @@ impl FormatHandler for CbzHandler {
         let mut archive = ZipArchive::new(file)?;
 
         let images = Self::get_image_files(&archive);
-        let first_image = match images.first() {
+        let info = Self::read_comic_info(&mut archive);
+        let cover_image = match images
+            .get(ComicInfo::cover_index(info.as_ref()))
+            .or_else(|| images.first())
+        {
             Some(name) => name,
             None => return Ok(None),
         };
 
         let mut data = Vec::new();
-        archive.by_name(first_image)?.read_to_end(&mut data)?;
+        archive.by_name(cover_image)?.read_to_end(&mut data)?;
 
         let png_data = Self::to_png(&data)?;
         Ok(Some(png_data))
@@ -101,8 +152,34 @@ impl FormatHandler for CbzHandler {
     }
 }
 
+/// Largest archive entry read into memory.
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Read an archive entry, given the size its header states.
+///
+/// The stated size is only trusted for preallocation up to
+/// [`MAX_ENTRY_SIZE`]; larger entries are rejected, whatever they claim.
+pub(super) fn read_entry_data(size: u64, reader: &mut dyn Read) -> std::io::Result<Vec<u8>> {
+    let too_large = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Archive entry is too large",
+        )
+    };
+    if size > MAX_ENTRY_SIZE {
+        return Err(too_large());
+    }
+
+    let mut data = Vec::with_capacity(size as usize);
+    reader.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(too_large());
+    }
+    Ok(data)
+}
+
 /// Natural string comparison for sorting.
-fn natord_compare(a: &str, b: &str) -> std::cmp::Ordering {
+pub(super) fn natord_compare(a: &str, b: &str) -> std::cmp::Ordering {
     let mut a_chars = a.chars().peekable();
     let mut b_chars = b.chars().peekable();
 
@@ -146,7 +223,7 @@ fn natord_compare(a: &str, b: &str) -> std::cmp::Ordering {
 }
 
 /// Parse comic book filename to extract series and volume/issue number.
-fn parse_comic_filename(filename: &str) -> Option<(String, f32)> {
+pub(super) fn parse_comic_filename(filename: &str) -> Option<(String, f32)> {
     // Patterns to try:
     // "Series Name v01" or "Series Name Vol. 01"
     // "Series Name #01" or "Series Name - 01"
@@ -185,6 +262,7 @@ fn parse_comic_filename(filename: &str) -> Option<(String, f32)> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::formats::test_util::PNG_1X1;
 
     #[test]
     fn test_natord_compare() {
@@ -196,6 +274,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_comic_info_front_cover() {
+        use std::io::Write;
+
+        let comic_info = r#"<ComicInfo>
+            <Series>Akira</Series><Number>2</Number><Writer>Katsuhiro Otomo</Writer>
+            <Manga>YesAndRightToLeft</Manga>
+            <Pages><Page Image="1" Type="FrontCover"/></Pages>
+        </ComicInfo>"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("akira_v07.cbz");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let files: [(&str, &[u8]); 3] = [
+            ("ComicInfo.xml", comic_info.as_bytes()),
+            ("01.jpg", b"not an image"),
+            ("02.png", PNG_1X1),
+        ];
+        for (name, data) in files {
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut book = Book::new(path.clone(), crate::config::BookFormat::Cbz);
+        CbzHandler.extract_metadata(&mut book).unwrap();
+        assert_eq!(book.page_count, Some(2));
+        assert_eq!(book.series.as_deref(), Some("Akira"));
+        assert_eq!(book.series_index, Some(2.0));
+        assert_eq!(book.authors, vec!["Katsuhiro Otomo"]);
+        assert_eq!(
+            book.reading_direction,
+            crate::library::book::ReadingDirection::Rtl
+        );
+
+        // The first page is not decodable: only the FrontCover page works
+        let cover = CbzHandler.extract_cover(&path).unwrap().unwrap();
+        assert!(cover.starts_with(&[0x89, b'P', b'N', b'G']));
+    }
+
+    #[test]
+    fn test_read_entry_data() {
+        let data = read_entry_data(3, &mut &b"abc"[..]).unwrap();
+        assert_eq!(data, b"abc");
+        // A stated size that cannot be allocated is refused up front
+        assert!(read_entry_data(u64::MAX, &mut &b"abc"[..]).is_err());
+    }
+
     #[test]
     fn test_parse_comic_filename() {
         assert_eq!(
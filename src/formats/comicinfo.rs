@@ -0,0 +1,277 @@
+use crate::error::Result;
+use crate::library::book::{Book, Contributor, ReadingDirection};
+use roxmltree::{Document, Node};
+
+/// Metadata read from a `ComicInfo.xml` file (ComicRack schema).
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct ComicInfo {
+    title: Option<String>,
+    series: Option<String>,
+    number: Option<String>,
+    volume: Option<String>,
+    summary: Option<String>,
+    publisher: Option<String>,
+    language: Option<String>,
+    writers: Vec<String>,
+    pencillers: Vec<String>,
+    genres: Vec<String>,
+    tags: Vec<String>,
+    year: Option<u32>,
+    month: Option<u32>,
+    day: Option<u32>,
+    right_to_left: bool,
+    front_cover: Option<usize>,
+}
+
+impl ComicInfo {
+    /// Check whether an archive entry is the ComicInfo file.
+    pub(super) fn is_comic_info(name: &str) -> bool {
+        let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        file_name.eq_ignore_ascii_case("ComicInfo.xml")
+    }
+
+    /// Parse ComicInfo XML (BOM-aware).
+    pub(super) fn parse(data: &[u8]) -> Result<Self> {
+        let content = encoding_rs::UTF_8.decode(data).0;
+        let doc = Document::parse(&content)?;
+        let root = doc.root_element();
+
+        let mut info = ComicInfo {
+            title: text(root, "Title"),
+            series: text(root, "Series"),
+            number: text(root, "Number"),
+            volume: text(root, "Volume"),
+            summary: text(root, "Summary"),
+            publisher: text(root, "Publisher"),
+            language: text(root, "LanguageISO"),
+            writers: list(root, "Writer"),
+            pencillers: list(root, "Penciller"),
+            genres: list(root, "Genre"),
+            tags: list(root, "Tags"),
+            year: number(root, "Year"),
+            month: number(root, "Month"),
+            day: number(root, "Day"),
+            right_to_left: text(root, "Manga")
+                .is_some_and(|m| m.eq_ignore_ascii_case("YesAndRightToLeft")),
+            front_cover: None,
+        };
+
+        // <Pages><Page Image="0" Type="FrontCover"/></Pages>
+        info.front_cover = child(root, "Pages").and_then(|pages| {
+            pages
+                .children()
+                .filter(|p| p.is_element() && p.tag_name().name() == "Page")
+                .find(|p| p.attribute("Type") == Some("FrontCover"))
+                .and_then(|p| p.attribute("Image"))
+                .and_then(|i| i.trim().parse().ok())
+        });
+
+        // The year is meaningless as 0 or -1 (unset in ComicRack)
+        info.year = info.year.filter(|&y| y > 0);
+
+        Ok(info)
+    }
+
+    /// Copy the metadata into a book, overriding filename heuristics.
+    pub(super) fn apply(&self, book: &mut Book) {
+        if let Some(title) = &self.title {
+            book.title = title.clone();
+        }
+
+        if let Some(series) = &self.series {
+            book.series = Some(series.clone());
+            book.series_index = None;
+        }
+        let index = self
+            .number
+            .as_deref()
+            .and_then(parse_index)
+            .or_else(|| self.volume.as_deref().and_then(parse_index));
+        if index.is_some() {
+            book.series_index = index;
+        }
+
+        let mut authors = Vec::new();
+        for name in &self.writers {
+            if !authors.contains(name) {
+                authors.push(name.clone());
+            }
+        }
+        if !authors.is_empty() {
+            book.authors = authors;
+        }
+
+        for name in &self.pencillers {
+            if !book.contributors.iter().any(|c| &c.name == name) {
+                book.contributors.push(Contributor {
+                    name: name.clone(),
+                    role: "art".to_string(),
+                });
+            }
+        }
+
+        let mut tags = Vec::new();
+        for tag in self.genres.iter().chain(&self.tags) {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        if !tags.is_empty() {
+            book.tags = tags;
+        }
+
+        if self.summary.is_some() {
+            book.description = self.summary.clone();
+        }
+        if self.publisher.is_some() {
+            book.publisher = self.publisher.clone();
+        }
+        if self.language.is_some() {
+            book.language = self.language.clone();
+        }
+        if let Some(year) = self.year {
+            book.published = Some(match (self.month, self.day) {
+                (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+                (Some(month), None) => format!("{:04}-{:02}", year, month),
+                _ => format!("{:04}", year),
+            });
+        }
+        if self.right_to_left {
+            book.reading_direction = ReadingDirection::Rtl;
+        }
+    }
+
+    /// Index of the cover among the sorted pages (the first page by default).
+    pub(super) fn cover_index(info: Option<&Self>) -> usize {
+        info.and_then(|i| i.front_cover).unwrap_or(0)
+    }
+}
+
+/// First child element with the given name.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// Trimmed text of a child element, if not empty.
+fn text(node: Node<'_, '_>, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|n| n.text())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// Comma-separated values of a child element.
+fn list(node: Node<'_, '_>, name: &str) -> Vec<String> {
+    text(node, name)
+        .map(|s| {
+            s.split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Numeric value of a child element.
+fn number(node: Node<'_, '_>, name: &str) -> Option<u32> {
+    text(node, name).and_then(|s| s.parse().ok())
+}
+
+/// Parse an issue or volume number ("12", "1.5", "007").
+fn parse_index(value: &str) -> Option<f32> {
+    value.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMIC_INFO: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>Romance Dawn</Title>
+  <Series>One Piece</Series>
+  <Number>1</Number>
+  <Volume>1</Volume>
+  <Summary>A boy made of rubber sets out to sea.</Summary>
+  <Year>1997</Year>
+  <Month>12</Month>
+  <Writer>Eiichiro Oda</Writer>
+  <Penciller>Eiichiro Oda, Assistant</Penciller>
+  <Publisher>Shueisha</Publisher>
+  <Genre>Action, Adventure</Genre>
+  <Tags>Pirates, Adventure</Tags>
+  <LanguageISO>ja</LanguageISO>
+  <Manga>YesAndRightToLeft</Manga>
+  <Pages>
+    <Page Image="0" Type="InnerCover"/>
+    <Page Image="2" Type="FrontCover"/>
+    <Page Image="3"/>
+  </Pages>
+</ComicInfo>"#;
+
+    #[test]
+    fn test_parse_and_apply() {
+        let info = ComicInfo::parse(COMIC_INFO.as_bytes()).unwrap();
+        assert_eq!(ComicInfo::cover_index(Some(&info)), 2);
+
+        let mut book = Book {
+            title: "One Piece v01".to_string(),
+            series: Some("One Piece".to_string()),
+            series_index: Some(99.0),
+            ..Default::default()
+        };
+        info.apply(&mut book);
+
+        assert_eq!(book.title, "Romance Dawn");
+        assert_eq!(book.series.as_deref(), Some("One Piece"));
+        assert_eq!(book.series_index, Some(1.0));
+        assert_eq!(book.authors, vec!["Eiichiro Oda"]);
+        let artists: Vec<_> = book
+            .contributors
+            .iter()
+            .map(|c| (c.name.as_str(), c.role.as_str()))
+            .collect();
+        assert_eq!(artists, vec![("Eiichiro Oda", "art"), ("Assistant", "art")]);
+        assert_eq!(book.tags, vec!["Action", "Adventure", "Pirates"]);
+        assert_eq!(
+            book.description.as_deref(),
+            Some("A boy made of rubber sets out to sea.")
+        );
+        assert_eq!(book.publisher.as_deref(), Some("Shueisha"));
+        assert_eq!(book.language.as_deref(), Some("ja"));
+        assert_eq!(book.published.as_deref(), Some("1997-12"));
+        assert_eq!(book.reading_direction, ReadingDirection::Rtl);
+    }
+
+    #[test]
+    fn test_sparse_comic_info_keeps_heuristics() {
+        let xml =
+            "\u{feff}<ComicInfo><Volume>3</Volume><Year>-1</Year><Manga>Yes</Manga></ComicInfo>";
+        let info = ComicInfo::parse(xml.as_bytes()).unwrap();
+        assert_eq!(ComicInfo::cover_index(Some(&info)), 0);
+        assert_eq!(ComicInfo::cover_index(None), 0);
+
+        let mut book = Book {
+            title: "Saga v03".to_string(),
+            series: Some("Saga".to_string()),
+            series_index: Some(3.0),
+            ..Default::default()
+        };
+        info.apply(&mut book);
+
+        assert_eq!(book.title, "Saga v03");
+        assert_eq!(book.series.as_deref(), Some("Saga"));
+        assert_eq!(book.series_index, Some(3.0));
+        assert_eq!(book.published, None);
+        assert_eq!(book.reading_direction, ReadingDirection::Ltr);
+    }
+
+    #[test]
+    fn test_is_comic_info() {
+        assert!(ComicInfo::is_comic_info("ComicInfo.xml"));
+        assert!(ComicInfo::is_comic_info("Saga/comicinfo.XML"));
+        assert!(!ComicInfo::is_comic_info("ComicInfo.xml.bak"));
+        assert!(!ComicInfo::is_comic_info("page01.jpg"));
+    }
+}
@@ -0,0 +1,316 @@
+//! On-demand conversion of text-like formats and comics to EPUB 3.
+//!
+//! Each text source format is turned into a list of top-level XHTML blocks,
+//! which are split into chapters on headings and packaged with the book
+//! metadata and cover. Comics become fixed-layout books with one page per
+//! image.
+
+mod comic;
+mod epub;
+mod optimize;
+mod page;
+
+use crate::config::{BookFormat, DeviceProfile, PageFormat};
+use crate::error::{AppError, Result};
+use crate::library::book::Book;
+use std::io::{Seek, Write};
+
+/// Chapters larger than this are split over several files, as some
+/// readers refuse to open very large XHTML documents.
+const MAX_PART_SIZE: usize = 200 * 1024;
+
+/// A top-level element of a converted document.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Block {
+    /// A heading with its level (1-6), plain text and XHTML markup.
+    Heading {
+        level: u8,
+        text: String,
+        xhtml: String,
+    },
+    /// Any other element, as well-formed XHTML.
+    Content(String),
+}
+
+impl Block {
+    fn xhtml(&self) -> &str {
+        match self {
+            Block::Heading { xhtml, .. } | Block::Content(xhtml) => xhtml,
+        }
+    }
+}
+
+/// A chapter of the converted book, stored as one or more XHTML files.
+#[derive(Debug, PartialEq)]
+struct Chapter {
+    title: String,
+    /// Body content of each file.
+    parts: Vec<String>,
+}
+
+/// Default JPEG quality of re-encoded comic pages.
+const DEFAULT_QUALITY: u8 = 85;
+
+/// Options for re-encoding comic pages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComicOptions {
+    /// Scale pages down to fit this resolution (width, height) in pixels.
+    pub max_size: Option<(u32, u32)>,
+    /// Convert pages to grayscale.
+    pub grayscale: bool,
+    /// Format of re-encoded pages; by default PNG pages stay PNG and
+    /// other pages become JPEG.
+    pub format: Option<PageFormat>,
+    /// JPEG quality (1-100) of re-encoded pages.
+    pub quality: u8,
+    /// Target size of the whole book in bytes.
+    pub max_file_size: Option<u64>,
+}
+
+/// Granularity of client-given screen sizes, in pixels.
+const SCREEN_STEP: u32 = 256;
+
+impl Default for ComicOptions {
+    fn default() -> Self {
+        Self {
+            max_size: None,
+            grayscale: false,
+            format: None,
+            quality: DEFAULT_QUALITY,
+            max_file_size: None,
+        }
+    }
+}
+
+impl From<&DeviceProfile> for ComicOptions {
+    fn from(profile: &DeviceProfile) -> Self {
+        Self {
+            max_size: Some((profile.width, profile.height)),
+            grayscale: profile.grayscale,
+            format: Some(profile.format),
+            quality: profile.quality,
+            max_file_size: profile.max_file_size_mb.map(|mb| mb * 1024 * 1024),
+        }
+    }
+}
+
+impl ComicOptions {
+    /// Options scaling pages down to a screen size given by a client.
+    ///
+    /// Sizes are rounded up to multiples of [`SCREEN_STEP`] (256 to 4096
+    /// pixels), so arbitrary sizes share a small number of cached files.
+    pub fn for_screen(width: u32, height: u32) -> Self {
+        let snap = |size: u32| size.clamp(SCREEN_STEP, 4096).div_ceil(SCREEN_STEP) * SCREEN_STEP;
+        Self {
+            max_size: Some((snap(width), snap(height))),
+            ..Default::default()
+        }
+    }
+
+    /// Short key identifying these options, for cache file names.
+    ///
+    /// `None` for the default options, which keep pages unchanged.
+    pub fn cache_key(&self) -> Option<String> {
+        if *self == Self::default() {
+            return None;
+        }
+        let digest = md5::compute(format!("{:?}", self));
+        Some(format!("{:x}", digest)[..12].to_string())
+    }
+}
+
+/// Whether books in this format can be converted to EPUB.
+pub fn can_convert(format: BookFormat) -> bool {
+    matches!(
+        format,
+        BookFormat::Txt | BookFormat::Md | BookFormat::Html | BookFormat::Fb2 | BookFormat::Cbz
+    )
+}
+
+/// Whether books in this format convert to fixed-layout EPUB.
+pub fn is_fixed_layout(format: BookFormat) -> bool {
+    format == BookFormat::Cbz
+}
+
+/// Convert a text book to EPUB 3, embedding the given cover image.
+pub fn to_epub<W: Write + Seek>(book: &Book, cover: Option<&[u8]>, writer: W) -> Result<()> {
+    let blocks = match book.format {
+        BookFormat::Txt | BookFormat::Md | BookFormat::Html => {
+            super::text::blocks(&book.path, book.format)?
+        }
+        BookFormat::Fb2 => super::fb2::blocks(&book.path)?,
+        format => {
+            return Err(AppError::InvalidFormat(format!(
+                "Cannot convert {} to EPUB",
+                format.mime_type()
+            )));
+        }
+    };
+
+    let chapters = split_chapters(blocks, &book.title);
+    epub::write(book, &chapters, cover, writer)
+}
+
+/// Convert a comic to a fixed-layout EPUB 3, one page per image.
+pub fn comic_to_epub<W: Write + Seek>(
+    book: &Book,
+    options: &ComicOptions,
+    writer: W,
+) -> Result<()> {
+    if !is_fixed_layout(book.format) {
+        return Err(AppError::InvalidFormat(format!(
+            "Cannot convert {} to fixed-layout EPUB",
+            book.format.mime_type()
+        )));
+    }
+    comic::write(book, options, writer)
+}
+
+/// Re-encode the pages of a comic archive (CBZ, CBR or CB7) into a CBZ.
+pub fn optimize_comic<W: Write + Seek>(
+    book: &Book,
+    options: &ComicOptions,
+    writer: W,
+) -> Result<()> {
+    if !book.format.is_comic() {
+        return Err(AppError::InvalidFormat(format!(
+            "Not a comic archive: {}",
+            book.format.mime_type()
+        )));
+    }
+    optimize::write(book, options, writer)
+}
+
+/// Split blocks into chapters.
+///
+/// Chapters start at the highest heading level used more than once, so a
+/// single title heading does not end up as a chapter of its own.
+fn split_chapters(blocks: Vec<Block>, title: &str) -> Vec<Chapter> {
+    let split_level = (1..=6).find(|&level| {
+        blocks
+            .iter()
+            .filter(|b| matches!(b, Block::Heading { level: l, .. } if *l == level))
+            .count()
+            >= 2
+    });
+
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let mut current = Chapter {
+        title: title.to_string(),
+        parts: vec![String::new()],
+    };
+
+    for block in blocks {
+        if let (Block::Heading { level, text, .. }, Some(split)) = (&block, split_level)
+            && *level <= split
+        {
+            let next = Chapter {
+                title: if text.is_empty() {
+                    title.to_string()
+                } else {
+                    text.clone()
+                },
+                parts: vec![String::new()],
+            };
+            let previous = std::mem::replace(&mut current, next);
+            if previous.parts.iter().any(|p| !p.is_empty()) {
+                chapters.push(previous);
+            }
+        }
+
+        let xhtml = block.xhtml();
+        if let Some(part) = current.parts.last_mut()
+            && !part.is_empty()
+            && part.len() + xhtml.len() > MAX_PART_SIZE
+        {
+            current.parts.push(String::new());
+        }
+        if let Some(part) = current.parts.last_mut() {
+            part.push_str(xhtml);
+            part.push('\n');
+        }
+    }
+
+    if current.parts.iter().any(|p| !p.is_empty()) || chapters.is_empty() {
+        chapters.push(current);
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screen_sizes_snapped() {
+        let options = ComicOptions::for_screen(1072, 1448);
+        assert_eq!(options.max_size, Some((1280, 1536)));
+        assert_eq!(
+            ComicOptions::for_screen(1073, 1440).cache_key(),
+            options.cache_key()
+        );
+        assert_eq!(
+            ComicOptions::for_screen(0, u32::MAX).max_size,
+            Some((256, 4096))
+        );
+    }
+
+    fn heading(level: u8, text: &str) -> Block {
+        Block::Heading {
+            level,
+            text: text.to_string(),
+            xhtml: format!("<h{0}>{1}</h{0}>", level, text),
+        }
+    }
+
+    fn content(text: &str) -> Block {
+        Block::Content(format!("<p>{}</p>", text))
+    }
+
+    #[test]
+    fn test_split_on_repeated_level() {
+        let blocks = vec![
+            heading(1, "Book"),
+            content("Intro"),
+            heading(2, "One"),
+            content("First"),
+            heading(3, "Detail"),
+            heading(2, "Two"),
+            content("Second"),
+        ];
+        let chapters = split_chapters(blocks, "Title");
+
+        let titles: Vec<&str> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Book", "One", "Two"]);
+        assert_eq!(
+            chapters[1].parts,
+            vec!["<h2>One</h2>\n<p>First</p>\n<h3>Detail</h3>\n"]
+        );
+    }
+
+    #[test]
+    fn test_single_chapter_without_headings() {
+        let chapters = split_chapters(vec![content("Text")], "Title");
+        assert_eq!(
+            chapters,
+            vec![Chapter {
+                title: "Title".to_string(),
+                parts: vec!["<p>Text</p>\n".to_string()],
+            }]
+        );
+
+        // Empty documents still get a chapter
+        assert_eq!(split_chapters(Vec::new(), "Title").len(), 1);
+    }
+
+    #[test]
+    fn test_large_chapters_are_split() {
+        let paragraph = "x".repeat(MAX_PART_SIZE / 3);
+        let blocks = (0..5).map(|_| content(&paragraph)).collect();
+        let chapters = split_chapters(blocks, "Title");
+
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].parts.len(), 3);
+        assert!(chapters[0].parts.iter().all(|p| p.len() <= MAX_PART_SIZE));
+    }
+}
@@ -0,0 +1,382 @@
+//! Fixed-layout EPUB 3 packaging of comic archives.
+//!
+//! Every page image gets its own pre-paginated content document sized to the
+//! image, and spine properties place pages on the left or right of a spread
+//! according to the reading direction.
+
+use super::ComicOptions;
+use super::epub;
+use super::page::Page;
+use crate::error::{AppError, Result};
+use crate::formats::cbz::CbzHandler;
+use crate::formats::comicinfo::ComicInfo;
+use crate::library::book::{Book, ReadingDirection};
+use quick_xml::escape::escape;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use zip::CompressionMethod;
+use zip::ZipArchive;
+use zip::write::SimpleFileOptions;
+
+const STYLE: &str =
+    "html, body { margin: 0; padding: 0; width: 100%; height: 100%; overflow: hidden; }
+img { display: block; width: 100%; height: 100%; }
+";
+
+/// Spine `properties` placing each page in a spread.
+///
+/// The first page stands alone on the recto side, following pages alternate,
+/// and landscape pages fill a whole spread so the next page starts a new one.
+fn spreads(landscape: &[bool], direction: ReadingDirection) -> Vec<&'static str> {
+    let (first, second) = match direction {
+        ReadingDirection::Ltr => ("page-spread-left", "page-spread-right"),
+        ReadingDirection::Rtl => ("page-spread-right", "page-spread-left"),
+    };
+
+    let mut next = second;
+    landscape
+        .iter()
+        .map(|&wide| {
+            if wide {
+                next = first;
+                return "rendition:page-spread-center";
+            }
+            let side = next;
+            next = if side == first { second } else { first };
+            side
+        })
+        .collect()
+}
+
+/// Write a fixed-layout EPUB with the pages of a CBZ archive.
+pub(super) fn write<W: Write + Seek>(book: &Book, options: &ComicOptions, writer: W) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(&book.path)?)?;
+    let images = CbzHandler::get_image_files(&archive);
+    if images.is_empty() {
+        return Err(AppError::InvalidFormat(format!(
+            "No pages in comic: {}",
+            book.path.display()
+        )));
+    }
+    let info = CbzHandler::read_comic_info(&mut archive);
+    let cover_index = ComicInfo::cover_index(info.as_ref()).min(images.len() - 1);
+    let language = book.language.as_deref().unwrap_or("und");
+
+    let mut zip = epub::start(writer)?;
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // Pages are written as they are read so large comics are never held in
+    // memory; the package document follows once all sizes are known
+    let mut pages = Vec::new();
+    let mut toc = Vec::new();
+    let mut folder = None;
+    for (index, name) in images.iter().enumerate() {
+        let mut data = Vec::new();
+        archive.by_name(name)?.read_to_end(&mut data)?;
+        let page = match Page::new(data, options, None) {
+            Ok(page) => page,
+            Err(e) => {
+                tracing::warn!(page = %name, error = %e, "Skipping unreadable comic page");
+                continue;
+            }
+        };
+
+        let number = pages.len() + 1;
+        let image = format!("images/page-{:04}.{}", number, page.extension);
+        let document = format!("page-{:04}.xhtml", number);
+
+        // Folders inside the archive are usually chapters
+        let parent = name.rsplit_once('/').map(|(parent, _)| parent);
+        if toc.is_empty() || parent != folder {
+            let title = parent
+                .and_then(|p| p.rsplit('/').next())
+                .map_or_else(|| book.title.clone(), str::to_string);
+            toc.push((document.clone(), title));
+            folder = parent;
+        }
+
+        zip.start_file(format!("OEBPS/{}", image), stored)?;
+        zip.write_all(&page.data)?;
+
+        let body = format!(
+            "<img src=\"{}\" alt=\"{}\" width=\"{}\" height=\"{}\"/>",
+            image,
+            if index == cover_index { "Cover" } else { "" },
+            page.width,
+            page.height
+        );
+        zip.start_file(format!("OEBPS/{}", document), deflated)?;
+        zip.write_all(
+            page_xhtml(
+                language,
+                &format!("Page {}", number),
+                page.width,
+                page.height,
+                &body,
+            )
+            .as_bytes(),
+        )?;
+
+        pages.push(PageEntry {
+            image,
+            media_type: page.media_type,
+            document,
+            landscape: page.width > page.height,
+            cover: index == cover_index,
+        });
+    }
+    if pages.is_empty() {
+        return Err(AppError::InvalidFormat(format!(
+            "No readable pages in comic: {}",
+            book.path.display()
+        )));
+    }
+    // Only one folder: the chapter list is just the book
+    if toc.len() == 1 {
+        toc[0].1 = book.title.clone();
+    }
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package(book, &pages).as_bytes())?;
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(epub::nav(book, language, &toc).as_bytes())?;
+
+    zip.start_file("OEBPS/toc.ncx", deflated)?;
+    zip.write_all(epub::ncx(book, &toc).as_bytes())?;
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLE.as_bytes())?;
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// A page stored in the package.
+struct PageEntry {
+    image: String,
+    media_type: &'static str,
+    document: String,
+    landscape: bool,
+    cover: bool,
+}
+
+/// Build the package document of a fixed-layout book.
+fn package(book: &Book, pages: &[PageEntry]) -> String {
+    let mut opf = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+"#,
+    );
+    epub::metadata(&mut opf, book);
+    opf.push_str(
+        "    <meta property=\"rendition:layout\">pre-paginated</meta>\n    \
+         <meta property=\"rendition:orientation\">auto</meta>\n    \
+         <meta property=\"rendition:spread\">landscape</meta>\n    \
+         <meta name=\"cover\" content=\"cover-image\"/>\n",
+    );
+
+    opf.push_str(
+        "  </metadata>\n  <manifest>\n    \
+         <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n    \
+         <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n    \
+         <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    for (index, page) in pages.iter().enumerate() {
+        let image_id = if page.cover {
+            "cover-image".to_string()
+        } else {
+            format!("image-{:04}", index + 1)
+        };
+        let _ = writeln!(
+            opf,
+            "    <item id=\"{}\" href=\"{}\" media-type=\"{}\"{}/>\n    \
+             <item id=\"page-{:04}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>",
+            image_id,
+            page.image,
+            page.media_type,
+            if page.cover {
+                " properties=\"cover-image\""
+            } else {
+                ""
+            },
+            index + 1,
+            page.document
+        );
+    }
+
+    let direction = book.reading_direction;
+    let _ = writeln!(
+        opf,
+        "  </manifest>\n  <spine toc=\"ncx\" page-progression-direction=\"{}\">",
+        direction.as_str()
+    );
+    let landscape: Vec<bool> = pages.iter().map(|p| p.landscape).collect();
+    for (index, spread) in spreads(&landscape, direction).into_iter().enumerate() {
+        let _ = writeln!(
+            opf,
+            "    <itemref idref=\"page-{:04}\" properties=\"{}\"/>",
+            index + 1,
+            spread
+        );
+    }
+    opf.push_str("  </spine>\n</package>\n");
+    opf
+}
+
+/// Wrap a page image in a content document whose viewport matches the image.
+fn page_xhtml(language: &str, title: &str, width: u32, height: u32, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{language}" xml:lang="{language}">
+<head>
+<title>{title}</title>
+<meta name="viewport" content="width={width}, height={height}"/>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        language = escape(language),
+        title = escape(title),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BookFormat;
+    use image::{DynamicImage, ImageFormat};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_spreads() {
+        let ltr = spreads(&[false, false, false, true, false], ReadingDirection::Ltr);
+        assert_eq!(
+            ltr,
+            vec![
+                "page-spread-right",
+                "page-spread-left",
+                "page-spread-right",
+                "rendition:page-spread-center",
+                "page-spread-left",
+            ]
+        );
+
+        let rtl = spreads(&[false, false, false], ReadingDirection::Rtl);
+        assert_eq!(
+            rtl,
+            vec!["page-spread-left", "page-spread-right", "page-spread-left"]
+        );
+    }
+
+    #[test]
+    fn test_write_fixed_layout() {
+        let dir = std::env::temp_dir().join(format!("ebook-rs-comic-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Manga v01.cbz");
+
+        let encode = |img: DynamicImage, format: ImageFormat| {
+            let mut data = Vec::new();
+            img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+            data
+        };
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default();
+        let pages = [
+            (
+                "01/page10.png",
+                encode(DynamicImage::new_rgb8(60, 90), ImageFormat::Png),
+            ),
+            (
+                "01/page2.jpg",
+                encode(DynamicImage::new_rgb8(60, 90), ImageFormat::Jpeg),
+            ),
+            (
+                "02/page1.png",
+                encode(DynamicImage::new_rgb8(120, 90), ImageFormat::Png),
+            ),
+            ("02/broken.jpg", b"not an image".to_vec()),
+        ];
+        for (name, data) in &pages {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut book = Book::new(path, BookFormat::Cbz);
+        book.title = "Manga".to_string();
+        book.reading_direction = ReadingDirection::Rtl;
+
+        let mut data = Vec::new();
+        let options = ComicOptions {
+            max_size: Some((40, 60)),
+            ..Default::default()
+        };
+        write(&book, &options, Cursor::new(&mut data)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(data)).unwrap();
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        let doc = roxmltree::Document::parse(&opf).unwrap();
+
+        assert!(opf.contains("<meta property=\"rendition:layout\">pre-paginated</meta>"));
+        let spine = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "spine")
+            .unwrap();
+        assert_eq!(spine.attribute("page-progression-direction"), Some("rtl"));
+        let properties: Vec<&str> = spine
+            .children()
+            .filter_map(|n| n.attribute("properties"))
+            .collect();
+        assert_eq!(
+            properties,
+            vec![
+                "page-spread-left",
+                "page-spread-right",
+                "rendition:page-spread-center",
+            ]
+        );
+
+        // Natural order, PNG kept as PNG, resized to fit
+        let mut page = String::new();
+        archive
+            .by_name("OEBPS/page-0001.xhtml")
+            .unwrap()
+            .read_to_string(&mut page)
+            .unwrap();
+        assert!(page.contains("width=40, height=60"));
+        assert!(page.contains("images/page-0001.jpg"));
+        assert!(archive.by_name("OEBPS/images/page-0002.png").is_ok());
+
+        let mut image_data = Vec::new();
+        archive
+            .by_name("OEBPS/images/page-0003.png")
+            .unwrap()
+            .read_to_end(&mut image_data)
+            .unwrap();
+        let image = image::load_from_memory(&image_data).unwrap();
+        assert_eq!((image.width(), image.height()), (53, 40));
+
+        // One chapter per folder
+        let mut nav = String::new();
+        archive
+            .by_name("OEBPS/nav.xhtml")
+            .unwrap()
+            .read_to_string(&mut nav)
+            .unwrap();
+        assert!(nav.contains("<a href=\"page-0001.xhtml\">01</a>"));
+        assert!(nav.contains("<a href=\"page-0003.xhtml\">02</a>"));
+    }
+}
@@ -0,0 +1,470 @@
+//! EPUB 3 packaging of converted chapters.
+//!
+//! A navigation document and an NCX table of contents are both written so
+//! that EPUB 2 readers can navigate the book too.
+
+use super::Chapter;
+use crate::error::Result;
+use crate::library::book::Book;
+use quick_xml::escape::escape;
+use std::fmt::Write as _;
+use std::io::{Seek, Write};
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
+
+const STYLE: &str = "body { margin: 0 2%; line-height: 1.4; }
+h1, h2, h3, h4, h5, h6 { text-align: center; page-break-after: avoid; }
+p { margin: 0; text-indent: 1.5em; text-align: justify; }
+h1 + p, h2 + p, h3 + p, h4 + p, h5 + p, h6 + p, hr + p { text-indent: 0; }
+blockquote { margin: 1em 2em; }
+pre { white-space: pre-wrap; font-size: 0.9em; }
+img { max-width: 100%; }
+.cover { margin: 0; padding: 0; text-align: center; }
+.cover img { height: 100%; max-width: 100%; }
+";
+
+/// Cover image stored in the package.
+struct CoverImage {
+    data: Vec<u8>,
+    href: &'static str,
+    media_type: &'static str,
+}
+
+impl CoverImage {
+    /// Keep JPEG and PNG covers as they are; convert anything else to PNG.
+    fn new(data: &[u8]) -> Option<Self> {
+        match image::guess_format(data).ok()? {
+            image::ImageFormat::Jpeg => Some(Self {
+                data: data.to_vec(),
+                href: "cover.jpg",
+                media_type: "image/jpeg",
+            }),
+            image::ImageFormat::Png => Some(Self {
+                data: data.to_vec(),
+                href: "cover.png",
+                media_type: "image/png",
+            }),
+            _ => {
+                let mut png = Vec::new();
+                image::load_from_memory(data)
+                    .ok()?
+                    .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                    .ok()?;
+                Some(Self {
+                    data: png,
+                    href: "cover.png",
+                    media_type: "image/png",
+                })
+            }
+        }
+    }
+}
+
+/// A content document of the package.
+struct Document {
+    id: String,
+    href: String,
+    title: String,
+    body: String,
+}
+
+/// Write the EPUB archive.
+pub(super) fn write<W: Write + Seek>(
+    book: &Book,
+    chapters: &[Chapter],
+    cover: Option<&[u8]>,
+    writer: W,
+) -> Result<()> {
+    let language = book.language.as_deref().unwrap_or("und");
+    let cover = cover.and_then(CoverImage::new);
+
+    // One document per chapter part; the table of contents links to the first
+    let mut documents = Vec::new();
+    let mut toc = Vec::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        for (part, body) in chapter.parts.iter().enumerate() {
+            let id = match part {
+                0 => format!("chapter-{:03}", index + 1),
+                part => format!("chapter-{:03}-{}", index + 1, part + 1),
+            };
+            if part == 0 {
+                toc.push((format!("{}.xhtml", id), chapter.title.clone()));
+            }
+            documents.push(Document {
+                href: format!("{}.xhtml", id),
+                id,
+                title: chapter.title.clone(),
+                body: body.clone(),
+            });
+        }
+    }
+
+    let mut zip = start(writer)?;
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package(book, &documents, cover.as_ref()).as_bytes())?;
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(nav(book, language, &toc).as_bytes())?;
+
+    zip.start_file("OEBPS/toc.ncx", deflated)?;
+    zip.write_all(ncx(book, &toc).as_bytes())?;
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLE.as_bytes())?;
+
+    if let Some(cover) = &cover {
+        zip.start_file(format!("OEBPS/{}", cover.href), stored)?;
+        zip.write_all(&cover.data)?;
+
+        let body = format!(
+            "<div class=\"cover\"><img src=\"{}\" alt=\"{}\"/></div>",
+            cover.href,
+            escape(book.title.as_str())
+        );
+        zip.start_file("OEBPS/cover.xhtml", deflated)?;
+        zip.write_all(xhtml(language, &book.title, &body).as_bytes())?;
+    }
+
+    for document in &documents {
+        zip.start_file(format!("OEBPS/{}", document.href), deflated)?;
+        zip.write_all(xhtml(language, &document.title, &document.body).as_bytes())?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Start an EPUB archive with its `mimetype` and container entries.
+pub(super) fn start<W: Write + Seek>(writer: W) -> Result<zip::ZipWriter<W>> {
+    let mut zip = zip::ZipWriter::new(writer);
+
+    // The mimetype entry must come first and be stored uncompressed
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER.as_bytes())?;
+
+    Ok(zip)
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// Build the package document.
+fn package(book: &Book, documents: &[Document], cover: Option<&CoverImage>) -> String {
+    let mut opf = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+"#,
+    );
+    metadata(&mut opf, book);
+    if cover.is_some() {
+        opf.push_str("    <meta name=\"cover\" content=\"cover-image\"/>\n");
+    }
+
+    opf.push_str(
+        "  </metadata>\n  <manifest>\n    \
+         <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n    \
+         <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n    \
+         <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    if let Some(cover) = cover {
+        let _ = writeln!(
+            opf,
+            "    <item id=\"cover-image\" href=\"{}\" media-type=\"{}\" properties=\"cover-image\"/>\n    \
+             <item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>",
+            cover.href, cover.media_type
+        );
+    }
+    for document in documents {
+        let _ = writeln!(
+            opf,
+            "    <item id=\"{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>",
+            document.id, document.href
+        );
+    }
+
+    opf.push_str("  </manifest>\n  <spine toc=\"ncx\">\n");
+    if cover.is_some() {
+        opf.push_str("    <itemref idref=\"cover\"/>\n");
+    }
+    for document in documents {
+        let _ = writeln!(opf, "    <itemref idref=\"{}\"/>", document.id);
+    }
+    opf.push_str("  </spine>\n");
+    if cover.is_some() {
+        opf.push_str(
+            "  <guide>\n    <reference type=\"cover\" title=\"Cover\" href=\"cover.xhtml\"/>\n  </guide>\n",
+        );
+    }
+    opf.push_str("</package>\n");
+    opf
+}
+
+/// Write the opening `metadata` element and the book metadata, leaving the
+/// element open for format-specific properties.
+pub(super) fn metadata(opf: &mut String, book: &Book) {
+    let _ = write!(
+        opf,
+        r#"  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:uuid:{id}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+    <meta property="dcterms:modified">{modified}</meta>
+"#,
+        id = escape(book.id.as_str()),
+        title = escape(book.title.as_str()),
+        language = escape(book.language.as_deref().unwrap_or("und")),
+        modified = book.modified.format("%Y-%m-%dT%H:%M:%SZ"),
+    );
+
+    for (index, author) in book.authors.iter().enumerate() {
+        let _ = writeln!(
+            opf,
+            "    <dc:creator id=\"creator-{0}\">{1}</dc:creator>\n    \
+             <meta refines=\"#creator-{0}\" property=\"role\" scheme=\"marc:relators\">aut</meta>",
+            index + 1,
+            escape(author.as_str())
+        );
+    }
+    for (index, contributor) in book.contributors.iter().enumerate() {
+        let _ = writeln!(
+            opf,
+            "    <dc:contributor id=\"contributor-{0}\">{1}</dc:contributor>\n    \
+             <meta refines=\"#contributor-{0}\" property=\"role\" scheme=\"marc:relators\">{2}</meta>",
+            index + 1,
+            escape(contributor.name.as_str()),
+            escape(contributor.role.as_str())
+        );
+    }
+    for identifier in &book.identifiers {
+        let _ = writeln!(
+            opf,
+            "    <dc:identifier>{}</dc:identifier>",
+            escape(identifier.urn())
+        );
+    }
+    let optional = [
+        ("dc:description", &book.description),
+        ("dc:publisher", &book.publisher),
+        ("dc:date", &book.published),
+    ];
+    for (element, value) in optional {
+        if let Some(value) = value {
+            let _ = writeln!(opf, "    <{0}>{1}</{0}>", element, escape(value.as_str()));
+        }
+    }
+    for tag in &book.tags {
+        let _ = writeln!(opf, "    <dc:subject>{}</dc:subject>", escape(tag.as_str()));
+    }
+
+    if let Some(series) = &book.series {
+        let series = escape(series.as_str());
+        let _ = writeln!(
+            opf,
+            "    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>\n    \
+             <meta refines=\"#series\" property=\"collection-type\">series</meta>",
+            series
+        );
+        // calibre's series metadata is understood by more reading systems
+        let _ = writeln!(
+            opf,
+            "    <meta name=\"calibre:series\" content=\"{}\"/>",
+            series
+        );
+        if let Some(index) = book.series_index {
+            let _ = writeln!(
+                opf,
+                "    <meta refines=\"#series\" property=\"group-position\">{0}</meta>\n    \
+                 <meta name=\"calibre:series_index\" content=\"{0}\"/>",
+                index
+            );
+        }
+    }
+}
+
+/// Build the EPUB 3 navigation document.
+pub(super) fn nav(book: &Book, language: &str, toc: &[(String, String)]) -> String {
+    let mut body = String::from("<nav epub:type=\"toc\" id=\"toc\">\n<h1>");
+    body.push_str(&escape(book.title.as_str()));
+    body.push_str("</h1>\n<ol>\n");
+    for (href, title) in toc {
+        let _ = writeln!(
+            body,
+            "<li><a href=\"{}\">{}</a></li>",
+            href,
+            escape(title.as_str())
+        );
+    }
+    body.push_str("</ol>\n</nav>");
+    xhtml(language, &book.title, &body)
+}
+
+/// Build the NCX table of contents for EPUB 2 readers.
+pub(super) fn ncx(book: &Book, toc: &[(String, String)]) -> String {
+    let mut ncx = String::new();
+    let _ = write!(
+        ncx,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head>
+    <meta name="dtb:uid" content="urn:uuid:{}"/>
+    <meta name="dtb:depth" content="1"/>
+    <meta name="dtb:totalPageCount" content="0"/>
+    <meta name="dtb:maxPageNumber" content="0"/>
+  </head>
+  <docTitle><text>{}</text></docTitle>
+  <navMap>
+"#,
+        escape(book.id.as_str()),
+        escape(book.title.as_str())
+    );
+    for (index, (href, title)) in toc.iter().enumerate() {
+        let _ = writeln!(
+            ncx,
+            "    <navPoint id=\"nav-{0}\" playOrder=\"{0}\">\n      \
+             <navLabel><text>{1}</text></navLabel>\n      \
+             <content src=\"{2}\"/>\n    </navPoint>",
+            index + 1,
+            escape(title.as_str()),
+            href
+        );
+    }
+    ncx.push_str("  </navMap>\n</ncx>\n");
+    ncx
+}
+
+/// Wrap body content in an XHTML content document.
+fn xhtml(language: &str, title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{language}" xml:lang="{language}">
+<head>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        language = escape(language),
+        title = escape(title),
+        body = body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BookFormat;
+    use crate::library::book::Identifier;
+    use std::io::Read;
+
+    fn entry(archive: &mut zip::ZipArchive<std::io::Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_write_package() {
+        let mut book = Book::new("/books/a.md".into(), BookFormat::Md);
+        book.title = "Tom & Jerry".to_string();
+        book.authors = vec!["Ann <Author>".to_string()];
+        book.language = Some("en".to_string());
+        book.series = Some("Cartoons".to_string());
+        book.series_index = Some(2.0);
+        book.identifiers = vec![Identifier {
+            scheme: "isbn".to_string(),
+            value: "9780306406157".to_string(),
+        }];
+
+        let chapters = vec![
+            Chapter {
+                title: "One".to_string(),
+                parts: vec!["<p>a</p>".to_string(), "<p>b</p>".to_string()],
+            },
+            Chapter {
+                title: "Two".to_string(),
+                parts: vec!["<p>c</p>".to_string()],
+            },
+        ];
+        let mut png = Vec::new();
+        image::RgbImage::new(4, 6)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let mut data = Vec::new();
+        write(
+            &book,
+            &chapters,
+            Some(&png),
+            std::io::Cursor::new(&mut data),
+        )
+        .unwrap();
+        assert_eq!(&data[30..38], b"mimetype");
+        assert_eq!(&data[38..58], b"application/epub+zip");
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        let opf = entry(&mut archive, "OEBPS/content.opf");
+        let doc = roxmltree::Document::parse(&opf).unwrap();
+        let text = |name: &str| {
+            doc.descendants()
+                .filter(|n| n.tag_name().name() == name)
+                .filter_map(|n| n.text())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(text("title"), vec!["Tom & Jerry"]);
+        assert_eq!(text("creator"), vec!["Ann <Author>"]);
+        assert!(text("identifier").contains(&"urn:isbn:9780306406157"));
+        assert!(opf.contains("properties=\"cover-image\""));
+        assert!(opf.contains("<meta refines=\"#series\" property=\"group-position\">2</meta>"));
+
+        let spine: Vec<&str> = doc
+            .descendants()
+            .filter(|n| n.tag_name().name() == "itemref")
+            .filter_map(|n| n.attribute("idref"))
+            .collect();
+        assert_eq!(
+            spine,
+            vec!["cover", "chapter-001", "chapter-001-2", "chapter-002"]
+        );
+
+        // Every content document is well-formed
+        for name in [
+            "OEBPS/nav.xhtml",
+            "OEBPS/toc.ncx",
+            "OEBPS/chapter-001-2.xhtml",
+        ] {
+            roxmltree::Document::parse_with_options(
+                &entry(&mut archive, name),
+                roxmltree::ParsingOptions {
+                    allow_dtd: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            archive.by_name("OEBPS/cover.png").unwrap().size(),
+            png.len() as u64
+        );
+    }
+}
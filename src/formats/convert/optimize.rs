@@ -0,0 +1,160 @@
+//! Re-encoding of comic archives for reading devices.
+//!
+//! Pages keep their names (with the extension of the new format) so readers
+//! order them as before, and `ComicInfo.xml` is carried over.
+
+use super::ComicOptions;
+use super::page::Page;
+use crate::config::BookFormat;
+use crate::error::{AppError, Result};
+use crate::formats::cb7::Cb7Handler;
+use crate::formats::cbr::CbrHandler;
+use crate::formats::cbz::CbzHandler;
+use crate::formats::comicinfo::ComicInfo;
+use crate::library::book::Book;
+use std::collections::HashSet;
+use std::io::{Seek, Write};
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
+
+/// Write a CBZ with the re-encoded pages of a comic archive.
+pub(super) fn write<W: Write + Seek>(book: &Book, options: &ComicOptions, writer: W) -> Result<()> {
+    let page_count = crate::formats::get_handler(book.format)
+        .page_count(&book.path)?
+        .unwrap_or(0);
+    // The size limit is spread evenly over the pages
+    let budget = options
+        .max_file_size
+        .filter(|_| page_count > 0)
+        .map(|size| (size / u64::from(page_count)) as usize);
+
+    let mut zip = zip::ZipWriter::new(writer);
+    // Images are already compressed
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut names = HashSet::new();
+    let mut pages = 0;
+
+    {
+        let mut visit = |name: &str, data: Vec<u8>| -> Result<()> {
+            if ComicInfo::is_comic_info(name) {
+                if names.insert(name.to_string()) {
+                    zip.start_file(name, deflated)?;
+                    zip.write_all(&data)?;
+                }
+                return Ok(());
+            }
+
+            let page = match Page::new(data, options, budget) {
+                Ok(page) => page,
+                Err(e) => {
+                    tracing::warn!(page = %name, error = %e, "Skipping unreadable comic page");
+                    return Ok(());
+                }
+            };
+            let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+            let mut target = format!("{}.{}", stem, page.extension);
+            // "01.png" and "01.jxl" would both become "01.jpg"
+            if !names.insert(target.clone()) {
+                target = format!("{}-{}.{}", stem, pages + 1, page.extension);
+                names.insert(target.clone());
+            }
+
+            zip.start_file(target, stored)?;
+            zip.write_all(&page.data)?;
+            pages += 1;
+            Ok(())
+        };
+
+        match book.format {
+            BookFormat::Cbz => CbzHandler::for_each_file(&book.path, &mut visit)?,
+            BookFormat::Cbr => CbrHandler::for_each_file(&book.path, &mut visit)?,
+            BookFormat::Cb7 => Cb7Handler::for_each_file(&book.path, &mut visit)?,
+            format => {
+                return Err(AppError::InvalidFormat(format!(
+                    "Not a comic archive: {}",
+                    format.mime_type()
+                )));
+            }
+        }
+    }
+
+    if pages == 0 {
+        return Err(AppError::InvalidFormat(format!(
+            "No readable pages in comic: {}",
+            book.path.display()
+        )));
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PageFormat;
+    use image::{DynamicImage, ImageFormat};
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_optimize_cbz() {
+        let dir = std::env::temp_dir().join(format!("ebook-rs-optimize-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Comic 01.cbz");
+
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(300, 450)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let comic_info = b"<ComicInfo><Title>Comic</Title></ComicInfo>";
+
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default();
+        for (name, data) in [
+            ("pages/01.png", png.as_slice()),
+            ("pages/02.png", png.as_slice()),
+            ("pages/notes.txt", b"skip me".as_slice()),
+            ("ComicInfo.xml", comic_info.as_slice()),
+        ] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let book = Book::new(path, BookFormat::Cbz);
+        let options = ComicOptions {
+            max_size: Some((200, 200)),
+            grayscale: true,
+            format: Some(PageFormat::Jpeg),
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        write(&book, &options, Cursor::new(&mut data)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(data)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec!["ComicInfo.xml", "pages/01.jpg", "pages/02.jpg"]);
+
+        let mut page = Vec::new();
+        archive
+            .by_name("pages/01.jpg")
+            .unwrap()
+            .read_to_end(&mut page)
+            .unwrap();
+        let page = image::load_from_memory(&page).unwrap();
+        assert_eq!((page.width(), page.height()), (133, 200));
+        assert_eq!(page.color(), image::ColorType::L8);
+
+        let mut info = Vec::new();
+        archive
+            .by_name("ComicInfo.xml")
+            .unwrap()
+            .read_to_end(&mut info)
+            .unwrap();
+        assert_eq!(info, comic_info);
+    }
+}
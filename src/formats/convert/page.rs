@@ -0,0 +1,203 @@
+//! Re-encoding of comic pages for reading devices.
+
+use super::ComicOptions;
+use crate::config::PageFormat;
+use crate::error::Result;
+use crate::formats::jxl;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+/// Lowest JPEG quality used to fit a page in its size budget.
+const MIN_QUALITY: u8 = 40;
+
+/// A page image ready to be stored.
+pub(super) struct Page {
+    pub(super) data: Vec<u8>,
+    pub(super) extension: &'static str,
+    pub(super) media_type: &'static str,
+    pub(super) width: u32,
+    pub(super) height: u32,
+}
+
+impl Page {
+    /// Prepare a page according to the options.
+    ///
+    /// JPEG, PNG and GIF pages are kept as they are when nothing needs to
+    /// change; anything else (JPEG XL, WebP...) is re-encoded. `budget` is
+    /// the size in bytes the page should fit in.
+    pub(super) fn new(
+        data: Vec<u8>,
+        options: &ComicOptions,
+        budget: Option<usize>,
+    ) -> Result<Self> {
+        let format = if jxl::is_jxl(&data) {
+            None
+        } else {
+            Some(image::guess_format(&data)?)
+        };
+
+        let unchanged = options.max_size.is_none()
+            && !options.grayscale
+            && options.format.is_none()
+            && budget.is_none_or(|budget| data.len() <= budget);
+        if unchanged
+            && let Some(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif)) = format
+        {
+            let (width, height) =
+                ImageReader::with_format(Cursor::new(&data), format).into_dimensions()?;
+            let (extension, media_type) = match format {
+                ImageFormat::Jpeg => ("jpg", "image/jpeg"),
+                ImageFormat::Png => ("png", "image/png"),
+                _ => ("gif", "image/gif"),
+            };
+            return Ok(Self {
+                data,
+                extension,
+                media_type,
+                width,
+                height,
+            });
+        }
+
+        let mut img = match format {
+            Some(format) => image::load_from_memory_with_format(&data, format)?,
+            None => jxl::decode_to_image(&data)?,
+        };
+        if let Some(bounds) = options.max_size {
+            let (width, height) = fit_bounds(img.width(), img.height(), bounds);
+            if width < img.width() {
+                img = img.resize_exact(width, height, FilterType::Lanczos3);
+            }
+        }
+        // JPEG has no alpha channel; grayscale pages stay single-channel
+        let img = if options.grayscale || !img.color().has_color() {
+            DynamicImage::ImageLuma8(img.to_luma8())
+        } else {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        };
+        let (width, height) = (img.width(), img.height());
+
+        // Line art compresses better and stays sharp as PNG
+        let png = match options.format {
+            Some(format) => format == PageFormat::Png,
+            None => format == Some(ImageFormat::Png),
+        };
+        if png {
+            let mut encoded = Vec::new();
+            img.write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)?;
+            // Without an explicit format, oversized PNG pages fall back to JPEG
+            if options.format.is_some() || budget.is_none_or(|budget| encoded.len() <= budget) {
+                return Ok(Self {
+                    data: encoded,
+                    extension: "png",
+                    media_type: "image/png",
+                    width,
+                    height,
+                });
+            }
+        }
+
+        let mut quality = options.quality.clamp(1, 100);
+        let data = loop {
+            let mut encoded = Vec::new();
+            img.write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, quality))?;
+            match budget {
+                Some(budget) if encoded.len() > budget && quality > MIN_QUALITY => {
+                    quality = quality.saturating_sub(10).max(MIN_QUALITY);
+                }
+                _ => break encoded,
+            }
+        };
+        Ok(Self {
+            data,
+            extension: "jpg",
+            media_type: "image/jpeg",
+            width,
+            height,
+        })
+    }
+}
+
+/// Size of a page scaled down to fit the device resolution.
+///
+/// Landscape pages (double-page spreads) are fitted to the rotated screen,
+/// since readers show them with the device turned sideways.
+pub(super) fn fit_bounds(
+    width: u32,
+    height: u32,
+    (max_width, max_height): (u32, u32),
+) -> (u32, u32) {
+    let (max_width, max_height) = if width > height {
+        (max_width.max(max_height), max_width.min(max_height))
+    } else {
+        (max_width, max_height)
+    };
+    let scale = (max_width as f64 / width as f64)
+        .min(max_height as f64 / height as f64)
+        .min(1.0);
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(img: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_fit_bounds() {
+        assert_eq!(fit_bounds(2000, 3000, (1000, 1000)), (667, 1000));
+        // Spreads use the rotated screen
+        assert_eq!(fit_bounds(4000, 3000, (1072, 1448)), (1429, 1072));
+        // Pages are never enlarged
+        assert_eq!(fit_bounds(500, 800, (1072, 1448)), (500, 800));
+    }
+
+    #[test]
+    fn test_page_options() {
+        let png = encode(DynamicImage::new_rgb8(100, 150), ImageFormat::Png);
+
+        let page = Page::new(png.clone(), &ComicOptions::default(), None).unwrap();
+        assert_eq!(page.data, png);
+
+        let options = ComicOptions {
+            max_size: Some((50, 50)),
+            grayscale: true,
+            format: Some(PageFormat::Jpeg),
+            ..Default::default()
+        };
+        let page = Page::new(png, &options, None).unwrap();
+        assert_eq!((page.extension, page.width, page.height), ("jpg", 33, 50));
+        let decoded = image::load_from_memory(&page.data).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+    }
+
+    #[test]
+    fn test_page_budget() {
+        // Noise compresses badly, so the quality has to drop
+        let noise = image::RgbImage::from_fn(200, 200, |x, y| {
+            let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)).wrapping_mul(2_654_435_761);
+            image::Rgb([(v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+        });
+        let jpeg = encode(DynamicImage::ImageRgb8(noise), ImageFormat::Jpeg);
+        let options = ComicOptions {
+            format: Some(PageFormat::Jpeg),
+            quality: 95,
+            ..Default::default()
+        };
+
+        let full = Page::new(jpeg.clone(), &options, None).unwrap();
+        let budget = full.data.len() * 3 / 4;
+        let page = Page::new(jpeg, &options, Some(budget)).unwrap();
+        assert!(page.data.len() <= budget);
+    }
+}
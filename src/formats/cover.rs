@@ -0,0 +1,296 @@
+//! Generated covers for books without artwork.
+//!
+//! Draws the title, authors and a format label on a coloured background
+//! using the bundled Noto Sans fonts.
+
+use image::RgbaImage;
+use tiny_skia::{
+    Color, FillRule, GradientStop, LinearGradient, Paint, PathBuilder, Pixmap, Point, Rect,
+    SpreadMode, Transform,
+};
+use ttf_parser::{Face, GlyphId};
+
+const WIDTH: u32 = 600;
+const HEIGHT: u32 = 900;
+/// Horizontal margin around the text.
+const MARGIN: f32 = 60.0;
+const MAX_TITLE_LINES: usize = 5;
+const MAX_AUTHOR_LINES: usize = 2;
+
+/// Generate a PNG cover showing the title, authors and a format label.
+pub(super) fn generate_cover(title: &str, authors: &[String], label: &str) -> Option<Vec<u8>> {
+    let bold = Face::parse(notosans::BOLD_TTF, 0).ok()?;
+    let regular = Face::parse(notosans::REGULAR_TTF, 0).ok()?;
+    let mut pixmap = Pixmap::new(WIDTH, HEIGHT)?;
+
+    // Background colour derived from the title, darkening towards the bottom
+    let hash = title
+        .bytes()
+        .fold(0u32, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u32));
+    let (r, g, b) = hsv_to_rgb((hash % 360) as f32, 0.45, 0.45);
+    let (r2, g2, b2) = hsv_to_rgb((hash % 360) as f32, 0.55, 0.25);
+    let background = LinearGradient::new(
+        Point::from_xy(0.0, 0.0),
+        Point::from_xy(0.0, HEIGHT as f32),
+        vec![
+            GradientStop::new(0.0, Color::from_rgba8(r, g, b, 255)),
+            GradientStop::new(1.0, Color::from_rgba8(r2, g2, b2, 255)),
+        ],
+        SpreadMode::Pad,
+        Transform::identity(),
+    )?;
+    let paint = Paint {
+        shader: background,
+        ..Paint::default()
+    };
+    pixmap.fill_rect(
+        Rect::from_xywh(0.0, 0.0, WIDTH as f32, HEIGHT as f32)?,
+        &paint,
+        Transform::identity(),
+        None,
+    );
+
+    let max_width = WIDTH as f32 - 2.0 * MARGIN;
+    let center = WIDTH as f32 / 2.0;
+
+    // Title: the largest size that fits in a few lines
+    let title = title.trim();
+    let (size, lines) = [64.0, 56.0, 48.0, 42.0, 36.0]
+        .into_iter()
+        .map(|size| (size, wrap(&bold, title, size, max_width)))
+        .find(|(_, lines)| lines.len() <= MAX_TITLE_LINES)
+        .unwrap_or_else(|| (36.0, wrap(&bold, title, 36.0, max_width)));
+    let lines = truncate(&bold, lines, MAX_TITLE_LINES, size, max_width);
+
+    let white = solid(255, 255, 255, 255);
+    let line_height = size * 1.25;
+    let mut baseline = HEIGHT as f32 * 0.22 + size;
+    for line in &lines {
+        draw_line(&mut pixmap, &bold, line, size, center, baseline, &white);
+        baseline += line_height;
+    }
+
+    // Separator between title and authors
+    let rule_y = baseline - line_height + size * 0.6;
+    if let Some(rule) = Rect::from_xywh(center - 40.0, rule_y, 80.0, 3.0) {
+        pixmap.fill_rect(
+            rule,
+            &solid(255, 255, 255, 160),
+            Transform::identity(),
+            None,
+        );
+    }
+
+    if !authors.is_empty() {
+        let size = 30.0;
+        let lines = wrap(&regular, &authors.join(", "), size, max_width);
+        let lines = truncate(&regular, lines, MAX_AUTHOR_LINES, size, max_width);
+        let mut baseline = rule_y + 30.0 + size;
+        let paint = solid(255, 255, 255, 220);
+        for line in &lines {
+            draw_line(&mut pixmap, &regular, line, size, center, baseline, &paint);
+            baseline += size * 1.3;
+        }
+    }
+
+    let label = label.to_uppercase();
+    draw_line(
+        &mut pixmap,
+        &bold,
+        &label,
+        22.0,
+        center,
+        HEIGHT as f32 - MARGIN,
+        &solid(255, 255, 255, 150),
+    );
+
+    // The background is opaque, so premultiplied data is plain RGBA
+    let image = RgbaImage::from_raw(WIDTH, HEIGHT, pixmap.take())?;
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+fn solid(r: u8, g: u8, b: u8, a: u8) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, a);
+    paint.anti_alias = true;
+    paint
+}
+
+fn glyph(face: &Face, c: char) -> GlyphId {
+    face.glyph_index(c).unwrap_or(GlyphId(0))
+}
+
+/// Width of a line of text at the given size in pixels.
+fn text_width(face: &Face, text: &str, size: f32) -> f32 {
+    let scale = size / f32::from(face.units_per_em());
+    text.chars()
+        .map(|c| f32::from(face.glyph_hor_advance(glyph(face, c)).unwrap_or(0)) * scale)
+        .sum()
+}
+
+/// Greedy word wrap; words wider than a line are split between characters.
+fn wrap(face: &Face, text: &str, size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if text_width(face, &candidate, size) <= max_width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if text_width(face, &line, size) > max_width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Keep at most `max` lines, ending the last one with an ellipsis.
+fn truncate(
+    face: &Face,
+    mut lines: Vec<String>,
+    max: usize,
+    size: f32,
+    max_width: f32,
+) -> Vec<String> {
+    if lines.len() <= max {
+        return lines;
+    }
+    lines.truncate(max);
+    if let Some(last) = lines.last_mut() {
+        while !last.is_empty() && text_width(face, &format!("{}…", last), size) > max_width {
+            last.pop();
+        }
+        *last = format!("{}…", last.trim_end());
+    }
+    lines
+}
+
+/// Draw a line of text centred on `center`.
+fn draw_line(
+    pixmap: &mut Pixmap,
+    face: &Face,
+    text: &str,
+    size: f32,
+    center: f32,
+    baseline: f32,
+    paint: &Paint,
+) {
+    let scale = size / f32::from(face.units_per_em());
+    let mut x = center - text_width(face, text, size) / 2.0;
+
+    for c in text.chars() {
+        let id = glyph(face, c);
+        let mut outline = Outline(PathBuilder::new());
+        if face.outline_glyph(id, &mut outline).is_some()
+            && let Some(path) = outline.0.finish()
+        {
+            let transform = Transform::from_row(scale, 0.0, 0.0, -scale, x, baseline);
+            pixmap.fill_path(&path, paint, FillRule::Winding, transform, None);
+        }
+        x += f32::from(face.glyph_hor_advance(id).unwrap_or(0)) * scale;
+    }
+}
+
+/// Adapter from glyph outlines to tiny-skia paths.
+struct Outline(PathBuilder);
+
+impl ttf_parser::OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Convert HSV to RGB.
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
+    let c = v * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = v - c;
+
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    (
+        ((r + m) * 255.0) as u8,
+        ((g + m) * 255.0) as u8,
+        ((b + m) * 255.0) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_cover() {
+        let png = generate_cover(
+            "A Rather Long Title That Needs Several Lines To Fit",
+            &["Jane Doe".to_string()],
+            "Markdown",
+        )
+        .unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (WIDTH, HEIGHT));
+
+        // Some white title text is drawn in the upper part
+        let white = (0..HEIGHT / 2)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| image.get_pixel(x, y).0[..3] == [255, 255, 255])
+            .count();
+        assert!(white > 500);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let face = Face::parse(notosans::REGULAR_TTF, 0).unwrap();
+        let lines = wrap(&face, "one two three four five six", 30.0, 150.0);
+        assert!(lines.len() > 1);
+        assert_eq!(lines.join(" "), "one two three four five six");
+        assert!(lines.iter().all(|l| text_width(&face, l, 30.0) <= 150.0));
+
+        let lines = truncate(&face, lines, 1, 30.0, 150.0);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with('…'));
+    }
+}
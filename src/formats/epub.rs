@@ -1,7 +1,9 @@
 use crate::error::{AppError, Result};
-use crate::formats::FormatHandler;
-use crate::library::book::Book;
-use roxmltree::Document;
+use crate::formats::text::html_text;
+use crate::formats::{FormatHandler, TextLocation, TextSection};
+use crate::library::book::{Book, Contributor, Identifier};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
@@ -27,106 +29,176 @@ impl EpubHandler {
     }
 
     /// Parse the OPF file and extract metadata.
+    ///
+    /// Returns the manifest href of the cover image, if any.
     fn parse_opf(content: &str, book: &mut Book) -> Result<Option<String>> {
         let doc = Document::parse(content)?;
+        let Some(metadata) = doc
+            .descendants()
+            .find(|n| n.is_element() && n.tag_name().name() == "metadata")
+        else {
+            return Ok(Self::find_cover_href(&doc, None));
+        };
+
+        let refinements = Refinements::collect(metadata);
         let mut cover_id: Option<String> = None;
+        let mut collections: Vec<Collection> = Vec::new();
+        let mut calibre_series: Option<String> = None;
+        let mut calibre_index: Option<f32> = None;
+        let mut has_title = false;
+
+        for node in metadata_fields(metadata) {
+            let text = node.text().map(str::trim).filter(|t| !t.is_empty());
 
-        // Find metadata elements
-        for node in doc.descendants() {
             match node.tag_name().name() {
                 "title" => {
-                    if let Some(text) = node.text() {
-                        book.title = text.trim().to_string();
+                    // EPUB 3 may list subtitles after the main title
+                    if let Some(text) = text
+                        && !has_title
+                    {
+                        book.title = text.to_string();
+                        has_title = true;
                     }
                 }
-                "creator" => {
-                    if let Some(text) = node.text() {
-                        book.authors.push(text.trim().to_string());
+                name @ ("creator" | "contributor") => {
+                    let Some(text) = text else { continue };
+                    let role = local_attribute(node, "role")
+                        .or_else(|| refinements.get(node, "role"))
+                        .map(|r| r.trim().to_lowercase())
+                        .unwrap_or_else(|| {
+                            if name == "creator" { "aut" } else { "ctb" }.to_string()
+                        });
+
+                    if role == "aut" {
+                        book.authors.push(text.to_string());
+                    } else {
+                        book.contributors.push(Contributor {
+                            name: text.to_string(),
+                            role,
+                        });
                     }
                 }
                 "description" => {
-                    if let Some(text) = node.text() {
-                        book.description = Some(text.trim().to_string());
+                    if let Some(text) = text {
+                        book.description = Some(text.to_string());
                     }
                 }
                 "publisher" => {
-                    if let Some(text) = node.text() {
-                        book.publisher = Some(text.trim().to_string());
+                    if let Some(text) = text {
+                        book.publisher = Some(text.to_string());
                     }
                 }
                 "language" => {
-                    if let Some(text) = node.text() {
-                        book.language = Some(text.trim().to_string());
+                    if let Some(text) = text {
+                        book.language = Some(text.to_string());
                     }
                 }
                 "date" => {
-                    if let Some(text) = node.text() {
-                        book.published = Some(text.trim().to_string());
+                    if let Some(text) = text {
+                        book.published = Some(text.to_string());
                     }
                 }
                 "subject" => {
-                    if let Some(text) = node.text() {
-                        book.tags.push(text.trim().to_string());
+                    if let Some(text) = text {
+                        book.tags.push(text.to_string());
                     }
                 }
                 "identifier" => {
-                    if let Some(text) = node.text() {
-                        // Check if it looks like an ISBN
-                        let trimmed = text.trim();
-                        if trimmed.starts_with("978")
-                            || trimmed.starts_with("979")
-                            || trimmed.len() == 10
-                            || trimmed.len() == 13
-                        {
-                            book.isbn = Some(trimmed.to_string());
+                    let Some(text) = text else { continue };
+                    let scheme = local_attribute(node, "scheme")
+                        .or_else(|| refinements.get(node, "identifier-type"));
+                    if let Some(identifier) = parse_identifier(text, scheme) {
+                        if identifier.scheme == "isbn" && book.isbn.is_none() {
+                            book.isbn = Some(identifier.value.clone());
+                        }
+                        if !book.identifiers.contains(&identifier) {
+                            book.identifiers.push(identifier);
                         }
                     }
                 }
                 "meta" => {
-                    // Look for cover meta tag
+                    // EPUB 2 cover meta tag
                     if node.attribute("name") == Some("cover") {
                         cover_id = node.attribute("content").map(String::from);
                     }
-                    // Look for series metadata (calibre format)
-                    if node.attribute("name") == Some("calibre:series") {
-                        book.series = node.attribute("content").map(String::from);
+                    // Series metadata (calibre format)
+                    if node.attribute("name") == Some("calibre:series")
+                        && let Some(series) = node.attribute("content")
+                    {
+                        calibre_series = Some(series.to_string());
                     }
                     if node.attribute("name") == Some("calibre:series_index")
                         && let Some(idx) = node.attribute("content")
                     {
-                        book.series_index = idx.parse().ok();
+                        calibre_index = idx.trim().parse().ok();
+                    }
+                    // EPUB 3 collections
+                    if node.attribute("property") == Some("belongs-to-collection")
+                        && let Some(text) = text
+                    {
+                        collections.push(Collection {
+                            name: text.to_string(),
+                            kind: refinements.get(node, "collection-type"),
+                            position: refinements
+                                .get(node, "group-position")
+                                .and_then(|p| p.trim().parse().ok()),
+                        });
                     }
                 }
                 _ => {}
             }
         }
 
-        // Find cover href from manifest
-        if let Some(ref cover_id) = cover_id {
-            for node in doc.descendants() {
-                if node.tag_name().name() == "item" && node.attribute("id") == Some(cover_id) {
-                    return Ok(node.attribute("href").map(String::from));
-                }
-            }
+        // EPUB 3 series collection, then untyped collections, then calibre
+        let series = collections
+            .iter()
+            .find(|c| c.kind.as_deref() == Some("series"))
+            .or_else(|| collections.iter().find(|c| c.kind.is_none()))
+            .map(|c| (c.name.clone(), c.position))
+            .or_else(|| calibre_series.map(|name| (name, calibre_index)));
+        if let Some((name, index)) = series {
+            book.series = Some(name);
+            book.series_index = index;
         }
 
-        // Fallback: look for common cover image names in manifest
-        for node in doc.descendants() {
-            if node.tag_name().name() == "item"
-                && let Some(href) = node.attribute("href")
-            {
-                let lower = href.to_lowercase();
-                if lower.contains("cover")
-                    && (lower.ends_with(".jpg")
-                        || lower.ends_with(".jpeg")
-                        || lower.ends_with(".png"))
-                {
-                    return Ok(Some(href.to_string()));
-                }
-            }
+        Ok(Self::find_cover_href(&doc, cover_id.as_deref()))
+    }
+
+    /// Find the cover image href in the manifest.
+    ///
+    /// Tries the EPUB 3 `cover-image` property, then the EPUB 2 cover meta
+    /// (an item ID, or sometimes an href), then image names containing "cover".
+    fn find_cover_href(doc: &Document<'_>, cover_id: Option<&str>) -> Option<String> {
+        let items: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.is_element() && n.tag_name().name() == "item")
+            .collect();
+
+        let cover_image = items.iter().find(|item| {
+            item.attribute("properties")
+                .is_some_and(|p| p.split_whitespace().any(|p| p == "cover-image"))
+        });
+        let cover_meta = cover_id.and_then(|id| {
+            items
+                .iter()
+                .find(|item| item.attribute("id") == Some(id))
+                .or_else(|| items.iter().find(|item| item.attribute("href") == Some(id)))
+        });
+        if let Some(href) = cover_image
+            .or(cover_meta)
+            .and_then(|item| item.attribute("href"))
+        {
+            return Some(href.to_string());
         }
 
-        Ok(None)
+        // Fallback: look for common cover image names in manifest
+        items.iter().find_map(|item| {
+            let href = item.attribute("href")?;
+            let lower = href.to_lowercase();
+            let is_image =
+                lower.ends_with(".jpg") || lower.ends_with(".jpeg") || lower.ends_with(".png");
+            (lower.contains("cover") && is_image).then(|| href.to_string())
+        })
     }
 
     /// Extract cover image from EPUB.
@@ -158,6 +230,23 @@ impl EpubHandler {
         Self::ensure_png(data)
     }
 
+    /// Archive paths of the spine documents, in reading order.
+    fn spine_paths(opf: &str, opf_dir: &str) -> Result<Vec<String>> {
+        let doc = Document::parse(opf)?;
+        let manifest: HashMap<&str, &str> = doc
+            .descendants()
+            .filter(|n| n.is_element() && n.tag_name().name() == "item")
+            .filter_map(|item| Some((item.attribute("id")?, item.attribute("href")?)))
+            .collect();
+
+        Ok(doc
+            .descendants()
+            .filter(|n| n.is_element() && n.tag_name().name() == "itemref")
+            .filter_map(|itemref| manifest.get(itemref.attribute("idref")?))
+            .map(|href| resolve_href(opf_dir, href))
+            .collect())
+    }
+
     /// Ensure image data is PNG format.
     fn ensure_png(data: Vec<u8>) -> Result<Vec<u8>> {
         // Check if it's already PNG
@@ -224,4 +313,385 @@ impl FormatHandler for EpubHandler {
         // EPUB doesn't have fixed pages
         Ok(None)
     }
+
+    fn extract_content(&self, path: &Path) -> Result<Vec<TextSection>> {
+        let file = File::open(path)?;
+        let mut archive = ZipArchive::new(file)?;
+
+        let opf_path = Self::find_opf_path(&mut archive)?;
+        let opf_dir = opf_path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+        let mut opf_content = String::new();
+        archive
+            .by_name(&opf_path)?
+            .read_to_string(&mut opf_content)?;
+
+        let mut sections = Vec::new();
+        for (index, document) in Self::spine_paths(&opf_content, opf_dir)?.iter().enumerate() {
+            let mut data = Vec::new();
+            match archive.by_name(document) {
+                Ok(mut entry) => entry.read_to_end(&mut data)?,
+                Err(_) => {
+                    tracing::debug!(document = %document, "Missing spine document");
+                    continue;
+                }
+            };
+            let (title, text) = html_text(&String::from_utf8_lossy(&data));
+            if !text.is_empty() {
+                sections.push(TextSection {
+                    location: TextLocation::Chapter(index as u32 + 1),
+                    title,
+                    text,
+                });
+            }
+        }
+        Ok(sections)
+    }
+}
+
+/// Resolve a manifest href against the directory of the package document.
+fn resolve_href(base_dir: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+    let href = urlencoding::decode(href).map_or_else(|_| href.to_string(), |h| h.into_owned());
+
+    let mut parts: Vec<&str> = base_dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// EPUB 3 `<meta refines="#id" property="...">` values, by target ID.
+struct Refinements<'a> {
+    by_id: HashMap<&'a str, Vec<(&'a str, String)>>,
+}
+
+impl<'a> Refinements<'a> {
+    /// Collect refinements from the metadata element.
+    fn collect(metadata: Node<'a, '_>) -> Self {
+        let mut by_id: HashMap<&str, Vec<(&str, String)>> = HashMap::new();
+
+        for meta in metadata_fields(metadata).filter(|n| n.tag_name().name() == "meta") {
+            let (Some(target), Some(property), Some(value)) = (
+                meta.attribute("refines"),
+                meta.attribute("property"),
+                meta.text(),
+            ) else {
+                continue;
+            };
+            by_id
+                .entry(target.trim_start_matches('#'))
+                .or_default()
+                .push((property, value.trim().to_string()));
+        }
+
+        Self { by_id }
+    }
+
+    /// First value of a property refining the given element.
+    fn get(&self, node: Node<'_, '_>, property: &str) -> Option<String> {
+        self.by_id
+            .get(node.attribute("id")?)?
+            .iter()
+            .find(|(p, _)| *p == property)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+/// An EPUB 3 collection (`belongs-to-collection`).
+struct Collection {
+    name: String,
+    kind: Option<String>,
+    position: Option<f32>,
+}
+
+/// Metadata field elements, in document order.
+///
+/// OPF 1.x-style packages wrap fields in `<dc-metadata>` and `<x-metadata>`,
+/// whose children count as direct fields.
+fn metadata_fields<'a, 'input>(
+    metadata: Node<'a, 'input>,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    metadata
+        .children()
+        .filter(|n| n.is_element())
+        .flat_map(|node| match node.tag_name().name() {
+            "dc-metadata" | "x-metadata" => node.children().filter(|n| n.is_element()).collect(),
+            _ => vec![node],
+        })
+}
+
+/// Get an attribute by local name, whatever its namespace (e.g. `opf:role`).
+fn local_attribute(node: Node<'_, '_>, name: &str) -> Option<String> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value().to_string())
+}
+
+/// Classify a `dc:identifier` using its scheme or its value.
+///
+/// The scheme comes from `opf:scheme` (EPUB 2) or an `identifier-type`
+/// refinement (EPUB 3, including ONIX codes 02 and 15 for ISBNs).
+pub(super) fn parse_identifier(value: &str, scheme: Option<String>) -> Option<Identifier> {
+    let scheme = scheme.map(|s| s.trim().to_lowercase());
+
+    // Values like "urn:isbn:978...", "isbn:978..." or "urn:uuid:..."
+    let value = value.trim();
+    let lower = value.to_ascii_lowercase();
+    let unprefixed = lower.strip_prefix("urn:").unwrap_or(&lower);
+    let (prefix, rest) = match unprefixed.split_once(':') {
+        Some((prefix, _)) if matches!(prefix, "isbn" | "uuid" | "asin" | "amazon") => {
+            let start = lower.len() - unprefixed.len() + prefix.len() + 1;
+            (Some(prefix), value[start..].trim())
+        }
+        _ => (None, value),
+    };
+
+    let scheme = match scheme.as_deref().or(prefix) {
+        Some("isbn" | "02" | "15" | "onix:codelist5:15") => "isbn",
+        Some("uuid") => "uuid",
+        Some("asin" | "amazon" | "mobi-asin") => "asin",
+        Some(other) if !other.is_empty() && !other.starts_with("onix") => {
+            return Some(Identifier {
+                scheme: other.to_string(),
+                value: rest.to_string(),
+            });
+        }
+        _ => {
+            // Unlabelled: only accept values that are unambiguous
+            if normalize_isbn(rest).is_some() {
+                "isbn"
+            } else if uuid::Uuid::parse_str(rest).is_ok() {
+                "uuid"
+            } else {
+                return None;
+            }
+        }
+    };
+
+    let value = match scheme {
+        "isbn" => normalize_isbn(rest)?,
+        "uuid" => uuid::Uuid::parse_str(rest).ok()?.to_string(),
+        _ => rest.to_string(),
+    };
+
+    Some(Identifier {
+        scheme: scheme.to_string(),
+        value,
+    })
+}
+
+/// Strip separators from an ISBN and validate its check digit.
+fn normalize_isbn(value: &str) -> Option<String> {
+    let isbn: String = value
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .collect::<String>()
+        .to_uppercase();
+
+    let valid = match isbn.len() {
+        10 => {
+            let sum = isbn.chars().enumerate().try_fold(0, |sum, (i, c)| {
+                let digit = match c {
+                    'X' if i == 9 => 10,
+                    _ => c.to_digit(10)?,
+                };
+                Some(sum + digit * (10 - i as u32))
+            });
+            sum.is_some_and(|s| s % 11 == 0)
+        }
+        13 => {
+            let sum = isbn.chars().enumerate().try_fold(0, |sum, (i, c)| {
+                let weight = if i % 2 == 0 { 1 } else { 3 };
+                Some(sum + c.to_digit(10)? * weight)
+            });
+            sum.is_some_and(|s| s % 10 == 0)
+        }
+        _ => false,
+    };
+
+    valid.then_some(isbn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPUB3_OPF: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:A1B2C3D4-0000-4000-8000-000000000001</dc:identifier>
+    <dc:identifier id="isbn">978-0-306-40615-7</dc:identifier>
+    <meta refines="#isbn" property="identifier-type" scheme="onix:codelist5">15</meta>
+    <dc:identifier>B00TEST123</dc:identifier>
+    <dc:title id="t1">The Main Title</dc:title>
+    <dc:title id="t2">A Subtitle</dc:title>
+    <dc:creator id="c1">Jane Author</dc:creator>
+    <meta refines="#c1" property="role" scheme="marc:relators">aut</meta>
+    <dc:creator id="c2">Ian Illustrator</dc:creator>
+    <meta refines="#c2" property="role" scheme="marc:relators">ill</meta>
+    <dc:creator>Sam Second</dc:creator>
+    <dc:contributor id="c3">Tina Translator</dc:contributor>
+    <meta refines="#c3" property="role" scheme="marc:relators">trl</meta>
+    <meta property="belongs-to-collection" id="set">Box Set</meta>
+    <meta refines="#set" property="collection-type">set</meta>
+    <meta property="belongs-to-collection" id="series">The Series</meta>
+    <meta refines="#series" property="collection-type">series</meta>
+    <meta refines="#series" property="group-position">2.5</meta>
+    <meta name="calibre:series" content="Calibre Series"/>
+    <meta name="calibre:series_index" content="9"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" properties="nav"/>
+    <item id="not-cover" href="images/cover-old.jpg" media-type="image/jpeg"/>
+    <item id="img" href="images/front.png" media-type="image/png" properties="cover-image"/>
+  </manifest>
+</package>"##;
+
+    const EPUB2_OPF: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>Old Book</dc:title>
+    <dc:creator opf:role="aut">Old Author</dc:creator>
+    <dc:creator opf:role="edt">Eddie Editor</dc:creator>
+    <dc:identifier opf:scheme="ISBN">0-306-40615-2</dc:identifier>
+    <dc:identifier opf:scheme="MOBI-ASIN">B00ASIN000</dc:identifier>
+    <dc:identifier>1234567890</dc:identifier>
+    <meta name="cover" content="cover-img"/>
+    <meta name="calibre:series_index" content="3"/>
+    <meta name="calibre:series" content="Calibre Series"/>
+  </metadata>
+  <manifest>
+    <item id="cover-img" href="art.jpg" media-type="image/jpeg"/>
+  </manifest>
+</package>"##;
+
+    #[test]
+    fn test_spine_paths() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <manifest>
+    <item id="nav" href="nav.xhtml" properties="nav"/>
+    <item id="c1" href="text/chapter%201.xhtml"/>
+    <item id="c2" href="../shared/notes.xhtml#top"/>
+  </manifest>
+  <spine><itemref idref="c1"/><itemref idref="missing"/><itemref idref="c2"/></spine>
+</package>"#;
+        assert_eq!(
+            EpubHandler::spine_paths(opf, "OEBPS").unwrap(),
+            vec!["OEBPS/text/chapter 1.xhtml", "shared/notes.xhtml"]
+        );
+        assert_eq!(resolve_href("", "./a.xhtml"), "a.xhtml");
+    }
+
+    #[test]
+    fn test_parse_epub3_metadata() {
+        let mut book = Book::default();
+        let cover = EpubHandler::parse_opf(EPUB3_OPF, &mut book).unwrap();
+
+        assert_eq!(cover.as_deref(), Some("images/front.png"));
+        assert_eq!(book.title, "The Main Title");
+        assert_eq!(book.authors, vec!["Jane Author", "Sam Second"]);
+        assert_eq!(
+            book.contributors,
+            vec![
+                Contributor {
+                    name: "Ian Illustrator".to_string(),
+                    role: "ill".to_string()
+                },
+                Contributor {
+                    name: "Tina Translator".to_string(),
+                    role: "trl".to_string()
+                },
+            ]
+        );
+        assert_eq!(book.series.as_deref(), Some("The Series"));
+        assert_eq!(book.series_index, Some(2.5));
+        assert_eq!(book.isbn.as_deref(), Some("9780306406157"));
+        // The unlabelled "B00TEST123" is neither an ISBN nor a UUID
+        let urns: Vec<String> = book.identifiers.iter().map(Identifier::urn).collect();
+        assert_eq!(
+            urns,
+            vec![
+                "urn:uuid:a1b2c3d4-0000-4000-8000-000000000001",
+                "urn:isbn:9780306406157",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_epub2_metadata() {
+        let mut book = Book::default();
+        let cover = EpubHandler::parse_opf(EPUB2_OPF, &mut book).unwrap();
+
+        assert_eq!(cover.as_deref(), Some("art.jpg"));
+        assert_eq!(book.authors, vec!["Old Author"]);
+        assert_eq!(book.contributors.len(), 1);
+        assert_eq!(book.contributors[0].role, "edt");
+        assert_eq!(book.series.as_deref(), Some("Calibre Series"));
+        assert_eq!(book.series_index, Some(3.0));
+        assert_eq!(book.isbn.as_deref(), Some("0306406152"));
+        // "1234567890" has a bad check digit and is not taken as an ISBN
+        let schemes: Vec<&str> = book.identifiers.iter().map(|i| i.scheme.as_str()).collect();
+        assert_eq!(schemes, vec!["isbn", "asin"]);
+    }
+
+    #[test]
+    fn test_parse_legacy_metadata_wrappers() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata>
+    <dc-metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+      <dc:title>Wrapped Book</dc:title>
+      <dc:creator>Wrapped Author</dc:creator>
+      <dc:language>fr</dc:language>
+    </dc-metadata>
+    <x-metadata>
+      <meta name="cover" content="cover-img"/>
+    </x-metadata>
+  </metadata>
+  <manifest>
+    <item id="cover-img" href="cover.jpg" media-type="image/jpeg"/>
+  </manifest>
+</package>"#;
+        let mut book = Book::default();
+        let cover = EpubHandler::parse_opf(opf, &mut book).unwrap();
+
+        assert_eq!(cover.as_deref(), Some("cover.jpg"));
+        assert_eq!(book.title, "Wrapped Book");
+        assert_eq!(book.authors, vec!["Wrapped Author"]);
+        assert_eq!(book.language.as_deref(), Some("fr"));
+    }
+
+    #[test]
+    fn test_normalize_isbn() {
+        assert_eq!(
+            normalize_isbn("978-0-306-40615-7").as_deref(),
+            Some("9780306406157")
+        );
+        assert_eq!(
+            normalize_isbn("0 8044 2957 x").as_deref(),
+            Some("080442957X")
+        );
+        assert_eq!(normalize_isbn("9780306406158"), None);
+        assert_eq!(normalize_isbn("12345"), None);
+    }
+
+    #[test]
+    fn test_parse_identifier_prefixes() {
+        let id = parse_identifier("urn:isbn:9780306406157", None).unwrap();
+        assert_eq!(id.scheme, "isbn");
+        assert_eq!(id.value, "9780306406157");
+
+        let id = parse_identifier("asin:B00TEST123", None).unwrap();
+        assert_eq!(id.urn(), "urn:asin:B00TEST123");
+
+        let id = parse_identifier("10.1000/182", Some("DOI".to_string())).unwrap();
+        assert_eq!(id.scheme, "doi");
+
+        assert!(parse_identifier("not-an-id", None).is_none());
+    }
 }
@@ -0,0 +1,530 @@
+use crate::error::{AppError, Result};
+use crate::formats::FormatHandler;
+use crate::formats::cbz::read_entry_data;
+use crate::formats::convert::Block;
+use crate::library::book::Book;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use quick_xml::escape::escape;
+use roxmltree::{Document, Node};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Handler for FictionBook files (`.fb2` and `.fb2.zip`).
+pub struct Fb2Handler;
+
+impl Fb2Handler {
+    /// Read the FB2 document as text, unzipping and decoding as needed.
+    fn read_document(path: &Path) -> Result<String> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        if data.starts_with(b"PK\x03\x04") {
+            data = Self::read_zipped(&data)?;
+        }
+
+        Ok(decode_xml(&data))
+    }
+
+    /// Extract the first `.fb2` entry of a zip archive.
+    fn read_zipped(data: &[u8]) -> Result<Vec<u8>> {
+        let mut archive = ZipArchive::new(std::io::Cursor::new(data))?;
+
+        let name = archive
+            .file_names()
+            .find(|name| name.to_lowercase().ends_with(".fb2"))
+            .map(String::from)
+            .ok_or_else(|| AppError::InvalidFormat("No .fb2 file in archive".into()))?;
+
+        let mut entry = archive.by_name(&name)?;
+        Ok(read_entry_data(entry.size(), &mut entry)?)
+    }
+
+    /// Parse the document into XML.
+    fn parse(content: &str) -> Result<Document<'_>> {
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        Ok(Document::parse_with_options(content, options)?)
+    }
+
+    /// Ensure image data is PNG format.
+    fn ensure_png(data: Vec<u8>) -> Result<Vec<u8>> {
+        if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+            return Ok(data);
+        }
+
+        let img = image::load_from_memory(&data)?;
+        let mut png_data = Vec::new();
+        img.write_to(
+            &mut std::io::Cursor::new(&mut png_data),
+            image::ImageFormat::Png,
+        )?;
+        Ok(png_data)
+    }
+}
+
+impl FormatHandler for Fb2Handler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        let content = Self::read_document(&book.path)?;
+        let doc = Self::parse(&content)?;
+
+        apply_metadata(&doc, book);
+        book.has_cover = cover_data(&doc).is_some();
+        Ok(())
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let content = Self::read_document(path)?;
+        let doc = Self::parse(&content)?;
+
+        match cover_data(&doc) {
+            Some(data) => Ok(Some(Self::ensure_png(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn page_count(&self, _path: &Path) -> Result<Option<u32>> {
+        // Reflowable format without fixed pages
+        Ok(None)
+    }
+}
+
+/// Read the bodies of an FB2 book as XHTML blocks for conversion.
+///
+/// Section titles become headings nested by section depth; images are
+/// not carried over.
+pub(super) fn blocks(path: &Path) -> Result<Vec<Block>> {
+    let content = Fb2Handler::read_document(path)?;
+    let doc = Fb2Handler::parse(&content)?;
+
+    let mut blocks = Vec::new();
+    for body in children(doc.root_element(), "body") {
+        section_blocks(body, 1, &mut blocks);
+    }
+    Ok(blocks)
+}
+
+/// Append the blocks of a `<body>` or `<section>` at the given depth.
+fn section_blocks(section: Node<'_, '_>, depth: u8, blocks: &mut Vec<Block>) {
+    for node in section.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "title" => {
+                let level = depth.min(6);
+                let lines: Vec<String> = children(node, "p").map(inline_xhtml).collect();
+                blocks.push(Block::Heading {
+                    level,
+                    text: text_of(node),
+                    xhtml: format!("<h{0}>{1}</h{0}>", level, lines.join("<br/>")),
+                });
+            }
+            "section" => section_blocks(node, depth + 1, blocks),
+            _ => {
+                if let Some(xhtml) = block_xhtml(node) {
+                    blocks.push(Block::Content(xhtml));
+                }
+            }
+        }
+    }
+}
+
+/// XHTML for a block-level FB2 element.
+fn block_xhtml(node: Node<'_, '_>) -> Option<String> {
+    let nested = |node: Node<'_, '_>| -> String {
+        node.children()
+            .filter(Node::is_element)
+            .filter_map(block_xhtml)
+            .collect()
+    };
+
+    match node.tag_name().name() {
+        "p" => Some(format!("<p>{}</p>", inline_xhtml(node))),
+        "subtitle" => Some(format!(
+            "<p class=\"subtitle\"><strong>{}</strong></p>",
+            inline_xhtml(node)
+        )),
+        "text-author" => Some(format!(
+            "<p class=\"author\"><em>{}</em></p>",
+            inline_xhtml(node)
+        )),
+        "epigraph" | "cite" | "annotation" => Some(format!(
+            "<blockquote class=\"{}\">{}</blockquote>",
+            node.tag_name().name(),
+            nested(node)
+        )),
+        "poem" => Some(format!("<div class=\"poem\">{}</div>", nested(node))),
+        "stanza" => {
+            let verses: Vec<String> = children(node, "v").map(inline_xhtml).collect();
+            Some(format!(
+                "{}<p class=\"stanza\">{}</p>",
+                child(node, "title")
+                    .map(|t| format!("<p><strong>{}</strong></p>", escape(text_of(t))))
+                    .unwrap_or_default(),
+                verses.join("<br/>")
+            ))
+        }
+        "title" => Some(format!("<p><strong>{}</strong></p>", escape(text_of(node)))),
+        _ => None,
+    }
+}
+
+/// XHTML for the inline content of a paragraph.
+fn inline_xhtml(node: Node<'_, '_>) -> String {
+    let mut xhtml = String::new();
+    for child in node.children() {
+        if child.is_text() {
+            xhtml.push_str(&escape(child.text().unwrap_or("")));
+            continue;
+        }
+        let element = match child.tag_name().name() {
+            "emphasis" => "em",
+            "strong" => "strong",
+            "strikethrough" => "s",
+            "sub" => "sub",
+            "sup" => "sup",
+            "code" => "code",
+            "a" => {
+                let href = child
+                    .attributes()
+                    .find(|a| a.name() == "href")
+                    .map(|a| a.value())
+                    .filter(|href| href.starts_with("http://") || href.starts_with("https://"));
+                match href {
+                    Some(href) => {
+                        xhtml.push_str(&format!(
+                            "<a href=\"{}\">{}</a>",
+                            escape(href),
+                            inline_xhtml(child)
+                        ));
+                        continue;
+                    }
+                    // Note references point inside the document
+                    None if child.attribute("type") == Some("note") => "sup",
+                    None => {
+                        xhtml.push_str(&inline_xhtml(child));
+                        continue;
+                    }
+                }
+            }
+            // <style> and unknown elements keep only their content
+            _ => {
+                xhtml.push_str(&inline_xhtml(child));
+                continue;
+            }
+        };
+        xhtml.push_str(&format!("<{0}>{1}</{0}>", element, inline_xhtml(child)));
+    }
+    xhtml
+}
+
+/// Decode FB2 bytes using the encoding declared in the XML header.
+///
+/// A byte order mark takes precedence; undeclared documents are UTF-8.
+fn decode_xml(data: &[u8]) -> String {
+    let encoding = declared_encoding(data)
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+
+    encoding.decode(data).0.into_owned()
+}
+
+/// Read the `encoding` attribute of the XML declaration.
+fn declared_encoding(data: &[u8]) -> Option<String> {
+    let head = &data[..data.len().min(200)];
+    let head = String::from_utf8_lossy(head);
+    let declaration = &head[head.find("<?xml")?..];
+    let declaration = &declaration[..declaration.find("?>")?];
+
+    let rest = &declaration[declaration.find("encoding")? + "encoding".len()..];
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    Some(value[..value.find(quote)?].trim().to_string())
+}
+
+/// First child element with the given local name.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// Child elements with the given local name.
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// Concatenated, whitespace-normalised text of a node.
+fn text_of(node: Node<'_, '_>) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Text of a child element, if not empty.
+fn child_text(node: Node<'_, '_>, name: &str) -> Option<String> {
+    child(node, name).map(text_of).filter(|s| !s.is_empty())
+}
+
+/// The `<description><title-info>` element.
+fn title_info<'a, 'input>(doc: &'a Document<'input>) -> Option<Node<'a, 'input>> {
+    child(doc.root_element(), "description").and_then(|d| child(d, "title-info"))
+}
+
+/// Format an `<author>` element as "First Middle Last" (or its nickname).
+fn author_name(author: Node<'_, '_>) -> Option<String> {
+    let name = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|part| child_text(author, part))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if name.is_empty() {
+        child_text(author, "nickname")
+    } else {
+        Some(name)
+    }
+}
+
+/// Copy `<title-info>` and `<publish-info>` metadata into a book.
+fn apply_metadata(doc: &Document<'_>, book: &mut Book) {
+    let Some(info) = title_info(doc) else {
+        return;
+    };
+
+    if let Some(title) = child_text(info, "book-title") {
+        book.title = title;
+    }
+    book.authors = children(info, "author").filter_map(author_name).collect();
+    book.tags = children(info, "genre")
+        .map(text_of)
+        .filter(|g| !g.is_empty())
+        .collect();
+    book.language = child_text(info, "lang");
+    book.description = child(info, "annotation")
+        .map(|annotation| {
+            children(annotation, "p")
+                .map(text_of)
+                .filter(|p| !p.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter(|s| !s.is_empty());
+
+    if let Some(date) = child(info, "date") {
+        book.published = date
+            .attribute("value")
+            .map(String::from)
+            .or_else(|| Some(text_of(date)))
+            .filter(|s| !s.is_empty());
+    }
+
+    if let Some(sequence) = child(info, "sequence") {
+        book.series = sequence
+            .attribute("name")
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        book.series_index = sequence
+            .attribute("number")
+            .and_then(|n| n.trim().parse().ok());
+    }
+
+    let publish_info =
+        child(doc.root_element(), "description").and_then(|d| child(d, "publish-info"));
+    if let Some(publish) = publish_info {
+        book.publisher = child_text(publish, "publisher");
+        book.isbn = child_text(publish, "isbn").map(|isbn| isbn.replace('-', ""));
+        if book.published.is_none() {
+            book.published = child_text(publish, "year");
+        }
+    }
+}
+
+/// Decode the `<binary>` image referenced by `<coverpage>`.
+fn cover_data(doc: &Document<'_>) -> Option<Vec<u8>> {
+    let image = child(title_info(doc)?, "coverpage")?
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name() == "image")?;
+
+    // l:href / xlink:href, whatever the namespace prefix
+    let href = image
+        .attributes()
+        .find(|a| a.name() == "href")?
+        .value()
+        .trim_start_matches('#');
+
+    let binary =
+        children(doc.root_element(), "binary").find(|b| b.attribute("id") == Some(href))?;
+    let encoded: String = binary
+        .text()?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    STANDARD.decode(encoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::test_util::{PNG_1X1, write_file};
+    use std::io::Write;
+
+    fn fixture(encoding: &str) -> String {
+        format!(
+            r##"<?xml version="1.0" encoding="{encoding}"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description>
+    <title-info>
+      <genre>sf_fantasy</genre>
+      <genre>adventure</genre>
+      <author>
+        <first-name>Аркадий</first-name>
+        <middle-name>Натанович</middle-name>
+        <last-name>Стругацкий</last-name>
+      </author>
+      <author><nickname>Anon</nickname></author>
+      <book-title>Пикник на обочине</book-title>
+      <annotation>
+        <p>Первый абзац.</p>
+        <p>Second   paragraph.</p>
+      </annotation>
+      <date value="1972-01-01">1972</date>
+      <coverpage><image l:href="#cover.png"/></coverpage>
+      <lang>ru</lang>
+      <sequence name="Мир Полудня" number="3"/>
+    </title-info>
+    <publish-info>
+      <publisher>Молодая гвардия</publisher>
+      <isbn>978-5-17-000000-0</isbn>
+    </publish-info>
+  </description>
+  <body><section><p>Текст</p></section></body>
+  <binary id="cover.png" content-type="image/png">{cover}</binary>
+</FictionBook>"##,
+            encoding = encoding,
+            cover = STANDARD.encode(PNG_1X1),
+        )
+    }
+
+    fn check_book(path: &Path) {
+        let mut book = Book::new(path.to_path_buf(), crate::config::BookFormat::Fb2);
+        Fb2Handler.extract_metadata(&mut book).unwrap();
+
+        assert_eq!(book.title, "Пикник на обочине");
+        assert_eq!(book.authors, vec!["Аркадий Натанович Стругацкий", "Anon"]);
+        assert_eq!(book.tags, vec!["sf_fantasy", "adventure"]);
+        assert_eq!(book.language.as_deref(), Some("ru"));
+        assert_eq!(
+            book.description.as_deref(),
+            Some("Первый абзац.\nSecond paragraph.")
+        );
+        assert_eq!(book.published.as_deref(), Some("1972-01-01"));
+        assert_eq!(book.series.as_deref(), Some("Мир Полудня"));
+        assert_eq!(book.series_index, Some(3.0));
+        assert_eq!(book.publisher.as_deref(), Some("Молодая гвардия"));
+        assert_eq!(book.isbn.as_deref(), Some("9785170000000"));
+        assert!(book.has_cover);
+
+        let cover = Fb2Handler.extract_cover(path).unwrap().unwrap();
+        assert_eq!(cover, PNG_1X1);
+    }
+
+    #[test]
+    fn test_utf8_fb2() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "book.fb2", fixture("UTF-8").as_bytes());
+        check_book(&path);
+    }
+
+    #[test]
+    fn test_windows_1251_fb2() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = fixture("windows-1251");
+        let (encoded, _, _) = encoding_rs::WINDOWS_1251.encode(&content);
+        let path = write_file(dir.path(), "book.fb2", &encoded);
+        check_book(&path);
+    }
+
+    #[test]
+    fn test_zipped_fb2() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut zipped = Vec::new();
+        {
+            let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut zipped));
+            writer
+                .start_file("book.fb2", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(fixture("UTF-8").as_bytes()).unwrap();
+            writer.finish().unwrap();
+        }
+        let path = write_file(dir.path(), "book.fb2.zip", &zipped);
+        check_book(&path);
+    }
+
+    #[test]
+    fn test_declared_encoding() {
+        assert_eq!(
+            declared_encoding(b"<?xml version='1.0' encoding='koi8-r'?><a/>").as_deref(),
+            Some("koi8-r")
+        );
+        assert_eq!(declared_encoding(b"<?xml version=\"1.0\"?><a/>"), None);
+        assert_eq!(declared_encoding(b"<a/>"), None);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = r##"<?xml version="1.0" encoding="UTF-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <body>
+    <title><p>The Book</p></title>
+    <section>
+      <title><p>Part One</p><p>Beginnings</p></title>
+      <epigraph><p>Quote &amp; more</p><text-author>Someone</text-author></epigraph>
+      <section>
+        <title><p>Chapter 1</p></title>
+        <p>Text with <emphasis>stress</emphasis><a l:href="#n1" type="note">1</a>.</p>
+        <poem><stanza><v>Line one</v><v>Line two</v></stanza></poem>
+      </section>
+    </section>
+  </body>
+</FictionBook>"##;
+        let path = write_file(dir.path(), "book.fb2", content.as_bytes());
+
+        let blocks = blocks(&path).unwrap();
+        let heading = |level: u8, text: &str, xhtml: &str| Block::Heading {
+            level,
+            text: text.to_string(),
+            xhtml: xhtml.to_string(),
+        };
+        assert_eq!(
+            blocks,
+            vec![
+                heading(1, "The Book", "<h1>The Book</h1>"),
+                heading(2, "Part One Beginnings", "<h2>Part One<br/>Beginnings</h2>"),
+                Block::Content(
+                    "<blockquote class=\"epigraph\"><p>Quote &amp; more</p>\
+                     <p class=\"author\"><em>Someone</em></p></blockquote>"
+                        .to_string()
+                ),
+                heading(3, "Chapter 1", "<h3>Chapter 1</h3>"),
+                Block::Content("<p>Text with <em>stress</em><sup>1</sup>.</p>".to_string()),
+                Block::Content(
+                    "<div class=\"poem\"><p class=\"stanza\">Line one<br/>Line two</p></div>"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
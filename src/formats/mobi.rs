@@ -0,0 +1,509 @@
+use crate::error::{AppError, Result};
+use crate::formats::FormatHandler;
+use crate::library::book::Book;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Handler for MOBI/AZW/AZW3 files (Mobipocket and Kindle KF8).
+pub struct MobiHandler;
+
+/// EXTH record types used for metadata.
+mod exth {
+    pub const AUTHOR: u32 = 100;
+    pub const PUBLISHER: u32 = 101;
+    pub const DESCRIPTION: u32 = 103;
+    pub const ISBN: u32 = 104;
+    pub const SUBJECT: u32 = 105;
+    pub const PUBLISHED: u32 = 106;
+    pub const COVER_OFFSET: u32 = 201;
+    pub const THUMB_OFFSET: u32 = 202;
+    pub const UPDATED_TITLE: u32 = 503;
+    pub const LANGUAGE: u32 = 524;
+}
+
+/// Size of the PalmDB header, before the record list.
+const PALMDB_HEADER_LEN: usize = 78;
+
+/// Offset of the MOBI header in record 0 (after the PalmDOC header).
+const MOBI_HEADER_OFFSET: usize = 16;
+
+/// "No image" marker for the first image index and EXTH offsets.
+const NO_INDEX: u32 = 0xFFFF_FFFF;
+
+/// Parsed PalmDB/MOBI/EXTH headers.
+struct MobiFile<R> {
+    reader: R,
+    /// Start offset of each PalmDB record.
+    records: Vec<u32>,
+    /// Total file length (end of the last record).
+    file_len: u64,
+    /// PalmDB database name (fallback title).
+    db_name: String,
+    /// Full name from the MOBI header.
+    full_name: Option<String>,
+    /// Windows locale identifier.
+    locale: u32,
+    /// Record index of the first image.
+    first_image: Option<u32>,
+    /// Whether strings are UTF-8 (65001) rather than CP1252.
+    utf8: bool,
+    /// EXTH records (type, data).
+    exth: Vec<(u32, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> MobiFile<R> {
+    /// Parse the PalmDB header, record list and record 0 headers.
+    fn parse(mut reader: R) -> Result<Self> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0u8; PALMDB_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+
+        let db_type = &header[60..68];
+        if db_type != b"BOOKMOBI" && db_type != b"TEXtREAd" {
+            return Err(AppError::InvalidFormat("Not a MOBI file".into()));
+        }
+
+        let record_count = u16::from_be_bytes([header[76], header[77]]) as usize;
+        if record_count == 0 {
+            return Err(AppError::InvalidFormat("MOBI file has no records".into()));
+        }
+
+        let mut list = vec![0u8; record_count * 8];
+        reader.read_exact(&mut list)?;
+        let records: Vec<u32> = list.chunks_exact(8).map(|c| be_u32(c, 0)).collect();
+
+        let db_name = String::from_utf8_lossy(&header[..32])
+            .trim_end_matches('\0')
+            .replace('_', " ")
+            .trim()
+            .to_string();
+
+        let mut mobi = Self {
+            reader,
+            records,
+            file_len,
+            db_name,
+            full_name: None,
+            locale: 0,
+            first_image: None,
+            utf8: false,
+            exth: Vec::new(),
+        };
+
+        let record0 = mobi.read_record(0)?;
+        mobi.parse_record0(&record0);
+        Ok(mobi)
+    }
+
+    /// Parse the MOBI and EXTH headers from record 0.
+    fn parse_record0(&mut self, record0: &[u8]) {
+        let mobi = MOBI_HEADER_OFFSET;
+        if record0.len() < mobi + 8 || &record0[mobi..mobi + 4] != b"MOBI" {
+            // Plain PalmDOC: no metadata beyond the database name
+            return;
+        }
+
+        let header_len = be_u32(record0, mobi + 4) as usize;
+        self.utf8 = be_u32(record0, mobi + 12) == 65001;
+
+        if record0.len() >= mobi + 0x60 {
+            let name_offset = be_u32(record0, mobi + 0x44) as usize;
+            let name_len = be_u32(record0, mobi + 0x48) as usize;
+            self.full_name = record0
+                .get(name_offset..name_offset.saturating_add(name_len))
+                .map(|bytes| self.decode(bytes))
+                .filter(|name| !name.is_empty());
+            self.locale = be_u32(record0, mobi + 0x4C);
+            self.first_image = Some(be_u32(record0, mobi + 0x5C)).filter(|&i| i != NO_INDEX);
+        }
+
+        let has_exth = record0.len() >= mobi + 0x74 && be_u32(record0, mobi + 0x70) & 0x40 != 0;
+        if has_exth {
+            self.exth = parse_exth(record0.get(mobi + header_len..).unwrap_or_default());
+        }
+    }
+
+    /// Read the raw data of a PalmDB record.
+    fn read_record(&mut self, index: usize) -> Result<Vec<u8>> {
+        let start = *self
+            .records
+            .get(index)
+            .ok_or_else(|| AppError::InvalidFormat(format!("Missing MOBI record {}", index)))?
+            as u64;
+        let end = self
+            .records
+            .get(index + 1)
+            .map(|&o| o as u64)
+            .unwrap_or(self.file_len);
+        if end < start || end > self.file_len {
+            return Err(AppError::InvalidFormat(format!(
+                "Invalid MOBI record {}",
+                index
+            )));
+        }
+
+        let mut data = vec![0u8; (end - start) as usize];
+        self.reader.seek(SeekFrom::Start(start))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Decode a header string using the book text encoding.
+    fn decode(&self, bytes: &[u8]) -> String {
+        let text = if self.utf8 {
+            String::from_utf8_lossy(bytes).into_owned()
+        } else {
+            encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned()
+        };
+        text.trim_end_matches('\0').trim().to_string()
+    }
+
+    /// All values of an EXTH record type, decoded as text.
+    fn exth_strings(&self, record_type: u32) -> Vec<String> {
+        self.exth
+            .iter()
+            .filter(|(t, _)| *t == record_type)
+            .map(|(_, data)| self.decode(data))
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// First value of an EXTH record type, decoded as text.
+    fn exth_string(&self, record_type: u32) -> Option<String> {
+        self.exth_strings(record_type).into_iter().next()
+    }
+
+    /// First value of a numeric EXTH record type.
+    fn exth_u32(&self, record_type: u32) -> Option<u32> {
+        self.exth
+            .iter()
+            .find(|(t, data)| *t == record_type && data.len() >= 4)
+            .map(|(_, data)| be_u32(data, 0))
+    }
+
+    /// Book title: EXTH updated title, then full name, then database name.
+    fn title(&self) -> String {
+        self.exth_string(exth::UPDATED_TITLE)
+            .or_else(|| self.full_name.clone())
+            .unwrap_or_else(|| self.db_name.clone())
+    }
+
+    /// Language: EXTH language, then the header locale.
+    fn language(&self) -> Option<String> {
+        self.exth_string(exth::LANGUAGE)
+            .or_else(|| locale_language(self.locale).map(String::from))
+    }
+
+    /// Read the cover image, falling back to the thumbnail.
+    fn cover(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(first_image) = self.first_image else {
+            return Ok(None);
+        };
+
+        for record_type in [exth::COVER_OFFSET, exth::THUMB_OFFSET] {
+            let Some(offset) = self.exth_u32(record_type).filter(|&o| o != NO_INDEX) else {
+                continue;
+            };
+            let index = first_image as usize + offset as usize;
+            if let Ok(data) = self.read_record(index)
+                && is_image(&data)
+            {
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl MobiHandler {
+    /// Open and parse a MOBI file.
+    fn open(path: &Path) -> Result<MobiFile<BufReader<File>>> {
+        MobiFile::parse(BufReader::new(File::open(path)?))
+    }
+
+    /// Ensure image data is PNG format.
+    fn ensure_png(data: Vec<u8>) -> Result<Vec<u8>> {
+        if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+            return Ok(data);
+        }
+
+        let img = image::load_from_memory(&data)?;
+        let mut png_data = Vec::new();
+        img.write_to(
+            &mut std::io::Cursor::new(&mut png_data),
+            image::ImageFormat::Png,
+        )?;
+        Ok(png_data)
+    }
+}
+
+impl FormatHandler for MobiHandler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        let mut mobi = Self::open(&book.path)?;
+        apply_metadata(&mobi, book);
+        book.has_cover = mobi.cover()?.is_some();
+        Ok(())
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let mut mobi = Self::open(path)?;
+        match mobi.cover()? {
+            Some(data) => Ok(Some(Self::ensure_png(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn page_count(&self, _path: &Path) -> Result<Option<u32>> {
+        // Reflowable format without fixed pages
+        Ok(None)
+    }
+}
+
+/// Copy parsed metadata into a book.
+fn apply_metadata<R: Read + Seek>(mobi: &MobiFile<R>, book: &mut Book) {
+    let title = mobi.title();
+    if !title.is_empty() {
+        book.title = title;
+    }
+
+    book.authors = mobi.exth_strings(exth::AUTHOR);
+    book.publisher = mobi.exth_string(exth::PUBLISHER);
+    book.description = mobi.exth_string(exth::DESCRIPTION);
+    book.isbn = mobi
+        .exth_string(exth::ISBN)
+        .map(|isbn| isbn.replace('-', ""));
+    book.published = mobi.exth_string(exth::PUBLISHED);
+    book.language = mobi.language();
+    book.tags = mobi
+        .exth_strings(exth::SUBJECT)
+        .iter()
+        .flat_map(|s| s.split(';'))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+}
+
+/// Parse EXTH records from the data following the MOBI header.
+fn parse_exth(data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    if data.len() < 12 || &data[..4] != b"EXTH" {
+        return Vec::new();
+    }
+
+    let count = be_u32(data, 8) as usize;
+    let mut records = Vec::with_capacity(count.min(256));
+    let mut pos = 12;
+
+    for _ in 0..count {
+        if pos + 8 > data.len() {
+            break;
+        }
+        let record_type = be_u32(data, pos);
+        let len = be_u32(data, pos + 4) as usize;
+        if len < 8 || pos + len > data.len() {
+            break;
+        }
+        records.push((record_type, data[pos + 8..pos + len].to_vec()));
+        pos += len;
+    }
+
+    records
+}
+
+/// Map a Windows locale identifier to an ISO 639-1 language code.
+fn locale_language(locale: u32) -> Option<&'static str> {
+    // The primary language is the low 10 bits of the LCID
+    Some(match locale & 0x3FF {
+        0x04 => "zh",
+        0x05 => "cs",
+        0x06 => "da",
+        0x07 => "de",
+        0x08 => "el",
+        0x09 => "en",
+        0x0A => "es",
+        0x0B => "fi",
+        0x0C => "fr",
+        0x0E => "hu",
+        0x10 => "it",
+        0x11 => "ja",
+        0x12 => "ko",
+        0x13 => "nl",
+        0x14 => "nb",
+        0x15 => "pl",
+        0x16 => "pt",
+        0x19 => "ru",
+        0x1D => "sv",
+        0x1F => "tr",
+        _ => return None,
+    })
+}
+
+/// Check for JPEG, PNG or GIF magic bytes.
+fn is_image(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xD8, 0xFF])
+        || data.starts_with(&[0x89, b'P', b'N', b'G'])
+        || data.starts_with(b"GIF8")
+}
+
+/// Read a big-endian u32 (0 when out of bounds).
+fn be_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Build a minimal MOBI file: record 0 with headers, one text record,
+    /// then the given image records.
+    fn build_mobi(
+        encoding: u32,
+        full_name: &[u8],
+        exth: &[(u32, &[u8])],
+        images: &[&[u8]],
+    ) -> Vec<u8> {
+        // EXTH block
+        let mut exth_data = Vec::new();
+        for (record_type, data) in exth {
+            exth_data.extend_from_slice(&record_type.to_be_bytes());
+            exth_data.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+            exth_data.extend_from_slice(data);
+        }
+        let mut exth_block = b"EXTH".to_vec();
+        exth_block.extend_from_slice(&(exth_data.len() as u32 + 12).to_be_bytes());
+        exth_block.extend_from_slice(&(exth.len() as u32).to_be_bytes());
+        exth_block.extend_from_slice(&exth_data);
+
+        // Record 0: PalmDOC header + MOBI header (0xE8 bytes) + EXTH + name
+        let header_len = 0xE8usize;
+        let mut mobi = vec![0u8; header_len];
+        mobi[..4].copy_from_slice(b"MOBI");
+        mobi[4..8].copy_from_slice(&(header_len as u32).to_be_bytes());
+        mobi[8..12].copy_from_slice(&2u32.to_be_bytes());
+        mobi[12..16].copy_from_slice(&encoding.to_be_bytes());
+        let name_offset = (MOBI_HEADER_OFFSET + header_len + exth_block.len()) as u32;
+        mobi[0x44..0x48].copy_from_slice(&name_offset.to_be_bytes());
+        mobi[0x48..0x4C].copy_from_slice(&(full_name.len() as u32).to_be_bytes());
+        mobi[0x4C..0x50].copy_from_slice(&0x040Cu32.to_be_bytes()); // fr-FR
+        mobi[0x5C..0x60].copy_from_slice(&2u32.to_be_bytes()); // first image
+        mobi[0x70..0x74].copy_from_slice(&0x40u32.to_be_bytes()); // EXTH present
+
+        let mut record0 = vec![0u8; MOBI_HEADER_OFFSET];
+        record0.extend_from_slice(&mobi);
+        record0.extend_from_slice(&exth_block);
+        record0.extend_from_slice(full_name);
+
+        let mut records: Vec<Vec<u8>> = vec![record0, b"<html>text</html>".to_vec()];
+        records.extend(images.iter().map(|i| i.to_vec()));
+
+        // PalmDB header and record list
+        let mut file = vec![0u8; PALMDB_HEADER_LEN];
+        file[..9].copy_from_slice(b"Test_Book");
+        file[60..68].copy_from_slice(b"BOOKMOBI");
+        file[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+
+        let mut offset = PALMDB_HEADER_LEN + records.len() * 8;
+        for (i, record) in records.iter().enumerate() {
+            file.extend_from_slice(&(offset as u32).to_be_bytes());
+            file.extend_from_slice(&(i as u32 * 2).to_be_bytes());
+            offset += record.len();
+        }
+        for record in &records {
+            file.extend_from_slice(record);
+        }
+        file
+    }
+
+    fn jpeg_stub() -> Vec<u8> {
+        vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 0, 0xFF, 0xD9]
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let data = build_mobi(
+            65001,
+            "Le Petit Prince".as_bytes(),
+            &[
+                (exth::AUTHOR, b"Antoine de Saint-Exup\xC3\xA9ry"),
+                (exth::PUBLISHER, b"Gallimard"),
+                (exth::DESCRIPTION, b"A pilot meets a prince."),
+                (exth::ISBN, b"978-2-07-061275-8"),
+                (exth::SUBJECT, b"Fiction; Classics"),
+                (exth::PUBLISHED, b"1943-04-06"),
+                (exth::COVER_OFFSET, &1u32.to_be_bytes()),
+            ],
+            &[b"not an image", &jpeg_stub()],
+        );
+
+        let mut mobi = MobiFile::parse(Cursor::new(data)).unwrap();
+        let mut book = Book::default();
+        apply_metadata(&mobi, &mut book);
+
+        assert_eq!(book.title, "Le Petit Prince");
+        assert_eq!(book.authors, vec!["Antoine de Saint-Exupéry"]);
+        assert_eq!(book.publisher.as_deref(), Some("Gallimard"));
+        assert_eq!(book.description.as_deref(), Some("A pilot meets a prince."));
+        assert_eq!(book.isbn.as_deref(), Some("9782070612758"));
+        assert_eq!(book.published.as_deref(), Some("1943-04-06"));
+        assert_eq!(book.tags, vec!["Fiction", "Classics"]);
+        // No EXTH language: falls back to the fr-FR locale
+        assert_eq!(book.language.as_deref(), Some("fr"));
+
+        assert_eq!(mobi.cover().unwrap(), Some(jpeg_stub()));
+    }
+
+    #[test]
+    fn test_exth_title_and_language_override() {
+        let data = build_mobi(
+            1252,
+            b"Caf\xE9",
+            &[
+                (exth::UPDATED_TITLE, b"Updated Title"),
+                (exth::LANGUAGE, b"en"),
+                (exth::AUTHOR, b"First Author"),
+                (exth::AUTHOR, b"Second Author"),
+            ],
+            &[],
+        );
+
+        let mut mobi = MobiFile::parse(Cursor::new(data)).unwrap();
+        assert_eq!(mobi.full_name.as_deref(), Some("Café"));
+        assert_eq!(mobi.title(), "Updated Title");
+        assert_eq!(mobi.language().as_deref(), Some("en"));
+        assert_eq!(
+            mobi.exth_strings(exth::AUTHOR),
+            vec!["First Author", "Second Author"]
+        );
+        assert_eq!(mobi.cover().unwrap(), None);
+    }
+
+    #[test]
+    fn test_thumbnail_fallback() {
+        let data = build_mobi(
+            65001,
+            b"Book",
+            &[
+                (exth::COVER_OFFSET, &NO_INDEX.to_be_bytes()),
+                (exth::THUMB_OFFSET, &0u32.to_be_bytes()),
+            ],
+            &[&jpeg_stub()],
+        );
+
+        let mut mobi = MobiFile::parse(Cursor::new(data)).unwrap();
+        assert_eq!(mobi.cover().unwrap(), Some(jpeg_stub()));
+    }
+
+    #[test]
+    fn test_rejects_non_mobi() {
+        let mut data = build_mobi(65001, b"Book", &[], &[]);
+        data[60..68].copy_from_slice(b"XXXXXXXX");
+        assert!(MobiFile::parse(Cursor::new(data)).is_err());
+        assert!(MobiFile::parse(Cursor::new(vec![0u8; 10])).is_err());
+    }
+}
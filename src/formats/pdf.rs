@@ -1,41 +1,107 @@
+mod font;
+mod render;
+mod xmp;
+mod xobject;
+
 use crate::error::{AppError, Result};
-use crate::formats::FormatHandler;
+use crate::formats::{FormatHandler, TextLocation, TextSection};
 use crate::library::book::Book;
-use lopdf::Document;
+use image::DynamicImage;
+use lopdf::{Document, Object, ObjectId};
 use std::path::Path;
+use xmp::Xmp;
+use xobject::{PdfImage, dict_entry};
+
+/// Height of covers rendered from the first page.
+const COVER_HEIGHT: u32 = 800;
+
+/// Images smaller than this on either side (logos, bullets) are not covers.
+const MIN_COVER_SIZE: u32 = 64;
+
+/// Decompressed content read per page when extracting text.
+const MAX_PAGE_CONTENT: usize = 16 * 1024 * 1024;
 
 /// Handler for PDF files.
 pub struct PdfHandler;
 
 impl PdfHandler {
+    /// Cover from the first page: its first sizeable image, or a rendering
+    /// of the page when it has none.
+    fn cover(doc: &Document) -> Option<Vec<u8>> {
+        let &page_id = doc.get_pages().values().next()?;
+
+        Self::first_image(doc, page_id).or_else(|| {
+            let page = render::render_page(doc, page_id, COVER_HEIGHT)?;
+            encode_png(DynamicImage::ImageRgb8(page))
+        })
+    }
+
+    /// First image XObject of a page, as JPEG (passed through) or PNG.
+    fn first_image(doc: &Document, page_id: ObjectId) -> Option<Vec<u8>> {
+        let page = doc.get_dictionary(page_id).ok()?;
+        let resources = dict_entry(doc, page, b"Resources")?;
+        let xobjects = dict_entry(doc, resources, b"XObject")?;
+
+        for (_name, obj) in xobjects.iter() {
+            let Ok((_, Object::Stream(stream))) = doc.dereference(obj) else {
+                continue;
+            };
+            let is_image = matches!(
+                stream.dict.get(b"Subtype"),
+                Ok(Object::Name(n)) if n == b"Image"
+            );
+            if !is_image {
+                continue;
+            }
+
+            let Some(image) = PdfImage::new(stream) else {
+                continue;
+            };
+            if image.is_stencil() || image.width < MIN_COVER_SIZE || image.height < MIN_COVER_SIZE {
+                continue;
+            }
+
+            // RGB and gray JPEGs are usable as-is
+            if image.is_plain_jpeg(doc) {
+                return Some(image.content().to_vec());
+            }
+            if let Some(rgba) = image.decode(doc, Some(resources)) {
+                return encode_png(DynamicImage::ImageRgba8(rgba));
+            }
+        }
+
+        None
+    }
+
     /// Extract text content from a PDF info dictionary value.
     fn extract_text(obj: &lopdf::Object) -> Option<String> {
         match obj {
-            lopdf::Object::String(bytes, _) => {
-                // Try UTF-16BE first (starts with BOM)
-                if bytes.starts_with(&[0xFE, 0xFF]) {
-                    let utf16: Vec<u16> = bytes[2..]
-                        .chunks(2)
-                        .map(|chunk| {
-                            u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)])
-                        })
-                        .collect();
-                    String::from_utf16(&utf16).ok()
-                } else {
-                    // Try as UTF-8 or Latin-1
-                    String::from_utf8(bytes.clone())
-                        .or_else(|_| {
-                            Ok::<_, std::string::FromUtf8Error>(
-                                bytes.iter().map(|&b| b as char).collect(),
-                            )
-                        })
-                        .ok()
-                }
-            }
+            lopdf::Object::String(bytes, _) => Some(decode_pdf_string(bytes)),
             lopdf::Object::Name(name) => String::from_utf8(name.clone()).ok(),
             _ => None,
         }
     }
+
+    /// XMP packet from the catalog's `/Metadata` stream.
+    fn read_xmp(doc: &Document) -> Option<Xmp> {
+        let catalog = doc.catalog().ok()?;
+        let (_, Object::Stream(stream)) = doc.dereference(catalog.get(b"Metadata").ok()?).ok()?
+        else {
+            return None;
+        };
+        // Metadata streams are usually stored uncompressed
+        let content = stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone());
+
+        match Xmp::parse(&content) {
+            Ok(xmp) => Some(xmp),
+            Err(e) => {
+                tracing::debug!("Invalid XMP metadata: {}", e);
+                None
+            }
+        }
+    }
 }
 
 impl FormatHandler for PdfHandler {
@@ -102,9 +168,12 @@ impl FormatHandler for PdfHandler {
             }
         }
 
-        // PDF might have a cover, but extraction is complex
-        // For now, mark as having cover if it has pages
-        book.has_cover = book.page_count.map(|c| c > 0).unwrap_or(false);
+        // XMP metadata takes precedence over the Info dictionary
+        if let Some(xmp) = Self::read_xmp(&doc) {
+            xmp.apply(book);
+        }
+
+        // has_cover is set by the scanner once a cover has been produced
 
         Ok(())
     }
@@ -112,130 +181,319 @@ impl FormatHandler for PdfHandler {
     fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
         let doc = Document::load(path).map_err(|e| AppError::Pdf(e.to_string()))?;
 
-        // Get first page - get_pages() returns BTreeMap<u32, ObjectId>
-        let pages = doc.get_pages();
-        let Some(&first_page_id) = pages.values().next() else {
-            return Ok(None);
-        };
+        Ok(Self::cover(&doc))
+    }
 
-        // Get page dictionary
-        let Ok(page) = doc.get_dictionary(first_page_id) else {
-            return Ok(None);
-        };
+    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
+        let doc = Document::load(path).map_err(|e| AppError::Pdf(e.to_string()))?;
 
-        // Get Resources - handle both direct dict and reference
-        let resources = match page.get(b"Resources") {
-            Ok(lopdf::Object::Reference(r)) => doc.get_dictionary(*r).ok(),
-            Ok(lopdf::Object::Dictionary(d)) => Some(d),
-            _ => None,
-        };
+        Ok(Some(doc.get_pages().len() as u32))
+    }
 
-        let Some(resources) = resources else {
-            return Ok(None);
-        };
+    fn extract_content(&self, path: &Path) -> Result<Vec<TextSection>> {
+        let doc = Document::load(path).map_err(|e| AppError::Pdf(e.to_string()))?;
 
-        // Get XObject dictionary
-        let xobjects = match resources.get(b"XObject") {
-            Ok(lopdf::Object::Reference(r)) => doc.get_dictionary(*r).ok(),
-            Ok(lopdf::Object::Dictionary(d)) => Some(d),
-            _ => None,
-        };
+        Ok(doc
+            .get_pages()
+            .into_keys()
+            .filter_map(|page| {
+                // Pages with unsupported fonts are skipped, not the whole book
+                let text = doc
+                    .extract_text_with_limit(&[page], MAX_PAGE_CONTENT)
+                    .map_err(|e| tracing::debug!(page, error = %e, "No text on PDF page"))
+                    .ok()?;
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                (!text.is_empty()).then_some(TextSection {
+                    location: TextLocation::Page(page),
+                    title: None,
+                    text,
+                })
+            })
+            .collect())
+    }
+}
 
-        let Some(xobjects) = xobjects else {
-            return Ok(None);
-        };
+/// Encode an image as PNG.
+fn encode_png(image: DynamicImage) -> Option<Vec<u8>> {
+    let mut png_data = Vec::new();
+    image
+        .write_to(
+            &mut std::io::Cursor::new(&mut png_data),
+            image::ImageFormat::Png,
+        )
+        .ok()?;
+    Some(png_data)
+}
 
-        // Find first image XObject
-        for (_name, obj) in xobjects.iter() {
-            let lopdf::Object::Reference(xobj_ref) = obj else {
-                continue;
-            };
+/// Decode a PDF text string: UTF-16BE or UTF-8 with a byte order mark,
+/// otherwise PDFDocEncoding.
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if let Some(data) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = data
+            .chunks(2)
+            .map(|chunk| u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]))
+            .collect();
+        let text = String::from_utf16_lossy(&units);
+        return strip_language_escapes(&text);
+    }
+    if let Some(data) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(data).into_owned();
+    }
+    // Some producers write UTF-8 without a BOM
+    if !bytes.is_ascii()
+        && let Ok(text) = std::str::from_utf8(bytes)
+    {
+        return text.to_string();
+    }
 
-            let Ok(lopdf::Object::Stream(xobj_stream)) = doc.get_object(*xobj_ref) else {
-                continue;
-            };
+    bytes.iter().map(|&b| pdf_doc_char(b)).collect()
+}
 
-            // Check if it's an image
-            let is_image = matches!(
-                xobj_stream.dict.get(b"Subtype"),
-                Ok(lopdf::Object::Name(n)) if n == b"Image"
-            );
+/// Remove the `ESC lang ESC` language markers allowed in UTF-16 strings.
+fn strip_language_escapes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_escape = false;
+    for c in text.chars() {
+        if c == '\u{1B}' {
+            in_escape = !in_escape;
+        } else if !in_escape {
+            result.push(c);
+        }
+    }
+    result
+}
 
-            if !is_image {
-                continue;
-            }
+/// Map a PDFDocEncoding byte to its character (ISO 8859-1 with the
+/// differences listed in Annex D of the PDF specification).
+fn pdf_doc_char(byte: u8) -> char {
+    const LOW: [char; 8] = ['˘', 'ˇ', 'ˆ', '˙', '˝', '˛', '˚', '˜'];
+    const HIGH: [char; 31] = [
+        '•', '†', '‡', '…', '—', '–', 'ƒ', '⁄', '‹', '›', '−', '‰', '„', '“', '”', '‘', '’', '‚',
+        '™', 'ﬁ', 'ﬂ', 'Ł', 'Œ', 'Š', 'Ÿ', 'Ž', 'ı', 'ł', 'œ', 'š', 'ž',
+    ];
+
+    match byte {
+        0x18..=0x1F => LOW[(byte - 0x18) as usize],
+        0x80..=0x9E => HIGH[(byte - 0x80) as usize],
+        0x9F | 0xAD => '\u{FFFD}',
+        0xA0 => '€',
+        _ => byte as char,
+    }
+}
 
-            // Check filter - we support DCTDecode (JPEG)
-            let is_dct = match xobj_stream.dict.get(b"Filter") {
-                Ok(lopdf::Object::Name(n)) => n == b"DCTDecode",
-                Ok(lopdf::Object::Array(arr)) => arr
-                    .iter()
-                    .any(|item| matches!(item, lopdf::Object::Name(n) if n == b"DCTDecode")),
-                _ => false,
-            };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Stream, dictionary};
+
+    /// Write a one-page PDF with the given content and image XObjects.
+    fn write_pdf(path: &Path, content: &[u8], images: Vec<(&str, Stream)>) {
+        let mut doc = Document::with_version("1.5");
+        let mut xobjects = lopdf::Dictionary::new();
+        for (name, image) in images {
+            xobjects.set(name, doc.add_object(image));
+        }
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => xobjects },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), 300.into(), 400.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path).unwrap();
+    }
 
-            if is_dct {
-                // For DCTDecode, try content first, then decompressed_content
-                let data = if !xobj_stream.content.is_empty() {
-                    xobj_stream.content.clone()
-                } else if let Ok(decoded) = xobj_stream.decompressed_content() {
-                    decoded
-                } else {
-                    continue;
-                };
-
-                // Verify it's JPEG
-                if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
-                    return Ok(Some(data));
-                }
-            }
+    #[test]
+    fn test_extract_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("text.pdf");
+
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let mut kids = Vec::new();
+        for content in [
+            &b"BT /F1 12 Tf 72 700 Td (Call me   Ishmael.) Tj ET"[..],
+            b"",
+        ] {
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.to_vec()));
+            kids.push(
+                doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                    "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                })
+                .into(),
+            );
+        }
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => 2,
+                "MediaBox" => vec![0.into(), 0.into(), 300.into(), 400.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(&path).unwrap();
+
+        let sections = PdfHandler.extract_content(&path).unwrap();
+        assert_eq!(
+            sections,
+            vec![TextSection {
+                location: TextLocation::Page(1),
+                title: None,
+                text: "Call me Ishmael.".to_string(),
+            }]
+        );
+    }
 
-            // For other filters, try to decode
-            if let Ok(data) = xobj_stream.decompressed_content() {
-                // Check if it looks like JPEG
-                if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
-                    return Ok(Some(data));
-                }
+    fn decode(png: &[u8]) -> image::RgbaImage {
+        image::load_from_memory(png).unwrap().to_rgba8()
+    }
 
-                // Check if it looks like PNG
-                if data.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
-                    return Ok(Some(data));
-                }
+    #[test]
+    fn test_vector_page_is_rendered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vector.pdf");
+        write_pdf(&path, b"0 0 1 rg 0 200 300 200 re f", Vec::new());
+
+        let mut book = Book::new(path.clone(), crate::config::BookFormat::Pdf);
+        PdfHandler.extract_metadata(&mut book).unwrap();
+        assert_eq!(book.page_count, Some(1));
+        assert!(!book.has_cover);
+
+        let cover = decode(&PdfHandler.extract_cover(&path).unwrap().unwrap());
+        assert_eq!(cover.dimensions(), (600, COVER_HEIGHT));
+        assert_eq!(cover.get_pixel(300, 200).0, [0, 0, 255, 255]);
+        assert_eq!(cover.get_pixel(300, 600).0, [255, 255, 255, 255]);
+    }
 
-                // Raw image data - try to convert to PNG
-                let width = match xobj_stream.dict.get(b"Width") {
-                    Ok(lopdf::Object::Integer(i)) => Some(*i as u32),
-                    _ => None,
-                };
-                let height = match xobj_stream.dict.get(b"Height") {
-                    Ok(lopdf::Object::Integer(i)) => Some(*i as u32),
-                    _ => None,
-                };
-
-                if let (Some(w), Some(h)) = (width, height) {
-                    // Try to create image from raw RGB data
-                    if let Some(img) = image::RgbImage::from_raw(w, h, data.clone()) {
-                        let mut png_data = Vec::new();
-                        if image::DynamicImage::ImageRgb8(img)
-                            .write_to(
-                                &mut std::io::Cursor::new(&mut png_data),
-                                image::ImageFormat::Png,
-                            )
-                            .is_ok()
-                        {
-                            return Ok(Some(png_data));
-                        }
-                    }
-                }
-            }
-        }
+    #[test]
+    fn test_flate_cmyk_image_is_cover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.pdf");
+
+        // 64x64 magenta in CMYK, Flate-compressed
+        let mut image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 64,
+                "Height" => 64,
+                "ColorSpace" => "DeviceCMYK",
+                "BitsPerComponent" => 8,
+            },
+            [0u8, 255, 0, 0].repeat(64 * 64),
+        );
+        image.compress().unwrap();
+        write_pdf(
+            &path,
+            b"q 300 0 0 400 0 0 cm /Im0 Do Q",
+            vec![("Im0", image)],
+        );
+
+        let cover = decode(&PdfHandler.extract_cover(&path).unwrap().unwrap());
+        assert_eq!(cover.dimensions(), (64, 64));
+        assert_eq!(cover.get_pixel(10, 10).0, [255, 0, 255, 255]);
+    }
 
-        Ok(None)
+    #[test]
+    fn test_small_images_are_not_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logo.pdf");
+
+        // A tiny gray logo falls back to rendering the whole page
+        let logo = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 2,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            vec![0; 4],
+        );
+        write_pdf(
+            &path,
+            b"q 300 0 0 400 0 0 cm /Logo Do Q",
+            vec![("Logo", logo)],
+        );
+
+        let cover = decode(&PdfHandler.extract_cover(&path).unwrap().unwrap());
+        assert_eq!(cover.dimensions(), (600, COVER_HEIGHT));
+        assert_eq!(cover.get_pixel(300, 400).0, [0, 0, 0, 255]);
     }
 
-    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
-        let doc = Document::load(path).map_err(|e| AppError::Pdf(e.to_string()))?;
+    #[test]
+    fn test_decode_pdf_string() {
+        assert_eq!(decode_pdf_string(b"Plain"), "Plain");
+        // PDFDocEncoding quotes, dash and ligature
+        assert_eq!(decode_pdf_string(b"\x8Dfi\x93\x84x\x8E"), "“fiﬁ—x”");
+        assert_eq!(decode_pdf_string(b"Caf\xE9 \xA0"), "Café €");
+        assert_eq!(decode_pdf_string("Café".as_bytes()), "Café");
+        assert_eq!(
+            decode_pdf_string(b"\xFE\xFF\x00\x1Bfr\x00\x1B\x00\xC9\x00t\x00\xE9"),
+            "Été"
+        );
+    }
 
-        Ok(Some(doc.get_pages().len() as u32))
+    #[test]
+    fn test_xmp_overrides_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xmp.pdf");
+        write_pdf(&path, b"", Vec::new());
+
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+      <dc:title><rdf:Alt><rdf:li xml:lang="x-default">XMP Title</rdf:li></rdf:Alt></dc:title>
+      <dc:creator><rdf:Seq><rdf:li>First</rdf:li><rdf:li>Second</rdf:li></rdf:Seq></dc:creator>
+      <dc:language><rdf:Bag><rdf:li>fr</rdf:li></rdf:Bag></dc:language>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+        let mut doc = Document::load(&path).unwrap();
+        let metadata_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            packet.as_bytes().to_vec(),
+        ));
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal(b"Info \x8DTitle\x8E".to_vec()),
+            "Author" => Object::string_literal(b"Info Author".to_vec()),
+            "Keywords" => Object::string_literal(b"one, two".to_vec()),
+        });
+        doc.catalog_mut().unwrap().set("Metadata", metadata_id);
+        doc.trailer.set("Info", info_id);
+        doc.save(&path).unwrap();
+
+        let mut book = Book::new(path, crate::config::BookFormat::Pdf);
+        PdfHandler.extract_metadata(&mut book).unwrap();
+
+        assert_eq!(book.title, "XMP Title");
+        assert_eq!(book.authors, vec!["First", "Second"]);
+        assert_eq!(book.language.as_deref(), Some("fr"));
+        assert_eq!(book.tags, vec!["one", "two"]);
     }
 }
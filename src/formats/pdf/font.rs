@@ -0,0 +1,446 @@
+//! Font loading for page rendering: widths, encodings and embedded outlines.
+
+use super::xobject::floats;
+use lopdf::{Dictionary, Document, Object};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use tiny_skia::{Path, PathBuilder, Transform};
+
+/// Embedded font program.
+enum Program {
+    /// TrueType or OpenType (`FontFile2`, `FontFile3/OpenType`).
+    Sfnt(Vec<u8>),
+    /// Bare CFF (`FontFile3/Type1C` or `CIDFontType0C`).
+    Cff(Vec<u8>),
+}
+
+/// A PDF font resource.
+pub(super) struct PdfFont {
+    /// Two-byte codes (Type0 with Identity encoding).
+    composite: bool,
+    /// Advance widths in thousandths of a text space unit.
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+    /// Unicode value of each single-byte code, from the font encoding.
+    unicode: Vec<Option<char>>,
+    /// Glyph names from `/Differences`.
+    names: HashMap<u32, String>,
+    /// Composite font CID to glyph id map (`None` is identity).
+    cid_to_gid: Option<Vec<u16>>,
+    program: Option<Program>,
+    outlines: RefCell<HashMap<u32, Option<Path>>>,
+}
+
+impl PdfFont {
+    /// Load a font from its resource dictionary.
+    pub(super) fn load(doc: &Document, font: &Dictionary) -> Self {
+        let subtype = font
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .unwrap_or(b"");
+        if subtype == b"Type0" {
+            return Self::load_composite(doc, font);
+        }
+
+        let first_char = font
+            .get(b"FirstChar")
+            .and_then(Object::as_i64)
+            .unwrap_or(0)
+            .max(0) as u32;
+        let widths = floats(doc, font.get(b"Widths").ok())
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(i, w)| (first_char + i as u32, w))
+            .collect();
+
+        let descriptor = deref_dict(doc, font.get(b"FontDescriptor").ok());
+        let base_font = font
+            .get(b"BaseFont")
+            .and_then(Object::as_name)
+            .unwrap_or(b"");
+        let default_width = descriptor
+            .and_then(|d| d.get(b"MissingWidth").and_then(Object::as_float).ok())
+            .filter(|w| *w > 0.0)
+            .unwrap_or(if base_font.windows(7).any(|w| w == b"Courier") {
+                600.0
+            } else {
+                500.0
+            });
+
+        let unicode = match font.get_font_encoding(doc) {
+            Ok(encoding) => (0..=255u8)
+                .map(|code| {
+                    encoding
+                        .bytes_to_string(&[code])
+                        .ok()
+                        .and_then(|s| s.chars().next())
+                        .filter(|c| *c != '\u{fffd}')
+                })
+                .collect(),
+            Err(_) => (0..=255u8).map(|code| Some(code as char)).collect(),
+        };
+
+        Self {
+            composite: false,
+            widths,
+            default_width,
+            unicode,
+            names: differences(doc, font),
+            cid_to_gid: None,
+            program: descriptor.and_then(|d| program(doc, d)),
+            outlines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn load_composite(doc: &Document, font: &Dictionary) -> Self {
+        let descendant = font
+            .get(b"DescendantFonts")
+            .ok()
+            .and_then(|d| doc.dereference(d).ok())
+            .and_then(|(_, d)| d.as_array().ok())
+            .and_then(|a| deref_dict(doc, a.first()));
+
+        let mut widths = HashMap::new();
+        let mut default_width = 1000.0;
+        let mut cid_to_gid = None;
+        let mut program_data = None;
+
+        if let Some(cid_font) = descendant {
+            default_width = cid_font
+                .get(b"DW")
+                .and_then(Object::as_float)
+                .unwrap_or(1000.0);
+            widths = cid_widths(doc, cid_font.get(b"W").ok());
+
+            cid_to_gid = cid_font
+                .get(b"CIDToGIDMap")
+                .ok()
+                .and_then(|m| doc.dereference(m).ok())
+                .and_then(|(_, m)| m.as_stream().ok())
+                .and_then(|s| s.decompressed_content().ok())
+                .map(|data| {
+                    data.chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect()
+                });
+
+            program_data =
+                deref_dict(doc, cid_font.get(b"FontDescriptor").ok()).and_then(|d| program(doc, d));
+        }
+
+        Self {
+            composite: true,
+            widths,
+            default_width,
+            unicode: Vec::new(),
+            names: HashMap::new(),
+            cid_to_gid,
+            program: program_data,
+            outlines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Split a string operand into character codes.
+    pub(super) fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.composite {
+            bytes
+                .chunks(2)
+                .map(|c| u32::from(c[0]) << 8 | u32::from(c.get(1).copied().unwrap_or(0)))
+                .collect()
+        } else {
+            bytes.iter().map(|&b| u32::from(b)).collect()
+        }
+    }
+
+    /// Whether word spacing applies to the code (single-byte space).
+    pub(super) fn is_space(&self, code: u32) -> bool {
+        !self.composite && code == 32
+    }
+
+    /// Whether the code maps to whitespace (nothing to draw).
+    pub(super) fn is_blank(&self, code: u32) -> bool {
+        self.unicode
+            .get(code as usize)
+            .copied()
+            .flatten()
+            .is_some_and(char::is_whitespace)
+    }
+
+    /// Advance width in text space units (before font size scaling).
+    pub(super) fn width(&self, code: u32) -> f32 {
+        self.widths
+            .get(&code)
+            .copied()
+            .unwrap_or(self.default_width)
+            / 1000.0
+    }
+
+    /// Whether the font has an embedded program we can draw from.
+    pub(super) fn has_outlines(&self) -> bool {
+        self.program.is_some()
+    }
+
+    /// Glyph outline in text space units (em = 1), if available.
+    pub(super) fn outline(&self, code: u32) -> Option<Path> {
+        if let Some(cached) = self.outlines.borrow().get(&code) {
+            return cached.clone();
+        }
+        let path = self.build_outline(code);
+        self.outlines.borrow_mut().insert(code, path.clone());
+        path
+    }
+
+    fn build_outline(&self, code: u32) -> Option<Path> {
+        let mut builder = Outline(PathBuilder::new());
+
+        let scale = match self.program.as_ref()? {
+            Program::Sfnt(data) => {
+                let face = ttf_parser::Face::parse(data, 0).ok()?;
+                let gid = self.sfnt_glyph(&face, code)?;
+                face.outline_glyph(gid, &mut builder)?;
+                Transform::from_scale(
+                    1.0 / f32::from(face.units_per_em()),
+                    1.0 / f32::from(face.units_per_em()),
+                )
+            }
+            Program::Cff(data) => {
+                let table = ttf_parser::cff::Table::parse(data)?;
+                let gid = self.cff_glyph(&table, code)?;
+                table.outline(gid, &mut builder).ok()?;
+                let m = table.matrix();
+                Transform::from_row(m.sx, m.ky, m.kx, m.sy, m.tx, m.ty)
+            }
+        };
+
+        builder.0.finish()?.transform(scale)
+    }
+
+    /// Glyph id in a TrueType/OpenType font.
+    fn sfnt_glyph(&self, face: &ttf_parser::Face, code: u32) -> Option<ttf_parser::GlyphId> {
+        if self.composite {
+            return Some(ttf_parser::GlyphId(self.cid_gid(code)));
+        }
+
+        let subtables = face.tables().cmap.map(|c| c.subtables);
+        let lookup = |platform: ttf_parser::PlatformId, encoding: u16, c: u32| {
+            subtables.and_then(|subtables| {
+                subtables
+                    .into_iter()
+                    .filter(|s| s.platform_id == platform && s.encoding_id == encoding)
+                    .find_map(|s| s.glyph_index(c))
+            })
+        };
+        let unicode = self.unicode.get(code as usize).copied().flatten();
+
+        // Symbolic fonts map codes into the 0xF000 range of a (3,0) cmap
+        lookup(ttf_parser::PlatformId::Windows, 0, 0xF000 | code)
+            .or_else(|| lookup(ttf_parser::PlatformId::Windows, 0, code))
+            .or_else(|| unicode.and_then(|c| face.glyph_index(c)))
+            .or_else(|| lookup(ttf_parser::PlatformId::Macintosh, 0, code))
+            .or_else(|| {
+                self.names
+                    .get(&code)
+                    .and_then(|name| face.glyph_index_by_name(name))
+            })
+            // Subset fonts without a usable cmap are often code-ordered
+            .or_else(|| (face.tables().cmap.is_none()).then_some(ttf_parser::GlyphId(code as u16)))
+    }
+
+    /// Glyph id in a bare CFF font.
+    fn cff_glyph(&self, table: &ttf_parser::cff::Table, code: u32) -> Option<ttf_parser::GlyphId> {
+        if self.composite {
+            let cid = self.cid_gid(code);
+            // CID-keyed fonts map glyphs to CIDs through their charset
+            let gid = (0..table.number_of_glyphs())
+                .map(ttf_parser::GlyphId)
+                .find(|&gid| table.glyph_cid(gid) == Some(cid));
+            return gid.or(Some(ttf_parser::GlyphId(cid)));
+        }
+
+        self.names
+            .get(&code)
+            .and_then(|name| table.glyph_index_by_name(name))
+            .or_else(|| table.glyph_index(u8::try_from(code).ok()?))
+    }
+
+    /// Map a composite font CID to a glyph id.
+    fn cid_gid(&self, cid: u32) -> u16 {
+        match &self.cid_to_gid {
+            Some(map) => map.get(cid as usize).copied().unwrap_or(0),
+            None => cid as u16,
+        }
+    }
+}
+
+/// Adapter from ttf-parser outlines to a tiny-skia path.
+struct Outline(PathBuilder);
+
+impl ttf_parser::OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Resolve an optional object to a dictionary.
+fn deref_dict<'a>(doc: &'a Document, obj: Option<&'a Object>) -> Option<&'a Dictionary> {
+    doc.dereference(obj?).ok()?.1.as_dict().ok()
+}
+
+/// Read the embedded font program from a font descriptor.
+fn program(doc: &Document, descriptor: &Dictionary) -> Option<Program> {
+    let stream = |key: &[u8]| {
+        descriptor
+            .get(key)
+            .ok()
+            .and_then(|o| doc.dereference(o).ok())
+            .and_then(|(_, o)| o.as_stream().ok())
+    };
+
+    if let Some(file) = stream(b"FontFile2") {
+        return file.decompressed_content().ok().map(Program::Sfnt);
+    }
+    let file = stream(b"FontFile3")?;
+    let data = file.decompressed_content().ok()?;
+    match file.dict.get(b"Subtype").and_then(Object::as_name) {
+        Ok(b"OpenType") => Some(Program::Sfnt(data)),
+        _ => Some(Program::Cff(data)),
+    }
+}
+
+/// Glyph names from an `/Encoding` dictionary's `/Differences` array.
+fn differences(doc: &Document, font: &Dictionary) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    let Some(encoding) = deref_dict(doc, font.get(b"Encoding").ok()) else {
+        return names;
+    };
+    let Ok(entries) = encoding.get(b"Differences").and_then(Object::as_array) else {
+        return names;
+    };
+
+    let mut code = 0u32;
+    for entry in entries {
+        match entry {
+            Object::Integer(i) => code = (*i).max(0) as u32,
+            Object::Name(name) => {
+                names.insert(code, String::from_utf8_lossy(name).into_owned());
+                code += 1;
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Parse a CIDFont `/W` array: `c [w1 w2 ...]` or `cfirst clast w`.
+fn cid_widths(doc: &Document, obj: Option<&Object>) -> HashMap<u32, f32> {
+    let mut widths = HashMap::new();
+    let Some(items) = obj
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+    else {
+        return widths;
+    };
+
+    let number = |o: &Object| doc.dereference(o).ok().and_then(|(_, o)| o.as_float().ok());
+    let mut i = 0;
+    while i + 1 < items.len() {
+        let Some(first) = number(&items[i]) else {
+            break;
+        };
+        let first = first.max(0.0) as u32;
+        if let Some(list) = floats(doc, Some(&items[i + 1])) {
+            for (offset, w) in list.into_iter().enumerate() {
+                widths.insert(first + offset as u32, w);
+            }
+            i += 2;
+        } else {
+            let (Some(last), Some(w)) = (number(&items[i + 1]), items.get(i + 2).and_then(number))
+            else {
+                break;
+            };
+            // Cap ranges so a malformed array cannot exhaust memory
+            for cid in first..=(last.max(0.0) as u32).min(first + 0xFFFF) {
+                widths.insert(cid, w);
+            }
+            i += 3;
+        }
+    }
+    widths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    #[test]
+    fn test_simple_font_widths_and_codes() {
+        let mut doc = Document::with_version("1.5");
+        let widths = doc.add_object(vec![250.into(), 333.into()]);
+        let font = dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "FirstChar" => 32,
+            "Widths" => widths,
+            "Encoding" => dictionary! {
+                "Type" => "Encoding",
+                "Differences" => vec![33.into(), Object::Name(b"exclam".to_vec())],
+            },
+        };
+
+        let font = PdfFont::load(&doc, &font);
+        assert_eq!(font.codes(b" !A"), vec![32, 33, 65]);
+        assert!(font.is_space(32));
+        assert_eq!(font.width(32), 0.25);
+        assert_eq!(font.width(33), 0.333);
+        assert_eq!(font.width(65), 0.5);
+        assert_eq!(font.names.get(&33).map(String::as_str), Some("exclam"));
+        assert!(!font.has_outlines());
+        assert_eq!(font.outline(65), None);
+    }
+
+    #[test]
+    fn test_composite_font_widths() {
+        let doc = Document::with_version("1.5");
+        let font = dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "Encoding" => "Identity-H",
+            "DescendantFonts" => vec![dictionary! {
+                "Type" => "Font",
+                "Subtype" => "CIDFontType2",
+                "DW" => 900,
+                "W" => vec![
+                    1.into(), vec![100.into(), 200.into()].into(),
+                    10.into(), 12.into(), 700.into(),
+                ],
+            }.into()],
+        };
+
+        let font = PdfFont::load(&doc, &font);
+        assert_eq!(font.codes(&[0x00, 0x01, 0x00, 0x0B]), vec![1, 11]);
+        assert!(!font.is_space(32));
+        assert_eq!(font.width(1), 0.1);
+        assert_eq!(font.width(2), 0.2);
+        assert_eq!(font.width(11), 0.7);
+        assert_eq!(font.width(5), 0.9);
+    }
+}
@@ -0,0 +1,1104 @@
+//! Minimal page rasteriser, used for covers of PDFs without a cover image.
+//!
+//! Handles paths, clipping, colours, images, axial/radial shadings and text
+//! (embedded TrueType, OpenType and CFF glyphs; other fonts are drawn as
+//! greeked bars). It aims for a recognisable thumbnail, not exact output.
+
+use super::font::PdfFont;
+use super::xobject::{ColorSpace, PdfImage, dict_entry, floats};
+use image::RgbImage;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use std::rc::Rc;
+use tiny_skia::{
+    Color, FillRule, FilterQuality, GradientStop, IntSize, LineCap, LineJoin, LinearGradient, Mask,
+    Paint, Path, PathBuilder, Pixmap, PixmapPaint, Point, RadialGradient, Rect, Shader, SpreadMode,
+    Stroke, StrokeDash, Transform,
+};
+
+/// Maximum nesting of form XObjects.
+const MAX_DEPTH: u8 = 8;
+
+/// Maximum number of content stream operations executed per page.
+const MAX_OPERATIONS: usize = 500_000;
+
+/// Maximum output width, for very wide pages.
+const MAX_WIDTH: u32 = 2000;
+
+/// Number of colour stops sampled from shading functions.
+const GRADIENT_STOPS: usize = 16;
+
+/// Render a page to an opaque RGB image of the given height.
+pub(super) fn render_page(doc: &Document, page_id: ObjectId, height: u32) -> Option<RgbImage> {
+    let page = doc.get_dictionary(page_id).ok()?;
+
+    let media = page_box(doc, page, b"MediaBox").unwrap_or([0.0, 0.0, 612.0, 792.0]);
+    let [x0, y0, x1, y1] = match page_box(doc, page, b"CropBox") {
+        Some(crop) => [
+            crop[0].max(media[0]),
+            crop[1].max(media[1]),
+            crop[2].min(media[2]),
+            crop[3].min(media[3]),
+        ],
+        None => media,
+    };
+    let (box_width, box_height) = (x1 - x0, y1 - y0);
+    if box_width <= 0.0 || box_height <= 0.0 {
+        return None;
+    }
+
+    let rotate = inherited(doc, page, b"Rotate")
+        .and_then(|r| r.as_i64().ok())
+        .unwrap_or(0)
+        .rem_euclid(360)
+        / 90
+        * 90;
+    let (out_width, out_height) = if rotate % 180 == 0 {
+        (box_width, box_height)
+    } else {
+        (box_height, box_width)
+    };
+
+    let scale = height as f32 / out_height;
+    let width = ((out_width * scale).round() as u32).clamp(1, MAX_WIDTH);
+    let mut pixmap = Pixmap::new(width, height)?;
+    pixmap.fill(Color::WHITE);
+
+    // PDF user space (y up) to unrotated device space (y down), then rotate
+    let flip = Transform::from_row(scale, 0.0, 0.0, -scale, -x0 * scale, y1 * scale);
+    let (w, h) = (box_width * scale, box_height * scale);
+    let rotation = match rotate {
+        90 => Transform::from_row(0.0, 1.0, -1.0, 0.0, h, 0.0),
+        180 => Transform::from_row(-1.0, 0.0, 0.0, -1.0, w, h),
+        270 => Transform::from_row(0.0, -1.0, 1.0, 0.0, 0.0, w),
+        _ => Transform::identity(),
+    };
+    let base = rotation.pre_concat(flip);
+
+    let resources = inherited(doc, page, b"Resources")
+        .and_then(|r| doc.dereference(r).ok())
+        .and_then(|(_, r)| r.as_dict().ok());
+
+    let mut renderer = Renderer::new(doc, pixmap, base);
+    renderer.execute(&doc.get_page_content(page_id), resources, 0);
+
+    let pixmap = renderer.pixmap;
+    let data = pixmap
+        .data()
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    RgbImage::from_raw(pixmap.width(), pixmap.height(), data)
+}
+
+/// Look up a page attribute, following the page tree for inherited values.
+fn inherited<'a>(doc: &'a Document, page: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut node = page;
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// A normalised page rectangle `[x0, y0, x1, y1]`.
+fn page_box(doc: &Document, page: &Dictionary, key: &[u8]) -> Option<[f32; 4]> {
+    let r = floats(doc, inherited(doc, page, key)).filter(|r| r.len() == 4)?;
+    Some([
+        r[0].min(r[2]),
+        r[1].min(r[3]),
+        r[0].max(r[2]),
+        r[1].max(r[3]),
+    ])
+}
+
+/// What a fill or stroke paints with.
+#[derive(Clone)]
+enum Brush {
+    Color([u8; 3]),
+    Shader(Shader<'static>),
+    /// Unsupported paint (e.g. tiling patterns): nothing is drawn.
+    None,
+}
+
+/// The graphics state saved by `q` and restored by `Q`.
+#[derive(Clone)]
+struct State {
+    ctm: Transform,
+    clip: Option<Rc<Mask>>,
+    fill: Brush,
+    stroke: Brush,
+    fill_space: ColorSpace,
+    stroke_space: ColorSpace,
+    fill_alpha: f32,
+    stroke_alpha: f32,
+    line: Stroke,
+    font: Option<Rc<PdfFont>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    leading: f32,
+    rise: f32,
+    render_mode: i64,
+}
+
+impl State {
+    fn new(ctm: Transform) -> Self {
+        Self {
+            ctm,
+            clip: None,
+            fill: Brush::Color([0, 0, 0]),
+            stroke: Brush::Color([0, 0, 0]),
+            fill_space: ColorSpace::Gray,
+            stroke_space: ColorSpace::Gray,
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            line: Stroke::default(),
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+            render_mode: 0,
+        }
+    }
+}
+
+/// Content stream interpreter drawing onto a pixmap.
+struct Renderer<'a> {
+    doc: &'a Document,
+    pixmap: Pixmap,
+    base: Transform,
+    state: State,
+    stack: Vec<State>,
+    path: PathBuilder,
+    current: (f32, f32),
+    clip_rule: Option<FillRule>,
+    text_matrix: Transform,
+    line_matrix: Transform,
+    fonts: HashMap<ObjectId, Rc<PdfFont>>,
+    operations: usize,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(doc: &'a Document, pixmap: Pixmap, base: Transform) -> Self {
+        Self {
+            doc,
+            pixmap,
+            base,
+            state: State::new(base),
+            stack: Vec::new(),
+            path: PathBuilder::new(),
+            current: (0.0, 0.0),
+            clip_rule: None,
+            text_matrix: Transform::identity(),
+            line_matrix: Transform::identity(),
+            fonts: HashMap::new(),
+            operations: 0,
+        }
+    }
+
+    /// Run a content stream.
+    fn execute(&mut self, content: &[u8], resources: Option<&'a Dictionary>, depth: u8) {
+        let Ok(content) = Content::decode(content) else {
+            return;
+        };
+        for op in &content.operations {
+            self.operations += 1;
+            if self.operations > MAX_OPERATIONS {
+                return;
+            }
+            self.operation(op, resources, depth);
+        }
+    }
+
+    fn operation(&mut self, op: &Operation, resources: Option<&'a Dictionary>, depth: u8) {
+        let n = numbers(op);
+        let num = |i: usize| n.get(i).copied().unwrap_or(0.0);
+
+        match op.operator.as_str() {
+            // Graphics state
+            "q" => self.stack.push(self.state.clone()),
+            "Q" => {
+                if let Some(state) = self.stack.pop() {
+                    self.state = state;
+                }
+            }
+            "cm" => self.state.ctm = self.state.ctm.pre_concat(matrix(&n)),
+            "w" => self.state.line.width = num(0).abs(),
+            "J" => {
+                self.state.line.line_cap = match num(0) as i64 {
+                    1 => LineCap::Round,
+                    2 => LineCap::Square,
+                    _ => LineCap::Butt,
+                }
+            }
+            "j" => {
+                self.state.line.line_join = match num(0) as i64 {
+                    1 => LineJoin::Round,
+                    2 => LineJoin::Bevel,
+                    _ => LineJoin::Miter,
+                }
+            }
+            "M" => self.state.line.miter_limit = num(0).max(1.0),
+            "d" => {
+                let dashes = op
+                    .operands
+                    .first()
+                    .and_then(|a| floats(self.doc, Some(a)))
+                    .unwrap_or_default();
+                let phase = op.operands.get(1).and_then(|p| p.as_float().ok());
+                self.state.line.dash = StrokeDash::new(dashes, phase.unwrap_or(0.0));
+            }
+            "gs" => self.ext_gstate(op, resources),
+
+            // Path construction
+            "m" => {
+                self.path.move_to(num(0), num(1));
+                self.current = (num(0), num(1));
+            }
+            "l" => {
+                self.path.line_to(num(0), num(1));
+                self.current = (num(0), num(1));
+            }
+            "c" => {
+                self.path
+                    .cubic_to(num(0), num(1), num(2), num(3), num(4), num(5));
+                self.current = (num(4), num(5));
+            }
+            "v" => {
+                let (x, y) = self.current;
+                self.path.cubic_to(x, y, num(0), num(1), num(2), num(3));
+                self.current = (num(2), num(3));
+            }
+            "y" => {
+                self.path
+                    .cubic_to(num(0), num(1), num(2), num(3), num(2), num(3));
+                self.current = (num(2), num(3));
+            }
+            "h" => self.path.close(),
+            "re" => {
+                let (x, y, w, h) = (num(0), num(1), num(2), num(3));
+                self.path.move_to(x, y);
+                self.path.line_to(x + w, y);
+                self.path.line_to(x + w, y + h);
+                self.path.line_to(x, y + h);
+                self.path.close();
+                self.current = (x, y);
+            }
+
+            // Path painting and clipping
+            "S" => self.paint_path(None, true, false),
+            "s" => self.paint_path(None, true, true),
+            "f" | "F" => self.paint_path(Some(FillRule::Winding), false, false),
+            "f*" => self.paint_path(Some(FillRule::EvenOdd), false, false),
+            "B" => self.paint_path(Some(FillRule::Winding), true, false),
+            "B*" => self.paint_path(Some(FillRule::EvenOdd), true, false),
+            "b" => self.paint_path(Some(FillRule::Winding), true, true),
+            "b*" => self.paint_path(Some(FillRule::EvenOdd), true, true),
+            "n" => self.paint_path(None, false, false),
+            "W" => self.clip_rule = Some(FillRule::Winding),
+            "W*" => self.clip_rule = Some(FillRule::EvenOdd),
+
+            // Colour
+            "g" | "G" | "rg" | "RG" | "k" | "K" => {
+                let space = match op.operator.as_str() {
+                    "g" | "G" => ColorSpace::Gray,
+                    "rg" | "RG" => ColorSpace::Rgb,
+                    _ => ColorSpace::Cmyk,
+                };
+                let brush = Brush::Color(space.to_rgb(&n));
+                if op.operator.chars().all(|c| c.is_ascii_lowercase()) {
+                    self.state.fill_space = space;
+                    self.state.fill = brush;
+                } else {
+                    self.state.stroke_space = space;
+                    self.state.stroke = brush;
+                }
+            }
+            "cs" | "CS" => {
+                let space = op
+                    .operands
+                    .first()
+                    .and_then(|cs| ColorSpace::resolve(self.doc, cs, resources))
+                    .unwrap_or(ColorSpace::Gray);
+                let brush = match space {
+                    ColorSpace::Pattern => Brush::None,
+                    ref space => Brush::Color(space.to_rgb(&space.initial_color())),
+                };
+                if op.operator == "cs" {
+                    self.state.fill_space = space;
+                    self.state.fill = brush;
+                } else {
+                    self.state.stroke_space = space;
+                    self.state.stroke = brush;
+                }
+            }
+            "sc" | "scn" | "SC" | "SCN" => {
+                let fill = op.operator.starts_with('s');
+                let space = if fill {
+                    &self.state.fill_space
+                } else {
+                    &self.state.stroke_space
+                };
+                let brush = match op.operands.last() {
+                    Some(Object::Name(name)) => self.pattern(name, resources),
+                    _ => Brush::Color(space.to_rgb(&n)),
+                };
+                if fill {
+                    self.state.fill = brush;
+                } else {
+                    self.state.stroke = brush;
+                }
+            }
+            "sh" => {
+                let shader = name_operand(op)
+                    .and_then(|name| resource(self.doc, resources, b"Shading", name))
+                    .and_then(|obj| self.shading(obj, resources, self.state.ctm));
+                if let Some(shader) = shader {
+                    self.fill_all(Brush::Shader(shader));
+                }
+            }
+
+            // External objects and inline images
+            "Do" => {
+                let stream = name_operand(op)
+                    .and_then(|name| resource(self.doc, resources, b"XObject", name))
+                    .and_then(|obj| obj.as_stream().ok());
+                if let Some(stream) = stream {
+                    match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                        Ok(b"Image") => self.draw_image(stream, resources),
+                        Ok(b"Form") => self.draw_form(stream, resources, depth),
+                        _ => {}
+                    }
+                }
+            }
+            "BI" => {
+                if let Some(Object::Stream(stream)) = op.operands.first() {
+                    self.draw_image(stream, resources);
+                }
+            }
+
+            // Text
+            "BT" => {
+                self.text_matrix = Transform::identity();
+                self.line_matrix = Transform::identity();
+            }
+            "Tf" => {
+                self.state.font = name_operand(op).and_then(|name| self.font(name, resources));
+                self.state.font_size = num(0);
+            }
+            "Tc" => self.state.char_spacing = num(0),
+            "Tw" => self.state.word_spacing = num(0),
+            "Tz" => self.state.horizontal_scale = num(0) / 100.0,
+            "TL" => self.state.leading = num(0),
+            "Ts" => self.state.rise = num(0),
+            "Tr" => self.state.render_mode = num(0) as i64,
+            "Td" => self.next_line(num(0), num(1)),
+            "TD" => {
+                self.state.leading = -num(1);
+                self.next_line(num(0), num(1));
+            }
+            "Tm" => {
+                self.line_matrix = matrix(&n);
+                self.text_matrix = self.line_matrix;
+            }
+            "T*" => self.next_line(0.0, -self.state.leading),
+            "Tj" => self.show_operand(op.operands.first()),
+            "'" => {
+                self.next_line(0.0, -self.state.leading);
+                self.show_operand(op.operands.first());
+            }
+            "\"" => {
+                self.state.word_spacing = num(0);
+                self.state.char_spacing = num(1);
+                self.next_line(0.0, -self.state.leading);
+                self.show_operand(op.operands.get(2));
+            }
+            "TJ" => {
+                let items = op.operands.first().and_then(|a| a.as_array().ok());
+                for item in items.into_iter().flatten() {
+                    match item {
+                        Object::String(..) => self.show_operand(Some(item)),
+                        other => {
+                            let adjust = other.as_float().unwrap_or(0.0);
+                            let tx = -adjust / 1000.0
+                                * self.state.font_size
+                                * self.state.horizontal_scale;
+                            self.text_matrix = self
+                                .text_matrix
+                                .pre_concat(Transform::from_translate(tx, 0.0));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply an `/ExtGState` resource (line width and opacity).
+    fn ext_gstate(&mut self, op: &Operation, resources: Option<&'a Dictionary>) {
+        let Some(gs) = name_operand(op)
+            .and_then(|name| resource(self.doc, resources, b"ExtGState", name))
+            .and_then(|obj| obj.as_dict().ok())
+        else {
+            return;
+        };
+        let float = |key: &[u8]| gs.get(key).and_then(Object::as_float).ok();
+
+        if let Some(width) = float(b"LW") {
+            self.state.line.width = width.abs();
+        }
+        if let Some(alpha) = float(b"CA") {
+            self.state.stroke_alpha = alpha.clamp(0.0, 1.0);
+        }
+        if let Some(alpha) = float(b"ca") {
+            self.state.fill_alpha = alpha.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Build a paint for a brush, or `None` when nothing should be drawn.
+    fn paint(brush: &Brush, alpha: f32) -> Option<Paint<'static>> {
+        let mut paint = Paint {
+            anti_alias: true,
+            ..Default::default()
+        };
+        match brush {
+            Brush::Color([r, g, b]) => {
+                paint.set_color_rgba8(*r, *g, *b, (alpha * 255.0).round() as u8)
+            }
+            Brush::Shader(shader) => {
+                paint.shader = shader.clone();
+                paint.shader.apply_opacity(alpha);
+            }
+            Brush::None => return None,
+        }
+        Some(paint)
+    }
+
+    /// Fill and/or stroke the current path, then apply any pending clip.
+    fn paint_path(&mut self, fill: Option<FillRule>, stroke: bool, close: bool) {
+        if close {
+            self.path.close();
+        }
+        let builder = std::mem::replace(&mut self.path, PathBuilder::new());
+        let clip_rule = self.clip_rule.take();
+        let Some(path) = builder.finish() else {
+            return;
+        };
+
+        let ctm = self.state.ctm;
+        if let Some(rule) = fill
+            && let Some(paint) = Self::paint(&self.state.fill, self.state.fill_alpha)
+        {
+            self.pixmap
+                .fill_path(&path, &paint, rule, ctm, self.state.clip.as_deref());
+        }
+        if stroke && let Some(paint) = Self::paint(&self.state.stroke, self.state.stroke_alpha) {
+            self.pixmap.stroke_path(
+                &path,
+                &paint,
+                &self.state.line,
+                ctm,
+                self.state.clip.as_deref(),
+            );
+        }
+        if let Some(rule) = clip_rule {
+            self.clip(&path, rule, ctm);
+        }
+    }
+
+    /// Intersect the clipping region with a path.
+    fn clip(&mut self, path: &Path, rule: FillRule, transform: Transform) {
+        let mask = match &self.state.clip {
+            Some(mask) => {
+                let mut mask = Mask::clone(mask);
+                mask.intersect_path(path, rule, true, transform);
+                mask
+            }
+            None => {
+                let Some(mut mask) = Mask::new(self.pixmap.width(), self.pixmap.height()) else {
+                    return;
+                };
+                mask.fill_path(path, rule, true, transform);
+                mask
+            }
+        };
+        self.state.clip = Some(Rc::new(mask));
+    }
+
+    /// Paint the whole clipping region (the `sh` operator).
+    fn fill_all(&mut self, brush: Brush) {
+        let rect = Rect::from_xywh(
+            0.0,
+            0.0,
+            self.pixmap.width() as f32,
+            self.pixmap.height() as f32,
+        );
+        if let (Some(rect), Some(paint)) = (rect, Self::paint(&brush, self.state.fill_alpha)) {
+            self.pixmap.fill_rect(
+                rect,
+                &paint,
+                Transform::identity(),
+                self.state.clip.as_deref(),
+            );
+        }
+    }
+
+    /// Brush for a pattern resource (shading patterns only).
+    fn pattern(&self, name: &[u8], resources: Option<&'a Dictionary>) -> Brush {
+        let Some(pattern) = resource(self.doc, resources, b"Pattern", name) else {
+            return Brush::None;
+        };
+        let dict = match pattern {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &stream.dict,
+            _ => return Brush::None,
+        };
+        if dict.get(b"PatternType").and_then(Object::as_i64).ok() != Some(2) {
+            return Brush::None;
+        }
+
+        // Pattern space is the page's default space, not the current CTM
+        let m = floats(self.doc, dict.get(b"Matrix").ok()).unwrap_or_default();
+        let transform = self.base.pre_concat(matrix(&m));
+        dict.get(b"Shading")
+            .ok()
+            .and_then(|s| self.shading(s, resources, transform))
+            .map_or(Brush::None, Brush::Shader)
+    }
+
+    /// Build a gradient shader from an axial or radial shading.
+    fn shading(
+        &self,
+        obj: &Object,
+        resources: Option<&'a Dictionary>,
+        transform: Transform,
+    ) -> Option<Shader<'static>> {
+        let (_, obj) = self.doc.dereference(obj).ok()?;
+        let dict = match obj {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &stream.dict,
+            _ => return None,
+        };
+
+        let space = ColorSpace::resolve(self.doc, dict.get(b"ColorSpace").ok()?, resources)?;
+        let coords = floats(self.doc, dict.get(b"Coords").ok())?;
+        let domain = floats(self.doc, dict.get(b"Domain").ok())
+            .filter(|d| d.len() == 2)
+            .unwrap_or(vec![0.0, 1.0]);
+        let function = dict.get(b"Function").ok()?;
+
+        let stops = (0..GRADIENT_STOPS)
+            .map(|i| {
+                let pos = i as f32 / (GRADIENT_STOPS - 1) as f32;
+                let t = domain[0] + pos * (domain[1] - domain[0]);
+                let [r, g, b] = space.to_rgb(&evaluate(self.doc, function, t, 0)?);
+                Some(GradientStop::new(pos, Color::from_rgba8(r, g, b, 255)))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        match dict.get(b"ShadingType").and_then(Object::as_i64) {
+            Ok(2) if coords.len() == 4 => LinearGradient::new(
+                Point::from_xy(coords[0], coords[1]),
+                Point::from_xy(coords[2], coords[3]),
+                stops,
+                SpreadMode::Pad,
+                transform,
+            ),
+            // Approximated as a focal gradient towards the outer circle
+            Ok(3) if coords.len() == 6 => RadialGradient::new(
+                Point::from_xy(coords[0], coords[1]),
+                Point::from_xy(coords[3], coords[4]),
+                coords[5],
+                stops,
+                SpreadMode::Pad,
+                transform,
+            ),
+            _ => None,
+        }
+    }
+
+    /// Draw an image XObject or inline image into the unit square.
+    fn draw_image(&mut self, stream: &Stream, resources: Option<&'a Dictionary>) {
+        let Some(image) = PdfImage::new(stream) else {
+            return;
+        };
+
+        let rgba = if image.is_stencil() {
+            let Brush::Color([r, g, b]) = self.state.fill else {
+                return;
+            };
+            let Some(mask) = image.decode_stencil() else {
+                return;
+            };
+            image::RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+                image::Rgba([r, g, b, mask.get_pixel(x, y).0[0]])
+            })
+        } else {
+            match image.decode(self.doc, resources) {
+                Some(rgba) => rgba,
+                None => return,
+            }
+        };
+
+        // Downscale large images first: the pixmap filter does not mipmap
+        let ctm = self.state.ctm;
+        let target_w = (ctm.sx.hypot(ctm.ky).ceil() as u32).max(1);
+        let target_h = (ctm.kx.hypot(ctm.sy).ceil() as u32).max(1);
+        let rgba = if rgba.width() > target_w * 2 || rgba.height() > target_h * 2 {
+            image::imageops::resize(
+                &rgba,
+                target_w.min(rgba.width()),
+                target_h.min(rgba.height()),
+                image::imageops::FilterType::Triangle,
+            )
+        } else {
+            rgba
+        };
+
+        let (width, height) = rgba.dimensions();
+        let premultiplied = rgba
+            .pixels()
+            .flat_map(|p| {
+                let [r, g, b, a] = p.0;
+                let m = |c: u8| ((u16::from(c) * u16::from(a) + 127) / 255) as u8;
+                [m(r), m(g), m(b), a]
+            })
+            .collect();
+        let Some(pixmap) =
+            IntSize::from_wh(width, height).and_then(|size| Pixmap::from_vec(premultiplied, size))
+        else {
+            return;
+        };
+
+        let paint = PixmapPaint {
+            opacity: self.state.fill_alpha,
+            quality: FilterQuality::Bilinear,
+            ..Default::default()
+        };
+        // Image space has its origin at the top-left corner of the unit square
+        let transform = ctm.pre_concat(Transform::from_row(
+            1.0 / width as f32,
+            0.0,
+            0.0,
+            -1.0 / height as f32,
+            0.0,
+            1.0,
+        ));
+        self.pixmap.draw_pixmap(
+            0,
+            0,
+            pixmap.as_ref(),
+            &paint,
+            transform,
+            self.state.clip.as_deref(),
+        );
+    }
+
+    /// Run a form XObject in its own graphics state.
+    fn draw_form(&mut self, stream: &'a Stream, resources: Option<&'a Dictionary>, depth: u8) {
+        if depth >= MAX_DEPTH {
+            return;
+        }
+        let Ok(content) = stream.decompressed_content() else {
+            return;
+        };
+
+        let saved = self.state.clone();
+        let stack_len = self.stack.len();
+
+        let m = floats(self.doc, stream.dict.get(b"Matrix").ok()).unwrap_or_default();
+        self.state.ctm = self.state.ctm.pre_concat(matrix(&m));
+        if let Some(bbox) = floats(self.doc, stream.dict.get(b"BBox").ok()).filter(|b| b.len() == 4)
+            && let Some(rect) = Rect::from_ltrb(
+                bbox[0].min(bbox[2]),
+                bbox[1].min(bbox[3]),
+                bbox[0].max(bbox[2]),
+                bbox[1].max(bbox[3]),
+            )
+        {
+            self.clip(
+                &PathBuilder::from_rect(rect),
+                FillRule::Winding,
+                self.state.ctm,
+            );
+        }
+
+        let form_resources = dict_entry(self.doc, &stream.dict, b"Resources").or(resources);
+        self.path = PathBuilder::new();
+        self.execute(&content, form_resources, depth + 1);
+
+        self.stack.truncate(stack_len);
+        self.state = saved;
+        self.path = PathBuilder::new();
+        self.clip_rule = None;
+    }
+
+    /// Load (or reuse) a font resource.
+    fn font(&mut self, name: &[u8], resources: Option<&'a Dictionary>) -> Option<Rc<PdfFont>> {
+        let fonts = resources.and_then(|r| dict_entry(self.doc, r, b"Font"))?;
+        let entry = fonts.get(name).ok()?;
+        let (id, obj) = self.doc.dereference(entry).ok()?;
+        let dict = obj.as_dict().ok()?;
+
+        match id {
+            Some(id) => Some(
+                self.fonts
+                    .entry(id)
+                    .or_insert_with(|| Rc::new(PdfFont::load(self.doc, dict)))
+                    .clone(),
+            ),
+            None => Some(Rc::new(PdfFont::load(self.doc, dict))),
+        }
+    }
+
+    /// Move to the start of the next line, offset from the current one.
+    fn next_line(&mut self, tx: f32, ty: f32) {
+        self.line_matrix = self
+            .line_matrix
+            .pre_concat(Transform::from_translate(tx, ty));
+        self.text_matrix = self.line_matrix;
+    }
+
+    fn show_operand(&mut self, operand: Option<&Object>) {
+        if let Some(Object::String(bytes, _)) = operand {
+            self.show(bytes);
+        }
+    }
+
+    /// Draw a string and advance the text matrix.
+    fn show(&mut self, bytes: &[u8]) {
+        let Some(font) = self.state.font.clone() else {
+            return;
+        };
+        let size = self.state.font_size;
+        let scale = self.state.horizontal_scale;
+        // Modes 3 and 7 are invisible (text used only for search or clipping)
+        let visible = !matches!(self.state.render_mode, 3 | 7);
+        let fill = matches!(self.state.render_mode, 0 | 2 | 4 | 6);
+        let stroke = matches!(self.state.render_mode, 1 | 2 | 5 | 6);
+
+        for code in font.codes(bytes) {
+            let advance = font.width(code);
+
+            if visible {
+                let glyph =
+                    self.state
+                        .ctm
+                        .pre_concat(self.text_matrix)
+                        .pre_concat(Transform::from_row(
+                            size * scale,
+                            0.0,
+                            0.0,
+                            size,
+                            0.0,
+                            self.state.rise,
+                        ));
+                if font.has_outlines() {
+                    if let Some(path) = font.outline(code) {
+                        self.draw_glyph(&path, glyph, fill, stroke);
+                    }
+                } else if !font.is_blank(code) {
+                    // Greeked text: an x-height bar across the glyph advance
+                    let bar = Rect::from_ltrb(advance * 0.08, 0.0, advance * 0.92, 0.5)
+                        .map(PathBuilder::from_rect);
+                    if let (Some(bar), Some(paint)) = (
+                        bar,
+                        Self::paint(&self.state.fill, self.state.fill_alpha * 0.6),
+                    ) {
+                        self.pixmap.fill_path(
+                            &bar,
+                            &paint,
+                            FillRule::Winding,
+                            glyph,
+                            self.state.clip.as_deref(),
+                        );
+                    }
+                }
+            }
+
+            let mut tx = advance * size + self.state.char_spacing;
+            if font.is_space(code) {
+                tx += self.state.word_spacing;
+            }
+            self.text_matrix = self
+                .text_matrix
+                .pre_concat(Transform::from_translate(tx * scale, 0.0));
+        }
+    }
+
+    fn draw_glyph(&mut self, path: &Path, transform: Transform, fill: bool, stroke: bool) {
+        let clip = self.state.clip.as_deref();
+        if fill && let Some(paint) = Self::paint(&self.state.fill, self.state.fill_alpha) {
+            self.pixmap
+                .fill_path(path, &paint, FillRule::Winding, transform, clip);
+        }
+        if stroke && let Some(paint) = Self::paint(&self.state.stroke, self.state.stroke_alpha) {
+            // Line width is in user space, glyph outlines in text space
+            let mut line = self.state.line.clone();
+            line.width /= self.state.font_size.abs().max(f32::EPSILON);
+            self.pixmap
+                .stroke_path(path, &paint, &line, transform, clip);
+        }
+    }
+}
+
+/// Numeric operands of an operation.
+fn numbers(op: &Operation) -> Vec<f32> {
+    op.operands
+        .iter()
+        .filter_map(|o| o.as_float().ok())
+        .collect()
+}
+
+/// Name operand of an operation (the first one).
+fn name_operand(op: &Operation) -> Option<&[u8]> {
+    op.operands.iter().find_map(|o| o.as_name().ok())
+}
+
+/// A PDF matrix `[a b c d e f]` (identity when malformed).
+fn matrix(m: &[f32]) -> Transform {
+    match m {
+        [a, b, c, d, e, f] => Transform::from_row(*a, *b, *c, *d, *e, *f),
+        _ => Transform::identity(),
+    }
+}
+
+/// Look up a named resource of the given category.
+fn resource<'a>(
+    doc: &'a Document,
+    resources: Option<&'a Dictionary>,
+    category: &[u8],
+    name: &[u8],
+) -> Option<&'a Object> {
+    let entries = dict_entry(doc, resources?, category)?;
+    doc.dereference(entries.get(name).ok()?)
+        .ok()
+        .map(|(_, o)| o)
+}
+
+/// Evaluate a PDF function (sampled, exponential or stitching) at `t`.
+fn evaluate(doc: &Document, obj: &Object, t: f32, depth: u8) -> Option<Vec<f32>> {
+    if depth > 8 {
+        return None;
+    }
+    let (_, obj) = doc.dereference(obj).ok()?;
+
+    // An array of single-output functions, one per colour component
+    if let Object::Array(functions) = obj {
+        return functions
+            .iter()
+            .map(|f| evaluate(doc, f, t, depth + 1))
+            .collect::<Option<Vec<_>>>()
+            .map(|outputs| outputs.concat());
+    }
+
+    let (dict, stream) = match obj {
+        Object::Dictionary(dict) => (dict, None),
+        Object::Stream(stream) => (&stream.dict, Some(stream)),
+        _ => return None,
+    };
+    let array = |key: &[u8]| floats(doc, dict.get(key).ok());
+    let domain = array(b"Domain")
+        .filter(|d| d.len() >= 2)
+        .unwrap_or(vec![0.0, 1.0]);
+    let t = t.clamp(domain[0].min(domain[1]), domain[0].max(domain[1]));
+    let interpolate = |x: f32, x0: f32, x1: f32, y0: f32, y1: f32| {
+        if (x1 - x0).abs() < f32::EPSILON {
+            y0
+        } else {
+            y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+        }
+    };
+
+    match dict.get(b"FunctionType").and_then(Object::as_i64).ok()? {
+        // Sampled (one input, nearest sample)
+        0 => {
+            let data = stream?.decompressed_content().ok()?;
+            let size = array(b"Size")?.first().copied()?.max(1.0) as usize;
+            let range = array(b"Range")?;
+            let outputs = range.len() / 2;
+            let bits = dict.get(b"BitsPerSample").and_then(Object::as_i64).ok()? as usize;
+            if outputs == 0 || !matches!(bits, 1 | 2 | 4 | 8 | 12 | 16 | 24 | 32) {
+                return None;
+            }
+            // Arrays too short to use fall back to their defaults
+            let encode = array(b"Encode")
+                .filter(|e| e.len() >= 2)
+                .unwrap_or(vec![0.0, (size - 1) as f32]);
+            let decode = array(b"Decode")
+                .filter(|d| d.len() >= range.len())
+                .unwrap_or_else(|| range.clone());
+
+            let e = interpolate(t, domain[0], domain[1], encode[0], encode[1]);
+            let index = (e.round().max(0.0) as usize).min(size - 1);
+            let max = ((1u64 << bits) - 1) as f32;
+            (0..outputs)
+                .map(|j| {
+                    let start = (index * outputs + j) * bits;
+                    let mut value = 0u64;
+                    for bit in start..start + bits {
+                        let byte = *data.get(bit / 8)?;
+                        value = value << 1 | u64::from((byte >> (7 - bit % 8)) & 1);
+                    }
+                    let (d0, d1) = (*decode.get(2 * j)?, *decode.get(2 * j + 1)?);
+                    let v = interpolate(value as f32, 0.0, max, d0, d1);
+                    // Reversed ranges must not panic in `clamp`
+                    let (lo, hi) = (*range.get(2 * j)?, *range.get(2 * j + 1)?);
+                    Some(v.max(lo.min(hi)).min(lo.max(hi)))
+                })
+                .collect()
+        }
+        // Exponential interpolation
+        2 => {
+            let c0 = array(b"C0").unwrap_or(vec![0.0]);
+            let c1 = array(b"C1").unwrap_or(vec![1.0]);
+            let exponent = dict.get(b"N").and_then(Object::as_float).unwrap_or(1.0);
+            let x = t.powf(exponent);
+            Some(c0.iter().zip(&c1).map(|(a, b)| a + x * (b - a)).collect())
+        }
+        // Stitching
+        3 => {
+            let functions = dict
+                .get(b"Functions")
+                .ok()
+                .and_then(|f| doc.dereference(f).ok())
+                .and_then(|(_, f)| f.as_array().ok())?;
+            let bounds = array(b"Bounds").unwrap_or_default();
+            let encode = array(b"Encode")?;
+
+            let k = bounds.iter().take_while(|&&b| t >= b).count();
+            let k = k.min(functions.len().checked_sub(1)?);
+            let lo = if k == 0 { domain[0] } else { bounds[k - 1] };
+            let hi = bounds.get(k).copied().unwrap_or(domain[1]);
+            let t = interpolate(t, lo, hi, *encode.get(2 * k)?, *encode.get(2 * k + 1)?);
+            evaluate(doc, &functions[k], t, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    /// Build a one-page document with the given content and resources.
+    fn document(content: &str, resources: Dictionary, rotate: i64) -> (Document, ObjectId) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.as_bytes().to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => resources,
+            "Rotate" => rotate,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), 100.into(), 200.into()],
+            }),
+        );
+        (doc, page_id)
+    }
+
+    #[test]
+    fn test_render_paths_and_clip() {
+        // Red top half, clipped blue square bottom-left
+        let content = "1 0 0 rg 0 100 100 100 re f \
+                       q 0 0 50 50 re W n 0 0 1 rg 0 0 100 100 re f Q";
+        let (doc, page_id) = document(content, dictionary! {}, 0);
+
+        let img = render_page(&doc, page_id, 200).unwrap();
+        assert_eq!(img.dimensions(), (100, 200));
+        assert_eq!(img.get_pixel(50, 50).0, [255, 0, 0]);
+        assert_eq!(img.get_pixel(25, 175).0, [0, 0, 255]);
+        assert_eq!(img.get_pixel(75, 175).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_render_rotated_page() {
+        let (doc, page_id) = document("1 0 0 rg 0 100 100 100 re f", dictionary! {}, 90);
+
+        // Rotated clockwise: the top half becomes the right half
+        let img = render_page(&doc, page_id, 100).unwrap();
+        assert_eq!(img.dimensions(), (200, 100));
+        assert_eq!(img.get_pixel(150, 50).0, [255, 0, 0]);
+        assert_eq!(img.get_pixel(50, 50).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_render_shading_and_greeked_text() {
+        let resources = dictionary! {
+            "Shading" => dictionary! {
+                "Sh0" => dictionary! {
+                    "ShadingType" => 2,
+                    "ColorSpace" => "DeviceRGB",
+                    "Coords" => vec![0.into(), 0.into(), 100.into(), 0.into()],
+                    "Function" => dictionary! {
+                        "FunctionType" => 2,
+                        "Domain" => vec![0.into(), 1.into()],
+                        "C0" => vec![1.into(), 0.into(), 0.into()],
+                        "C1" => vec![0.into(), 0.into(), 1.into()],
+                        "N" => 1,
+                    },
+                },
+            },
+            "Font" => dictionary! {
+                "F1" => dictionary! {
+                    "Type" => "Font",
+                    "Subtype" => "Type1",
+                    "BaseFont" => "Helvetica",
+                },
+            },
+        };
+        let content = "q 0 100 100 100 re W n /Sh0 sh Q \
+                       BT /F1 40 Tf 0 0 0 rg 10 20 Td (HI) Tj ET";
+        let (doc, page_id) = document(content, resources, 0);
+
+        let img = render_page(&doc, page_id, 200).unwrap();
+        let left = img.get_pixel(2, 50).0;
+        let right = img.get_pixel(97, 50).0;
+        assert!(left[0] > 200 && left[2] < 50, "left is red: {left:?}");
+        assert!(right[2] > 200 && right[0] < 50, "right is blue: {right:?}");
+
+        // Greeked glyph bars sit on the baseline
+        let text = img.get_pixel(20, 200 - 25).0;
+        assert!(text[0] < 200, "text is drawn: {text:?}");
+        assert_eq!(img.get_pixel(20, 200 - 60).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_sampled_function_malformed_arrays() {
+        let doc = Document::with_version("1.5");
+        let function = Object::Stream(Stream::new(
+            dictionary! {
+                "FunctionType" => 0,
+                "Domain" => vec![0.into(), 1.into()],
+                "Size" => vec![2.into()],
+                "BitsPerSample" => 8,
+                "Encode" => Vec::<Object>::new(),
+                "Decode" => vec![0.into()],
+                "Range" => vec![1.into(), 0.into()],
+            },
+            vec![0, 255],
+        ));
+
+        // The short Decode falls back to the reversed Range: 255 maps to 0
+        assert_eq!(evaluate(&doc, &function, 1.0, 0), Some(vec![0.0]));
+        assert_eq!(evaluate(&doc, &function, 0.0, 0), Some(vec![1.0]));
+    }
+}
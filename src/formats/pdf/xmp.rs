@@ -0,0 +1,347 @@
+//! XMP metadata packets (the catalog's `/Metadata` stream).
+
+use crate::error::Result;
+use crate::formats::epub::parse_identifier;
+use crate::library::book::{Book, Identifier};
+use roxmltree::{Document, Node};
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const DC: &str = "http://purl.org/dc/elements/1.1/";
+const PDF: &str = "http://ns.adobe.com/pdf/1.3/";
+const XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const PDFX: &str = "http://ns.adobe.com/pdfx/1.3/";
+const PRISM: &str = "http://prismstandard.org/namespaces/";
+const CALIBRE: &str = "http://calibre-ebook.com/xmp-namespace";
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Metadata read from an XMP packet.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct Xmp {
+    title: Option<String>,
+    authors: Vec<String>,
+    description: Option<String>,
+    subjects: Vec<String>,
+    keywords: Option<String>,
+    language: Option<String>,
+    publisher: Option<String>,
+    published: Option<String>,
+    identifiers: Vec<Identifier>,
+    series: Option<String>,
+    series_index: Option<f32>,
+}
+
+impl Xmp {
+    /// Parse an XMP packet (UTF-8 or UTF-16 with BOM).
+    pub(super) fn parse(data: &[u8]) -> Result<Self> {
+        let encoding = encoding_rs::Encoding::for_bom(data).map_or(encoding_rs::UTF_8, |(e, _)| e);
+        let content = encoding.decode(data).0;
+        let doc = Document::parse(&content)?;
+
+        let mut xmp = Xmp::default();
+        for description in doc
+            .descendants()
+            .filter(|n| n.is_element() && n.has_tag_name((RDF, "Description")))
+        {
+            // Simple properties may be written as attributes
+            for attr in description.attributes() {
+                if let Some(ns) = attr.namespace() {
+                    xmp.property(ns, attr.name(), vec![attr.value().trim().to_string()], None);
+                }
+            }
+            for property in description.children().filter(Node::is_element) {
+                let Some(ns) = property.tag_name().namespace() else {
+                    continue;
+                };
+                xmp.property(
+                    ns,
+                    property.tag_name().name(),
+                    values(property),
+                    Some(property),
+                );
+            }
+        }
+
+        Ok(xmp)
+    }
+
+    /// Record a property value (`node` is absent for attribute values).
+    fn property(&mut self, ns: &str, name: &str, values: Vec<String>, node: Option<Node>) {
+        let values: Vec<String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+        let first = values.first().cloned();
+
+        match (ns, name) {
+            (DC, "title") => self.title = first,
+            (DC, "creator") => self.authors = values,
+            (DC, "description") => self.description = first,
+            (DC, "subject") => self.subjects = values,
+            (DC, "language") => self.language = first,
+            (DC, "publisher") => self.publisher = first,
+            (DC, "date") => self.published = first.map(|d| date(&d)),
+            (DC, "identifier") => {
+                for value in &values {
+                    self.add_identifier(value, None);
+                }
+            }
+            (PDF, "Keywords") => self.keywords = first,
+            (XMP, "Identifier") => {
+                for item in node.into_iter().flat_map(list_items) {
+                    let scheme = item
+                        .children()
+                        .find(|n| n.is_element() && n.tag_name().name() == "Scheme")
+                        .and_then(|n| n.text())
+                        .map(str::to_string);
+                    if let Some(value) = item_value(item) {
+                        self.add_identifier(&value, scheme);
+                    }
+                }
+            }
+            (PDFX, "ISBN") => {
+                if let Some(value) = first {
+                    self.add_identifier(&value, Some("isbn".to_string()));
+                }
+            }
+            (CALIBRE, "series") => {
+                // <calibre:series rdf:parseType="Resource">
+                //   <rdf:value>Name</rdf:value><calibreSI:series_index>2</calibreSI:series_index>
+                let index = node.and_then(|n| {
+                    n.descendants()
+                        .find(|d| d.is_element() && d.tag_name().name() == "series_index")
+                        .and_then(|d| d.text())
+                        .and_then(|t| t.trim().parse().ok())
+                });
+                self.series = node.and_then(item_value).or(first);
+                self.series_index = index;
+            }
+            (ns, name) if ns.starts_with(PRISM) => match name {
+                "isbn" | "eIsbn" => {
+                    if let Some(value) = first {
+                        self.add_identifier(&value, Some("isbn".to_string()));
+                    }
+                }
+                "doi" => {
+                    if let Some(value) = first {
+                        self.add_identifier(&value, Some("doi".to_string()));
+                    }
+                }
+                "publicationDate" | "coverDate" if self.published.is_none() => {
+                    self.published = first.map(|d| date(&d));
+                }
+                "publicationName" if self.series.is_none() => self.series = first,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn add_identifier(&mut self, value: &str, scheme: Option<String>) {
+        if let Some(id) = parse_identifier(value, scheme)
+            && !self.identifiers.contains(&id)
+        {
+            self.identifiers.push(id);
+        }
+    }
+
+    /// Copy the metadata into a book, overriding Info dictionary values.
+    pub(super) fn apply(&self, book: &mut Book) {
+        if let Some(title) = &self.title {
+            book.title = title.clone();
+        }
+        if !self.authors.is_empty() {
+            book.authors = self.authors.clone();
+        }
+        if self.description.is_some() {
+            book.description = self.description.clone();
+        }
+
+        let tags: Vec<String> = if self.subjects.is_empty() {
+            self.keywords
+                .iter()
+                .flat_map(|k| k.split([',', ';']))
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        } else {
+            self.subjects.clone()
+        };
+        if !tags.is_empty() {
+            book.tags = tags;
+        }
+
+        if self.language.is_some() {
+            book.language = self.language.clone();
+        }
+        if self.publisher.is_some() {
+            book.publisher = self.publisher.clone();
+        }
+        if self.published.is_some() {
+            book.published = self.published.clone();
+        }
+        if self.series.is_some() {
+            book.series = self.series.clone();
+            book.series_index = self.series_index;
+        }
+
+        for id in &self.identifiers {
+            if !book.identifiers.contains(id) {
+                book.identifiers.push(id.clone());
+            }
+        }
+        if let Some(isbn) = self.identifiers.iter().find(|id| id.scheme == "isbn") {
+            book.isbn = Some(isbn.value.clone());
+        }
+    }
+}
+
+/// Items of an `rdf:Seq`, `rdf:Bag` or `rdf:Alt` container.
+fn list_items<'a, 'input>(property: Node<'a, 'input>) -> Vec<Node<'a, 'input>> {
+    let Some(container) = property.children().find(|n| {
+        n.is_element()
+            && n.tag_name().namespace() == Some(RDF)
+            && matches!(n.tag_name().name(), "Seq" | "Bag" | "Alt")
+    }) else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<Node> = container
+        .children()
+        .filter(|n| n.is_element() && n.has_tag_name((RDF, "li")))
+        .collect();
+    // The x-default entry of a language alternative comes first
+    items.sort_by_key(|n| n.attribute((XML, "lang")) != Some("x-default"));
+    items
+}
+
+/// Text of a simple value, or the `rdf:value` of a structured one.
+fn item_value(node: Node) -> Option<String> {
+    let value = node
+        .children()
+        .find(|n| n.is_element() && n.has_tag_name((RDF, "value")))
+        .and_then(|n| n.text())
+        .or_else(|| node.attribute((RDF, "value")))
+        .or_else(|| {
+            node.children()
+                .all(|n| !n.is_element())
+                .then(|| node.text())
+                .flatten()
+        })?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// All values of a property (container items or a single value).
+fn values(property: Node) -> Vec<String> {
+    let items = list_items(property);
+    if items.is_empty() {
+        item_value(property).into_iter().collect()
+    } else {
+        items.into_iter().filter_map(item_value).collect()
+    }
+}
+
+/// Keep the date part of an XMP timestamp ("2019-05-01T10:00:00Z").
+fn date(value: &str) -> String {
+    value.split('T').next().unwrap_or(value).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: &str = r#"<?xpacket begin="﻿" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about=""
+        xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmlns:pdf="http://ns.adobe.com/pdf/1.3/"
+        xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+        xmlns:xmpidq="http://ns.adobe.com/xmp/Identifier/qual/1.0/"
+        xmlns:prism="http://prismstandard.org/namespaces/basic/2.0/"
+        xmlns:calibre="http://calibre-ebook.com/xmp-namespace"
+        xmlns:calibreSI="http://calibre-ebook.com/xmp-namespace-series-index"
+        pdf:Keywords="ignored, because, subjects"
+        prism:publicationDate="2001-01-01">
+      <dc:title>
+        <rdf:Alt>
+          <rdf:li xml:lang="fr">Le Titre</rdf:li>
+          <rdf:li xml:lang="x-default">The Title</rdf:li>
+        </rdf:Alt>
+      </dc:title>
+      <dc:creator><rdf:Seq><rdf:li>Ann Author</rdf:li><rdf:li>Bob Writer</rdf:li></rdf:Seq></dc:creator>
+      <dc:subject><rdf:Bag><rdf:li>Fiction</rdf:li><rdf:li>Space</rdf:li></rdf:Bag></dc:subject>
+      <dc:description><rdf:Alt><rdf:li xml:lang="x-default">A story.</rdf:li></rdf:Alt></dc:description>
+      <dc:language><rdf:Bag><rdf:li>en</rdf:li></rdf:Bag></dc:language>
+      <dc:publisher><rdf:Bag><rdf:li>Big House</rdf:li></rdf:Bag></dc:publisher>
+      <dc:date><rdf:Seq><rdf:li>2019-05-01T10:00:00+02:00</rdf:li></rdf:Seq></dc:date>
+      <xmp:Identifier>
+        <rdf:Bag>
+          <rdf:li rdf:parseType="Resource">
+            <xmpidq:Scheme>ISBN</xmpidq:Scheme>
+            <rdf:value>978-0-306-40615-7</rdf:value>
+          </rdf:li>
+        </rdf:Bag>
+      </xmp:Identifier>
+      <prism:doi>10.1000/182</prism:doi>
+      <calibre:series rdf:parseType="Resource">
+        <rdf:value>The Saga</rdf:value>
+        <calibreSI:series_index>2.0</calibreSI:series_index>
+      </calibre:series>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    #[test]
+    fn test_parse_and_apply() {
+        let xmp = Xmp::parse(PACKET.as_bytes()).unwrap();
+
+        let mut book = Book {
+            title: "Info Title".to_string(),
+            authors: vec!["Info Author".to_string()],
+            publisher: Some("Producer".to_string()),
+            ..Default::default()
+        };
+        xmp.apply(&mut book);
+
+        assert_eq!(book.title, "The Title");
+        assert_eq!(book.authors, vec!["Ann Author", "Bob Writer"]);
+        assert_eq!(book.tags, vec!["Fiction", "Space"]);
+        assert_eq!(book.description.as_deref(), Some("A story."));
+        assert_eq!(book.language.as_deref(), Some("en"));
+        assert_eq!(book.publisher.as_deref(), Some("Big House"));
+        assert_eq!(book.published.as_deref(), Some("2019-05-01"));
+        assert_eq!(book.series.as_deref(), Some("The Saga"));
+        assert_eq!(book.series_index, Some(2.0));
+        assert_eq!(book.isbn.as_deref(), Some("9780306406157"));
+        let urns: Vec<String> = book.identifiers.iter().map(Identifier::urn).collect();
+        assert_eq!(urns, vec!["urn:isbn:9780306406157", "urn:doi:10.1000/182"]);
+    }
+
+    #[test]
+    fn test_sparse_packet_keeps_info() {
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:pdf="http://ns.adobe.com/pdf/1.3/"
+        xmlns:prism="http://prismstandard.org/namespaces/basic/3.0/">
+      <pdf:Keywords>rust; pdf</pdf:Keywords>
+      <prism:isbn>0306406152</prism:isbn>
+      <prism:coverDate>2020-02</prism:coverDate>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+        let xmp = Xmp::parse(packet.as_bytes()).unwrap();
+
+        let mut book = Book {
+            title: "Info Title".to_string(),
+            authors: vec!["Info Author".to_string()],
+            ..Default::default()
+        };
+        xmp.apply(&mut book);
+
+        assert_eq!(book.title, "Info Title");
+        assert_eq!(book.authors, vec!["Info Author"]);
+        assert_eq!(book.tags, vec!["rust", "pdf"]);
+        assert_eq!(book.published.as_deref(), Some("2020-02"));
+        assert_eq!(book.isbn.as_deref(), Some("0306406152"));
+        assert_eq!(book.series, None);
+    }
+}
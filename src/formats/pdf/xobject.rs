@@ -0,0 +1,637 @@
+//! Colour spaces and image XObject decoding.
+
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use lopdf::{Dictionary, Document, Object, Stream};
+
+/// Upper bound on decoded image data (guards against decompression bombs).
+const MAX_IMAGE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Upper bound on image size in pixels.
+const MAX_IMAGE_PIXELS: u64 = 40_000_000;
+
+/// Maximum number of colour components handled (DeviceN is capped).
+const MAX_COMPONENTS: usize = 8;
+
+/// A resolved PDF colour space.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// CIE L*a*b* with its white point and a*/b* ranges.
+    Lab {
+        white: [f32; 3],
+        range: [f32; 4],
+    },
+    /// Palette lookup into a base colour space.
+    Indexed {
+        base: Box<ColorSpace>,
+        hival: u32,
+        lookup: Vec<u8>,
+    },
+    /// Separation or DeviceN, approximated as a darkening tint.
+    Tint(usize),
+    /// Pattern colour space (no colour components of its own).
+    Pattern,
+}
+
+impl ColorSpace {
+    /// Resolve a colour space object, looking up named spaces in `resources`.
+    pub(super) fn resolve(
+        doc: &Document,
+        obj: &Object,
+        resources: Option<&Dictionary>,
+    ) -> Option<Self> {
+        Self::resolve_depth(doc, obj, resources, 0)
+    }
+
+    fn resolve_depth(
+        doc: &Document,
+        obj: &Object,
+        resources: Option<&Dictionary>,
+        depth: u8,
+    ) -> Option<Self> {
+        if depth > 8 {
+            return None;
+        }
+        let (_, obj) = doc.dereference(obj).ok()?;
+
+        match obj {
+            Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" | b"G" | b"CalGray" => Some(Self::Gray),
+                b"DeviceRGB" | b"RGB" | b"CalRGB" => Some(Self::Rgb),
+                b"DeviceCMYK" | b"CMYK" => Some(Self::Cmyk),
+                b"Pattern" => Some(Self::Pattern),
+                other => {
+                    let named = resources
+                        .and_then(|r| dict_entry(doc, r, b"ColorSpace"))
+                        .and_then(|spaces| spaces.get(other).ok())?;
+                    Self::resolve_depth(doc, named, resources, depth + 1)
+                }
+            },
+            Object::Array(items) => {
+                let family = items.first()?.as_name().ok()?;
+                match family {
+                    b"DeviceGray" | b"CalGray" | b"G" => Some(Self::Gray),
+                    b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(Self::Rgb),
+                    b"DeviceCMYK" | b"CMYK" => Some(Self::Cmyk),
+                    b"Pattern" => Some(Self::Pattern),
+                    b"ICCBased" => {
+                        let stream = deref(doc, items.get(1)?)?.as_stream().ok()?;
+                        match stream.dict.get(b"N").and_then(Object::as_i64) {
+                            Ok(1) => Some(Self::Gray),
+                            Ok(3) => Some(Self::Rgb),
+                            Ok(4) => Some(Self::Cmyk),
+                            _ => stream.dict.get(b"Alternate").ok().and_then(|alt| {
+                                Self::resolve_depth(doc, alt, resources, depth + 1)
+                            }),
+                        }
+                    }
+                    b"Lab" => {
+                        let params = deref(doc, items.get(1)?)?.as_dict().ok()?;
+                        let white = floats(doc, params.get(b"WhitePoint").ok())
+                            .filter(|w| w.len() == 3)
+                            .map(|w| [w[0], w[1], w[2]])
+                            .unwrap_or([0.9505, 1.0, 1.089]);
+                        let range = floats(doc, params.get(b"Range").ok())
+                            .filter(|r| r.len() == 4)
+                            .map(|r| [r[0], r[1], r[2], r[3]])
+                            .unwrap_or([-100.0, 100.0, -100.0, 100.0]);
+                        Some(Self::Lab { white, range })
+                    }
+                    b"Indexed" | b"I" => {
+                        let base = Self::resolve_depth(doc, items.get(1)?, resources, depth + 1)?;
+                        let hival = deref(doc, items.get(2)?)?.as_i64().ok()?.clamp(0, 255) as u32;
+                        let lookup = match deref(doc, items.get(3)?)? {
+                            Object::String(bytes, _) => bytes.clone(),
+                            Object::Stream(stream) => stream
+                                .decompressed_content()
+                                .unwrap_or_else(|_| stream.content.clone()),
+                            _ => return None,
+                        };
+                        Some(Self::Indexed {
+                            base: Box::new(base),
+                            hival,
+                            lookup,
+                        })
+                    }
+                    b"Separation" => Some(Self::Tint(1)),
+                    b"DeviceN" => {
+                        let names = deref(doc, items.get(1)?)?.as_array().ok()?;
+                        (1..=MAX_COMPONENTS)
+                            .contains(&names.len())
+                            .then_some(Self::Tint(names.len()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Number of colour components.
+    pub(super) fn components(&self) -> usize {
+        match self {
+            Self::Gray | Self::Indexed { .. } => 1,
+            Self::Rgb | Self::Lab { .. } => 3,
+            Self::Cmyk => 4,
+            Self::Tint(n) => *n,
+            Self::Pattern => 0,
+        }
+    }
+
+    /// Initial colour when the space is selected (black, or full tint).
+    pub(super) fn initial_color(&self) -> Vec<f32> {
+        match self {
+            Self::Cmyk => vec![0.0, 0.0, 0.0, 1.0],
+            Self::Tint(n) => vec![1.0; *n],
+            other => vec![0.0; other.components()],
+        }
+    }
+
+    /// Default image decode ranges for the given bits per component.
+    fn default_decode(&self, bits: u8) -> Vec<(f32, f32)> {
+        match self {
+            Self::Indexed { .. } => vec![(0.0, ((1u32 << bits) - 1) as f32)],
+            Self::Lab { range, .. } => {
+                vec![(0.0, 100.0), (range[0], range[1]), (range[2], range[3])]
+            }
+            other => vec![(0.0, 1.0); other.components()],
+        }
+    }
+
+    /// Convert colour components to sRGB.
+    pub(super) fn to_rgb(&self, c: &[f32]) -> [u8; 3] {
+        let get = |i: usize| c.get(i).copied().unwrap_or(0.0);
+        match self {
+            Self::Gray => {
+                let v = unit(get(0));
+                [v, v, v]
+            }
+            Self::Rgb => [unit(get(0)), unit(get(1)), unit(get(2))],
+            Self::Cmyk => {
+                let k = 1.0 - get(3).clamp(0.0, 1.0);
+                [
+                    unit((1.0 - get(0).clamp(0.0, 1.0)) * k),
+                    unit((1.0 - get(1).clamp(0.0, 1.0)) * k),
+                    unit((1.0 - get(2).clamp(0.0, 1.0)) * k),
+                ]
+            }
+            Self::Lab { white, .. } => lab_to_rgb(get(0), get(1), get(2), white),
+            Self::Indexed {
+                base,
+                hival,
+                lookup,
+            } => {
+                let index = (get(0).round().max(0.0) as u32).min(*hival) as usize;
+                let n = base.components();
+                let mut comps = [0.0f32; MAX_COMPONENTS];
+                for (i, comp) in comps.iter_mut().enumerate().take(n) {
+                    *comp = lookup.get(index * n + i).copied().unwrap_or(0) as f32 / 255.0;
+                }
+                base.to_rgb(&comps[..n])
+            }
+            Self::Tint(n) => {
+                // Average coverage of all inks, printed on white paper
+                let tint = (0..*n).map(|i| get(i).clamp(0.0, 1.0)).sum::<f32>() / *n as f32;
+                let v = unit(1.0 - tint);
+                [v, v, v]
+            }
+            Self::Pattern => [128, 128, 128],
+        }
+    }
+}
+
+/// Convert a 0..1 value to a byte.
+fn unit(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Convert CIE L*a*b* to sRGB.
+fn lab_to_rgb(l: f32, a: f32, b: f32, white: &[f32; 3]) -> [u8; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let g = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t * t * t
+        } else {
+            108.0 / 841.0 * (t - 4.0 / 29.0)
+        }
+    };
+    let (x, y, z) = (white[0] * g(fx), white[1] * g(fy), white[2] * g(fz));
+
+    let linear = [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ];
+    linear.map(|v| {
+        let v = v.clamp(0.0, 1.0);
+        unit(if v <= 0.003_130_8 {
+            12.92 * v
+        } else {
+            1.055 * v.powf(1.0 / 2.4) - 0.055
+        })
+    })
+}
+
+/// Follow a reference to its object.
+fn deref<'a>(doc: &'a Document, obj: &'a Object) -> Option<&'a Object> {
+    doc.dereference(obj).ok().map(|(_, obj)| obj)
+}
+
+/// Look up a dictionary entry that may be stored by reference.
+pub(super) fn dict_entry<'a>(
+    doc: &'a Document,
+    dict: &'a Dictionary,
+    key: &[u8],
+) -> Option<&'a Dictionary> {
+    deref(doc, dict.get(key).ok()?)?.as_dict().ok()
+}
+
+/// Read an array of numbers.
+pub(super) fn floats(doc: &Document, obj: Option<&Object>) -> Option<Vec<f32>> {
+    deref(doc, obj?)?
+        .as_array()
+        .ok()?
+        .iter()
+        .map(|v| deref(doc, v).and_then(|v| v.as_float().ok()))
+        .collect()
+}
+
+/// Image dictionary with inline-image abbreviations expanded.
+fn image_dict(stream: &Stream) -> Dictionary {
+    let mut dict = Dictionary::new();
+    for (key, value) in stream.dict.iter() {
+        let key: &[u8] = match key.as_slice() {
+            b"W" => b"Width",
+            b"H" => b"Height",
+            b"CS" => b"ColorSpace",
+            b"BPC" => b"BitsPerComponent",
+            b"F" => b"Filter",
+            b"DP" => b"DecodeParms",
+            b"D" => b"Decode",
+            b"IM" => b"ImageMask",
+            b"I" => b"Interpolate",
+            other => other,
+        };
+        let expand = |name: &[u8]| -> Vec<u8> {
+            match name {
+                b"AHx" => b"ASCIIHexDecode".to_vec(),
+                b"A85" => b"ASCII85Decode".to_vec(),
+                b"LZW" => b"LZWDecode".to_vec(),
+                b"Fl" => b"FlateDecode".to_vec(),
+                b"RL" => b"RunLengthDecode".to_vec(),
+                b"CCF" => b"CCITTFaxDecode".to_vec(),
+                b"DCT" => b"DCTDecode".to_vec(),
+                other => other.to_vec(),
+            }
+        };
+        let value = match (key, value) {
+            (b"Filter", Object::Name(name)) => Object::Name(expand(name)),
+            (b"Filter", Object::Array(names)) => Object::Array(
+                names
+                    .iter()
+                    .map(|n| match n {
+                        Object::Name(name) => Object::Name(expand(name)),
+                        other => other.clone(),
+                    })
+                    .collect(),
+            ),
+            (_, value) => value.clone(),
+        };
+        dict.set(key.to_vec(), value);
+    }
+    dict
+}
+
+/// An image XObject (or inline image) ready for decoding.
+pub(super) struct PdfImage {
+    stream: Stream,
+    pub(super) width: u32,
+    pub(super) height: u32,
+    bits: u8,
+}
+
+impl PdfImage {
+    /// Wrap an image stream, validating its dimensions.
+    pub(super) fn new(stream: &Stream) -> Option<Self> {
+        let stream = Stream::new(image_dict(stream), stream.content.clone());
+        let int = |key: &[u8]| stream.dict.get(key).and_then(Object::as_i64).ok();
+
+        let width = u32::try_from(int(b"Width")?).ok()?;
+        let height = u32::try_from(int(b"Height")?).ok()?;
+        if width == 0 || height == 0 || u64::from(width) * u64::from(height) > MAX_IMAGE_PIXELS {
+            return None;
+        }
+        let bits = if stream
+            .dict
+            .get(b"ImageMask")
+            .and_then(Object::as_bool)
+            .unwrap_or(false)
+        {
+            1
+        } else {
+            int(b"BitsPerComponent").unwrap_or(8) as u8
+        };
+        if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+            return None;
+        }
+
+        Some(Self {
+            stream,
+            width,
+            height,
+            bits,
+        })
+    }
+
+    /// Whether this is a stencil mask painted with the fill colour.
+    pub(super) fn is_stencil(&self) -> bool {
+        self.stream
+            .dict
+            .get(b"ImageMask")
+            .and_then(Object::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// Whether the data is a JPEG that needs no colour conversion.
+    pub(super) fn is_plain_jpeg(&self, doc: &Document) -> bool {
+        is_dct(&self.stream)
+            && self.stream.filters().map(|f| f.len()).unwrap_or(0) == 1
+            && !matches!(self.color_space(doc, None), Some(ColorSpace::Cmyk))
+            && self.stream.content.starts_with(&[0xFF, 0xD8, 0xFF])
+    }
+
+    /// The raw stream content.
+    pub(super) fn content(&self) -> &[u8] {
+        &self.stream.content
+    }
+
+    fn color_space(&self, doc: &Document, resources: Option<&Dictionary>) -> Option<ColorSpace> {
+        self.stream
+            .dict
+            .get(b"ColorSpace")
+            .ok()
+            .and_then(|cs| ColorSpace::resolve(doc, cs, resources))
+    }
+
+    /// Decoded stream data, or `None` for unsupported filters.
+    fn data(&self) -> Option<Vec<u8>> {
+        let filters = self.stream.filters().unwrap_or_default();
+        match filters.last() {
+            Some(&(b"JPXDecode" | b"CCITTFaxDecode" | b"JBIG2Decode" | b"DCTDecode")) => None,
+            _ => self
+                .stream
+                .decompressed_content_with_limit(MAX_IMAGE_BYTES)
+                .ok(),
+        }
+    }
+
+    /// Decode to RGBA, applying the colour space, decode array and soft mask.
+    pub(super) fn decode(
+        &self,
+        doc: &Document,
+        resources: Option<&Dictionary>,
+    ) -> Option<RgbaImage> {
+        let mut img = if is_dct(&self.stream) {
+            let data = if self.stream.filters().map(|f| f.len()).unwrap_or(0) == 1 {
+                self.stream.content.clone()
+            } else {
+                // e.g. [/ASCII85Decode /DCTDecode]: decode the outer filters only
+                let mut outer = self.stream.clone();
+                let filters: Vec<Object> = outer
+                    .filters()
+                    .ok()?
+                    .iter()
+                    .filter(|f| **f != b"DCTDecode")
+                    .map(|f| Object::Name(f.to_vec()))
+                    .collect();
+                outer.dict.set("Filter", Object::Array(filters));
+                outer
+                    .decompressed_content_with_limit(MAX_IMAGE_BYTES)
+                    .ok()?
+            };
+            image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)
+                .ok()?
+                .to_rgba8()
+        } else {
+            let space = self.color_space(doc, resources).unwrap_or(ColorSpace::Gray);
+            if matches!(space, ColorSpace::Pattern) {
+                return None;
+            }
+            self.decode_raw(&space)?
+        };
+
+        if let Some(mask) = self.soft_mask(doc) {
+            for (x, y, pixel) in img.enumerate_pixels_mut() {
+                let mx = (u64::from(x) * u64::from(mask.width()) / u64::from(self.width)) as u32;
+                let my = (u64::from(y) * u64::from(mask.height()) / u64::from(self.height)) as u32;
+                pixel.0[3] = mask.get_pixel(mx, my).0[0];
+            }
+        }
+
+        Some(img)
+    }
+
+    /// Decode a stencil mask: 255 where the fill colour is painted.
+    pub(super) fn decode_stencil(&self) -> Option<GrayImage> {
+        let data = self.data()?;
+        // Sample 0 paints unless the decode array is inverted
+        let inverted = self
+            .stream
+            .dict
+            .get(b"Decode")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|d| d.first())
+            .and_then(|v| v.as_float().ok())
+            == Some(1.0);
+
+        let samples = Samples::new(&data, self.width, 1, 1);
+        Some(GrayImage::from_fn(self.width, self.height, |x, y| {
+            let bit = samples.get(x, y, 0) != 0;
+            Luma([if bit == inverted { 255 } else { 0 }])
+        }))
+    }
+
+    /// Decode a raw (non-JPEG) sample stream.
+    fn decode_raw(&self, space: &ColorSpace) -> Option<RgbaImage> {
+        let data = self.data()?;
+        let n = space.components();
+        if n == 0 || n > MAX_COMPONENTS {
+            return None;
+        }
+
+        let decode = self
+            .stream
+            .dict
+            .get(b"Decode")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|d| {
+                d.iter()
+                    .map(|v| v.as_float().ok())
+                    .collect::<Option<Vec<f32>>>()
+            })
+            .filter(|d| d.len() == n * 2)
+            .map(|d| d.chunks(2).map(|p| (p[0], p[1])).collect())
+            .unwrap_or_else(|| space.default_decode(self.bits));
+
+        let max = ((1u32 << self.bits) - 1) as f32;
+        let samples = Samples::new(&data, self.width, n, self.bits);
+        let mut comps = [0.0f32; MAX_COMPONENTS];
+
+        Some(RgbaImage::from_fn(self.width, self.height, |x, y| {
+            for (c, comp) in comps.iter_mut().enumerate().take(n) {
+                let (lo, hi) = decode[c];
+                *comp = lo + samples.get(x, y, c) as f32 * (hi - lo) / max;
+            }
+            let [r, g, b] = space.to_rgb(&comps[..n]);
+            Rgba([r, g, b, 255])
+        }))
+    }
+
+    /// Decode the `/SMask` soft mask as an alpha channel.
+    fn soft_mask(&self, doc: &Document) -> Option<GrayImage> {
+        let smask = deref(doc, self.stream.dict.get(b"SMask").ok()?)?
+            .as_stream()
+            .ok()?;
+        let mask = PdfImage::new(smask)?;
+        let alpha = mask.decode_raw(&ColorSpace::Gray)?;
+        Some(GrayImage::from_fn(alpha.width(), alpha.height(), |x, y| {
+            Luma([alpha.get_pixel(x, y).0[0]])
+        }))
+    }
+}
+
+/// Whether a stream's last filter is DCTDecode.
+fn is_dct(stream: &Stream) -> bool {
+    stream
+        .filters()
+        .ok()
+        .and_then(|f| f.last().copied())
+        .is_some_and(|f| f == b"DCTDecode")
+}
+
+/// Bit-packed image samples (rows padded to whole bytes).
+struct Samples<'a> {
+    data: &'a [u8],
+    row_bytes: usize,
+    components: usize,
+    bits: u8,
+}
+
+impl<'a> Samples<'a> {
+    fn new(data: &'a [u8], width: u32, components: usize, bits: u8) -> Self {
+        Self {
+            data,
+            row_bytes: (width as usize * components * bits as usize).div_ceil(8),
+            components,
+            bits,
+        }
+    }
+
+    /// Sample value of one component; missing data reads as zero.
+    fn get(&self, x: u32, y: u32, component: usize) -> u32 {
+        let index = x as usize * self.components + component;
+        let row = y as usize * self.row_bytes;
+        let byte = |i: usize| self.data.get(row + i).copied().unwrap_or(0) as u32;
+        match self.bits {
+            8 => byte(index),
+            16 => byte(index * 2),
+            bits => {
+                let bit = index * bits as usize;
+                let shift = 8 - bits as usize - (bit % 8);
+                (byte(bit / 8) >> shift) & ((1 << bits) - 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    fn image(dict: Dictionary, data: Vec<u8>) -> PdfImage {
+        PdfImage::new(&Stream::new(dict, data)).unwrap()
+    }
+
+    #[test]
+    fn test_decode_gray_and_cmyk() {
+        let doc = Document::with_version("1.5");
+
+        let gray = image(
+            dictionary! { "Width" => 2, "Height" => 1, "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8 },
+            vec![0, 255],
+        );
+        let img = gray.decode(&doc, None).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [255, 255, 255, 255]);
+
+        // Cyan in CMYK
+        let cmyk = image(
+            dictionary! { "Width" => 1, "Height" => 1, "ColorSpace" => "DeviceCMYK", "BitsPerComponent" => 8 },
+            vec![255, 0, 0, 0],
+        );
+        let img = cmyk.decode(&doc, None).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_decode_indexed_4bit() {
+        let doc = Document::with_version("1.5");
+        let space = Object::Array(vec![
+            Object::Name(b"Indexed".to_vec()),
+            Object::Name(b"DeviceRGB".to_vec()),
+            Object::Integer(1),
+            Object::string_literal(vec![255, 0, 0, 0, 0, 255]),
+        ]);
+        // Three pixels (red, blue, red) packed in 4 bits, row padded
+        let indexed = image(
+            dictionary! { "Width" => 3, "Height" => 1, "ColorSpace" => space, "BitsPerComponent" => 4 },
+            vec![0x01, 0x00],
+        );
+        let img = indexed.decode(&doc, None).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [0, 0, 255, 255]);
+        assert_eq!(img.get_pixel(2, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_flate_with_decode_array() {
+        use std::io::Write;
+
+        let doc = Document::with_version("1.5");
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&[0b1000_0000]).unwrap();
+        let data = encoder.finish().unwrap();
+
+        // 1-bit gray, inverted by the decode array
+        let inverted = image(
+            dictionary! {
+                "Width" => 2, "Height" => 1, "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 1, "Filter" => "FlateDecode",
+                "Decode" => vec![Object::Integer(1), Object::Integer(0)],
+            },
+            data,
+        );
+        let img = inverted.decode(&doc, None).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_stencil_and_inline_abbreviations() {
+        let inline = image(
+            dictionary! { "W" => 2, "H" => 1, "IM" => true },
+            vec![0b0100_0000],
+        );
+        assert!(inline.is_stencil());
+        let mask = inline.decode_stencil().unwrap();
+        assert_eq!(mask.get_pixel(0, 0).0, [255]);
+        assert_eq!(mask.get_pixel(1, 0).0, [0]);
+    }
+}
@@ -0,0 +1,471 @@
+//! Plain text, Markdown and HTML books.
+
+mod front_matter;
+mod html;
+mod xhtml;
+
+use crate::config::BookFormat;
+use crate::error::Result;
+use crate::formats::FormatHandler;
+use crate::formats::convert::Block;
+use crate::formats::cover::generate_cover;
+use crate::library::book::Book;
+use encoding_rs::Encoding;
+use std::path::Path;
+
+/// Average number of words on a printed page, for page estimates.
+const WORDS_PER_PAGE: usize = 250;
+
+/// Number of lines searched for a Project Gutenberg style header.
+const HEADER_LINES: usize = 60;
+
+/// Words that start a chapter heading line in plain text.
+const CHAPTER_WORDS: &[&str] = &[
+    "chapter",
+    "chapitre",
+    "capítulo",
+    "capitulo",
+    "kapitel",
+    "part",
+    "partie",
+    "book",
+    "livre",
+    "prologue",
+    "epilogue",
+    "épilogue",
+    "introduction",
+    "preface",
+    "préface",
+    "foreword",
+    "afterword",
+    "appendix",
+];
+
+/// Handler for plain text files.
+pub struct TxtHandler;
+
+/// Handler for Markdown files.
+pub struct MarkdownHandler;
+
+/// Handler for HTML files.
+pub struct HtmlHandler;
+
+/// Shared behaviour of the text handlers.
+trait TextFormat {
+    const FORMAT: BookFormat;
+    /// Label printed on generated covers.
+    const LABEL: &'static str;
+
+    /// Decode the raw file contents.
+    fn decode(data: &[u8]) -> String {
+        decode_text(data, None)
+    }
+
+    /// Apply the document metadata to a book and return its word count.
+    fn parse(content: &str, book: &mut Book) -> usize;
+
+    /// Read and parse a file.
+    fn read(book: &mut Book) -> Result<usize> {
+        let data = std::fs::read(&book.path)?;
+        let content = Self::decode(&data);
+        Ok(Self::parse(&content, book))
+    }
+}
+
+impl TextFormat for TxtHandler {
+    const FORMAT: BookFormat = BookFormat::Txt;
+    const LABEL: &'static str = "Text";
+
+    fn parse(content: &str, book: &mut Book) -> usize {
+        // "Title: ..." and "Author: ..." lines as found in Project Gutenberg texts
+        for line in content.lines().take(HEADER_LINES) {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match key.trim().to_lowercase().as_str() {
+                "title" => book.title = value.to_string(),
+                "author" => book.authors = vec![value.to_string()],
+                _ => {}
+            }
+        }
+
+        word_count(content)
+    }
+}
+
+impl TextFormat for MarkdownHandler {
+    const FORMAT: BookFormat = BookFormat::Md;
+    const LABEL: &'static str = "Markdown";
+
+    fn parse(content: &str, book: &mut Book) -> usize {
+        let (front_matter, body) = front_matter::split(content);
+
+        if let Some(heading) = first_heading(body) {
+            book.title = heading;
+        }
+        if let Some(front_matter) = front_matter {
+            front_matter.apply(book);
+        }
+
+        word_count(body)
+    }
+}
+
+impl TextFormat for HtmlHandler {
+    const FORMAT: BookFormat = BookFormat::Html;
+    const LABEL: &'static str = "HTML";
+
+    fn decode(data: &[u8]) -> String {
+        let declared = html::declared_charset(data).and_then(|c| Encoding::for_label(c.as_bytes()));
+        decode_text(data, declared)
+    }
+
+    fn parse(content: &str, book: &mut Book) -> usize {
+        let document = html::Document::parse(content);
+        document.apply(book);
+        document.words
+    }
+}
+
+/// Read a text book's metadata, estimating its page count.
+fn extract_metadata<T: TextFormat>(book: &mut Book) -> Result<()> {
+    let words = T::read(book)?;
+
+    book.page_count = estimate_pages(words);
+    // A cover is always generated from the metadata
+    book.has_cover = true;
+    Ok(())
+}
+
+/// Generate a cover from the title and authors of a text book.
+fn extract_cover<T: TextFormat>(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut book = Book::new(path.to_path_buf(), T::FORMAT);
+    T::read(&mut book)?;
+
+    Ok(generate_cover(&book.title, &book.authors, T::LABEL))
+}
+
+fn page_count<T: TextFormat>(path: &Path) -> Result<Option<u32>> {
+    let mut book = Book::new(path.to_path_buf(), T::FORMAT);
+    Ok(estimate_pages(T::read(&mut book)?))
+}
+
+impl FormatHandler for TxtHandler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        extract_metadata::<Self>(book)
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        extract_cover::<Self>(path)
+    }
+
+    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
+        page_count::<Self>(path)
+    }
+}
+
+impl FormatHandler for MarkdownHandler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        extract_metadata::<Self>(book)
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        extract_cover::<Self>(path)
+    }
+
+    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
+        page_count::<Self>(path)
+    }
+}
+
+impl FormatHandler for HtmlHandler {
+    fn extract_metadata(&self, book: &mut Book) -> Result<()> {
+        extract_metadata::<Self>(book)
+    }
+
+    fn extract_cover(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        extract_cover::<Self>(path)
+    }
+
+    fn page_count(&self, path: &Path) -> Result<Option<u32>> {
+        page_count::<Self>(path)
+    }
+}
+
+/// Read a text book as XHTML blocks for conversion.
+pub(super) fn blocks(path: &Path, format: BookFormat) -> Result<Vec<Block>> {
+    let data = std::fs::read(path)?;
+
+    Ok(match format {
+        BookFormat::Md => {
+            let content = MarkdownHandler::decode(&data);
+            let (_, body) = front_matter::split(&content);
+            markdown_blocks(body)
+        }
+        BookFormat::Html => xhtml::to_blocks(&HtmlHandler::decode(&data)),
+        _ => text_blocks(&TxtHandler::decode(&data)),
+    })
+}
+
+/// Visible text of an (X)HTML document with its first heading, or its title.
+pub(super) fn html_text(content: &str) -> (Option<String>, String) {
+    let document = html::Document::parse(content);
+    (document.heading.or(document.title), document.text)
+}
+
+/// Render Markdown to XHTML blocks.
+fn markdown_blocks(markdown: &str) -> Vec<Block> {
+    use pulldown_cmark::{Options, Parser};
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_SMART_PUNCTUATION;
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options));
+
+    // Raw HTML in the Markdown source is not necessarily well-formed
+    xhtml::to_blocks(&html)
+}
+
+/// Split plain text into paragraphs on blank lines, detecting chapter
+/// headings such as "Chapter 1" or a lone roman numeral.
+fn text_blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines: Vec<&str> = Vec::new();
+
+    for line in text.lines().chain(std::iter::once("")) {
+        let line = line.trim();
+        if !line.is_empty() {
+            lines.push(line);
+            continue;
+        }
+        if lines.is_empty() {
+            continue;
+        }
+
+        // Hard-wrapped lines are joined back into paragraphs
+        let paragraph = lines.join(" ");
+        let escaped = quick_xml::escape::escape(paragraph.as_str()).into_owned();
+        blocks.push(if lines.len() == 1 && is_chapter_heading(&paragraph) {
+            Block::Heading {
+                level: 2,
+                xhtml: format!("<h2>{}</h2>", escaped),
+                text: paragraph,
+            }
+        } else {
+            Block::Content(format!("<p>{}</p>", escaped))
+        });
+        lines.clear();
+    }
+
+    blocks
+}
+
+fn is_chapter_heading(line: &str) -> bool {
+    if line.chars().count() > 80 {
+        return false;
+    }
+    let first = line
+        .split(|c: char| c.is_whitespace() || c == '.' || c == ':')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+
+    // Upper case roman numerals on their own
+    let numeral = line.trim_end_matches('.');
+    CHAPTER_WORDS.contains(&first.as_str())
+        || (!numeral.is_empty() && numeral.chars().all(|c| "IVXLC".contains(c)))
+}
+
+/// Decode text using its byte order mark, the declared encoding, UTF-8
+/// when valid, or else the encoding guessed from its content.
+fn decode_text(data: &[u8], declared: Option<&'static Encoding>) -> String {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return encoding.decode(data).0.into_owned();
+    }
+    if declared.is_none()
+        && let Ok(text) = std::str::from_utf8(data)
+    {
+        return text.to_string();
+    }
+
+    let encoding = declared.unwrap_or_else(|| {
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(data, true);
+        detector.guess(None, true)
+    });
+    encoding.decode(data).0.into_owned()
+}
+
+fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Approximate printed page count from a word count.
+fn estimate_pages(words: usize) -> Option<u32> {
+    (words > 0).then(|| words.div_ceil(WORDS_PER_PAGE) as u32)
+}
+
+/// First level-one heading of a Markdown document (ATX or setext style).
+fn first_heading(body: &str) -> Option<String> {
+    let mut previous: Option<&str> = None;
+    let mut in_code = false;
+
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            previous = None;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+
+        if let Some(heading) = trimmed.strip_prefix("# ") {
+            let heading = heading.trim().trim_end_matches('#').trim();
+            if !heading.is_empty() {
+                return Some(heading.to_string());
+            }
+        }
+        if !trimmed.is_empty()
+            && trimmed.chars().all(|c| c == '=')
+            && let Some(text) = previous.filter(|p| !p.is_empty())
+        {
+            return Some(text.to_string());
+        }
+        previous = Some(trimmed);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::test_util::write_file;
+
+    #[test]
+    fn test_markdown_front_matter() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = "word ".repeat(600);
+        let content = format!(
+            "---\ntitle: \"The Book\"\nauthor:\n  - Ann Author\n  - Bob Writer\n\
+             tags: [essay, rust]\nlang: en\ndate: 2021-03-04\n---\n\n# Heading\n\n{}",
+            body
+        );
+        let path = write_file(dir.path(), "notes.md", content.as_bytes());
+
+        let mut book = Book::new(path.clone(), BookFormat::Md);
+        MarkdownHandler.extract_metadata(&mut book).unwrap();
+
+        assert_eq!(book.title, "The Book");
+        assert_eq!(book.authors, vec!["Ann Author", "Bob Writer"]);
+        assert_eq!(book.tags, vec!["essay", "rust"]);
+        assert_eq!(book.language.as_deref(), Some("en"));
+        assert_eq!(book.published.as_deref(), Some("2021-03-04"));
+        assert_eq!(book.page_count, Some(3));
+        assert!(book.has_cover);
+
+        let cover = MarkdownHandler.extract_cover(&path).unwrap().unwrap();
+        assert!(image::load_from_memory(&cover).is_ok());
+    }
+
+    #[test]
+    fn test_markdown_heading_title() {
+        assert_eq!(
+            first_heading("```\n# not a title\n```\n# Real Title #\n").as_deref(),
+            Some("Real Title")
+        );
+        assert_eq!(
+            first_heading("Intro text\n\nSetext Title\n===\n").as_deref(),
+            Some("Setext Title")
+        );
+        assert_eq!(first_heading("## Only a subheading\n"), None);
+    }
+
+    #[test]
+    fn test_txt_encoding_and_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = "Title: Les Misérables\nAuthor: Victor Hugo\n\n\
+                       Il était une fois, dans une ville où l'été brûlait, un forçat.\n";
+        let (encoded, _, _) = encoding_rs::WINDOWS_1252.encode(content);
+        let path = write_file(dir.path(), "hugo.txt", &encoded);
+
+        let mut book = Book::new(path, BookFormat::Txt);
+        TxtHandler.extract_metadata(&mut book).unwrap();
+
+        assert_eq!(book.title, "Les Misérables");
+        assert_eq!(book.authors, vec!["Victor Hugo"]);
+        assert_eq!(book.page_count, Some(1));
+    }
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(decode_text("héllo".as_bytes(), None), "héllo");
+        assert_eq!(decode_text(b"\xEF\xBB\xBFbom", None), "bom");
+        assert_eq!(
+            decode_text(b"caf\xE9", Some(encoding_rs::WINDOWS_1252)),
+            "café"
+        );
+        assert_eq!(decode_text(b"\xFF\xFEh\x00i\x00", None), "hi");
+    }
+
+    #[test]
+    fn test_text_blocks() {
+        let blocks =
+            text_blocks("CHAPTER I.\n\nIt was a\nlong night & day.\n\n\nIV\n\nIndeed <sic>.\n");
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Heading {
+                    level: 2,
+                    text: "CHAPTER I.".to_string(),
+                    xhtml: "<h2>CHAPTER I.</h2>".to_string(),
+                },
+                Block::Content("<p>It was a long night &amp; day.</p>".to_string()),
+                Block::Heading {
+                    level: 2,
+                    text: "IV".to_string(),
+                    xhtml: "<h2>IV</h2>".to_string(),
+                },
+                Block::Content("<p>Indeed &lt;sic&gt;.</p>".to_string()),
+            ]
+        );
+        assert!(is_chapter_heading("XII."));
+        assert!(!is_chapter_heading("Civil"));
+        assert!(!is_chapter_heading("Particularly so."));
+    }
+
+    #[test]
+    fn test_markdown_blocks() {
+        let blocks = markdown_blocks("# Title\n\nSome *text*<br>\n\n<div>raw <b>html</div>\n");
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Heading {
+                    level: 1,
+                    text: "Title".to_string(),
+                    xhtml: "<h1>Title</h1>".to_string(),
+                },
+                Block::Content("<p>Some <em>text</em><br/></p>".to_string()),
+                Block::Content("<p>raw <b>html</b></p>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_estimate_pages() {
+        assert_eq!(estimate_pages(0), None);
+        assert_eq!(estimate_pages(1), Some(1));
+        assert_eq!(estimate_pages(250), Some(1));
+        assert_eq!(estimate_pages(251), Some(2));
+    }
+}
@@ -0,0 +1,319 @@
+//! Markdown front matter (YAML between `---` or TOML between `+++`).
+
+use crate::formats::epub::parse_identifier;
+use crate::library::book::Book;
+
+/// Front matter fields as lists of strings, keyed by lowercase name.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct FrontMatter {
+    fields: Vec<(String, Vec<String>)>,
+}
+
+/// Split a document into its front matter and body.
+pub(super) fn split(content: &str) -> (Option<FrontMatter>, &str) {
+    let content = content.trim_start_matches('\u{feff}');
+    let first = content.lines().next().unwrap_or("").trim_end();
+    let (toml, closing): (bool, &[&str]) = match first {
+        "---" => (false, &["---", "..."]),
+        "+++" => (true, &["+++"]),
+        _ => return (None, content),
+    };
+
+    // Find the closing delimiter line
+    let mut offset = content.find('\n').map_or(content.len(), |i| i + 1);
+    let start = offset;
+    while offset < content.len() {
+        let end = content[offset..]
+            .find('\n')
+            .map_or(content.len(), |i| offset + i + 1);
+        if closing.contains(&content[offset..end].trim_end()) {
+            let source = &content[start..offset];
+            let front_matter = if toml {
+                FrontMatter::from_toml(source)
+            } else {
+                FrontMatter::from_yaml(source)
+            };
+            return (Some(front_matter), &content[end..]);
+        }
+        offset = end;
+    }
+
+    (None, content)
+}
+
+impl FrontMatter {
+    /// Parse the subset of YAML used in front matter: scalars, flow
+    /// sequences (`[a, b]`), block sequences, block scalars (`|`, `>`)
+    /// and `name:` in nested maps.
+    fn from_yaml(source: &str) -> Self {
+        let mut front_matter = FrontMatter::default();
+        let mut current: Option<String> = None;
+        // Separator of the block scalar being read, if any
+        let mut block: Option<&str> = None;
+
+        for line in source.lines() {
+            let indented = line.starts_with([' ', '\t']);
+            if let (Some(separator), Some(key), true) = (block, &current, indented) {
+                front_matter.append(key, line.trim(), separator);
+                continue;
+            }
+            block = None;
+
+            let content = strip_comment(line);
+            if content.trim().is_empty() {
+                continue;
+            }
+            let content = content.trim();
+
+            if indented || content.starts_with("- ") {
+                // Continuation of the current key: list items or a nested map
+                let Some(key) = &current else {
+                    continue;
+                };
+                let item = content.strip_prefix("- ").unwrap_or(content).trim();
+                let value = match item.split_once(':') {
+                    Some((name, value)) if is_key(name) => {
+                        if name.trim() != "name" {
+                            continue;
+                        }
+                        value
+                    }
+                    _ => item,
+                };
+                front_matter.push(key, yaml_values(value));
+                continue;
+            }
+
+            let Some((key, value)) = content.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().trim_matches(['"', '\'']).to_lowercase();
+            let value = value.trim();
+            if value.starts_with(['|', '>']) {
+                block = Some(if value.starts_with('|') { "\n" } else { " " });
+            } else {
+                front_matter.push(&key, yaml_values(value));
+            }
+            current = Some(key);
+        }
+
+        front_matter
+    }
+
+    /// Parse TOML front matter.
+    fn from_toml(source: &str) -> Self {
+        let mut front_matter = FrontMatter::default();
+        let Ok(table) = source.parse::<toml::Table>() else {
+            return front_matter;
+        };
+
+        for (key, value) in table {
+            let values = match value {
+                toml::Value::Array(items) => items.iter().filter_map(toml_string).collect(),
+                value => toml_string(&value).into_iter().collect(),
+            };
+            front_matter.push(&key.to_lowercase(), values);
+        }
+
+        front_matter
+    }
+
+    fn push(&mut self, key: &str, values: Vec<String>) {
+        let values = values.into_iter().filter(|v| !v.is_empty());
+        match self.fields.iter_mut().find(|(k, _)| k == key) {
+            Some((_, existing)) => existing.extend(values),
+            None => self.fields.push((key.to_string(), values.collect())),
+        }
+    }
+
+    /// Append a line to the last value of a key.
+    fn append(&mut self, key: &str, line: &str, separator: &str) {
+        match self.fields.iter_mut().find(|(k, _)| k == key) {
+            Some((_, values)) if !values.is_empty() => {
+                if let Some(last) = values.last_mut() {
+                    last.push_str(separator);
+                    last.push_str(line);
+                }
+            }
+            _ => self.push(key, vec![line.to_string()]),
+        }
+    }
+
+    /// Values of the first of `keys` that has any.
+    fn get(&self, keys: &[&str]) -> &[String] {
+        keys.iter()
+            .find_map(|key| {
+                self.fields
+                    .iter()
+                    .find(|(k, v)| k == key && !v.is_empty())
+                    .map(|(_, v)| v.as_slice())
+            })
+            .unwrap_or(&[])
+    }
+
+    fn first(&self, keys: &[&str]) -> Option<String> {
+        self.get(keys).first().cloned()
+    }
+
+    /// Copy the front matter into a book.
+    pub(super) fn apply(&self, book: &mut Book) {
+        if let Some(title) = self.first(&["title"]) {
+            book.title = title;
+        }
+
+        let authors = self.get(&["author", "authors", "creator"]);
+        if !authors.is_empty() {
+            book.authors = authors.to_vec();
+        }
+
+        if let Some(description) = self.first(&["description", "summary", "abstract"]) {
+            book.description = Some(description);
+        }
+
+        // Comma separated strings are accepted as well as lists
+        let tags: Vec<String> = self
+            .get(&["tags", "keywords", "categories", "subject"])
+            .iter()
+            .flat_map(|t| t.split(','))
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        if !tags.is_empty() {
+            book.tags = tags;
+        }
+
+        if let Some(language) = self.first(&["lang", "language"]) {
+            book.language = Some(language);
+        }
+        if let Some(publisher) = self.first(&["publisher"]) {
+            book.publisher = Some(publisher);
+        }
+        if let Some(date) = self.first(&["date", "published", "pubdate"]) {
+            book.published = Some(date.split('T').next().unwrap_or(&date).to_string());
+        }
+
+        if let Some(series) = self.first(&["series"]) {
+            book.series = Some(series);
+            book.series_index = self
+                .first(&["series_index", "series-index", "series_number"])
+                .and_then(|i| i.parse().ok());
+        }
+
+        if let Some(id) = self
+            .first(&["isbn"])
+            .and_then(|isbn| parse_identifier(&isbn, Some("isbn".to_string())))
+        {
+            book.isbn = Some(id.value.clone());
+            if !book.identifiers.contains(&id) {
+                book.identifiers.push(id);
+            }
+        }
+    }
+}
+
+/// Whether `name` looks like a YAML mapping key rather than text.
+fn is_key(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Remove a trailing `# comment` outside of quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) if i == 0 || line[..i].ends_with([' ', '\t']) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Values of a YAML scalar or flow sequence.
+fn yaml_values(value: &str) -> Vec<String> {
+    let value = value.trim();
+    match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(items) => items.split(',').map(unquote).collect(),
+        None => vec![unquote(value)],
+    }
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
+
+fn toml_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Datetime(d) => Some(d.to_string()),
+        // Nested author tables: { name = "..." }
+        toml::Value::Table(t) => t.get("name").and_then(toml_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yaml_front_matter() {
+        let content = "---\n\
+            title: 'Quoted: Title' # comment\n\
+            authors:\n  - name: Ann Author\n    url: https://example.com\n  - Bob Writer\n\
+            keywords: rust, markdown\n\
+            description: >\n  A long\n  summary.\n\
+            series: Notes\n\
+            series_index: 2\n\
+            isbn: 978-0-306-40615-7\n\
+            ---\nBody text\n";
+        let (front_matter, body) = split(content);
+        assert_eq!(body, "Body text\n");
+
+        let mut book = Book::default();
+        front_matter.unwrap().apply(&mut book);
+        assert_eq!(book.title, "Quoted: Title");
+        assert_eq!(book.authors, vec!["Ann Author", "Bob Writer"]);
+        assert_eq!(book.tags, vec!["rust", "markdown"]);
+        assert_eq!(book.description.as_deref(), Some("A long summary."));
+        assert_eq!(book.series.as_deref(), Some("Notes"));
+        assert_eq!(book.series_index, Some(2.0));
+        assert_eq!(book.isbn.as_deref(), Some("9780306406157"));
+    }
+
+    #[test]
+    fn test_toml_front_matter() {
+        let content = "+++\ntitle = \"Toml Title\"\nauthors = [\"One\", \"Two\"]\n\
+                       date = 2020-01-02T03:04:05Z\n+++\n# Heading\n";
+        let (front_matter, body) = split(content);
+        assert_eq!(body, "# Heading\n");
+
+        let mut book = Book::default();
+        front_matter.unwrap().apply(&mut book);
+        assert_eq!(book.title, "Toml Title");
+        assert_eq!(book.authors, vec!["One", "Two"]);
+        assert_eq!(book.published.as_deref(), Some("2020-01-02"));
+    }
+
+    #[test]
+    fn test_no_front_matter() {
+        assert_eq!(split("# Title\n---\n"), (None, "# Title\n---\n"));
+        // An unterminated block is body text
+        assert_eq!(split("---\ntitle: x\n"), (None, "---\ntitle: x\n"));
+    }
+}
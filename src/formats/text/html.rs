@@ -0,0 +1,454 @@
+//! Metadata from HTML documents: `<title>`, `<meta>` tags and `<html lang>`.
+//!
+//! HTML is rarely well-formed XML, so tags are scanned rather than parsed.
+
+use crate::formats::epub::parse_identifier;
+use crate::library::book::Book;
+
+/// Bytes searched for a `<meta charset>` declaration.
+const CHARSET_SCAN: usize = 1024;
+
+/// Metadata and word count of an HTML document.
+#[derive(Debug, Default)]
+pub(super) struct Document {
+    pub(super) title: Option<String>,
+    language: Option<String>,
+    /// `(name, content)` of each `<meta>` tag, names lowercased.
+    meta: Vec<(String, String)>,
+    /// First `h1`-`h3` heading.
+    pub(super) heading: Option<String>,
+    /// Visible text, with whitespace collapsed.
+    pub(super) text: String,
+    /// Number of words of visible text.
+    pub(super) words: usize,
+}
+
+/// A start or end tag.
+pub(super) struct Tag<'a> {
+    /// Lowercase element name, empty for declarations.
+    pub(super) name: String,
+    pub(super) closing: bool,
+    /// Written as `<name/>`.
+    pub(super) self_closing: bool,
+    /// Attributes with lowercase names and raw (escaped) values.
+    pub(super) attributes: Vec<(String, &'a str)>,
+}
+
+impl Tag<'_> {
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+    }
+}
+
+impl Document {
+    /// Scan an HTML document.
+    pub(super) fn parse(content: &str) -> Self {
+        let mut document = Document::default();
+        let mut text = String::new();
+        let mut rest = content;
+        // Element whose content is not text (script, style) or the title
+        let mut raw: Option<String> = None;
+        // Offset in `text` where the first heading starts
+        let mut heading_start: Option<usize> = None;
+
+        while let Some(start) = rest.find('<') {
+            let before = &rest[..start];
+            match raw.as_deref() {
+                Some("title") if document.title.is_none() => {
+                    document.title = Some(collapse(&unescape(before)));
+                }
+                Some(_) => {}
+                None => {
+                    text.push_str(before);
+                    text.push(' ');
+                }
+            }
+            rest = &rest[start..];
+
+            // Comments and declarations
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+            let Some((tag, len)) = parse_tag(rest) else {
+                // A lone '<' in text
+                if raw.is_none() {
+                    text.push('<');
+                }
+                rest = &rest[1..];
+                continue;
+            };
+            rest = &rest[len..];
+
+            if let Some(open) = &raw {
+                if tag.closing && tag.name == *open {
+                    raw = None;
+                }
+                continue;
+            }
+
+            match (tag.name.as_str(), tag.closing) {
+                ("script" | "style" | "title" | "template", false) => raw = Some(tag.name.clone()),
+                ("html", false) => {
+                    document.language = tag.attribute("lang").map(str::to_string);
+                }
+                ("h1" | "h2" | "h3", false) if document.heading.is_none() => {
+                    heading_start = Some(text.len());
+                }
+                ("h1" | "h2" | "h3", true) => {
+                    if let Some(start) = heading_start.take() {
+                        let heading = collapse(&unescape(&text[start..]));
+                        document.heading = (!heading.is_empty()).then_some(heading);
+                    }
+                }
+                ("meta", false) => {
+                    let name = tag.attribute("name").or_else(|| tag.attribute("property"));
+                    if let (Some(name), Some(value)) = (name, tag.attribute("content")) {
+                        let value = collapse(&unescape(value));
+                        if !value.is_empty() {
+                            document.meta.push((name.to_lowercase(), value));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if raw.is_none() {
+            text.push_str(rest);
+        }
+
+        document.text = collapse(&unescape(&text));
+        document.words = super::word_count(&document.text);
+        document
+    }
+
+    /// Values of the meta tags with any of the given names.
+    fn meta(&self, names: &[&str]) -> Vec<String> {
+        self.meta
+            .iter()
+            .filter(|(name, _)| names.contains(&name.as_str()))
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn first(&self, names: &[&str]) -> Option<String> {
+        // Earlier names take precedence
+        names
+            .iter()
+            .find_map(|name| self.meta(&[name]).into_iter().next())
+    }
+
+    /// Copy the metadata into a book.
+    pub(super) fn apply(&self, book: &mut Book) {
+        let title = self
+            .first(&["dc.title", "dcterms.title", "og:title"])
+            .or_else(|| self.title.clone().filter(|t| !t.is_empty()));
+        if let Some(title) = title {
+            book.title = title;
+        }
+
+        let mut authors = self.meta(&["author", "dc.creator", "dcterms.creator"]);
+        if authors.is_empty() {
+            authors = self.meta(&["book:author"]);
+        }
+        if !authors.is_empty() {
+            book.authors = authors;
+        }
+
+        if let Some(description) = self.first(&[
+            "description",
+            "dc.description",
+            "dcterms.description",
+            "og:description",
+        ]) {
+            book.description = Some(description);
+        }
+
+        let tags: Vec<String> = self
+            .meta(&["keywords", "dc.subject", "dcterms.subject", "book:tag"])
+            .iter()
+            .flat_map(|k| k.split([',', ';']))
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        if !tags.is_empty() {
+            book.tags = tags;
+        }
+
+        if let Some(language) = self
+            .first(&["dc.language", "dcterms.language"])
+            .or_else(|| self.language.clone())
+        {
+            book.language = Some(language);
+        }
+        if let Some(publisher) = self.first(&["dc.publisher", "dcterms.publisher", "publisher"]) {
+            book.publisher = Some(publisher);
+        }
+        if let Some(date) = self.first(&[
+            "dc.date",
+            "dcterms.issued",
+            "dcterms.date",
+            "book:release_date",
+        ]) {
+            book.published = Some(date.split('T').next().unwrap_or(&date).to_string());
+        }
+
+        for (value, scheme) in self
+            .meta(&["dc.identifier", "dcterms.identifier"])
+            .into_iter()
+            .map(|v| (v, None))
+            .chain(
+                self.meta(&["book:isbn"])
+                    .into_iter()
+                    .map(|v| (v, Some("isbn"))),
+            )
+        {
+            let Some(id) = parse_identifier(&value, scheme.map(str::to_string)) else {
+                continue;
+            };
+            if id.scheme == "isbn" && book.isbn.is_none() {
+                book.isbn = Some(id.value.clone());
+            }
+            if !book.identifiers.contains(&id) {
+                book.identifiers.push(id);
+            }
+        }
+    }
+}
+
+/// Parse a tag at the start of `input`, returning it and its length.
+pub(super) fn parse_tag(input: &str) -> Option<(Tag<'_>, usize)> {
+    let body = input.strip_prefix('<')?;
+    let (closing, body) = match body.strip_prefix('/') {
+        Some(body) => (true, body),
+        None => (false, body),
+    };
+    // Declarations and processing instructions (<!DOCTYPE>, <?xml?>)
+    if body.starts_with(['!', '?']) {
+        let end = input.find('>')?;
+        return Some((
+            Tag {
+                name: String::new(),
+                closing: false,
+                self_closing: false,
+                attributes: Vec::new(),
+            },
+            end + 1,
+        ));
+    }
+
+    let name_len = body
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(body.len());
+    let name = &body[..name_len];
+    if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = &body[name_len..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if let Some(after) = rest.strip_prefix('>') {
+            let len = input.len() - after.len();
+            let tag = Tag {
+                name: name.to_lowercase(),
+                closing,
+                self_closing: input[..len - 1].ends_with('/'),
+                attributes,
+            };
+            return Some((tag, len));
+        }
+        if rest.is_empty() {
+            return None;
+        }
+
+        let key_len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len())
+            .max(1);
+        let key = rest[..key_len].to_lowercase();
+        rest = rest[key_len..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let inner = &after[1..];
+                        let end = inner.find(quote)?;
+                        rest = &inner[end + 1..];
+                        &inner[..end]
+                    }
+                    _ => {
+                        let end = after
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(after.len());
+                        rest = &after[end..];
+                        &after[..end]
+                    }
+                }
+            }
+            None => "",
+        };
+        attributes.push((key, value));
+    }
+}
+
+/// Charset declared by a `<meta charset>` or `http-equiv` tag.
+pub(super) fn declared_charset(data: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&data[..data.len().min(CHARSET_SCAN)]);
+    let mut rest = head.as_ref();
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        match parse_tag(rest) {
+            Some((tag, len)) => {
+                if tag.name == "meta" && !tag.closing {
+                    if let Some(charset) = tag.attribute("charset") {
+                        return Some(charset.trim().to_string());
+                    }
+                    // <meta http-equiv="Content-Type" content="text/html; charset=...">
+                    // ASCII lowercasing keeps byte offsets valid in `content`
+                    if let Some(content) = tag.attribute("content")
+                        && let Some(index) = content.to_ascii_lowercase().find("charset=")
+                    {
+                        let charset = &content[index + "charset=".len()..];
+                        let charset = charset.split(';').next().unwrap_or(charset);
+                        return Some(charset.trim().to_string());
+                    }
+                }
+                rest = &rest[len..];
+            }
+            None => rest = &rest[1..],
+        }
+    }
+
+    None
+}
+
+/// Collapse runs of whitespace into single spaces.
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Decode character references.
+pub(super) fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| {
+                let entity = &rest[1..end + 1];
+                let c = match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    _ => {
+                        let code = match entity.strip_prefix('#') {
+                            Some(hex) if hex.starts_with(['x', 'X']) => {
+                                u32::from_str_radix(&hex[1..], 16).ok()
+                            }
+                            Some(decimal) => decimal.parse().ok(),
+                            None => None,
+                        };
+                        code.and_then(char::from_u32)
+                    }
+                };
+                c.map(|c| (c, end + 2))
+            });
+
+        match decoded {
+            Some((c, len)) => {
+                result.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="fr">
+<head>
+  <meta charset="utf-8">
+  <title>Site &amp; Page</title>
+  <meta name="DC.title" content="Le Livre">
+  <meta name="author" content="Ann Author">
+  <meta name="author" content='Bob &quot;B&quot; Writer'>
+  <meta name="keywords" content="roman, aventure">
+  <meta name="description" content="Une  histoire.">
+  <meta name=DC.identifier content="urn:isbn:9780306406157">
+  <style>body { color: red; }</style>
+  <script>if (a < b) { document.write("hidden words"); }</script>
+</head>
+<body>
+  <!-- not counted -->
+  <p>Un deux <b>trois</b> quatre&nbsp;cinq.</p>
+</body>
+</html>"#;
+
+    #[test]
+    fn test_parse_and_apply() {
+        let document = Document::parse(PAGE);
+        assert_eq!(document.title.as_deref(), Some("Site & Page"));
+        assert_eq!(document.words, 5);
+        assert_eq!(document.text, "Un deux trois quatre cinq.");
+
+        let mut book = Book::default();
+        document.apply(&mut book);
+        assert_eq!(book.title, "Le Livre");
+        assert_eq!(book.authors, vec!["Ann Author", "Bob \"B\" Writer"]);
+        assert_eq!(book.tags, vec!["roman", "aventure"]);
+        assert_eq!(book.description.as_deref(), Some("Une histoire."));
+        assert_eq!(book.language.as_deref(), Some("fr"));
+        assert_eq!(book.isbn.as_deref(), Some("9780306406157"));
+    }
+
+    #[test]
+    fn test_declared_charset() {
+        assert_eq!(declared_charset(PAGE.as_bytes()).as_deref(), Some("utf-8"));
+        assert_eq!(
+            declared_charset(
+                br#"<meta http-equiv="Content-Type" content="text/html; charset=ISO-8859-1">"#
+            )
+            .as_deref(),
+            Some("ISO-8859-1")
+        );
+        assert_eq!(declared_charset(b"<p>no charset</p>"), None);
+        // Lowercasing "İ" changes its length
+        assert_eq!(
+            declared_charset(r#"<meta content="İİİİİİİİ Charset=koi8-r">"#.as_bytes()).as_deref(),
+            Some("koi8-r")
+        );
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(
+            unescape("a &lt;b&gt; &#233;&#xE9; &unknown; & c"),
+            "a <b> éé &unknown; & c"
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! Conversion of HTML into well-formed XHTML blocks for EPUB output.
+//!
+//! Only a safe subset of elements is kept: layout containers such as
+//! `<div>` are unwrapped, scripts and forms are dropped, unclosed elements
+//! are closed, and loose text is wrapped in paragraphs.
+
+use super::html::{parse_tag, unescape};
+use crate::formats::convert::Block;
+use quick_xml::escape::escape;
+
+/// How an HTML element is carried over.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// Block element kept as-is.
+    Block,
+    /// Inline element kept as-is.
+    Inline,
+    /// Element without content (`<br>`, `<hr>`).
+    Void,
+    /// Element dropped along with its content.
+    Skip,
+    /// Element dropped, keeping its content.
+    Transparent,
+}
+
+fn kind(name: &str) -> Kind {
+    match name {
+        "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "pre" | "ul" | "ol"
+        | "li" | "dl" | "dt" | "dd" | "table" | "thead" | "tbody" | "tfoot" | "tr" | "th"
+        | "td" | "caption" | "figure" | "figcaption" => Kind::Block,
+        "a" | "em" | "strong" | "b" | "i" | "u" | "s" | "sub" | "sup" | "code" | "span"
+        | "small" | "cite" | "q" | "abbr" | "del" | "ins" | "mark" | "kbd" | "var" | "samp" => {
+            Kind::Inline
+        }
+        "br" | "hr" => Kind::Void,
+        "head" | "script" | "style" | "template" | "title" | "noscript" | "iframe" | "svg"
+        | "math" | "object" | "form" | "select" | "textarea" | "button" | "canvas" | "audio"
+        | "video" => Kind::Skip,
+        _ => Kind::Transparent,
+    }
+}
+
+/// Heading level of `h1`..`h6`.
+fn heading_level(name: &str) -> Option<u8> {
+    match name.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some(level - b'0'),
+        _ => None,
+    }
+}
+
+/// Builds blocks from a stream of tags and text.
+#[derive(Default)]
+struct Builder {
+    blocks: Vec<Block>,
+    /// Open elements, outermost first.
+    stack: Vec<String>,
+    /// Markup of the current top-level element.
+    xhtml: String,
+    /// Plain text of the current top-level heading.
+    heading: String,
+}
+
+impl Builder {
+    fn open(&mut self, name: &str, attributes: &[(String, &str)]) {
+        match kind(name) {
+            Kind::Block => {
+                // Blocks cannot live inside paragraphs or inline elements
+                if let Some(p) = self.stack.iter().position(|e| e == "p") {
+                    self.close_to(p);
+                }
+                while self.stack.last().is_some_and(|e| kind(e) == Kind::Inline) {
+                    self.close_to(self.stack.len() - 1);
+                }
+                // A new item implicitly closes the previous one
+                let siblings: &[&str] = match name {
+                    "li" => &["li"],
+                    "dt" | "dd" => &["dt", "dd"],
+                    "tr" => &["tr"],
+                    "td" | "th" => &["td", "th"],
+                    _ => &[],
+                };
+                if let Some(last) = self.stack.last()
+                    && siblings.contains(&last.as_str())
+                {
+                    self.close_to(self.stack.len() - 1);
+                }
+            }
+            Kind::Inline => self.ensure_paragraph(),
+            _ => {}
+        }
+
+        self.xhtml.push('<');
+        self.xhtml.push_str(name);
+        for (key, value) in attributes {
+            let keep = match (name, key.as_str()) {
+                // External links only: local targets are not part of the book
+                ("a", "href") => ["http://", "https://", "mailto:"]
+                    .iter()
+                    .any(|scheme| value.starts_with(scheme)),
+                ("td" | "th", "colspan" | "rowspan") | ("ol", "start") => {
+                    value.chars().all(|c| c.is_ascii_digit())
+                }
+                _ => false,
+            };
+            if keep {
+                self.xhtml.push(' ');
+                self.xhtml.push_str(key);
+                self.xhtml.push_str("=\"");
+                self.xhtml.push_str(&escape(unescape(value)));
+                self.xhtml.push('"');
+            }
+        }
+        self.xhtml.push('>');
+        self.stack.push(name.to_string());
+    }
+
+    fn void(&mut self, name: &str) {
+        if name == "hr" {
+            if let Some(p) = self.stack.iter().position(|e| e == "p") {
+                self.close_to(p);
+            }
+        } else if self.stack.is_empty() {
+            // A line break between blocks has no meaning
+            return;
+        }
+        self.xhtml.push_str(&format!("<{}/>", name));
+        if self.stack.is_empty() {
+            self.flush();
+        }
+    }
+
+    /// Close an element if it is open.
+    fn close(&mut self, name: &str) {
+        if let Some(index) = self.stack.iter().rposition(|e| e == name) {
+            self.close_to(index);
+        }
+    }
+
+    /// Close the element at `index` of the stack and everything inside it.
+    fn close_to(&mut self, index: usize) {
+        while self.stack.len() > index {
+            if let Some(name) = self.stack.pop() {
+                self.xhtml.push_str("</");
+                self.xhtml.push_str(&name);
+                self.xhtml.push('>');
+            }
+        }
+        if self.stack.is_empty() {
+            self.flush();
+        }
+    }
+
+    /// End the paragraph implicitly opened around loose content, if any.
+    fn close_paragraph(&mut self) {
+        if let Some(p) = self.stack.iter().position(|e| e == "p") {
+            self.close_to(p);
+        }
+    }
+
+    fn ensure_paragraph(&mut self) {
+        if self.stack.is_empty() {
+            self.open("p", &[]);
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let text = unescape(text);
+        if self.stack.is_empty() && text.trim().is_empty() {
+            return;
+        }
+        self.ensure_paragraph();
+        if self.stack.iter().any(|e| heading_level(e).is_some()) {
+            self.heading.push_str(&text);
+        }
+        self.xhtml.push_str(&escape(text.as_str()));
+    }
+
+    /// Emit the finished top-level element as a block.
+    fn flush(&mut self) {
+        if self.xhtml.is_empty() {
+            return;
+        }
+        let xhtml = std::mem::take(&mut self.xhtml);
+        let heading = std::mem::take(&mut self.heading);
+        let level = xhtml
+            .get(1..3)
+            .and_then(heading_level)
+            .filter(|_| xhtml.as_bytes().get(3) == Some(&b'>'));
+
+        self.blocks.push(match level {
+            Some(level) => Block::Heading {
+                level,
+                text: heading.split_whitespace().collect::<Vec<_>>().join(" "),
+                xhtml,
+            },
+            None => Block::Content(xhtml),
+        });
+    }
+
+    fn finish(mut self) -> Vec<Block> {
+        self.close_to(0);
+        self.blocks
+    }
+}
+
+/// Convert an HTML document or fragment into XHTML blocks.
+pub(super) fn to_blocks(html: &str) -> Vec<Block> {
+    let mut builder = Builder::default();
+    let mut rest = html;
+    // Element whose content is being dropped
+    let mut skipping: Option<String> = None;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            builder.text(&rest[..start]);
+        }
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some((tag, len)) = parse_tag(rest) else {
+            if skipping.is_none() {
+                builder.text("&lt;");
+            }
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[len..];
+
+        if let Some(name) = &skipping {
+            if tag.closing && tag.name == *name {
+                skipping = None;
+            }
+            continue;
+        }
+        if tag.name.is_empty() {
+            // Doctype or processing instruction
+            continue;
+        }
+
+        let self_closing = tag.self_closing;
+        match (kind(&tag.name), tag.closing) {
+            (Kind::Skip, false) if !self_closing => skipping = Some(tag.name),
+            (Kind::Void, false) => builder.void(&tag.name),
+            (Kind::Block | Kind::Inline, false) => {
+                builder.open(&tag.name, &tag.attributes);
+                if self_closing {
+                    builder.close(&tag.name);
+                }
+            }
+            (Kind::Block | Kind::Inline, true) => builder.close(&tag.name),
+            (Kind::Transparent, _) => {
+                if tag.name == "img" {
+                    if let Some(alt) = tag.attribute("alt").filter(|a| !a.trim().is_empty()) {
+                        builder.text(alt);
+                    }
+                } else {
+                    // Layout containers separate paragraphs
+                    builder.close_paragraph();
+                }
+            }
+            _ => {}
+        }
+    }
+    if skipping.is_none() {
+        builder.text(rest);
+    }
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xhtml(blocks: &[Block]) -> Vec<&str> {
+        blocks
+            .iter()
+            .map(|b| match b {
+                Block::Heading { xhtml, .. } | Block::Content(xhtml) => xhtml.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let blocks = to_blocks(
+            "<html><head><title>T</title><style>p{}</style></head><body>\
+             <div class=x><h1 id=top>A &amp; <em>B</em></h1>Loose <b>text<p>Para<br>two\
+             <ul><li>one<li>two</ul><script>x < y</script>\
+             <a href=\"https://example.com/?a=1&amp;b=2\" onclick=\"x\">link</a>\
+             <a href=\"chapter2.html\">local</a><img src=x.png alt=\"An image\"></div>\
+             <hr><p>Last</body></html>",
+        );
+
+        assert_eq!(
+            blocks[0],
+            Block::Heading {
+                level: 1,
+                text: "A & B".to_string(),
+                xhtml: "<h1>A &amp; <em>B</em></h1>".to_string(),
+            }
+        );
+        assert_eq!(
+            xhtml(&blocks[1..]),
+            vec![
+                "<p>Loose <b>text</b></p>",
+                "<p>Para<br/>two</p>",
+                "<ul><li>one</li><li>two</li></ul>",
+                "<p><a href=\"https://example.com/?a=1&amp;b=2\">link</a>\
+                 <a>local</a>An image</p>",
+                "<hr/>",
+                "<p>Last</p>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_markup() {
+        let blocks = to_blocks("</p>text</em> <i>open <pre>a < b\n  c</i>");
+        assert_eq!(
+            xhtml(&blocks),
+            vec!["<p>text <i>open </i></p>", "<pre>a &lt; b\n  c</pre>"]
+        );
+    }
+}
@@ -1,4 +1,13 @@
 /// Book metadata model.
 pub mod book;
+/// Browsing books by metadata field.
+pub mod browse;
+/// Search query parsing.
+pub mod search;
+/// Directory-based category tree.
+pub mod tree;
 
-pub use book::{Book, Category};
+pub use book::{Book, Category, Contributor, Identifier, ReadingDirection};
+pub use browse::BrowseField;
+pub use search::SearchQuery;
+pub use tree::{CategoryNode, CategoryTree};
@@ -16,6 +16,10 @@ pub struct Book {
     /// Authors (may be empty).
     pub authors: Vec<String>,
 
+    /// Other contributors (illustrators, translators, editors...).
+    #[serde(default)]
+    pub contributors: Vec<Contributor>,
+
     /// Book description or summary.
     pub description: Option<String>,
 
@@ -28,9 +32,13 @@ pub struct Book {
     /// Language code (e.g., "en", "fr").
     pub language: Option<String>,
 
-    /// ISBN or other identifier.
+    /// ISBN (digits only).
     pub isbn: Option<String>,
 
+    /// All known identifiers (ISBN, ASIN, UUID...).
+    #[serde(default)]
+    pub identifiers: Vec<Identifier>,
+
     /// Series name.
     pub series: Option<String>,
 
@@ -57,16 +65,79 @@ pub struct Book {
 
     /// Number of pages (if known).
     pub page_count: Option<u32>,
+
+    /// Partial MD5 digest of the file (KOReader "binary" document digest).
+    #[serde(default)]
+    pub file_hash: Option<String>,
+
+    /// ID of the library containing the book.
+    #[serde(default)]
+    pub library_id: String,
+
+    /// Page progression (right-to-left for manga).
+    #[serde(default)]
+    pub reading_direction: ReadingDirection,
+}
+
+/// A person credited on a book in a role other than author.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contributor {
+    /// Contributor name.
+    pub name: String,
+    /// MARC relator code (e.g. "ill", "trl", "edt").
+    pub role: String,
+}
+
+/// A book identifier with its scheme.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identifier {
+    /// Lowercase scheme name (e.g. "isbn", "asin", "uuid").
+    pub scheme: String,
+    /// Identifier value, without any `urn:` prefix.
+    pub value: String,
+}
+
+impl Identifier {
+    /// Get the identifier as a URN (e.g. `urn:isbn:9780000000000`).
+    pub fn urn(&self) -> String {
+        format!("urn:{}:{}", self.scheme, self.value)
+    }
+}
+
+/// Page progression direction of a book.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingDirection {
+    /// Left to right.
+    #[default]
+    Ltr,
+    /// Right to left (manga).
+    Rtl,
+}
+
+impl ReadingDirection {
+    /// Get the direction as stored in the database ("ltr" or "rtl").
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadingDirection::Ltr => "ltr",
+            ReadingDirection::Rtl => "rtl",
+        }
+    }
+
+    /// Parse a stored direction, defaulting to left to right.
+    pub fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("rtl") {
+            ReadingDirection::Rtl
+        } else {
+            ReadingDirection::Ltr
+        }
+    }
 }
 
 impl Book {
     /// Create a new book with minimal information.
     pub fn new(path: PathBuf, format: BookFormat) -> Self {
-        let title = path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("Unknown")
-            .to_string();
+        let title = file_stem(&path).unwrap_or("Unknown").to_string();
 
         // Generate a deterministic UUID based on the file path
         let id = Uuid::new_v5(&Uuid::NAMESPACE_URL, path.to_string_lossy().as_bytes()).to_string();
@@ -75,11 +146,13 @@ impl Book {
             id,
             title,
             authors: Vec::new(),
+            contributors: Vec::new(),
             description: None,
             publisher: None,
             published: None,
             language: None,
             isbn: None,
+            identifiers: Vec::new(),
             series: None,
             series_index: None,
             tags: Vec::new(),
@@ -89,6 +162,9 @@ impl Book {
             modified: Utc::now(),
             has_cover: false,
             page_count: None,
+            file_hash: None,
+            library_id: String::new(),
+            reading_direction: ReadingDirection::Ltr,
         }
     }
 
@@ -100,6 +176,17 @@ impl Book {
             .unwrap_or("unknown")
     }
 
+    /// Get the MIME type of the file as stored on disk.
+    ///
+    /// Zipped FictionBook files (`.fb2.zip`) are served as archives.
+    pub fn mime_type(&self) -> &'static str {
+        if self.format == BookFormat::Fb2 && self.filename().to_lowercase().ends_with(".zip") {
+            "application/x-zip-compressed-fb2"
+        } else {
+            self.format.mime_type()
+        }
+    }
+
     /// Get display name for authors.
     pub fn authors_display(&self) -> String {
         if self.authors.is_empty() {
@@ -113,6 +200,68 @@ impl Book {
     pub fn relative_path(&self, library_root: &std::path::Path) -> Option<PathBuf> {
         self.path.strip_prefix(library_root).ok().map(PathBuf::from)
     }
+
+    /// Get the filename without its extension (`.fb2.zip` counts as one).
+    pub fn stem(&self) -> &str {
+        file_stem(&self.path).unwrap_or("book")
+    }
+
+    /// Get the MD5 digest of the filename (KOReader "filename" document digest).
+    pub fn filename_digest(&self) -> String {
+        format!("{:x}", md5::compute(self.filename()))
+    }
+}
+
+/// File name without its extension, dropping both parts of `.fb2.zip`.
+pub fn file_stem(path: &std::path::Path) -> Option<&str> {
+    let stem = path.file_stem()?.to_str()?;
+    let zipped_fb2 = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+        && stem.to_ascii_lowercase().ends_with(".fb2");
+    Some(if zipped_fb2 {
+        &stem[..stem.len() - ".fb2".len()]
+    } else {
+        stem
+    })
+}
+
+/// Compute KOReader's partial MD5 digest of a file.
+///
+/// Samples 1 KiB at offsets 0, 1 KiB, 4 KiB, 16 KiB... (`1024 << 2i`)
+/// up to 1 GiB, stopping at the end of the file. This matches
+/// `util.partialMD5` used by the KOReader progress sync plugin.
+pub fn partial_md5(path: &std::path::Path) -> std::io::Result<String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const STEP: u64 = 1024;
+    const SIZE: usize = 1024;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0u8; SIZE];
+
+    for i in -1i32..=10 {
+        // LuaJIT masks negative shift counts, so i = -1 reads at offset 0
+        let offset = if i < 0 { 0 } else { STEP << (2 * i) };
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut read = 0;
+        while read < SIZE {
+            let n = file.read(&mut buf[read..])?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+
+        if read == 0 {
+            break;
+        }
+        context.consume(&buf[..read]);
+    }
+
+    Ok(format!("{:x}", context.finalize()))
 }
 
 impl Default for Book {
@@ -121,11 +270,13 @@ impl Default for Book {
             id: String::new(),
             title: "Unknown".to_string(),
             authors: Vec::new(),
+            contributors: Vec::new(),
             description: None,
             publisher: None,
             published: None,
             language: None,
             isbn: None,
+            identifiers: Vec::new(),
             series: None,
             series_index: None,
             tags: Vec::new(),
@@ -135,6 +286,9 @@ impl Default for Book {
             modified: Utc::now(),
             has_cover: false,
             page_count: None,
+            file_hash: None,
+            library_id: String::new(),
+            reading_direction: ReadingDirection::Ltr,
         }
     }
 }
@@ -160,6 +314,9 @@ pub struct Category {
 
 impl Category {
     /// Create a new category from a directory path.
+    ///
+    /// The ID is derived from the absolute path, so folders with the same
+    /// relative path in different libraries stay distinct.
     pub fn new(path: PathBuf, library_root: &std::path::Path) -> Self {
         let name = path
             .file_name()
@@ -167,14 +324,13 @@ impl Category {
             .unwrap_or("Unknown")
             .to_string();
 
+        let id = Uuid::new_v5(&Uuid::NAMESPACE_URL, path.to_string_lossy().as_bytes()).to_string();
+
         let relative = path
             .strip_prefix(library_root)
             .unwrap_or(&path)
             .to_path_buf();
 
-        let id =
-            Uuid::new_v5(&Uuid::NAMESPACE_URL, relative.to_string_lossy().as_bytes()).to_string();
-
         Self {
             id,
             name,
@@ -0,0 +1,155 @@
+//! Browsing books by metadata field.
+//!
+//! Values are grouped case-insensitively, keeping the spelling of the first
+//! book seen, so "Discworld" and "discworld" make one series.
+
+use super::book::Book;
+use std::collections::HashMap;
+
+/// A metadata field the catalog can be browsed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowseField {
+    /// Book authors.
+    Author,
+    /// Series name.
+    Series,
+    /// Tags (subjects).
+    Tag,
+    /// Publisher.
+    Publisher,
+    /// Primary language subtag (`en` for `en-GB`).
+    Language,
+}
+
+impl BrowseField {
+    /// Every field, in catalog order.
+    pub const ALL: [Self; 5] = [
+        Self::Author,
+        Self::Series,
+        Self::Tag,
+        Self::Publisher,
+        Self::Language,
+    ];
+
+    /// Parse the URL path segment of a field.
+    pub fn parse(segment: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.segment() == segment)
+    }
+
+    /// URL path segment of the field, e.g. `authors`.
+    pub fn segment(self) -> &'static str {
+        match self {
+            Self::Author => "authors",
+            Self::Series => "series",
+            Self::Tag => "tags",
+            Self::Publisher => "publishers",
+            Self::Language => "languages",
+        }
+    }
+
+    /// Feed title listing the values.
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Author => "Authors",
+            Self::Series => "Series",
+            Self::Tag => "Tags",
+            Self::Publisher => "Publishers",
+            Self::Language => "Languages",
+        }
+    }
+
+    /// Values of the field for a book.
+    pub fn values(self, book: &Book) -> Vec<String> {
+        match self {
+            Self::Author => book.authors.clone(),
+            Self::Series => book.series.iter().cloned().collect(),
+            Self::Tag => book.tags.clone(),
+            Self::Publisher => book.publisher.iter().cloned().collect(),
+            Self::Language => book
+                .language
+                .iter()
+                .filter_map(|language| language.split(['-', '_']).next())
+                .map(str::to_lowercase)
+                .collect(),
+        }
+        .into_iter()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+    }
+
+    /// Whether a book has a value (case-insensitive).
+    pub fn matches(self, book: &Book, value: &str) -> bool {
+        let value = value.trim().to_lowercase();
+        self.values(book).iter().any(|v| v.to_lowercase() == value)
+    }
+
+    /// Distinct values among books with their book counts, sorted by value.
+    pub fn distinct(self, books: &[Book]) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, (String, usize)> = HashMap::new();
+        for book in books {
+            let mut seen = Vec::new();
+            for value in self.values(book) {
+                let key = value.to_lowercase();
+                // A book lists each value once, whatever its duplicates
+                if seen.contains(&key) {
+                    continue;
+                }
+                seen.push(key.clone());
+                counts.entry(key).or_insert((value, 0)).1 += 1;
+            }
+        }
+
+        let mut values: Vec<(String, (String, usize))> = counts.into_iter().collect();
+        values.sort_by(|(a, _), (b, _)| a.cmp(b));
+        values.into_iter().map(|(_, value)| value).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BookFormat;
+
+    #[test]
+    fn test_distinct_values() {
+        let mut a = Book::new("/books/a.epub".into(), BookFormat::Epub);
+        a.authors = vec!["Terry Pratchett".to_string(), "Neil Gaiman".to_string()];
+        a.series = Some("Discworld".to_string());
+        a.language = Some("en-GB".to_string());
+        let mut b = Book::new("/books/b.epub".into(), BookFormat::Epub);
+        b.authors = vec!["terry pratchett".to_string()];
+        b.series = Some("discworld ".to_string());
+        b.language = Some("EN".to_string());
+        b.tags = vec!["Fantasy".to_string(), "fantasy".to_string()];
+        let books = vec![a, b];
+
+        assert_eq!(
+            BrowseField::Author.distinct(&books),
+            vec![
+                ("Neil Gaiman".to_string(), 1),
+                ("Terry Pratchett".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            BrowseField::Series.distinct(&books),
+            vec![("Discworld".to_string(), 2)]
+        );
+        assert_eq!(
+            BrowseField::Tag.distinct(&books),
+            vec![("Fantasy".to_string(), 1)]
+        );
+        assert_eq!(
+            BrowseField::Language.distinct(&books),
+            vec![("en".to_string(), 2)]
+        );
+        assert!(BrowseField::Publisher.distinct(&books).is_empty());
+
+        assert!(BrowseField::Series.matches(&books[1], "DISCWORLD"));
+        assert!(!BrowseField::Author.matches(&books[1], "Neil Gaiman"));
+        assert_eq!(BrowseField::parse("tags"), Some(BrowseField::Tag));
+        assert_eq!(BrowseField::parse("tag"), None);
+    }
+}
@@ -0,0 +1,265 @@
+//! Search query parsing.
+//!
+//! Queries are plain words, quoted phrases and `field:value` pairs, e.g.
+//! `author:tolkien series:"Discworld" lang:fr format:epub`. Text terms become
+//! an FTS5 match expression; `lang:` and `format:` filter the results.
+
+use super::book::Book;
+use crate::config::BookFormat;
+
+/// A parsed search query.
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    terms: Vec<Term>,
+    languages: Vec<String>,
+    formats: Vec<String>,
+}
+
+/// A text term, optionally restricted to one index column.
+#[derive(Debug, PartialEq)]
+struct Term {
+    column: Option<&'static str>,
+    text: String,
+    phrase: bool,
+}
+
+/// Where a `field:` prefix sends its value.
+enum Field {
+    Column(&'static str),
+    Language,
+    Format,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "title" => Self::Column("title"),
+            "author" | "authors" | "by" => Self::Column("authors"),
+            "series" => Self::Column("series"),
+            "tag" | "tags" | "subject" | "genre" => Self::Column("tags"),
+            "publisher" => Self::Column("publisher"),
+            "description" | "desc" => Self::Column("description"),
+            "isbn" => Self::Column("isbn"),
+            "lang" | "language" => Self::Language,
+            "format" | "ext" => Self::Format,
+            _ => return None,
+        })
+    }
+}
+
+impl SearchQuery {
+    /// Parse a query string.
+    ///
+    /// Unknown fields are searched as ordinary words, so titles such as
+    /// "Re:Zero" still match.
+    pub fn parse(query: &str) -> Self {
+        let mut parsed = Self::default();
+        let mut chars = query.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            if c == '"' {
+                chars.next();
+                let text = read_quoted(&mut chars);
+                parsed.push_term(None, text, true);
+                continue;
+            }
+
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || (c == '"' && word.ends_with(':')) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+
+            let field = word
+                .split_once(':')
+                .and_then(|(name, value)| Some((Field::parse(name)?, value)));
+            let Some((field, value)) = field else {
+                parsed.push_term(None, word, false);
+                continue;
+            };
+
+            let (value, phrase) = if value.is_empty() && chars.peek() == Some(&'"') {
+                chars.next();
+                (read_quoted(&mut chars), true)
+            } else {
+                (value.to_string(), false)
+            };
+            match field {
+                Field::Column(column) => parsed.push_term(Some(column), value, phrase),
+                Field::Language if !value.is_empty() => parsed.languages.push(value.to_lowercase()),
+                Field::Format if !value.is_empty() => parsed.formats.push(value.to_lowercase()),
+                _ => {}
+            }
+        }
+
+        parsed
+    }
+
+    fn push_term(&mut self, column: Option<&'static str>, text: String, phrase: bool) {
+        // Punctuation alone has no tokens to match
+        if !text.chars().any(char::is_alphanumeric) {
+            return;
+        }
+        // ISBNs are indexed without separators
+        let text = if column == Some("isbn") || (column.is_none() && looks_like_isbn(&text)) {
+            text.chars().filter(char::is_ascii_alphanumeric).collect()
+        } else {
+            text
+        };
+        self.terms.push(Term {
+            column,
+            text,
+            phrase,
+        });
+    }
+
+    /// Whether the query has no text terms and no filters.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.languages.is_empty() && self.formats.is_empty()
+    }
+
+    /// FTS5 match expression for the text terms, if there are any.
+    ///
+    /// Every term is quoted so user input never reaches the FTS5 query
+    /// syntax; unquoted words also match as prefixes.
+    pub fn fts_expression(&self) -> Option<String> {
+        self.expression(|_| true)
+    }
+
+    /// Match expression for the words without a field, for content search.
+    pub fn text_expression(&self) -> Option<String> {
+        self.expression(|term| term.column.is_none())
+    }
+
+    /// Match expression for the `field:` terms only.
+    pub fn field_expression(&self) -> Option<String> {
+        self.expression(|term| term.column.is_some())
+    }
+
+    fn expression(&self, include: impl Fn(&Term) -> bool) -> Option<String> {
+        let terms: Vec<String> = self
+            .terms
+            .iter()
+            .filter(|term| include(term))
+            .map(|term| {
+                let mut expr = String::new();
+                if let Some(column) = term.column {
+                    expr.push_str(column);
+                    expr.push_str(" : ");
+                }
+                expr.push('"');
+                expr.push_str(&term.text.replace('"', "\"\""));
+                expr.push('"');
+                if !term.phrase {
+                    expr.push('*');
+                }
+                expr
+            })
+            .collect();
+        (!terms.is_empty()).then(|| terms.join(" "))
+    }
+
+    /// Check the `lang:` and `format:` filters against a book.
+    ///
+    /// Languages match on their primary subtag, so `lang:en` finds `en-GB`.
+    pub fn matches(&self, book: &Book) -> bool {
+        let language_ok = self.languages.is_empty()
+            || book.language.as_deref().is_some_and(|language| {
+                self.languages
+                    .iter()
+                    .any(|wanted| language_matches(language, wanted))
+            });
+        let format_ok = self.formats.is_empty()
+            || self
+                .formats
+                .iter()
+                .any(|format| BookFormat::from_extension(format) == Some(book.format));
+        language_ok && format_ok
+    }
+}
+
+/// Whether a language tag matches a wanted (lowercase) language.
+///
+/// Languages match on their primary subtag, so `en` matches `en-GB`.
+pub fn language_matches(language: &str, wanted: &str) -> bool {
+    let language = language.to_lowercase();
+    language == wanted
+        || language
+            .strip_prefix(wanted)
+            .is_some_and(|rest| rest.starts_with(['-', '_']))
+}
+
+/// Read up to the closing quote (or the end of the query).
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            break;
+        }
+        text.push(c);
+    }
+    text
+}
+
+/// Whether a bare word is an ISBN written with separators.
+fn looks_like_isbn(word: &str) -> bool {
+    let digits: String = word.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    word.contains('-')
+        && matches!(digits.len(), 10 | 13)
+        && digits
+            .chars()
+            .enumerate()
+            .all(|(i, c)| c.is_ascii_digit() || (i == digits.len() - 1 && matches!(c, 'x' | 'X')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fields() {
+        let query =
+            SearchQuery::parse(r#"author:tolkien series:"Discworld" lang:FR format:epub ring"#);
+        assert_eq!(
+            query.fts_expression().as_deref(),
+            Some(r#"authors : "tolkien"* series : "Discworld" "ring"*"#)
+        );
+        assert_eq!(query.text_expression().as_deref(), Some(r#""ring"*"#));
+        assert_eq!(
+            query.field_expression().as_deref(),
+            Some(r#"authors : "tolkien"* series : "Discworld""#)
+        );
+        assert_eq!(query.languages, vec!["fr"]);
+        assert_eq!(query.formats, vec!["epub"]);
+    }
+
+    #[test]
+    fn test_parse_plain_text() {
+        let query = SearchQuery::parse(r#""the hobbit" Re:Zero 978-0-306-40615-7 - x"y"#);
+        assert_eq!(
+            query.fts_expression().as_deref(),
+            Some(r#""the hobbit" "Re:Zero"* "9780306406157"* "x""y"*"#)
+        );
+        assert!(SearchQuery::parse("  ").is_empty());
+        assert_eq!(SearchQuery::parse("format:cbz").fts_expression(), None);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut book = Book::new("/books/a.epub".into(), BookFormat::Epub);
+        book.language = Some("en-GB".to_string());
+
+        assert!(SearchQuery::parse("lang:en format:epub").matches(&book));
+        assert!(!SearchQuery::parse("lang:e").matches(&book));
+        assert!(!SearchQuery::parse("format:pdf").matches(&book));
+        assert!(SearchQuery::parse("format:pdf format:epub").matches(&book));
+    }
+}
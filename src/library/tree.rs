@@ -0,0 +1,171 @@
+use crate::db::Library;
+use crate::library::book::{Book, Category};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A folder in the category tree.
+#[derive(Debug, Clone)]
+pub struct CategoryNode {
+    /// Category details (name, counts).
+    pub category: Category,
+    /// ID of the library containing the folder.
+    pub library_id: String,
+    /// Parent category ID (None for library roots).
+    pub parent: Option<String>,
+    /// Direct subcategory IDs, sorted by name.
+    pub children: Vec<String>,
+    /// IDs of books stored directly in this folder.
+    pub books: Vec<String>,
+}
+
+/// Browse-by-folder hierarchy: library → subfolder → books.
+#[derive(Debug, Clone, Default)]
+pub struct CategoryTree {
+    nodes: HashMap<String, CategoryNode>,
+    roots: Vec<String>,
+}
+
+impl CategoryTree {
+    /// Build the tree from libraries and their books.
+    ///
+    /// Only folders containing at least one book (directly or in a
+    /// subfolder) appear in the tree.
+    pub fn build(libraries: &[Library], books: &[Book]) -> Self {
+        let mut tree = Self::default();
+
+        for library in libraries {
+            let root_path = PathBuf::from(&library.path);
+            let mut root = Category::new(root_path.clone(), &root_path);
+            root.name = library.name.clone();
+            let root_id = root.id.clone();
+
+            tree.nodes.insert(
+                root_id.clone(),
+                CategoryNode {
+                    category: root,
+                    library_id: library.id.clone(),
+                    parent: None,
+                    children: Vec::new(),
+                    books: Vec::new(),
+                },
+            );
+            tree.roots.push(root_id.clone());
+
+            for book in books.iter().filter(|b| b.library_id == library.id) {
+                let Some(folder) = book
+                    .path
+                    .parent()
+                    .and_then(|p| p.strip_prefix(&root_path).ok())
+                else {
+                    continue;
+                };
+
+                let node_id = tree.insert_folder(&root_id, &root_path, folder, &library.id);
+                if let Some(node) = tree.nodes.get_mut(&node_id) {
+                    node.books.push(book.id.clone());
+                }
+            }
+        }
+
+        // Book counts include subfolders
+        let roots = tree.roots.clone();
+        for root in &roots {
+            tree.count_books(root);
+        }
+
+        let names: HashMap<String, String> = tree
+            .nodes
+            .iter()
+            .map(|(id, node)| (id.clone(), node.category.name.to_lowercase()))
+            .collect();
+        for node in tree.nodes.values_mut() {
+            node.children.sort_by_key(|id| names.get(id).cloned());
+            node.category.subcategory_count = node.children.len();
+        }
+
+        tree
+    }
+
+    /// Create the nodes for `folder` (relative to the library root) and its
+    /// ancestors, returning the ID of the deepest one.
+    fn insert_folder(
+        &mut self,
+        root_id: &str,
+        root_path: &Path,
+        folder: &Path,
+        library_id: &str,
+    ) -> String {
+        let mut parent_id = root_id.to_string();
+        let mut path = root_path.to_path_buf();
+
+        for component in folder.components() {
+            path.push(component);
+            let category = Category::new(path.clone(), root_path);
+            let id = category.id.clone();
+
+            if !self.nodes.contains_key(&id) {
+                self.nodes.insert(
+                    id.clone(),
+                    CategoryNode {
+                        category,
+                        library_id: library_id.to_string(),
+                        parent: Some(parent_id.clone()),
+                        children: Vec::new(),
+                        books: Vec::new(),
+                    },
+                );
+                if let Some(parent) = self.nodes.get_mut(&parent_id) {
+                    parent.children.push(id.clone());
+                }
+            }
+
+            parent_id = id;
+        }
+
+        parent_id
+    }
+
+    /// Compute recursive book counts, returning the count for `id`.
+    fn count_books(&mut self, id: &str) -> usize {
+        let Some(node) = self.nodes.get(id) else {
+            return 0;
+        };
+        let children = node.children.clone();
+        let mut count = node.books.len();
+
+        for child in &children {
+            count += self.count_books(child);
+        }
+
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.category.book_count = count;
+        }
+        count
+    }
+
+    /// Get a category by ID.
+    pub fn get(&self, id: &str) -> Option<&CategoryNode> {
+        self.nodes.get(id)
+    }
+
+    /// Library root categories, in library order.
+    pub fn roots(&self) -> Vec<&CategoryNode> {
+        self.roots
+            .iter()
+            .filter_map(|id| self.nodes.get(id))
+            .collect()
+    }
+
+    /// Direct subcategories of a category, sorted by name.
+    pub fn children(&self, id: &str) -> Vec<&CategoryNode> {
+        self.nodes
+            .get(id)
+            .map(|node| {
+                node.children
+                    .iter()
+                    .filter_map(|child| self.nodes.get(child))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -149,7 +149,12 @@ async fn cmd_library(action: LibraryCommand, config: &Config) -> anyhow::Result<
     let db = Database::open(&config.database.path)?;
 
     match action {
-        LibraryCommand::Add { name, path, public } => {
+        LibraryCommand::Add {
+            name,
+            path,
+            public,
+            content_index,
+        } => {
             // Validate path
             if !path.exists() {
                 anyhow::bail!("Path does not exist: {}", path.display());
@@ -165,6 +170,7 @@ async fn cmd_library(action: LibraryCommand, config: &Config) -> anyhow::Result<
                 is_public: public,
                 owner_id: None,
                 created_at: ebook_rs::db::now_timestamp(),
+                content_index,
             };
 
             db.create_library(&library)?;
@@ -189,19 +195,47 @@ async fn cmd_library(action: LibraryCommand, config: &Config) -> anyhow::Result<
             if libraries.is_empty() {
                 println!("No libraries found.");
             } else {
-                println!("{:<20} {:<50} PUBLIC", "NAME", "PATH");
-                println!("{}", "-".repeat(80));
+                println!("{:<20} {:<50} {:<7} CONTENT", "NAME", "PATH", "PUBLIC");
+                println!("{}", "-".repeat(88));
                 for lib in libraries {
                     println!(
-                        "{:<20} {:<50} {}",
+                        "{:<20} {:<50} {:<7} {}",
                         lib.name,
                         lib.path,
-                        if lib.is_public { "yes" } else { "no" }
+                        if lib.is_public { "yes" } else { "no" },
+                        if lib.content_index { "yes" } else { "no" }
                     );
                 }
             }
         }
 
+        LibraryCommand::Grant { name, username } => {
+            let library = db
+                .get_library_by_name(&name)?
+                .ok_or_else(|| anyhow::anyhow!("Library not found: {}", name))?;
+            let user = db
+                .get_user_by_username(&username)?
+                .ok_or_else(|| anyhow::anyhow!("User not found: {}", username))?;
+
+            db.grant_library_access(&user.id, &library.id, false)?;
+            println!("Granted {} access to library: {}", username, name);
+        }
+
+        LibraryCommand::Revoke { name, username } => {
+            let library = db
+                .get_library_by_name(&name)?
+                .ok_or_else(|| anyhow::anyhow!("Library not found: {}", name))?;
+            let user = db
+                .get_user_by_username(&username)?
+                .ok_or_else(|| anyhow::anyhow!("User not found: {}", username))?;
+
+            if db.revoke_library_access(&user.id, &library.id)? {
+                println!("Revoked {} access to library: {}", username, name);
+            } else {
+                println!("{} had no access to library: {}", username, name);
+            }
+        }
+
         LibraryCommand::Scan { all, name } => {
             let libraries = if all {
                 db.list_libraries()?
@@ -279,6 +313,14 @@ async fn cmd_serve(
                 );
                 db.update_library_path(&lib_config.name, &path)?;
             }
+            if existing.content_index != lib_config.content_index {
+                tracing::info!(
+                    name = %lib_config.name,
+                    enabled = lib_config.content_index,
+                    "Updating library content indexing"
+                );
+                db.set_library_content_index(&lib_config.name, lib_config.content_index)?;
+            }
         } else {
             // Create new library
             tracing::info!(
@@ -294,6 +336,7 @@ async fn cmd_serve(
                 is_public: lib_config.public,
                 owner_id: None,
                 created_at: ebook_rs::db::now_timestamp(),
+                content_index: lib_config.content_index,
             };
             db.create_library(&library)?;
         }
@@ -318,6 +361,7 @@ async fn cmd_serve(
                 is_public: true,
                 owner_id: None,
                 created_at: ebook_rs::db::now_timestamp(),
+                content_index: false,
             };
             db.create_library(&library)?;
         } else {
@@ -1,3 +1,6 @@
+/// OPDS 2.0 (JSON) feeds.
+pub mod v2;
+
 use crate::library::Book;
 use chrono::{DateTime, Utc};
 use quick_xml::Writer;
@@ -29,6 +32,12 @@ pub struct Entry {
     pub updated: DateTime<Utc>,
     /// Authors list.
     pub authors: Vec<String>,
+    /// Contributors other than authors.
+    #[serde(default)]
+    pub contributors: Vec<String>,
+    /// Identifier URNs (e.g. `urn:isbn:...`).
+    #[serde(default)]
+    pub identifiers: Vec<String>,
     /// Short summary text.
     pub summary: Option<String>,
     /// Full content/description.
@@ -39,6 +48,94 @@ pub struct Entry {
     pub categories: Vec<String>,
 }
 
+/// One choice of a facet group (e.g. "EPUB" in "Format").
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Facet {
+    /// Name of the group the facet belongs to.
+    pub group: String,
+    /// Facet label.
+    pub title: String,
+    /// URL of the feed with this facet applied.
+    pub href: String,
+    /// Number of items the facet selects, if known.
+    pub count: Option<usize>,
+    /// Whether the facet is applied to the current feed.
+    pub active: bool,
+}
+
+/// Acquisition feed MIME type (used for pagination links).
+const ACQUISITION_MIME: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// Navigation feed MIME type.
+const NAVIGATION_MIME: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+
+/// Position of a feed page within a result set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    /// Index of the first item on this page (0-based).
+    pub offset: usize,
+    /// Maximum number of items per page.
+    pub per_page: usize,
+    /// Total number of items across all pages.
+    pub total: usize,
+}
+
+impl Pagination {
+    /// Create pagination from optional `page` (1-based) or `offset` query values.
+    ///
+    /// `offset` takes precedence over `page` when both are given. Offsets
+    /// past the end are clamped to `total`, so the page is empty.
+    pub fn new(page: Option<usize>, offset: Option<usize>, per_page: usize, total: usize) -> Self {
+        let per_page = per_page.max(1);
+        let offset = offset
+            .unwrap_or_else(|| page.unwrap_or(1).saturating_sub(1).saturating_mul(per_page))
+            .min(total);
+
+        Self {
+            offset,
+            per_page,
+            total,
+        }
+    }
+
+    /// Items of this page.
+    pub fn slice<T>(&self, items: Vec<T>) -> Vec<T> {
+        items
+            .into_iter()
+            .skip(self.offset)
+            .take(self.per_page)
+            .collect()
+    }
+
+    /// Offset of the last page.
+    pub fn last_offset(&self) -> usize {
+        self.total.saturating_sub(1) / self.per_page * self.per_page
+    }
+
+    /// Offset of the previous page, if any.
+    pub fn previous_offset(&self) -> Option<usize> {
+        (self.offset > 0).then(|| self.offset.saturating_sub(self.per_page))
+    }
+
+    /// Offset of the next page, if any.
+    pub fn next_offset(&self) -> Option<usize> {
+        let next = self.offset.saturating_add(self.per_page);
+        (next < self.total).then_some(next)
+    }
+
+    /// Build the URL of the page starting at `offset`.
+    ///
+    /// Aligned offsets use `page=N`, others keep the raw `offset=N`.
+    pub fn href(&self, base: &str, offset: usize) -> String {
+        let separator = if base.contains('?') { '&' } else { '?' };
+        if offset.is_multiple_of(self.per_page) {
+            format!("{}{}page={}", base, separator, offset / self.per_page + 1)
+        } else {
+            format!("{}{}offset={}", base, separator, offset)
+        }
+    }
+}
+
 /// OPDS feed builder.
 pub struct FeedBuilder {
     id: String,
@@ -47,6 +144,8 @@ pub struct FeedBuilder {
     author_name: Option<String>,
     links: Vec<Link>,
     entries: Vec<Entry>,
+    facets: Vec<Facet>,
+    pagination: Option<Pagination>,
 }
 
 impl FeedBuilder {
@@ -59,6 +158,8 @@ impl FeedBuilder {
             author_name: None,
             links: Vec::new(),
             entries: Vec::new(),
+            facets: Vec::new(),
+            pagination: None,
         }
     }
 
@@ -90,6 +191,17 @@ impl FeedBuilder {
         self
     }
 
+    /// Add an up link (parent feed).
+    pub fn up_link(mut self, href: impl Into<String>) -> Self {
+        self.links.push(Link {
+            rel: "up".to_string(),
+            href: href.into(),
+            link_type: "application/atom+xml;profile=opds-catalog".to_string(),
+            title: None,
+        });
+        self
+    }
+
     /// Add a search link.
     pub fn search_link(mut self, href: impl Into<String>) -> Self {
         self.links.push(Link {
@@ -101,46 +213,96 @@ impl FeedBuilder {
         self
     }
 
+    /// Add first/previous/next/last links and OpenSearch result counts.
+    ///
+    /// `base` is the feed URL without pagination parameters.
+    pub fn paginate(mut self, pagination: Pagination, base: &str) -> Self {
+        let mut rels = vec![("first", 0)];
+        if let Some(previous) = pagination.previous_offset() {
+            rels.push(("previous", previous));
+        }
+        if let Some(next) = pagination.next_offset() {
+            rels.push(("next", next));
+        }
+        rels.push(("last", pagination.last_offset()));
+
+        for (rel, offset) in rels {
+            self.links.push(Link {
+                rel: rel.to_string(),
+                href: pagination.href(base, offset),
+                link_type: ACQUISITION_MIME.to_string(),
+                title: None,
+            });
+        }
+
+        self.pagination = Some(pagination);
+        self
+    }
+
+    /// Add facet links (`rel="http://opds-spec.org/facet"`).
+    pub fn facets(mut self, facets: Vec<Facet>) -> Self {
+        self.facets.extend(facets);
+        self
+    }
+
     /// Add a navigation entry.
     pub fn navigation_entry(mut self, entry: Entry) -> Self {
         self.entries.push(entry);
         self
     }
 
+    /// Add a navigation entry linking to another feed.
+    ///
+    /// `acquisition` tells whether the linked feed lists books rather than
+    /// more feeds.
+    pub fn subsection_entry(
+        self,
+        id: impl Into<String>,
+        title: impl Into<String>,
+        summary: Option<String>,
+        href: impl Into<String>,
+        acquisition: bool,
+    ) -> Self {
+        let title = title.into();
+        self.navigation_entry(Entry {
+            id: id.into(),
+            title: title.clone(),
+            updated: Utc::now(),
+            authors: Vec::new(),
+            contributors: Vec::new(),
+            identifiers: Vec::new(),
+            summary,
+            content: None,
+            links: vec![Link {
+                rel: "subsection".to_string(),
+                href: href.into(),
+                link_type: if acquisition {
+                    ACQUISITION_MIME
+                } else {
+                    NAVIGATION_MIME
+                }
+                .to_string(),
+                title: Some(title),
+            }],
+            categories: Vec::new(),
+        })
+    }
+
     /// Add a book entry.
     pub fn book_entry(mut self, book: &Book, base_url: &str) -> Self {
-        let mut links = vec![
-            Link {
-                rel: "http://opds-spec.org/acquisition".to_string(),
-                href: format!("{}/books/{}/download", base_url, book.id),
-                link_type: book.format.mime_type().to_string(),
-                title: Some("Download".to_string()),
-            },
-            Link {
-                rel: "http://opds-spec.org/image".to_string(),
-                href: format!("{}/books/{}/cover", base_url, book.id),
-                link_type: "image/png".to_string(),
-                title: None,
-            },
-            Link {
-                rel: "http://opds-spec.org/image/thumbnail".to_string(),
-                href: format!("{}/books/{}/thumbnail", base_url, book.id),
-                link_type: "image/png".to_string(),
-                title: None,
-            },
-        ];
+        let mut links = book_links(book, base_url);
 
-        // Add series link if available
-        if book.series.is_some() {
+        // Other books of the series, in reading order
+        if let Some(series) = &book.series {
             links.push(Link {
                 rel: "related".to_string(),
                 href: format!(
-                    "{}/catalog/search?q={}",
+                    "{}/catalog/series/{}",
                     base_url,
-                    urlencoding::encode(book.series.as_ref().unwrap_or(&String::new()))
+                    urlencoding::encode(series)
                 ),
-                link_type: "application/atom+xml;profile=opds-catalog".to_string(),
-                title: Some("Series".to_string()),
+                link_type: ACQUISITION_MIME.to_string(),
+                title: Some(format!("Series: {}", series)),
             });
         }
 
@@ -149,6 +311,8 @@ impl FeedBuilder {
             title: book.title.clone(),
             updated: book.modified,
             authors: book.authors.clone(),
+            contributors: book.contributors.iter().map(|c| c.name.clone()).collect(),
+            identifiers: book.identifiers.iter().map(|i| i.urn()).collect(),
             summary: book.description.clone(),
             content: None,
             links,
@@ -166,6 +330,8 @@ impl FeedBuilder {
             title: category.name.clone(),
             updated: Utc::now(),
             authors: Vec::new(),
+            contributors: Vec::new(),
+            identifiers: Vec::new(),
             summary: Some(format!(
                 "{} books, {} subcategories",
                 category.book_count, category.subcategory_count
@@ -196,6 +362,8 @@ impl FeedBuilder {
         feed.push_attribute(("xmlns", "http://www.w3.org/2005/Atom"));
         feed.push_attribute(("xmlns:opds", "http://opds-spec.org/2010/catalog"));
         feed.push_attribute(("xmlns:dc", "http://purl.org/dc/elements/1.1/"));
+        feed.push_attribute(("xmlns:opensearch", "http://a9.com/-/spec/opensearch/1.1/"));
+        feed.push_attribute(("xmlns:thr", "http://purl.org/syndication/thread/1.0"));
         let _ = writer.write_event(Event::Start(feed));
 
         // ID
@@ -214,10 +382,32 @@ impl FeedBuilder {
             let _ = writer.write_event(Event::End(BytesEnd::new("author")));
         }
 
+        // OpenSearch result counts
+        if let Some(pagination) = &self.pagination {
+            write_text_element(
+                &mut writer,
+                "opensearch:totalResults",
+                &pagination.total.to_string(),
+            );
+            write_text_element(
+                &mut writer,
+                "opensearch:itemsPerPage",
+                &pagination.per_page.to_string(),
+            );
+            write_text_element(
+                &mut writer,
+                "opensearch:startIndex",
+                &(pagination.offset + 1).to_string(),
+            );
+        }
+
         // Links
         for link in &self.links {
             write_link(&mut writer, link);
         }
+        for facet in &self.facets {
+            write_facet(&mut writer, facet);
+        }
 
         // Entries
         for entry in &self.entries {
@@ -231,6 +421,51 @@ impl FeedBuilder {
     }
 }
 
+/// Acquisition and image links of a book, shared by both feed versions.
+fn book_links(book: &Book, base_url: &str) -> Vec<Link> {
+    let mut links = vec![
+        Link {
+            rel: "http://opds-spec.org/acquisition".to_string(),
+            href: format!("{}/books/{}/download", base_url, book.id),
+            link_type: book.mime_type().to_string(),
+            title: Some("Download".to_string()),
+        },
+        Link {
+            rel: "http://opds-spec.org/image".to_string(),
+            href: format!("{}/books/{}/cover", base_url, book.id),
+            link_type: "image/png".to_string(),
+            title: None,
+        },
+        Link {
+            rel: "http://opds-spec.org/image/thumbnail".to_string(),
+            href: format!("{}/books/{}/thumbnail", base_url, book.id),
+            link_type: "image/png".to_string(),
+            title: None,
+        },
+    ];
+
+    // Text-like books and comics are also offered as EPUB for readers
+    // without support for the original format
+    if crate::formats::convert::can_convert(book.format) {
+        let title = if crate::formats::convert::is_fixed_layout(book.format) {
+            "EPUB (fixed layout)"
+        } else {
+            "EPUB"
+        };
+        links.insert(
+            1,
+            Link {
+                rel: "http://opds-spec.org/acquisition".to_string(),
+                href: format!("{}/books/{}/convert/epub", base_url, book.id),
+                link_type: "application/epub+zip".to_string(),
+                title: Some(title.to_string()),
+            },
+        );
+    }
+
+    links
+}
+
 /// Write a simple text element.
 fn write_text_element<W: std::io::Write>(writer: &mut Writer<W>, name: &str, text: &str) {
     let _ = writer.write_event(Event::Start(BytesStart::new(name)));
@@ -250,6 +485,23 @@ fn write_link<W: std::io::Write>(writer: &mut Writer<W>, link: &Link) {
     let _ = writer.write_event(Event::Empty(elem));
 }
 
+/// Write a facet link element.
+fn write_facet<W: std::io::Write>(writer: &mut Writer<W>, facet: &Facet) {
+    let mut elem = BytesStart::new("link");
+    elem.push_attribute(("rel", "http://opds-spec.org/facet"));
+    elem.push_attribute(("href", facet.href.as_str()));
+    elem.push_attribute(("type", ACQUISITION_MIME));
+    elem.push_attribute(("title", facet.title.as_str()));
+    elem.push_attribute(("opds:facetGroup", facet.group.as_str()));
+    if facet.active {
+        elem.push_attribute(("opds:activeFacet", "true"));
+    }
+    if let Some(count) = facet.count {
+        elem.push_attribute(("thr:count", count.to_string().as_str()));
+    }
+    let _ = writer.write_event(Event::Empty(elem));
+}
+
 /// Write an entry element.
 fn write_entry<W: std::io::Write>(writer: &mut Writer<W>, entry: &Entry) {
     let _ = writer.write_event(Event::Start(BytesStart::new("entry")));
@@ -265,6 +517,18 @@ fn write_entry<W: std::io::Write>(writer: &mut Writer<W>, entry: &Entry) {
         let _ = writer.write_event(Event::End(BytesEnd::new("author")));
     }
 
+    // Contributors
+    for contributor in &entry.contributors {
+        let _ = writer.write_event(Event::Start(BytesStart::new("contributor")));
+        write_text_element(writer, "name", contributor);
+        let _ = writer.write_event(Event::End(BytesEnd::new("contributor")));
+    }
+
+    // Identifiers
+    for identifier in &entry.identifiers {
+        write_text_element(writer, "dc:identifier", identifier);
+    }
+
     // Summary
     if let Some(summary) = &entry.summary {
         let mut elem = BytesStart::new("summary");
@@ -0,0 +1,424 @@
+//! OPDS 2.0 feed generation.
+//!
+//! OPDS 2.0 catalogs are JSON documents built on the Readium Web Publication
+//! Manifest. [`FeedBuilder`] mirrors the Atom builder so handlers can serve
+//! both versions from the same queries.
+
+use super::{Facet, Pagination};
+use crate::library::{Book, Category};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// OPDS 2.0 feed MIME type.
+pub const FEED_MIME: &str = "application/opds+json";
+
+/// OPDS 2.0 publication MIME type.
+pub const PUBLICATION_MIME: &str = "application/opds-publication+json";
+
+/// schema.org type of every publication.
+const BOOK_TYPE: &str = "http://schema.org/Book";
+
+/// Feed or publication link.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    /// URL (or URI template) of the linked resource.
+    pub href: String,
+    /// MIME type of the linked resource.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// Link relation type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rel: Option<String>,
+    /// Optional title for the link.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Whether `href` is a URI template.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub templated: bool,
+    /// Extra link properties.
+    #[serde(default, skip_serializing_if = "LinkProperties::is_empty")]
+    pub properties: LinkProperties,
+}
+
+/// Link properties.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkProperties {
+    /// Number of items in the linked feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_of_items: Option<usize>,
+}
+
+impl LinkProperties {
+    fn is_empty(&self) -> bool {
+        self.number_of_items.is_none()
+    }
+}
+
+impl From<super::Link> for Link {
+    fn from(link: super::Link) -> Self {
+        Self {
+            href: link.href,
+            media_type: Some(link.link_type),
+            rel: Some(link.rel),
+            title: link.title,
+            ..Default::default()
+        }
+    }
+}
+
+/// Feed metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedMetadata {
+    /// Feed title.
+    pub title: String,
+    /// Total number of items across all pages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_of_items: Option<usize>,
+    /// Maximum number of items per page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items_per_page: Option<usize>,
+    /// Current page number (1-based).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_page: Option<usize>,
+}
+
+/// Facet group: a set of alternative views of the same feed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FacetGroup {
+    /// Group metadata (its title).
+    pub metadata: FeedMetadata,
+    /// One link per facet; the active one has `rel: "self"`.
+    pub links: Vec<Link>,
+}
+
+/// OPDS 2.0 feed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Feed {
+    /// Feed metadata.
+    pub metadata: FeedMetadata,
+    /// Feed links (self, start, search, pagination...).
+    pub links: Vec<Link>,
+    /// Facet groups.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub facets: Vec<FacetGroup>,
+    /// Links to other feeds.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub navigation: Vec<Link>,
+    /// Books of this page.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publications: Vec<Publication>,
+}
+
+/// A person credited on a publication.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contributor {
+    /// Display name.
+    pub name: String,
+}
+
+/// Series a publication belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Series {
+    /// Series name.
+    pub name: String,
+    /// Position of the publication in the series.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<f32>,
+    /// Feed of the series.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+}
+
+/// Collections a publication belongs to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BelongsTo {
+    /// Series.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub series: Vec<Series>,
+}
+
+impl BelongsTo {
+    fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+}
+
+/// Publication metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicationMetadata {
+    /// schema.org type.
+    #[serde(rename = "@type")]
+    pub kind: String,
+    /// Unique identifier URN.
+    pub identifier: String,
+    /// Title.
+    pub title: String,
+    /// Authors.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub author: Vec<Contributor>,
+    /// Contributors other than authors.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contributor: Vec<Contributor>,
+    /// Language code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Publisher name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    /// Publication date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    /// Last modification of the file.
+    pub modified: DateTime<Utc>,
+    /// Description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Subjects (tags).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subject: Vec<String>,
+    /// Series membership.
+    #[serde(default, skip_serializing_if = "BelongsTo::is_empty")]
+    pub belongs_to: BelongsTo,
+    /// Number of pages, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_of_pages: Option<u32>,
+}
+
+/// A book in an OPDS 2.0 feed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Publication {
+    /// Publication metadata.
+    pub metadata: PublicationMetadata,
+    /// Self and acquisition links.
+    pub links: Vec<Link>,
+    /// Cover and thumbnail.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Link>,
+}
+
+impl Publication {
+    /// Describe a book.
+    pub fn new(book: &Book, base_url: &str) -> Self {
+        let mut links = vec![Link {
+            href: format!("{}/opds/v2/publications/{}", base_url, book.id),
+            media_type: Some(PUBLICATION_MIME.to_string()),
+            rel: Some("self".to_string()),
+            ..Default::default()
+        }];
+        let mut images = Vec::new();
+        for link in super::book_links(book, base_url) {
+            if link.rel.starts_with("http://opds-spec.org/image") {
+                images.push(link.into());
+            } else {
+                links.push(link.into());
+            }
+        }
+
+        let series = book
+            .series
+            .iter()
+            .map(|name| Series {
+                name: name.clone(),
+                position: book.series_index,
+                links: vec![Link {
+                    href: format!("{}/opds/v2/series/{}", base_url, urlencoding::encode(name)),
+                    media_type: Some(FEED_MIME.to_string()),
+                    ..Default::default()
+                }],
+            })
+            .collect();
+
+        Self {
+            metadata: PublicationMetadata {
+                kind: BOOK_TYPE.to_string(),
+                identifier: format!("urn:uuid:{}", book.id),
+                title: book.title.clone(),
+                author: book
+                    .authors
+                    .iter()
+                    .map(|name| Contributor { name: name.clone() })
+                    .collect(),
+                contributor: book
+                    .contributors
+                    .iter()
+                    .map(|c| Contributor {
+                        name: c.name.clone(),
+                    })
+                    .collect(),
+                language: book.language.clone(),
+                publisher: book.publisher.clone(),
+                published: book.published.clone(),
+                modified: book.modified,
+                description: book.description.clone(),
+                subject: book.tags.clone(),
+                belongs_to: BelongsTo { series },
+                number_of_pages: book.page_count,
+            },
+            links,
+            images,
+        }
+    }
+
+    /// Serialize as a standalone publication document.
+    pub fn build(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// OPDS 2.0 feed builder.
+pub struct FeedBuilder {
+    feed: Feed,
+}
+
+impl FeedBuilder {
+    /// Create a new feed builder.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            feed: Feed {
+                metadata: FeedMetadata {
+                    title: title.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    fn link(mut self, rel: &str, href: impl Into<String>) -> Self {
+        self.feed.links.push(Link {
+            href: href.into(),
+            media_type: Some(FEED_MIME.to_string()),
+            rel: Some(rel.to_string()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Add a self link.
+    pub fn self_link(self, href: impl Into<String>) -> Self {
+        self.link("self", href)
+    }
+
+    /// Add a start link.
+    pub fn start_link(self, href: impl Into<String>) -> Self {
+        self.link("start", href)
+    }
+
+    /// Add an up link (parent feed).
+    pub fn up_link(self, href: impl Into<String>) -> Self {
+        self.link("up", href)
+    }
+
+    /// Add a search link; `href` is a URI template such as `/search{?query}`.
+    pub fn search_link(mut self, href: impl Into<String>) -> Self {
+        self.feed.links.push(Link {
+            href: href.into(),
+            media_type: Some(FEED_MIME.to_string()),
+            rel: Some("search".to_string()),
+            templated: true,
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Add first/previous/next/last links and the result counts.
+    ///
+    /// `base` is the feed URL without pagination parameters.
+    pub fn paginate(mut self, pagination: Pagination, base: &str) -> Self {
+        let mut rels = vec![("first", 0)];
+        if let Some(previous) = pagination.previous_offset() {
+            rels.push(("previous", previous));
+        }
+        if let Some(next) = pagination.next_offset() {
+            rels.push(("next", next));
+        }
+        rels.push(("last", pagination.last_offset()));
+
+        for (rel, offset) in rels {
+            self = self.link(rel, pagination.href(base, offset));
+        }
+
+        let metadata = &mut self.feed.metadata;
+        metadata.number_of_items = Some(pagination.total);
+        metadata.items_per_page = Some(pagination.per_page);
+        metadata.current_page = Some(pagination.offset / pagination.per_page + 1);
+        self
+    }
+
+    /// Add facet groups, keeping the order in which groups first appear.
+    pub fn facets(mut self, facets: Vec<Facet>) -> Self {
+        for facet in facets {
+            let link = Link {
+                href: facet.href,
+                media_type: Some(FEED_MIME.to_string()),
+                rel: facet.active.then(|| "self".to_string()),
+                title: Some(facet.title),
+                properties: LinkProperties {
+                    number_of_items: facet.count,
+                },
+                ..Default::default()
+            };
+            match self
+                .feed
+                .facets
+                .iter_mut()
+                .find(|group| group.metadata.title == facet.group)
+            {
+                Some(group) => group.links.push(link),
+                None => self.feed.facets.push(FacetGroup {
+                    metadata: FeedMetadata {
+                        title: facet.group,
+                        ..Default::default()
+                    },
+                    links: vec![link],
+                }),
+            }
+        }
+        self
+    }
+
+    /// Add a link to another feed.
+    pub fn navigation(
+        mut self,
+        title: impl Into<String>,
+        href: impl Into<String>,
+        count: Option<usize>,
+    ) -> Self {
+        self.feed.navigation.push(Link {
+            href: href.into(),
+            media_type: Some(FEED_MIME.to_string()),
+            rel: Some("subsection".to_string()),
+            title: Some(title.into()),
+            properties: LinkProperties {
+                number_of_items: count,
+            },
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Add a link to a folder feed.
+    pub fn category(self, category: &Category, base_url: &str) -> Self {
+        self.navigation(
+            category.name.clone(),
+            format!("{}/opds/v2/category/{}", base_url, category.id),
+            Some(category.book_count),
+        )
+    }
+
+    /// Add a book.
+    pub fn publication(mut self, book: &Book, base_url: &str) -> Self {
+        self.feed
+            .publications
+            .push(Publication::new(book, base_url));
+        self
+    }
+
+    /// Build the JSON feed.
+    pub fn build(self) -> String {
+        serde_json::to_string(&self.feed).unwrap_or_default()
+    }
+}
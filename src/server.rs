@@ -1,6 +1,11 @@
+mod extract;
+mod facets;
 mod handlers;
+mod kosync;
+mod range;
 mod state;
 
+pub use extract::{AdminUser, AuthUser};
 pub use state::AppState;
 
 use axum::{
@@ -15,7 +20,22 @@ pub fn create_router(state: AppState) -> Router {
         .route("/", get(handlers::catalog_root))
         .route("/recent", get(handlers::catalog_recent))
         .route("/all", get(handlers::catalog_all))
-        .route("/search", get(handlers::catalog_search));
+        .route("/search", get(handlers::catalog_search))
+        .route("/libraries", get(handlers::catalog_libraries))
+        .route("/category/{id}", get(handlers::catalog_category))
+        .route("/{field}", get(handlers::catalog_browse))
+        .route("/{field}/{value}", get(handlers::catalog_browse_books));
+
+    let opds2_routes = Router::new()
+        .route("/", get(handlers::opds2_root))
+        .route("/recent", get(handlers::opds2_recent))
+        .route("/all", get(handlers::opds2_all))
+        .route("/search", get(handlers::opds2_search))
+        .route("/libraries", get(handlers::opds2_libraries))
+        .route("/category/{id}", get(handlers::opds2_category))
+        .route("/publications/{id}", get(handlers::opds2_publication))
+        .route("/{field}", get(handlers::opds2_browse))
+        .route("/{field}/{value}", get(handlers::opds2_browse_books));
 
     let book_routes = Router::new()
         .route("/{id}", get(handlers::book_metadata))
@@ -24,6 +44,11 @@ pub fn create_router(state: AppState) -> Router {
             "/{id}/download.{ext}",
             get(handlers::book_download_with_ext),
         )
+        .route(
+            "/{id}/download/optimized",
+            get(handlers::book_download_optimized),
+        )
+        .route("/{id}/convert/epub", get(handlers::book_convert_epub))
         .route("/{id}/cover", get(handlers::book_cover))
         .route("/{id}/thumbnail", get(handlers::book_thumbnail))
         .route("/{id}/placeholder", get(handlers::book_placeholder));
@@ -32,7 +57,8 @@ pub fn create_router(state: AppState) -> Router {
         .route("/login", post(handlers::auth_login))
         .route("/register", post(handlers::auth_register))
         .route("/logout", post(handlers::auth_logout))
-        .route("/me", get(handlers::auth_me));
+        .route("/me", get(handlers::auth_me))
+        .route("/me/device-profile", put(handlers::auth_set_device_profile));
 
     let sync_routes = Router::new()
         // Progress by book
@@ -69,15 +95,19 @@ pub fn create_router(state: AppState) -> Router {
     let api_routes = Router::new()
         .route("/scan", post(handlers::api_scan))
         .route("/stats", get(handlers::api_stats))
-        .route("/library", get(handlers::api_library));
+        .route("/library", get(handlers::api_library))
+        .route("/search", get(handlers::api_search))
+        .route("/search/content", get(handlers::api_search_content));
 
     Router::new()
         .route("/", get(handlers::index))
         .route("/opensearch.xml", get(handlers::opensearch))
         .nest("/catalog", catalog_routes)
+        .nest("/opds/v2", opds2_routes)
         .nest("/books", book_routes)
         .nest("/api/auth", auth_routes)
         .nest("/api/sync", sync_routes)
+        .nest("/kosync", kosync::router())
         .nest("/api", api_routes)
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
@@ -0,0 +1,79 @@
+//! Request extractors for authenticated users.
+
+use crate::auth::decode_basic_auth;
+use crate::db::User;
+use crate::error::{AppError, Result};
+use crate::server::AppState;
+use axum::extract::FromRequestParts;
+use axum::http::{HeaderMap, header, request::Parts};
+
+/// Authenticated user (Bearer token or HTTP Basic credentials).
+///
+/// Rejects the request with `401 Unauthorized` when no valid credentials
+/// are given.
+pub struct AuthUser(pub User);
+
+/// Authenticated admin user.
+///
+/// Rejects with `401 Unauthorized` without credentials and with
+/// `403 Forbidden` for non-admin users.
+pub struct AdminUser(pub User);
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self> {
+        optional_user(state, &parts.headers)?
+            .map(AuthUser)
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))
+    }
+}
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self> {
+        let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+
+        if !state.auth.is_admin(&user) {
+            return Err(AppError::Forbidden("Admin access required".to_string()));
+        }
+        Ok(AdminUser(user))
+    }
+}
+
+/// Extract token from Authorization header.
+pub(super) fn extract_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|s| s.to_string())
+}
+
+/// Get the user from a Bearer token or HTTP Basic credentials.
+///
+/// Returns None for anonymous requests, and an error for invalid credentials.
+pub(super) fn optional_user(state: &AppState, headers: &HeaderMap) -> Result<Option<User>> {
+    if let Some(token) = extract_token(headers) {
+        return state
+            .auth
+            .validate_token(&token)?
+            .map(Some)
+            .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(decode_basic_auth);
+
+    match basic {
+        Some((username, password)) => state
+            .auth
+            .authenticate_basic(&username, &password)?
+            .map(Some)
+            .ok_or_else(|| AppError::Unauthorized("Invalid username or password".to_string())),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,502 @@
+//! Sorting and filtering of acquisition feeds.
+//!
+//! Both OPDS versions accept the same query parameters (`sort`, `format`,
+//! `lang` and `status`) and advertise them as facet groups, each facet
+//! linking to the current feed with one parameter changed.
+
+use crate::config::BookFormat;
+use crate::error::{AppError, Result};
+use crate::library::Book;
+use crate::library::search::language_matches;
+use crate::opds::Facet;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+/// Facet query parameters of an acquisition feed.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct FacetParams {
+    /// Sort order (`title`, `author`, `added`, `published` or `series`).
+    sort: Option<String>,
+    /// Only list books of this format (file extension).
+    format: Option<String>,
+    /// Only list books in this language.
+    #[serde(alias = "language")]
+    lang: Option<String>,
+    /// Only list books with this read status (`unread`, `reading` or `finished`).
+    status: Option<String>,
+}
+
+/// Order of the books in an acquisition feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SortOrder {
+    Title,
+    Author,
+    Added,
+    Published,
+    Series,
+}
+
+impl SortOrder {
+    const ALL: [Self; 5] = [
+        Self::Title,
+        Self::Author,
+        Self::Added,
+        Self::Published,
+        Self::Series,
+    ];
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|order| order.as_str() == value)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Author => "author",
+            Self::Added => "added",
+            Self::Published => "published",
+            Self::Series => "series",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::Author => "Author",
+            Self::Added => "Recently added",
+            Self::Published => "Publication date",
+            Self::Series => "Series",
+        }
+    }
+
+    /// Sort books; ties and books missing the sort key fall back to title order.
+    fn sort(self, books: &mut [Book]) {
+        books.sort_by_cached_key(|b| b.title.to_lowercase());
+        match self {
+            Self::Title => {}
+            Self::Author => books.sort_by_cached_key(|b| {
+                b.authors
+                    .first()
+                    .map_or((true, String::new()), |a| (false, a.to_lowercase()))
+            }),
+            Self::Added => books.sort_by_key(|b| Reverse(b.modified)),
+            Self::Published => books.sort_by(|a, b| match (&a.published, &b.published) {
+                (Some(a), Some(b)) => b.cmp(a),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            }),
+            Self::Series => books.sort_by(|a, b| match (&a.series, &b.series) {
+                (Some(sa), Some(sb)) => sa.to_lowercase().cmp(&sb.to_lowercase()).then_with(|| {
+                    let index = |b: &Book| b.series_index.unwrap_or(f32::MAX);
+                    index(a).total_cmp(&index(b))
+                }),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            }),
+        }
+    }
+}
+
+/// Reading state of a book for the requesting user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ReadStatus {
+    Unread,
+    Reading,
+    Finished,
+}
+
+impl ReadStatus {
+    const ALL: [Self; 3] = [Self::Unread, Self::Reading, Self::Finished];
+
+    /// Map a stored progress status; KOReader reports finished books as
+    /// "complete".
+    pub(super) fn from_progress(status: &str) -> Self {
+        match status {
+            "finished" | "complete" | "completed" => Self::Finished,
+            _ => Self::Reading,
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == value)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Unread => "unread",
+            Self::Reading => "reading",
+            Self::Finished => "finished",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Unread => "Unread",
+            Self::Reading => "Reading",
+            Self::Finished => "Finished",
+        }
+    }
+}
+
+/// Books of an acquisition feed after sorting and filtering.
+pub(super) struct Faceted {
+    /// Matching books, in feed order.
+    pub(super) books: Vec<Book>,
+    /// Facet links to advertise.
+    pub(super) facets: Vec<Facet>,
+    /// Feed URL with the active parameters, for self and pagination links.
+    pub(super) base: String,
+}
+
+/// Parsed filters, in the order their parameters appear in URLs.
+#[derive(Clone, Default)]
+struct Filters {
+    format: Option<BookFormat>,
+    language: Option<String>,
+    status: Option<ReadStatus>,
+}
+
+/// A facet group, for counting with the other groups' filters applied.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Format,
+    Language,
+    Status,
+}
+
+impl Filters {
+    fn keep(
+        &self,
+        book: &Book,
+        statuses: Option<&HashMap<String, ReadStatus>>,
+        skip: Option<Group>,
+    ) -> bool {
+        let format_ok = skip == Some(Group::Format) || self.format.is_none_or(|f| f == book.format);
+        let language_ok = skip == Some(Group::Language)
+            || self.language.as_deref().is_none_or(|wanted| {
+                book.language
+                    .as_deref()
+                    .is_some_and(|language| language_matches(language, wanted))
+            });
+        let status_ok = skip == Some(Group::Status)
+            || self
+                .status
+                .is_none_or(|wanted| read_status(book, statuses) == wanted);
+        format_ok && language_ok && status_ok
+    }
+}
+
+/// Read status of a book; books without progress are unread.
+fn read_status(book: &Book, statuses: Option<&HashMap<String, ReadStatus>>) -> ReadStatus {
+    statuses
+        .and_then(|statuses| statuses.get(&book.id).copied())
+        .unwrap_or(ReadStatus::Unread)
+}
+
+/// Primary language subtag, e.g. `en` for `en-GB`.
+fn primary_language(language: &str) -> String {
+    language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(language)
+        .to_lowercase()
+}
+
+/// Sort and filter the books of an acquisition feed and list its facets.
+///
+/// `default_sort` is the feed's own order (`None` keeps the order of
+/// `books`, e.g. search relevance). `statuses` holds the requesting user's
+/// reading statuses; the read status group is only offered to signed-in
+/// users.
+pub(super) fn apply(
+    mut books: Vec<Book>,
+    base: &str,
+    params: &FacetParams,
+    default_sort: Option<SortOrder>,
+    statuses: Option<&HashMap<String, ReadStatus>>,
+) -> Result<Faceted> {
+    let sort = match params.sort.as_deref() {
+        Some(value) => Some(
+            SortOrder::parse(value)
+                .ok_or_else(|| AppError::InvalidFormat(format!("Unknown sort order: {}", value)))?,
+        ),
+        None => None,
+    };
+    let filters = Filters {
+        format: match params.format.as_deref() {
+            Some(value) => Some(
+                BookFormat::from_extension(&value.to_lowercase())
+                    .ok_or_else(|| AppError::InvalidFormat(format!("Unknown format: {}", value)))?,
+            ),
+            None => None,
+        },
+        language: params.lang.as_deref().map(primary_language),
+        status: match params.status.as_deref() {
+            Some(_) if statuses.is_none() => {
+                return Err(AppError::Unauthorized(
+                    "Authentication required to filter by read status".to_string(),
+                ));
+            }
+            Some(value) => Some(ReadStatus::parse(value).ok_or_else(|| {
+                AppError::InvalidFormat(format!("Unknown read status: {}", value))
+            })?),
+            None => None,
+        },
+    };
+
+    let link = |sort: Option<SortOrder>, filters: &Filters| {
+        let mut href = base.to_string();
+        let sort = sort.filter(|&sort| Some(sort) != default_sort);
+        let params = [
+            ("sort", sort.map(|s| s.as_str().to_string())),
+            ("format", filters.format.map(|f| f.extension().to_string())),
+            ("lang", filters.language.clone()),
+            ("status", filters.status.map(|s| s.as_str().to_string())),
+        ];
+        for (name, value) in params {
+            if let Some(value) = value {
+                href = with_param(&href, name, &value);
+            }
+        }
+        href
+    };
+
+    // Facets of a filter group keep the sort order and the other filters
+    let with_filter = |change: &dyn Fn(&mut Filters)| {
+        let mut changed = filters.clone();
+        change(&mut changed);
+        link(sort, &changed)
+    };
+
+    let mut facets = Vec::new();
+    let active_sort = sort.or(default_sort);
+    for order in SortOrder::ALL {
+        facets.push(Facet {
+            group: "Sort by".to_string(),
+            title: order.label().to_string(),
+            href: link(Some(order), &filters),
+            count: None,
+            active: active_sort == Some(order),
+        });
+    }
+
+    // Counts of each group apply the filters of the other groups
+    let active = &filters;
+    let candidates = |group: Group| {
+        books
+            .iter()
+            .filter(move |b| active.keep(b, statuses, Some(group)))
+    };
+
+    let mut formats: BTreeMap<&str, (BookFormat, usize)> = BTreeMap::new();
+    for book in candidates(Group::Format) {
+        formats
+            .entry(book.format.extension())
+            .or_insert((book.format, 0))
+            .1 += 1;
+    }
+    facets.push(Facet {
+        group: "Format".to_string(),
+        title: "All".to_string(),
+        href: with_filter(&|f| f.format = None),
+        count: Some(formats.values().map(|(_, n)| n).sum()),
+        active: filters.format.is_none(),
+    });
+    for (extension, (format, n)) in formats {
+        facets.push(Facet {
+            group: "Format".to_string(),
+            title: extension.to_uppercase(),
+            href: with_filter(&|f| f.format = Some(format)),
+            count: Some(n),
+            active: filters.format == Some(format),
+        });
+    }
+
+    let mut languages: BTreeMap<String, usize> = BTreeMap::new();
+    for book in candidates(Group::Language) {
+        if let Some(language) = &book.language {
+            *languages.entry(primary_language(language)).or_default() += 1;
+        }
+    }
+    if !languages.is_empty() {
+        facets.push(Facet {
+            group: "Language".to_string(),
+            title: "All".to_string(),
+            href: with_filter(&|f| f.language = None),
+            count: Some(candidates(Group::Language).count()),
+            active: filters.language.is_none(),
+        });
+        for (language, n) in languages {
+            facets.push(Facet {
+                group: "Language".to_string(),
+                title: language.clone(),
+                href: with_filter(&|f| f.language = Some(language.clone())),
+                count: Some(n),
+                active: filters.language.as_ref() == Some(&language),
+            });
+        }
+    }
+
+    if statuses.is_some() {
+        let mut counts = [0; 3];
+        for book in candidates(Group::Status) {
+            counts[read_status(book, statuses) as usize] += 1;
+        }
+        facets.push(Facet {
+            group: "Read status".to_string(),
+            title: "All".to_string(),
+            href: with_filter(&|f| f.status = None),
+            count: Some(counts.iter().sum()),
+            active: filters.status.is_none(),
+        });
+        for status in ReadStatus::ALL {
+            facets.push(Facet {
+                group: "Read status".to_string(),
+                title: status.label().to_string(),
+                href: with_filter(&|f| f.status = Some(status)),
+                count: Some(counts[status as usize]),
+                active: filters.status == Some(status),
+            });
+        }
+    }
+
+    books.retain(|b| filters.keep(b, statuses, None));
+    if let Some(order) = sort.or(default_sort) {
+        order.sort(&mut books);
+    }
+
+    Ok(Faceted {
+        books,
+        facets,
+        base: link(sort, &filters),
+    })
+}
+
+/// Append a query parameter to a URL.
+fn with_param(base: &str, name: &str, value: &str) -> String {
+    let separator = if base.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}{}={}",
+        base,
+        separator,
+        name,
+        urlencoding::encode(value)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, format: BookFormat, language: Option<&str>) -> Book {
+        Book {
+            id: id.to_string(),
+            title: id.to_string(),
+            format,
+            language: language.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn ids(books: &[Book]) -> Vec<&str> {
+        books.iter().map(|b| b.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_sort_orders() {
+        let mut books = vec![
+            book("c", BookFormat::Epub, None),
+            book("a", BookFormat::Epub, None),
+            book("b", BookFormat::Epub, None),
+        ];
+        books[0].authors = vec!["Adams".to_string()];
+        books[2].authors = vec!["Banks".to_string()];
+        books[1].series = Some("Saga".to_string());
+        books[1].series_index = Some(2.0);
+        books[2].series = Some("saga".to_string());
+        books[2].series_index = Some(1.0);
+        books[2].published = Some("2001-05-01".to_string());
+        books[0].published = Some("1999".to_string());
+
+        SortOrder::Title.sort(&mut books);
+        assert_eq!(ids(&books), vec!["a", "b", "c"]);
+        SortOrder::Author.sort(&mut books);
+        assert_eq!(ids(&books), vec!["c", "b", "a"]);
+        SortOrder::Published.sort(&mut books);
+        assert_eq!(ids(&books), vec!["b", "c", "a"]);
+        SortOrder::Series.sort(&mut books);
+        assert_eq!(ids(&books), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_facets() {
+        let books = vec![
+            book("a", BookFormat::Epub, Some("en-GB")),
+            book("b", BookFormat::Pdf, Some("en")),
+            book("c", BookFormat::Epub, Some("fr")),
+            book("d", BookFormat::Epub, None),
+        ];
+        let params = FacetParams {
+            format: Some("EPUB".to_string()),
+            lang: Some("en".to_string()),
+            ..Default::default()
+        };
+        let statuses = HashMap::from([("a".to_string(), ReadStatus::Finished)]);
+
+        let faceted = apply(
+            books.clone(),
+            "/all",
+            &params,
+            Some(SortOrder::Title),
+            Some(&statuses),
+        )
+        .unwrap();
+        assert_eq!(ids(&faceted.books), vec!["a"]);
+        assert_eq!(faceted.base, "/all?format=epub&lang=en");
+
+        let facet = |group: &str, title: &str| {
+            faceted
+                .facets
+                .iter()
+                .find(|f| f.group == group && f.title == title)
+                .unwrap()
+        };
+        // The default order needs no parameter
+        assert_eq!(facet("Sort by", "Title").href, "/all?format=epub&lang=en");
+        assert!(facet("Sort by", "Title").active);
+        assert_eq!(
+            facet("Sort by", "Series").href,
+            "/all?sort=series&format=epub&lang=en"
+        );
+        // Counts apply the filters of the other groups
+        assert_eq!(facet("Format", "PDF").count, Some(1));
+        assert_eq!(facet("Format", "PDF").href, "/all?format=pdf&lang=en");
+        assert!(facet("Format", "EPUB").active);
+        assert_eq!(facet("Language", "All").count, Some(3));
+        assert_eq!(facet("Language", "fr").count, Some(1));
+        assert_eq!(facet("Read status", "Finished").count, Some(1));
+        assert_eq!(facet("Read status", "Unread").count, Some(0));
+
+        // Read status is only offered to signed-in users
+        let anonymous = apply(books.clone(), "/all", &FacetParams::default(), None, None).unwrap();
+        assert!(anonymous.facets.iter().all(|f| f.group != "Read status"));
+        let params = FacetParams {
+            status: Some("finished".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply(books.clone(), "/all", &params, None, None),
+            Err(AppError::Unauthorized(_))
+        ));
+
+        let params = FacetParams {
+            sort: Some("random".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply(books, "/all", &params, None, None),
+            Err(AppError::InvalidFormat(_))
+        ));
+    }
+}
@@ -1,8 +1,15 @@
+use crate::config::{BookFormat, DeviceProfile, MergeStrategy};
 use crate::db::{self, Bookmark, Highlight, ReadingProgress};
 use crate::error::{AppError, Result};
 use crate::formats;
-use crate::opds::{self, FeedBuilder, Link};
+use crate::formats::TextLocation;
+use crate::formats::convert::ComicOptions;
+use crate::library::{BrowseField, Category, CategoryNode};
+use crate::opds::{self, Facet, FeedBuilder, Pagination, v2};
 use crate::server::AppState;
+use crate::server::extract::{AdminUser, AuthUser, extract_token, optional_user};
+use crate::server::facets::{self, FacetParams, ReadStatus, SortOrder};
+use crate::server::range;
 use axum::{
     Json,
     body::Body,
@@ -11,7 +18,7 @@ use axum::{
     response::{Html, IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
-use tokio_util::io::ReaderStream;
+use std::collections::{HashMap, HashSet};
 
 /// OPDS content type.
 const OPDS_MIME: &str = "application/atom+xml;profile=opds-catalog";
@@ -58,8 +65,8 @@ pub async fn index(State(state): State<AppState>) -> Html<String> {
     <h2>Links</h2>
     <ul>
         <li><a href="/catalog">OPDS Catalog (XML)</a></li>
+        <li><a href="/opds/v2">OPDS 2.0 Catalog (JSON)</a></li>
         <li><a href="/opensearch.xml">OpenSearch Description</a></li>
-        <li><a href="/api/stats">API Stats (JSON)</a></li>
     </ul>
 </body>
 </html>"#,
@@ -75,8 +82,12 @@ pub async fn opensearch(State(state): State<AppState>) -> impl IntoResponse {
     build_response(StatusCode::OK, "application/opensearchdescription+xml", xml)
 }
 
-pub async fn catalog_root(State(state): State<AppState>) -> impl IntoResponse {
+pub async fn catalog_root(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>> {
     let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
 
     let mut feed = FeedBuilder::new(
         format!("urn:uuid:{}", uuid::Uuid::new_v4()),
@@ -88,158 +99,787 @@ pub async fn catalog_root(State(state): State<AppState>) -> impl IntoResponse {
     .search_link(format!("{}/opensearch.xml", base_url));
 
     // Add navigation entries
-    feed = feed.navigation_entry(opds::Entry {
-        id: "urn:uuid:recent".to_string(),
-        title: "Recent Books".to_string(),
-        updated: chrono::Utc::now(),
-        authors: Vec::new(),
-        summary: Some("Recently added books".to_string()),
-        content: None,
-        links: vec![Link {
-            rel: "subsection".to_string(),
-            href: format!("{}/catalog/recent", base_url),
-            link_type: "application/atom+xml;profile=opds-catalog;kind=acquisition".to_string(),
-            title: Some("Recent Books".to_string()),
-        }],
-        categories: Vec::new(),
-    });
-
-    feed = feed.navigation_entry(opds::Entry {
-        id: "urn:uuid:all".to_string(),
-        title: "All Books".to_string(),
-        updated: chrono::Utc::now(),
-        authors: Vec::new(),
-        summary: Some(format!("{} books total", state.book_count())),
-        content: None,
-        links: vec![Link {
-            rel: "subsection".to_string(),
-            href: format!("{}/catalog/all", base_url),
-            link_type: "application/atom+xml;profile=opds-catalog;kind=acquisition".to_string(),
-            title: Some("All Books".to_string()),
-        }],
-        categories: Vec::new(),
-    });
-
-    build_response(StatusCode::OK, OPDS_MIME, feed.build())
-}
-
-pub async fn catalog_recent(State(state): State<AppState>) -> impl IntoResponse {
+    feed = feed
+        .subsection_entry(
+            "urn:uuid:recent",
+            "Recent Books",
+            Some("Recently added books".to_string()),
+            format!("{}/catalog/recent", base_url),
+            true,
+        )
+        .subsection_entry(
+            "urn:uuid:all",
+            "All Books",
+            Some(format!(
+                "{} books total",
+                state.get_visible_books(&libraries).len()
+            )),
+            format!("{}/catalog/all", base_url),
+            true,
+        );
+
+    for field in BrowseField::ALL {
+        let count = state.browse(field, &libraries).len();
+        feed = feed.subsection_entry(
+            format!("urn:uuid:{}", field.segment()),
+            field.title(),
+            Some(format!("{}: {}", field.title(), count)),
+            format!("{}/catalog/{}", base_url, field.segment()),
+            false,
+        );
+    }
+
+    // One entry per visible library, browsable by folder
+    for node in state.categories().roots() {
+        if libraries.contains(&node.library_id) {
+            feed = feed.category_entry(&node.category, &base_url);
+        }
+    }
+
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+pub async fn catalog_recent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
     let base_url = state.base_url();
-    let books = state.get_recent(50);
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.get_recent(state.config.server.recent_books, &libraries);
+
+    let base = format!("{}/catalog/recent", base_url);
+    let page = acquisition_page(
+        &state,
+        &headers,
+        books,
+        &base,
+        &params,
+        Some(SortOrder::Added),
+    )?;
 
     let mut feed = FeedBuilder::new("urn:uuid:recent", "Recent Books")
-        .self_link(format!("{}/catalog/recent", base_url))
-        .start_link(format!("{}/catalog", base_url));
+        .self_link(page.self_href())
+        .start_link(format!("{}/catalog", base_url))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
 
-    for book in books {
-        feed = feed.book_entry(&book, &base_url);
+    for book in &page.books {
+        feed = feed.book_entry(book, &base_url);
     }
 
-    build_response(StatusCode::OK, OPDS_MIME, feed.build())
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+/// Acquisition feed page parameters.
+#[derive(Debug, Deserialize)]
+pub struct PageParams {
+    /// Page number (1-based).
+    page: Option<usize>,
+    /// Index of the first book (0-based), overrides `page`.
+    offset: Option<usize>,
+    /// Sort order and filters.
+    #[serde(flatten)]
+    facets: FacetParams,
 }
 
-pub async fn catalog_all(State(state): State<AppState>) -> impl IntoResponse {
+pub async fn catalog_all(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
     let base_url = state.base_url();
-    let mut books = state.get_all_books();
-    books.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.get_visible_books(&libraries);
+
+    let base = format!("{}/catalog/all", base_url);
+    let page = acquisition_page(
+        &state,
+        &headers,
+        books,
+        &base,
+        &params,
+        Some(SortOrder::Title),
+    )?;
 
     let mut feed = FeedBuilder::new("urn:uuid:all", "All Books")
-        .self_link(format!("{}/catalog/all", base_url))
-        .start_link(format!("{}/catalog", base_url));
+        .self_link(page.self_href())
+        .start_link(format!("{}/catalog", base_url))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
 
-    for book in books {
-        feed = feed.book_entry(&book, &base_url);
+    for book in &page.books {
+        feed = feed.book_entry(book, &base_url);
     }
 
-    build_response(StatusCode::OK, OPDS_MIME, feed.build())
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
 }
 
-/// Search query parameters.
+/// Search query parameters, next to the [`PageParams`] of the results.
 #[derive(Debug, Deserialize)]
 pub struct SearchParams {
+    /// Query text (`query` is the OPDS 2.0 template variable).
+    #[serde(alias = "query")]
     q: String,
 }
 
 pub async fn catalog_search(
     State(state): State<AppState>,
-    Query(params): Query<SearchParams>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+    Query(search): Query<SearchParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
     let base_url = state.base_url();
-    let books = state.search(&params.q);
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.search(&search.q, &libraries);
+
+    let base = format!(
+        "{}/catalog/search?q={}",
+        base_url,
+        urlencoding::encode(&search.q)
+    );
+    let page = acquisition_page(&state, &headers, books, &base, &params, None)?;
 
     let mut feed = FeedBuilder::new(
-        format!("urn:uuid:search:{}", params.q),
-        format!("Search: {}", params.q),
+        format!("urn:uuid:search:{}", search.q),
+        format!("Search: {}", search.q),
     )
-    .self_link(format!(
-        "{}/catalog/search?q={}",
+    .self_link(page.self_href())
+    .start_link(format!("{}/catalog", base_url))
+    .paginate(page.pagination, &page.base)
+    .facets(page.facets);
+
+    for book in &page.books {
+        feed = feed.book_entry(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+pub async fn catalog_libraries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+
+    let mut feed = FeedBuilder::new("urn:uuid:libraries", "Browse by Folder")
+        .self_link(format!("{}/catalog/libraries", base_url))
+        .start_link(format!("{}/catalog", base_url))
+        .up_link(format!("{}/catalog", base_url));
+
+    for node in state.categories().roots() {
+        if libraries.contains(&node.library_id) {
+            feed = feed.category_entry(&node.category, &base_url);
+        }
+    }
+
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+/// Folder feed: subfolders first, then the books stored directly in it.
+pub async fn catalog_category(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+
+    let (node, children, books) = category_listing(&state, &libraries, &id)?;
+
+    let base = format!("{}/catalog/category/{}", base_url, id);
+    let page = acquisition_page(&state, &headers, books, &base, &params, None)?;
+    let up = match &node.parent {
+        Some(parent) => format!("{}/catalog/category/{}", base_url, parent),
+        None => format!("{}/catalog/libraries", base_url),
+    };
+
+    let mut feed = FeedBuilder::new(format!("urn:uuid:{}", id), &node.category.name)
+        .self_link(page.self_href())
+        .start_link(format!("{}/catalog", base_url))
+        .up_link(up)
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    // Subfolders are only listed on the first page
+    if page.pagination.offset == 0 {
+        for child in &children {
+            feed = feed.category_entry(child, &base_url);
+        }
+    }
+
+    for book in &page.books {
+        feed = feed.book_entry(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+/// Navigation feed of the values of a field (authors, series...).
+pub async fn catalog_browse(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(segment): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let field = browse_field(&segment)?;
+    let values = state.browse(field, &libraries);
+
+    let base = format!("{}/catalog/{}", base_url, segment);
+    let pagination = Pagination::new(
+        params.page,
+        params.offset,
+        state.config.server.page_size,
+        values.len(),
+    );
+
+    let mut feed = FeedBuilder::new(format!("urn:uuid:{}", segment), field.title())
+        .self_link(pagination.href(&base, pagination.offset))
+        .start_link(format!("{}/catalog", base_url))
+        .up_link(format!("{}/catalog", base_url))
+        .paginate(pagination, &base);
+
+    for (value, count) in pagination.slice(values) {
+        let href = format!("{}/{}", base, urlencoding::encode(&value));
+        feed = feed.subsection_entry(
+            format!("urn:uuid:{}:{}", segment, value),
+            &value,
+            Some(format!("{} books", count)),
+            href,
+            true,
+        );
+    }
+
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+/// Books with one value of a field; series are listed in reading order.
+pub async fn catalog_browse_books(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((segment, value)): Path<(String, String)>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let field = browse_field(&segment)?;
+    let (title, books) = browse_books(&state, field, &value, &libraries)?;
+
+    let base = format!(
+        "{}/catalog/{}/{}",
+        base_url,
+        segment,
+        urlencoding::encode(&value)
+    );
+    let page = acquisition_page(
+        &state,
+        &headers,
+        books,
+        &base,
+        &params,
+        Some(browse_sort(field)),
+    )?;
+
+    let mut feed = FeedBuilder::new(format!("urn:uuid:{}:{}", segment, title), &title)
+        .self_link(page.self_href())
+        .start_link(format!("{}/catalog", base_url))
+        .up_link(format!("{}/catalog/{}", base_url, segment))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    for book in &page.books {
+        feed = feed.book_entry(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, OPDS_MIME, feed.build()))
+}
+
+pub async fn opds2_root(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+
+    let mut feed = v2::FeedBuilder::new(&state.config.server.title)
+        .self_link(format!("{}/opds/v2", base_url))
+        .start_link(format!("{}/opds/v2", base_url))
+        .search_link(format!("{}/opds/v2/search{{?query}}", base_url))
+        .navigation("Recent Books", format!("{}/opds/v2/recent", base_url), None)
+        .navigation(
+            "All Books",
+            format!("{}/opds/v2/all", base_url),
+            Some(state.get_visible_books(&libraries).len()),
+        );
+
+    for field in BrowseField::ALL {
+        feed = feed.navigation(
+            field.title(),
+            format!("{}/opds/v2/{}", base_url, field.segment()),
+            Some(state.browse(field, &libraries).len()),
+        );
+    }
+
+    for node in state.categories().roots() {
+        if libraries.contains(&node.library_id) {
+            feed = feed.category(&node.category, &base_url);
+        }
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+pub async fn opds2_recent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.get_recent(state.config.server.recent_books, &libraries);
+
+    let base = format!("{}/opds/v2/recent", base_url);
+    let page = acquisition_page(
+        &state,
+        &headers,
+        books,
+        &base,
+        &params,
+        Some(SortOrder::Added),
+    )?;
+
+    let mut feed = v2::FeedBuilder::new("Recent Books")
+        .self_link(page.self_href())
+        .start_link(format!("{}/opds/v2", base_url))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    for book in &page.books {
+        feed = feed.publication(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+pub async fn opds2_all(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.get_visible_books(&libraries);
+
+    let base = format!("{}/opds/v2/all", base_url);
+    let page = acquisition_page(
+        &state,
+        &headers,
+        books,
+        &base,
+        &params,
+        Some(SortOrder::Title),
+    )?;
+
+    let mut feed = v2::FeedBuilder::new("All Books")
+        .self_link(page.self_href())
+        .start_link(format!("{}/opds/v2", base_url))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    for book in &page.books {
+        feed = feed.publication(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+pub async fn opds2_search(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(search): Query<SearchParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.search(&search.q, &libraries);
+
+    let base = format!(
+        "{}/opds/v2/search?query={}",
         base_url,
-        urlencoding::encode(&params.q)
+        urlencoding::encode(&search.q)
+    );
+    let page = acquisition_page(&state, &headers, books, &base, &params, None)?;
+
+    let mut feed = v2::FeedBuilder::new(format!("Search: {}", search.q))
+        .self_link(page.self_href())
+        .start_link(format!("{}/opds/v2", base_url))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    for book in &page.books {
+        feed = feed.publication(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+pub async fn opds2_libraries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+
+    let mut feed = v2::FeedBuilder::new("Browse by Folder")
+        .self_link(format!("{}/opds/v2/libraries", base_url))
+        .start_link(format!("{}/opds/v2", base_url))
+        .up_link(format!("{}/opds/v2", base_url));
+
+    for node in state.categories().roots() {
+        if libraries.contains(&node.library_id) {
+            feed = feed.category(&node.category, &base_url);
+        }
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+/// Folder feed: subfolders as navigation, books as publications.
+pub async fn opds2_category(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+
+    let (node, children, books) = category_listing(&state, &libraries, &id)?;
+
+    let base = format!("{}/opds/v2/category/{}", base_url, id);
+    let page = acquisition_page(&state, &headers, books, &base, &params, None)?;
+    let up = match &node.parent {
+        Some(parent) => format!("{}/opds/v2/category/{}", base_url, parent),
+        None => format!("{}/opds/v2/libraries", base_url),
+    };
+
+    let mut feed = v2::FeedBuilder::new(&node.category.name)
+        .self_link(page.self_href())
+        .start_link(format!("{}/opds/v2", base_url))
+        .up_link(up)
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    // Subfolders are only listed on the first page
+    if page.pagination.offset == 0 {
+        for child in &children {
+            feed = feed.category(child, &base_url);
+        }
+    }
+
+    for book in &page.books {
+        feed = feed.publication(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+pub async fn opds2_publication(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Response<Body>> {
+    let book = get_visible_book(&state, &headers, &id).await?;
+    let publication = v2::Publication::new(&book, &state.base_url());
+
+    Ok(build_response(
+        StatusCode::OK,
+        v2::PUBLICATION_MIME,
+        publication.build(),
     ))
-    .start_link(format!("{}/catalog", base_url));
+}
+
+/// Navigation feed of the values of a field (authors, series...).
+pub async fn opds2_browse(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(segment): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let field = browse_field(&segment)?;
+    let values = state.browse(field, &libraries);
+
+    let base = format!("{}/opds/v2/{}", base_url, segment);
+    let pagination = Pagination::new(
+        params.page,
+        params.offset,
+        state.config.server.page_size,
+        values.len(),
+    );
+
+    let mut feed = v2::FeedBuilder::new(field.title())
+        .self_link(pagination.href(&base, pagination.offset))
+        .start_link(format!("{}/opds/v2", base_url))
+        .up_link(format!("{}/opds/v2", base_url))
+        .paginate(pagination, &base);
 
-    for book in books {
-        feed = feed.book_entry(&book, &base_url);
+    for (value, count) in pagination.slice(values) {
+        let href = format!("{}/{}", base, urlencoding::encode(&value));
+        feed = feed.navigation(value, href, Some(count));
     }
 
-    build_response(StatusCode::OK, OPDS_MIME, feed.build())
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
+}
+
+/// Books with one value of a field; series are listed in reading order.
+pub async fn opds2_browse_books(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((segment, value)): Path<(String, String)>,
+    Query(params): Query<PageParams>,
+) -> Result<Response<Body>> {
+    let base_url = state.base_url();
+    let libraries = visible_libraries(&state, &headers).await?;
+    let field = browse_field(&segment)?;
+    let (title, books) = browse_books(&state, field, &value, &libraries)?;
+
+    let base = format!(
+        "{}/opds/v2/{}/{}",
+        base_url,
+        segment,
+        urlencoding::encode(&value)
+    );
+    let page = acquisition_page(
+        &state,
+        &headers,
+        books,
+        &base,
+        &params,
+        Some(browse_sort(field)),
+    )?;
+
+    let mut feed = v2::FeedBuilder::new(title)
+        .self_link(page.self_href())
+        .start_link(format!("{}/opds/v2", base_url))
+        .up_link(format!("{}/opds/v2/{}", base_url, segment))
+        .paginate(page.pagination, &page.base)
+        .facets(page.facets);
+
+    for book in &page.books {
+        feed = feed.publication(book, &base_url);
+    }
+
+    Ok(build_response(StatusCode::OK, v2::FEED_MIME, feed.build()))
 }
 
 pub async fn book_metadata(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<crate::library::book::Book>> {
-    let book = state
-        .get_book(&id)
-        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+    let book = get_visible_book(&state, &headers, &id).await?;
 
     Ok(Json(book))
 }
 
 pub async fn book_download(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Response<Body>> {
-    book_download_impl(state, id).await
+    book_download_impl(state, headers, id).await
 }
 
 /// Book download with extension (e.g., /download.pdf).
 pub async fn book_download_with_ext(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path((id, _ext)): Path<(String, String)>,
 ) -> Result<Response<Body>> {
-    book_download_impl(state, id).await
+    book_download_impl(state, headers, id).await
 }
 
 /// Internal book download implementation.
-async fn book_download_impl(state: AppState, id: String) -> Result<Response<Body>> {
-    let book = state
-        .get_book(&id)
-        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+async fn book_download_impl(
+    state: AppState,
+    headers: HeaderMap,
+    id: String,
+) -> Result<Response<Body>> {
+    let book = get_visible_book(&state, &headers, &id).await?;
+
+    // Use the on-disk size so Content-Length and ranges match the file served
+    let size = tokio::fs::metadata(&book.path).await?.len();
+    let etag_seed = book.file_hash.as_deref().unwrap_or(&book.id);
+
+    range::serve_file(
+        &headers,
+        range::FileMeta {
+            path: &book.path,
+            size,
+            content_type: book.mime_type(),
+            filename: book.filename(),
+            etag: range::file_etag(etag_seed, book.modified, size),
+            modified: book.modified,
+        },
+    )
+    .await
+}
+
+/// Query parameters for EPUB conversion.
+#[derive(Debug, Deserialize)]
+pub struct ConvertQuery {
+    /// Device profile for comic pages (defaults to the user's profile).
+    pub profile: Option<String>,
+    /// Device screen width in pixels, to resize comic pages.
+    pub width: Option<u32>,
+    /// Device screen height in pixels, to resize comic pages.
+    pub height: Option<u32>,
+}
+
+/// Book converted to EPUB, for formats most readers do not open.
+///
+/// Comics become fixed-layout books, with pages re-encoded for the device
+/// profile; with both `width` and `height` (e.g. `?width=1072&height=1448`),
+/// pages are only scaled down to fit the screen, rounded up to 256 pixels.
+pub async fn book_convert_epub(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<ConvertQuery>,
+) -> Result<Response<Body>> {
+    let book = get_visible_book(&state, &headers, &id).await?;
+    if !formats::convert::can_convert(book.format) {
+        return Err(AppError::NotFound(format!(
+            "No EPUB conversion for book: {}",
+            id
+        )));
+    }
 
-    let file = tokio::fs::File::open(&book.path).await?;
-    let stream = ReaderStream::new(file);
-    let body = Body::from_stream(stream);
+    let mut options = ComicOptions::default();
+    if formats::convert::is_fixed_layout(book.format) {
+        if let (Some(width), Some(height)) = (params.width, params.height) {
+            options = ComicOptions::for_screen(width, height);
+        } else if let Some(profile) =
+            device_profile(&state, &headers, params.profile.as_deref()).await?
+        {
+            options = ComicOptions::from(&profile);
+        }
+    }
 
-    let filename = book.filename();
-    let content_disposition = format!("attachment; filename=\"{}\"", filename);
+    // Conversion reads the whole book, keep it off the async workers
+    let convert_state = state.clone();
+    let convert_book = book.clone();
+    let path = tokio::task::spawn_blocking(move || {
+        convert_state.get_converted_epub(&convert_book, &options)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Conversion task failed: {}", e)))??;
+
+    let metadata = tokio::fs::metadata(&path).await?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .map(chrono::DateTime::from)
+        .unwrap_or(book.modified);
+    let filename = format!("{}.epub", book.stem());
+    let mut etag_seed = format!("{}-epub", book.file_hash.as_deref().unwrap_or(&book.id));
+    if let Some(key) = options.cache_key() {
+        etag_seed.push_str(&format!("-{}", key));
+    }
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, book.format.mime_type())
-        .header(header::CONTENT_DISPOSITION, content_disposition)
-        .header(header::CONTENT_LENGTH, book.file_size)
-        .body(body)
-        .unwrap_or_else(|_| Response::default()))
+    range::serve_file(
+        &headers,
+        range::FileMeta {
+            path: &path,
+            size,
+            content_type: "application/epub+zip",
+            filename: &filename,
+            etag: range::file_etag(&etag_seed, modified, size),
+            modified,
+        },
+    )
+    .await
+}
+
+/// Device profile query parameter.
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    /// Device profile name (defaults to the user's profile).
+    pub profile: Option<String>,
+}
+
+/// Comic archive re-encoded as CBZ for a device profile.
+///
+/// Pages are resized, converted to grayscale and re-encoded as configured
+/// for the profile given with `?profile=`, or the user's default profile.
+pub async fn book_download_optimized(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<ProfileQuery>,
+) -> Result<Response<Body>> {
+    let book = get_visible_book(&state, &headers, &id).await?;
+    if !book.format.is_comic() {
+        return Err(AppError::NotFound(format!(
+            "No optimised download for book: {}",
+            id
+        )));
+    }
+    let profile = device_profile(&state, &headers, params.profile.as_deref())
+        .await?
+        .ok_or_else(|| {
+            AppError::InvalidFormat("No device profile selected, use ?profile=<name>".to_string())
+        })?;
+    let options = ComicOptions::from(&profile);
+
+    // Re-encoding decodes every page, keep it off the async workers
+    let convert_state = state.clone();
+    let convert_book = book.clone();
+    let path = tokio::task::spawn_blocking(move || {
+        convert_state.get_optimized_comic(&convert_book, &options)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Optimisation task failed: {}", e)))??;
+
+    let metadata = tokio::fs::metadata(&path).await?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .map(chrono::DateTime::from)
+        .unwrap_or(book.modified);
+    let filename = format!("{} ({}).cbz", book.stem(), profile.name);
+    let etag_seed = format!(
+        "{}-{}",
+        book.file_hash.as_deref().unwrap_or(&book.id),
+        options.cache_key().unwrap_or_default()
+    );
+
+    range::serve_file(
+        &headers,
+        range::FileMeta {
+            path: &path,
+            size,
+            content_type: BookFormat::Cbz.mime_type(),
+            filename: &filename,
+            etag: range::file_etag(&etag_seed, modified, size),
+            modified,
+        },
+    )
+    .await
 }
 
 pub async fn book_cover(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Response<Body>> {
-    let book = state
-        .get_book(&id)
-        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+    let book = get_visible_book(&state, &headers, &id).await?;
 
     let cover_data = state
         .get_cover(&book)
@@ -255,11 +895,10 @@ pub async fn book_cover(
 
 pub async fn book_thumbnail(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Response<Body>> {
-    let book = state
-        .get_book(&id)
-        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+    let book = get_visible_book(&state, &headers, &id).await?;
 
     let cover_data = state
         .get_cover(&book)
@@ -310,12 +949,11 @@ fn default_placeholder_quality() -> u8 {
 /// Query params: ?width=600&quality=90
 pub async fn book_placeholder(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(id): Path<String>,
     Query(params): Query<PlaceholderQuery>,
 ) -> Result<Response<Body>> {
-    let book = state
-        .get_book(&id)
-        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+    let book = get_visible_book(&state, &headers, &id).await?;
 
     // Get cover image from cache
     let cover_data = state.get_cover(&book);
@@ -480,8 +1118,32 @@ pub async fn auth_logout(State(state): State<AppState>, headers: HeaderMap) -> R
     Ok(StatusCode::OK)
 }
 
-pub async fn auth_me(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<db::User>> {
-    let user = get_authenticated_user(&state, &headers).await?;
+pub async fn auth_me(AuthUser(user): AuthUser) -> Result<Json<db::User>> {
+    Ok(Json(user))
+}
+
+/// Default device profile request.
+#[derive(Debug, Deserialize)]
+pub struct DeviceProfileRequest {
+    /// Profile name, or null to clear it.
+    profile: Option<String>,
+}
+
+/// Set the user's default device profile for optimised comic downloads.
+pub async fn auth_set_device_profile(
+    State(state): State<AppState>,
+    AuthUser(mut user): AuthUser,
+    Json(req): Json<DeviceProfileRequest>,
+) -> Result<Json<db::User>> {
+    let profile = match req.profile.as_deref() {
+        Some(name) => Some(find_device_profile(&state, name)?.name.clone()),
+        None => None,
+    };
+
+    state
+        .db
+        .set_user_device_profile(&user.id, profile.as_deref())?;
+    user.device_profile = profile;
     Ok(Json(user))
 }
 
@@ -497,29 +1159,79 @@ pub struct ProgressUpdateRequest {
     status: Option<String>,
 }
 
+/// Progress query parameters.
+#[derive(Debug, Deserialize)]
+pub struct ProgressQuery {
+    device_id: Option<String>,
+}
+
+/// Device ID used when the client does not send one.
+const DEFAULT_DEVICE_ID: &str = "default";
+
+/// Merged reading progress.
+#[derive(Debug, Serialize)]
+pub struct ProgressResponse {
+    /// Position the client should jump to.
+    #[serde(flatten)]
+    progress: ReadingProgress,
+    /// Merge strategy that selected this position.
+    strategy: MergeStrategy,
+    /// Whether the submitted update is the winning position (updates only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accepted: Option<bool>,
+    /// Positions of every device (per_device strategy only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    devices: Option<Vec<ReadingProgress>>,
+}
+
+/// Build the merged progress response for a user and book.
+fn merged_progress(
+    state: &AppState,
+    user_id: &str,
+    book_id: &str,
+    device_id: Option<&str>,
+) -> Result<Option<ProgressResponse>> {
+    let strategy = state.config.sync.merge_strategy;
+    let rows = state.db.get_device_progress(user_id, book_id)?;
+
+    let Some(progress) = strategy.select(&rows, device_id).cloned() else {
+        return Ok(None);
+    };
+
+    Ok(Some(ProgressResponse {
+        progress,
+        strategy,
+        accepted: None,
+        devices: (strategy == MergeStrategy::PerDevice).then_some(rows),
+    }))
+}
+
 pub async fn sync_get_progress(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
-) -> Result<Json<Option<ReadingProgress>>> {
-    let user = get_authenticated_user(&state, &headers).await?;
-    let progress = state.db.get_progress(&user.id, &book_id)?;
+    Query(query): Query<ProgressQuery>,
+) -> Result<Json<Option<ProgressResponse>>> {
+    let progress = merged_progress(&state, &user.id, &book_id, query.device_id.as_deref())?;
     Ok(Json(progress))
 }
 
 pub async fn sync_update_progress(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
     Json(req): Json<ProgressUpdateRequest>,
-) -> Result<StatusCode> {
-    let user = get_authenticated_user(&state, &headers).await?;
+) -> Result<Json<ProgressResponse>> {
+    // Rows are kept per device, a NULL device would never be upserted
+    let device_id = req
+        .device_id
+        .unwrap_or_else(|| DEFAULT_DEVICE_ID.to_string());
 
     let progress = ReadingProgress {
         id: 0, // Auto-increment
-        user_id: user.id,
-        book_id,
-        device_id: req.device_id,
+        user_id: user.id.clone(),
+        book_id: book_id.clone(),
+        device_id: Some(device_id.clone()),
         current_page: req.current_page,
         total_pages: req.total_pages,
         percentage: req.percentage,
@@ -531,8 +1243,23 @@ pub async fn sync_update_progress(
         updated_at: db::now_timestamp(),
     };
 
-    state.db.save_progress(&progress)?;
-    Ok(StatusCode::OK)
+    let saved = state
+        .db
+        .save_merged_progress(&progress, state.config.sync.merge_strategy)?;
+    if !saved {
+        tracing::debug!(
+            book_id = %book_id,
+            device_id = %device_id,
+            "Progress behind the saved position, ignored"
+        );
+    }
+
+    let mut merged = merged_progress(&state, &user.id, &book_id, Some(&device_id))?
+        .ok_or_else(|| AppError::Internal("Progress was not saved".to_string()))?;
+    merged.accepted =
+        Some(saved && merged.progress.device_id.as_deref() == Some(device_id.as_str()));
+
+    Ok(Json(merged))
 }
 
 /// Highlight request.
@@ -551,22 +1278,19 @@ pub struct HighlightRequest {
 
 pub async fn sync_get_highlights(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
 ) -> Result<Json<Vec<Highlight>>> {
-    let user = get_authenticated_user(&state, &headers).await?;
     let highlights = state.db.get_highlights(&user.id, &book_id)?;
     Ok(Json(highlights))
 }
 
 pub async fn sync_add_highlight(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
     Json(req): Json<HighlightRequest>,
 ) -> Result<Json<Highlight>> {
-    let user = get_authenticated_user(&state, &headers).await?;
-
     let highlight = Highlight {
         id: req.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
         user_id: user.id,
@@ -589,10 +1313,9 @@ pub async fn sync_add_highlight(
 
 pub async fn sync_delete_highlight(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(id): Path<String>,
 ) -> Result<StatusCode> {
-    let user = get_authenticated_user(&state, &headers).await?;
     state.db.delete_highlight(&id, &user.id)?;
     Ok(StatusCode::OK)
 }
@@ -608,22 +1331,19 @@ pub struct BookmarkRequest {
 
 pub async fn sync_get_bookmarks(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
 ) -> Result<Json<Vec<Bookmark>>> {
-    let user = get_authenticated_user(&state, &headers).await?;
     let bookmarks = state.db.get_bookmarks(&user.id, &book_id)?;
     Ok(Json(bookmarks))
 }
 
 pub async fn sync_add_bookmark(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
     Json(req): Json<BookmarkRequest>,
 ) -> Result<Json<Bookmark>> {
-    let user = get_authenticated_user(&state, &headers).await?;
-
     let bookmark = Bookmark {
         id: req.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
         user_id: user.id,
@@ -640,17 +1360,23 @@ pub async fn sync_add_bookmark(
 
 pub async fn sync_delete_bookmark(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(id): Path<String>,
 ) -> Result<StatusCode> {
-    let user = get_authenticated_user(&state, &headers).await?;
     state.db.delete_bookmark(&id, &user.id)?;
     Ok(StatusCode::OK)
 }
 
 /// API: Trigger library scan.
-pub async fn api_scan(State(state): State<AppState>) -> Result<Json<ScanResponse>> {
-    state.scan_all_libraries()?;
+pub async fn api_scan(
+    State(state): State<AppState>,
+    AdminUser(_): AdminUser,
+) -> Result<Json<ScanResponse>> {
+    // Scanning reads every changed file, keep it off the async workers
+    let scan_state = state.clone();
+    tokio::task::spawn_blocking(move || scan_state.scan_all_libraries())
+        .await
+        .map_err(|e| AppError::Internal(format!("Scan task failed: {}", e)))??;
 
     Ok(Json(ScanResponse {
         total_books: state.book_count(),
@@ -664,7 +1390,10 @@ pub struct ScanResponse {
 }
 
 /// API: Get library statistics.
-pub async fn api_stats(State(state): State<AppState>) -> Json<StatsResponse> {
+pub async fn api_stats(
+    State(state): State<AppState>,
+    AdminUser(_): AdminUser,
+) -> Json<StatsResponse> {
     let books = state.get_all_books();
     let total_size: u64 = books.iter().map(|b| b.file_size).sum();
 
@@ -749,9 +1478,135 @@ pub struct LibraryResponse {
 }
 
 /// Get full library listing for sync.
-pub async fn api_library(State(state): State<AppState>) -> impl IntoResponse {
-    let books_with_paths = state.get_books_with_paths();
+pub async fn api_library(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<LibraryResponse>> {
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books_with_paths = state.get_books_with_paths(&libraries);
+    Ok(Json(library_response(books_with_paths)))
+}
 
+/// API search query parameters.
+#[derive(Debug, Deserialize)]
+pub struct ApiSearchQuery {
+    /// Search query, with the same syntax as the catalog search.
+    q: String,
+}
+
+/// Search the library, best matches first.
+pub async fn api_search(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ApiSearchQuery>,
+) -> Result<Json<LibraryResponse>> {
+    let libraries = visible_libraries(&state, &headers).await?;
+    let books = state.search(&params.q, &libraries);
+    Ok(Json(library_response(state.with_relative_paths(books))))
+}
+
+/// Content search query parameters.
+#[derive(Debug, Deserialize)]
+pub struct ContentSearchQuery {
+    /// Words or quoted phrases to find in the text of books.
+    q: String,
+    /// Maximum number of books (default 20, at most 100).
+    limit: Option<usize>,
+}
+
+/// Books found by a content search.
+#[derive(Serialize)]
+pub struct ContentSearchResponse {
+    /// Matching books, best first.
+    pub results: Vec<ContentSearchResult>,
+    /// Number of books returned.
+    pub total: usize,
+}
+
+/// A book found by a content search.
+#[derive(Serialize)]
+pub struct ContentSearchResult {
+    /// Book ID.
+    pub id: String,
+    /// Book title.
+    pub title: String,
+    /// Authors.
+    pub authors: Vec<String>,
+    /// File format (epub, pdf...).
+    pub format: String,
+    /// Best matching passages.
+    pub matches: Vec<ContentPassage>,
+}
+
+/// A passage of a book matching a content search.
+#[derive(Serialize)]
+pub struct ContentPassage {
+    /// Chapter number in the reading order (EPUB).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<u32>,
+    /// Page number (PDF).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// Chapter title, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// HTML-escaped text around the match, matched words in `<mark>`.
+    pub snippet: String,
+}
+
+/// Search the text of books in libraries with content indexing.
+pub async fn api_search_content(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ContentSearchQuery>,
+) -> Result<Json<ContentSearchResponse>> {
+    let libraries = visible_libraries(&state, &headers).await?;
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+
+    let found =
+        tokio::task::spawn_blocking(move || state.search_content(&params.q, &libraries, limit))
+            .await
+            .map_err(|e| AppError::Internal(format!("Content search failed: {}", e)))??;
+
+    let results: Vec<ContentSearchResult> = found
+        .into_iter()
+        .map(|(book, matches)| ContentSearchResult {
+            id: book.id,
+            title: book.title,
+            authors: book.authors,
+            format: format!("{:?}", book.format).to_lowercase(),
+            matches: matches
+                .into_iter()
+                .map(|m| {
+                    let (chapter, page) = match m.location {
+                        TextLocation::Chapter(n) => (Some(n), None),
+                        TextLocation::Page(n) => (None, Some(n)),
+                    };
+                    ContentPassage {
+                        chapter,
+                        page,
+                        title: m.title,
+                        snippet: highlight_snippet(&m.snippet),
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let total = results.len();
+    Ok(Json(ContentSearchResponse { results, total }))
+}
+
+/// Escape a snippet for HTML and turn the match markers into `<mark>` tags.
+fn highlight_snippet(snippet: &str) -> String {
+    quick_xml::escape::escape(snippet)
+        .replace(db::MATCH_START, "<mark>")
+        .replace(db::MATCH_END, "</mark>")
+}
+
+fn library_response(
+    books_with_paths: Vec<(crate::library::book::Book, String)>,
+) -> LibraryResponse {
     let entries: Vec<LibraryEntry> = books_with_paths
         .into_iter()
         .map(|(book, rel_path)| LibraryEntry {
@@ -770,10 +1625,10 @@ pub async fn api_library(State(state): State<AppState>) -> impl IntoResponse {
 
     let total = entries.len();
 
-    Json(LibraryResponse {
+    LibraryResponse {
         books: entries,
         total,
-    })
+    }
 }
 
 // SDR SYNC API (KOReader .sdr folders)
@@ -796,9 +1651,8 @@ pub struct SdrListResponse {
 /// Get list of all SDR backups for user.
 pub async fn sync_get_sdr_list(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
 ) -> Result<Json<SdrListResponse>> {
-    let user = get_authenticated_user(&state, &headers).await?;
     let sdr_list = state.db.get_user_sdr_list(&user.id)?;
 
     let sdrs = sdr_list
@@ -817,10 +1671,9 @@ pub async fn sync_get_sdr_list(
 /// Get SDR info for a specific book.
 pub async fn sync_get_sdr_info(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
 ) -> Result<Json<Option<SdrInfoResponse>>> {
-    let user = get_authenticated_user(&state, &headers).await?;
     let sdr_info = state.db.get_sdr_info(&user.id, &book_id)?;
 
     Ok(Json(sdr_info.map(|s| SdrInfoResponse {
@@ -834,11 +1687,9 @@ pub async fn sync_get_sdr_info(
 /// Download SDR backup (returns tar.gz).
 pub async fn sync_download_sdr(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
 ) -> Result<Response<Body>> {
-    let user = get_authenticated_user(&state, &headers).await?;
-
     let sdr = state
         .db
         .get_sdr(&user.id, &book_id)?
@@ -855,12 +1706,10 @@ pub async fn sync_download_sdr(
 /// Upload SDR backup (receives tar.gz).
 pub async fn sync_upload_sdr(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Path(book_id): Path<String>,
     body: axum::body::Bytes,
 ) -> Result<StatusCode> {
-    let user = get_authenticated_user(&state, &headers).await?;
-
     // Extract metadata from tar.gz to get last_page and percent_finished
     let (last_page, percent_finished) = extract_sdr_metadata(&body)?;
 
@@ -950,22 +1799,186 @@ fn extract_lua_number(line: &str) -> Option<f64> {
     }
 }
 
-/// Extract token from Authorization header.
-fn extract_token(headers: &HeaderMap) -> Option<String> {
-    headers
-        .get(header::AUTHORIZATION)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("Bearer "))
-        .map(|s| s.to_string())
+/// A visible folder with its subfolders and the books stored directly in it,
+/// sorted by filename.
+fn category_listing(
+    state: &AppState,
+    libraries: &HashSet<String>,
+    id: &str,
+) -> Result<(CategoryNode, Vec<Category>, Vec<crate::library::book::Book>)> {
+    let (node, children) = {
+        let categories = state.categories();
+        let node = categories
+            .get(id)
+            .filter(|node| libraries.contains(&node.library_id))
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("Category not found: {}", id)))?;
+        let children: Vec<_> = categories
+            .children(id)
+            .into_iter()
+            .map(|child| child.category.clone())
+            .collect();
+        (node, children)
+    };
+
+    let mut books = state.get_books(&node.books);
+    books.sort_by_key(|b| b.filename().to_lowercase());
+    Ok((node, children, books))
+}
+
+/// Field of a browse feed URL.
+fn browse_field(segment: &str) -> Result<BrowseField> {
+    BrowseField::parse(segment)
+        .ok_or_else(|| AppError::NotFound(format!("Unknown catalog feed: {}", segment)))
+}
+
+/// Visible books with a value of a field, and the value as spelled in
+/// the first book (URLs may differ in case).
+fn browse_books(
+    state: &AppState,
+    field: BrowseField,
+    value: &str,
+    libraries: &HashSet<String>,
+) -> Result<(String, Vec<crate::library::book::Book>)> {
+    let books = state.get_books_by(field, value, libraries);
+    let title = books
+        .first()
+        .and_then(|book| {
+            field
+                .values(book)
+                .into_iter()
+                .find(|v| v.to_lowercase() == value.trim().to_lowercase())
+        })
+        .ok_or_else(|| {
+            AppError::NotFound(format!("No books for {}: {}", field.segment(), value))
+        })?;
+    Ok((title, books))
+}
+
+/// Default order of a browse feed: series in reading order, others by title.
+fn browse_sort(field: BrowseField) -> SortOrder {
+    match field {
+        BrowseField::Series => SortOrder::Series,
+        _ => SortOrder::Title,
+    }
+}
+
+/// Reading statuses of the requesting user, if signed in.
+fn reading_statuses(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<HashMap<String, ReadStatus>>> {
+    let Some(user) = optional_user(state, headers)? else {
+        return Ok(None);
+    };
+    let statuses = state
+        .db
+        .reading_statuses(&user.id)?
+        .into_iter()
+        .map(|(book_id, status)| (book_id, ReadStatus::from_progress(&status)))
+        .collect();
+    Ok(Some(statuses))
+}
+
+/// One page of an acquisition feed, with its facets applied.
+struct AcquisitionPage {
+    /// Books on the page.
+    books: Vec<crate::library::book::Book>,
+    pagination: Pagination,
+    facets: Vec<Facet>,
+    /// Feed URL with the active facets, for page links.
+    base: String,
+}
+
+impl AcquisitionPage {
+    /// URL of this page.
+    fn self_href(&self) -> String {
+        self.pagination.href(&self.base, self.pagination.offset)
+    }
+}
+
+/// Sort, filter and paginate the books of an acquisition feed at `base`.
+///
+/// Shared by the OPDS 1.2 and 2.0 feeds, which only differ in rendering.
+fn acquisition_page(
+    state: &AppState,
+    headers: &HeaderMap,
+    books: Vec<crate::library::book::Book>,
+    base: &str,
+    params: &PageParams,
+    default_sort: Option<SortOrder>,
+) -> Result<AcquisitionPage> {
+    let statuses = reading_statuses(state, headers)?;
+    let faceted = facets::apply(books, base, &params.facets, default_sort, statuses.as_ref())?;
+    let pagination = Pagination::new(
+        params.page,
+        params.offset,
+        state.config.server.page_size,
+        faceted.books.len(),
+    );
+
+    Ok(AcquisitionPage {
+        books: pagination.slice(faceted.books),
+        pagination,
+        facets: faceted.facets,
+        base: faceted.base,
+    })
+}
+
+/// IDs of the libraries visible to the requesting user.
+async fn visible_libraries(state: &AppState, headers: &HeaderMap) -> Result<HashSet<String>> {
+    let user = optional_user(state, headers)?;
+    if user.is_none() && state.config.auth.require_auth {
+        return Err(AppError::Unauthorized(
+            "Authentication required".to_string(),
+        ));
+    }
+    state.visible_library_ids(user.as_ref())
 }
 
-/// Get authenticated user from token.
-async fn get_authenticated_user(state: &AppState, headers: &HeaderMap) -> Result<db::User> {
-    let token = extract_token(headers)
-        .ok_or_else(|| AppError::InvalidFormat("Missing Authorization header".to_string()))?;
+/// Look up a configured device profile by name.
+fn find_device_profile<'a>(state: &'a AppState, name: &str) -> Result<&'a DeviceProfile> {
+    state.config.device_profile(name).ok_or_else(|| {
+        let names: Vec<&str> = state
+            .config
+            .devices
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        AppError::InvalidFormat(format!(
+            "Unknown device profile '{}' (available: {})",
+            name,
+            names.join(", ")
+        ))
+    })
+}
 
+/// Device profile for a request: the `profile` query parameter, or the
+/// authenticated user's default profile.
+async fn device_profile(
+    state: &AppState,
+    headers: &HeaderMap,
+    requested: Option<&str>,
+) -> Result<Option<DeviceProfile>> {
+    if let Some(name) = requested {
+        return find_device_profile(state, name).map(|p| Some(p.clone()));
+    }
+
+    let user = optional_user(state, headers)?;
+    // A profile removed from the configuration is ignored
+    Ok(user
+        .and_then(|u| u.device_profile)
+        .and_then(|name| state.config.device_profile(&name).cloned()))
+}
+
+/// Get a book, hiding books from libraries the user cannot see.
+async fn get_visible_book(
+    state: &AppState,
+    headers: &HeaderMap,
+    id: &str,
+) -> Result<crate::library::book::Book> {
+    let libraries = visible_libraries(state, headers).await?;
     state
-        .auth
-        .validate_token(&token)?
-        .ok_or_else(|| AppError::InvalidFormat("Invalid or expired token".to_string()))
+        .get_visible_book(id, &libraries)
+        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))
 }
@@ -0,0 +1,277 @@
+//! KOReader progress sync (kosync) compatible API.
+//!
+//! Implements the protocol spoken by KOReader's built-in "Progress sync"
+//! plugin, so devices without the CloudReader plugin can sync against the
+//! same `reading_progress` table. Documents are identified by KOReader's
+//! partial MD5 digest or by the MD5 of their filename.
+
+use crate::db::{self, Device, ReadingProgress};
+use crate::error::AppError;
+use crate::server::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Create the kosync router.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/users/create", post(create_user))
+        .route("/users/auth", get(auth_user))
+        .route("/syncs/progress", put(update_progress))
+        .route("/syncs/progress/{document}", get(get_progress))
+        .route("/healthcheck", get(healthcheck))
+}
+
+/// Errors in the format expected by KOReader.
+#[derive(Debug)]
+enum KosyncError {
+    /// Missing or invalid credentials.
+    Unauthorized,
+    /// Username already taken.
+    UserExists,
+    /// Registration disabled in config.
+    RegistrationDisabled,
+    /// Malformed request.
+    InvalidRequest(String),
+    /// Document digest does not match any book.
+    UnknownDocument,
+    /// Server-side failure.
+    Internal(AppError),
+}
+
+impl From<AppError> for KosyncError {
+    fn from(e: AppError) -> Self {
+        KosyncError::Internal(e)
+    }
+}
+
+impl IntoResponse for KosyncError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = match self {
+            KosyncError::Unauthorized => {
+                (StatusCode::UNAUTHORIZED, 2001, "Unauthorized".to_string())
+            }
+            KosyncError::UserExists => (
+                StatusCode::PAYMENT_REQUIRED,
+                2002,
+                "Username is already registered.".to_string(),
+            ),
+            KosyncError::InvalidRequest(message) => (StatusCode::FORBIDDEN, 2003, message),
+            KosyncError::RegistrationDisabled => (
+                StatusCode::FORBIDDEN,
+                2005,
+                "User registration is disabled.".to_string(),
+            ),
+            KosyncError::UnknownDocument => (
+                StatusCode::NOT_FOUND,
+                2006,
+                "Document not found in library.".to_string(),
+            ),
+            KosyncError::Internal(e) => {
+                tracing::error!(error = %e, "kosync request error");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    2000,
+                    "Unknown server error.".to_string(),
+                )
+            }
+        };
+
+        (status, Json(json!({ "code": code, "message": message }))).into_response()
+    }
+}
+
+type KosyncResult<T> = std::result::Result<T, KosyncError>;
+
+/// User creation request (password is the MD5 key).
+#[derive(Debug, Deserialize)]
+struct CreateUserRequest {
+    username: String,
+    password: String,
+}
+
+/// Progress document exchanged with KOReader.
+#[derive(Debug, Deserialize, Serialize)]
+struct KosyncProgress {
+    document: String,
+    progress: String,
+    /// Reading position as a fraction (0.0 - 1.0).
+    percentage: f64,
+    device: String,
+    device_id: String,
+    #[serde(skip_deserializing)]
+    timestamp: i64,
+}
+
+async fn healthcheck() -> Json<serde_json::Value> {
+    Json(json!({ "state": "OK" }))
+}
+
+async fn create_user(
+    State(state): State<AppState>,
+    Json(req): Json<CreateUserRequest>,
+) -> KosyncResult<impl IntoResponse> {
+    if !state.config.auth.registration_enabled() {
+        return Err(KosyncError::RegistrationDisabled);
+    }
+    if state.db.get_user_by_username(&req.username)?.is_some() {
+        return Err(KosyncError::UserExists);
+    }
+
+    let user = state
+        .auth
+        .register_kosync(&req.username, &req.password)
+        .map_err(|e| match e {
+            AppError::InvalidFormat(message) => KosyncError::InvalidRequest(message),
+            other => KosyncError::Internal(other),
+        })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "username": user.username })),
+    ))
+}
+
+async fn auth_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> KosyncResult<Json<serde_json::Value>> {
+    authenticate(&state, &headers)?;
+    Ok(Json(json!({ "authorized": "OK" })))
+}
+
+async fn update_progress(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<KosyncProgress>,
+) -> KosyncResult<Json<serde_json::Value>> {
+    let user = authenticate(&state, &headers)?;
+
+    let libraries = state.visible_library_ids(Some(&user))?;
+    let book = state
+        .get_book_by_digest(&req.document, &libraries)
+        .ok_or(KosyncError::UnknownDocument)?;
+
+    let now = db::now_timestamp();
+
+    state.db.save_device(&Device {
+        id: req.device_id.clone(),
+        user_id: user.id.clone(),
+        name: Some(req.device.clone()),
+        model: None,
+        last_seen: now,
+    })?;
+
+    let progress = ReadingProgress {
+        id: 0,
+        user_id: user.id,
+        book_id: book.id,
+        device_id: Some(req.device_id),
+        // Paged documents (PDF, CBZ) report the page number as progress
+        current_page: req.progress.parse().ok(),
+        total_pages: book.page_count.map(i64::from),
+        percentage: Some(req.percentage * 100.0),
+        current_chapter: None,
+        position_data: Some(req.progress),
+        status: "reading".to_string(),
+        started_at: Some(now),
+        finished_at: None,
+        updated_at: now,
+    };
+    let saved = state
+        .db
+        .save_merged_progress(&progress, state.config.sync.merge_strategy)?;
+
+    // A rejected update answers with the timestamp of the position kept
+    let timestamp = if saved {
+        now
+    } else {
+        tracing::debug!(
+            document = %req.document,
+            device = ?progress.device_id,
+            "kosync progress behind the saved position, ignored"
+        );
+        state
+            .db
+            .get_device_progress(&progress.user_id, &progress.book_id)?
+            .into_iter()
+            .find(|p| p.device_id == progress.device_id)
+            .map_or(now, |p| p.updated_at)
+    };
+
+    Ok(Json(json!({
+        "document": req.document,
+        "timestamp": timestamp,
+    })))
+}
+
+async fn get_progress(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(document): Path<String>,
+) -> KosyncResult<Response> {
+    let user = authenticate(&state, &headers)?;
+
+    let libraries = state.visible_library_ids(Some(&user))?;
+    let progress = match state.get_book_by_digest(&document, &libraries) {
+        Some(book) => state.db.get_merged_progress(
+            &user.id,
+            &book.id,
+            state.config.sync.merge_strategy,
+            None,
+        )?,
+        None => None,
+    };
+
+    // KOReader expects an empty object when there is nothing to sync
+    let Some(progress) = progress else {
+        return Ok(Json(json!({})).into_response());
+    };
+
+    let device_id = progress.device_id.unwrap_or_default();
+    let device = state
+        .db
+        .get_device(&device_id)?
+        .and_then(|d| d.name)
+        .unwrap_or_default();
+
+    Ok(Json(KosyncProgress {
+        document,
+        progress: progress.position_data.unwrap_or_else(|| {
+            progress
+                .current_page
+                .map(|p| p.to_string())
+                .unwrap_or_default()
+        }),
+        percentage: progress.percentage.unwrap_or(0.0) / 100.0,
+        device,
+        device_id,
+        timestamp: progress.updated_at,
+    })
+    .into_response())
+}
+
+/// Authenticate a request from its `x-auth-user`/`x-auth-key` headers.
+fn authenticate(state: &AppState, headers: &HeaderMap) -> KosyncResult<db::User> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+    };
+
+    let (Some(username), Some(key)) = (header("x-auth-user"), header("x-auth-key")) else {
+        return Err(KosyncError::Unauthorized);
+    };
+
+    state
+        .auth
+        .authenticate_kosync(username, key)?
+        .ok_or(KosyncError::Unauthorized)
+}
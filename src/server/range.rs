@@ -0,0 +1,468 @@
+//! File responses with HTTP range and conditional request support.
+//!
+//! Handles `Range` (single and multiple ranges), `If-Range`,
+//! `If-None-Match` and `If-Modified-Since`, so interrupted downloads can be
+//! resumed and viewers can fetch parts of large files.
+
+use crate::error::{AppError, Result};
+use axum::body::Body;
+use axum::http::{HeaderMap, Response, StatusCode, header};
+use chrono::{DateTime, Utc};
+use std::io::{Cursor, SeekFrom};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+/// Above this many ranges the `Range` header is ignored and the whole file
+/// is served.
+const MAX_RANGES: usize = 32;
+
+/// File served by [`serve_file`].
+pub(super) struct FileMeta<'a> {
+    /// Path on disk.
+    pub path: &'a Path,
+    /// File size in bytes.
+    pub size: u64,
+    /// MIME type.
+    pub content_type: &'a str,
+    /// Filename for `Content-Disposition`.
+    pub filename: &'a str,
+    /// Entity tag, including quotes.
+    pub etag: String,
+    /// Last modification time.
+    pub modified: DateTime<Utc>,
+}
+
+/// Parsed `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum RangeRequest {
+    /// No (usable) range: serve the whole file.
+    Full,
+    /// Satisfiable byte ranges, sorted and coalesced.
+    Partial(Vec<RangeInclusive<u64>>),
+    /// No requested range overlaps the file.
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header value for a file of `size` bytes.
+///
+/// Malformed headers are ignored (whole file), as allowed by RFC 9110.
+pub(super) fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    let mut count = 0;
+
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return RangeRequest::Full;
+        }
+
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = if start.is_empty() {
+            // Suffix range: last N bytes
+            let Ok(suffix) = end.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || size == 0 {
+                continue;
+            }
+            size.saturating_sub(suffix)..=size - 1
+        } else {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                u64::MAX
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            if start >= size {
+                continue;
+            }
+            start..=end.min(size - 1)
+        };
+
+        ranges.push(range);
+    }
+
+    if count == 0 {
+        return RangeRequest::Full;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    // Coalesce overlapping or adjacent ranges
+    ranges.sort_by_key(|r| *r.start());
+    let mut merged: Vec<RangeInclusive<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                *last = *last.start()..=(*last.end()).max(*range.end());
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    RangeRequest::Partial(merged)
+}
+
+/// Format a timestamp as an HTTP date (IMF-fixdate).
+pub(super) fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Parse an HTTP date (IMF-fixdate).
+pub(super) fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Check an `If-None-Match` style list against an entity tag.
+///
+/// Uses the weak comparison, as required for `If-None-Match`.
+pub(super) fn etag_matches(value: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Whether an `If-Range` value still matches the file.
+///
+/// Entity tags use the strong comparison; dates must match exactly.
+fn if_range_matches(value: &str, file: &FileMeta<'_>) -> bool {
+    let value = value.trim();
+    if value.starts_with('"') {
+        return value == file.etag;
+    }
+    if value.starts_with("W/") {
+        return false;
+    }
+    parse_http_date(value).is_some_and(|date| date.timestamp() == file.modified.timestamp())
+}
+
+/// Whether the client copy is still fresh (`304 Not Modified`).
+fn not_modified(headers: &HeaderMap, file: &FileMeta<'_>) -> bool {
+    if let Some(value) = header_str(headers, header::IF_NONE_MATCH) {
+        return etag_matches(value, &file.etag);
+    }
+    header_str(headers, header::IF_MODIFIED_SINCE)
+        .and_then(parse_http_date)
+        .is_some_and(|since| file.modified.timestamp() <= since.timestamp())
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Serve a file, honouring range and conditional request headers.
+pub(super) async fn serve_file(headers: &HeaderMap, file: FileMeta<'_>) -> Result<Response<Body>> {
+    let last_modified = http_date(file.modified);
+    let builder = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &file.etag)
+        .header(header::LAST_MODIFIED, &last_modified);
+
+    if not_modified(headers, &file) {
+        return build(builder.status(StatusCode::NOT_MODIFIED), Body::empty());
+    }
+
+    let content_disposition = format!("attachment; filename=\"{}\"", file.filename);
+    let builder = builder.header(header::CONTENT_DISPOSITION, content_disposition);
+
+    let range = match header_str(headers, header::RANGE) {
+        Some(value)
+            if header_str(headers, header::IF_RANGE)
+                .is_none_or(|if_range| if_range_matches(if_range, &file)) =>
+        {
+            parse_range(value, file.size)
+        }
+        _ => RangeRequest::Full,
+    };
+
+    match range {
+        RangeRequest::Full => {
+            let stream = ReaderStream::new(tokio::fs::File::open(file.path).await?);
+            build(
+                builder
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, file.content_type)
+                    .header(header::CONTENT_LENGTH, file.size),
+                Body::from_stream(stream),
+            )
+        }
+
+        RangeRequest::Unsatisfiable => build(
+            builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", file.size)),
+            Body::empty(),
+        ),
+
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            let length = range.end() - range.start() + 1;
+            let reader = open_range(file.path, range).await?;
+            build(
+                builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_TYPE, file.content_type)
+                    .header(header::CONTENT_LENGTH, length)
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", range.start(), range.end(), file.size),
+                    ),
+                Body::from_stream(ReaderStream::new(reader)),
+            )
+        }
+
+        RangeRequest::Partial(ranges) => {
+            let boundary = uuid::Uuid::new_v4().simple().to_string();
+            let mut reader: Box<dyn AsyncRead + Send + Unpin> = Box::new(Cursor::new(Vec::new()));
+            let mut length = 0u64;
+
+            for range in &ranges {
+                let part_header = format!(
+                    "--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    boundary,
+                    file.content_type,
+                    range.start(),
+                    range.end(),
+                    file.size
+                );
+                length += part_header.len() as u64 + (range.end() - range.start() + 1) + 2;
+
+                reader = Box::new(
+                    reader
+                        .chain(Cursor::new(part_header.into_bytes()))
+                        .chain(open_range(file.path, range).await?)
+                        .chain(Cursor::new(b"\r\n".to_vec())),
+                );
+            }
+
+            let closing = format!("--{}--\r\n", boundary);
+            length += closing.len() as u64;
+            reader = Box::new(reader.chain(Cursor::new(closing.into_bytes())));
+
+            build(
+                builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(
+                        header::CONTENT_TYPE,
+                        format!("multipart/byteranges; boundary={}", boundary),
+                    )
+                    .header(header::CONTENT_LENGTH, length),
+                Body::from_stream(ReaderStream::new(reader)),
+            )
+        }
+    }
+}
+
+/// Open a file positioned at the start of `range`, limited to its length.
+async fn open_range(
+    path: &Path,
+    range: &RangeInclusive<u64>,
+) -> Result<tokio::io::Take<tokio::fs::File>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(*range.start())).await?;
+    Ok(file.take(range.end() - range.start() + 1))
+}
+
+fn build(builder: axum::http::response::Builder, body: Body) -> Result<Response<Body>> {
+    builder
+        .body(body)
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// Strong entity tag for a book file.
+pub(super) fn file_etag(seed: &str, modified: DateTime<Utc>, size: u64) -> String {
+    format!("\"{}-{:x}-{:x}\"", seed, modified.timestamp(), size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn value(s: &str) -> HeaderValue {
+        HeaderValue::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_single_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Partial(vec![0..=99])
+        );
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            RangeRequest::Partial(vec![900..=999])
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Partial(vec![900..=999])
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![0..=999])
+        );
+        assert_eq!(
+            parse_range("bytes=500-5000", 1000),
+            RangeRequest::Partial(vec![500..=999])
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_ranges() {
+        assert_eq!(
+            parse_range("bytes=500-599, 0-99", 1000),
+            RangeRequest::Partial(vec![0..=99, 500..=599])
+        );
+        // Overlapping and adjacent ranges are coalesced
+        assert_eq!(
+            parse_range("bytes=0-99,100-199,150-300", 1000),
+            RangeRequest::Partial(vec![0..=300])
+        );
+        // Unsatisfiable parts are dropped
+        assert_eq!(
+            parse_range("bytes=0-9,2000-3000", 1000),
+            RangeRequest::Partial(vec![0..=9])
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_ranges() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=0-0", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("items=0-5", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=abc", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_http_date_roundtrip() {
+        let date = DateTime::from_timestamp(784111777, 0).unwrap();
+        assert_eq!(http_date(date), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(date));
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches(r#""abc""#, r#""abc""#));
+        assert!(etag_matches(r#""x", W/"abc""#, r#""abc""#));
+        assert!(etag_matches("*", r#""abc""#));
+        assert!(!etag_matches(r#""abd""#, r#""abc""#));
+    }
+
+    #[test]
+    fn test_conditional_headers() {
+        let file = FileMeta {
+            path: Path::new("/dev/null"),
+            size: 10,
+            content_type: "application/pdf",
+            filename: "book.pdf",
+            etag: file_etag("hash", DateTime::from_timestamp(1000, 0).unwrap(), 10),
+            modified: DateTime::from_timestamp(1000, 0).unwrap(),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, value(&file.etag));
+        assert!(not_modified(&headers, &file));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, value(&http_date(file.modified)));
+        assert!(not_modified(&headers, &file));
+
+        let earlier = DateTime::from_timestamp(500, 0).unwrap();
+        headers.insert(header::IF_MODIFIED_SINCE, value(&http_date(earlier)));
+        assert!(!not_modified(&headers, &file));
+
+        assert!(if_range_matches(&file.etag, &file));
+        assert!(if_range_matches(&http_date(file.modified), &file));
+        assert!(!if_range_matches(r#""stale""#, &file));
+        assert!(!if_range_matches(&format!("W/{}", file.etag), &file));
+    }
+
+    #[tokio::test]
+    async fn test_serve_multi_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.pdf");
+        std::fs::write(&path, (0u8..100).collect::<Vec<_>>()).unwrap();
+
+        let modified = DateTime::from_timestamp(1000, 0).unwrap();
+        let meta = || FileMeta {
+            path: &path,
+            size: 100,
+            content_type: "application/pdf",
+            filename: "book.pdf",
+            etag: file_etag("hash", modified, 100),
+            modified,
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, value("bytes=10-11"));
+        let response = serve_file(&headers, meta()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 10-11/100");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], &[10, 11]);
+
+        headers.insert(header::RANGE, value("bytes=0-1,-2"));
+        let response = serve_file(&headers, meta()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let length: usize = response.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), length);
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains("Content-Range: bytes 0-1/100"));
+        assert!(text.contains("Content-Range: bytes 98-99/100"));
+        assert!(text.ends_with(&format!("--{}--\r\n", boundary)));
+
+        // A stale If-Range serves the whole file
+        headers.insert(header::IF_RANGE, value(r#""stale""#));
+        let response = serve_file(&headers, meta()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        headers.insert(header::RANGE, value("bytes=200-"));
+        headers.remove(header::IF_RANGE);
+        let response = serve_file(&headers, meta()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */100");
+    }
+}
@@ -1,14 +1,25 @@
 use crate::auth::AuthService;
 use crate::config::{BookFormat, Config};
-use crate::db::{self, Database, StoredBook};
+use crate::db::{self, ContentMatch, Database, Library, SearchEntry, StoredBook, User};
 use crate::error::Result;
 use crate::formats;
-use crate::library::book::Book;
+use crate::formats::convert::{self, ComicOptions};
+use crate::library::book::{Book, ReadingDirection, file_stem};
+use crate::library::{BrowseField, CategoryTree, SearchQuery};
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Passages read from the content index for one search.
+const MAX_CONTENT_MATCHES: usize = 500;
+
+/// Passages returned for each book found by a content search.
+const PASSAGES_PER_BOOK: usize = 3;
 
 /// Shared application state.
 #[derive(Clone)]
@@ -21,6 +32,10 @@ pub struct AppState {
     pub auth: Arc<AuthService>,
     /// In-memory book cache (for quick access).
     books: Arc<parking_lot::RwLock<Vec<Book>>>,
+    /// Folder hierarchy of the cached books.
+    categories: Arc<parking_lot::RwLock<CategoryTree>>,
+    /// Book IDs by KOReader document digest, partial MD5 matches first.
+    digests: Arc<parking_lot::RwLock<HashMap<String, Vec<String>>>>,
     /// Whether initial load from DB is complete.
     loaded: Arc<AtomicBool>,
     /// Whether a scan is currently in progress.
@@ -35,6 +50,8 @@ impl AppState {
             db,
             auth: Arc::new(auth),
             books: Arc::new(parking_lot::RwLock::new(Vec::new())),
+            categories: Arc::new(parking_lot::RwLock::new(CategoryTree::default())),
+            digests: Arc::new(parking_lot::RwLock::new(HashMap::new())),
             loaded: Arc::new(AtomicBool::new(false)),
             scanning: Arc::new(AtomicBool::new(false)),
         }
@@ -60,6 +77,35 @@ impl AppState {
             .filter_map(|sb| Self::stored_to_book(&sb))
             .collect();
 
+        // Databases created before the search index existed have to fill it
+        if self.db.search_index_len()? != books.len() {
+            tracing::info!("Rebuilding search index...");
+            let entries: Vec<SearchEntry> = books.iter().map(Self::book_to_search_entry).collect();
+            self.db.rebuild_search_index(&entries)?;
+        }
+
+        let libraries = self.db.list_libraries()?;
+        *self.categories.write() = CategoryTree::build(&libraries, &books);
+
+        // Partial MD5s win over filename digests of other books; the same
+        // file may be in several libraries, so every match is kept
+        let mut digests: HashMap<String, Vec<String>> = HashMap::new();
+        for book in &books {
+            if let Some(hash) = &book.file_hash {
+                digests
+                    .entry(hash.to_lowercase())
+                    .or_default()
+                    .push(book.id.clone());
+            }
+        }
+        for book in &books {
+            digests
+                .entry(book.filename_digest())
+                .or_default()
+                .push(book.id.clone());
+        }
+        *self.digests.write() = digests;
+
         let count = books.len();
         *self.books.write() = books;
         self.loaded.store(true, Ordering::Relaxed);
@@ -84,11 +130,21 @@ impl AppState {
             id: sb.id.clone(),
             title: sb.title.clone(),
             authors,
+            contributors: sb
+                .contributors_json
+                .as_ref()
+                .and_then(|j| serde_json::from_str(j).ok())
+                .unwrap_or_default(),
             description: sb.description.clone(),
             publisher: sb.publisher.clone(),
             published: sb.published.clone(),
             language: sb.language.clone(),
             isbn: sb.isbn.clone(),
+            identifiers: sb
+                .identifiers_json
+                .as_ref()
+                .and_then(|j| serde_json::from_str(j).ok())
+                .unwrap_or_default(),
             series: sb.series.clone(),
             series_index: sb.series_index,
             tags: sb
@@ -103,26 +159,43 @@ impl AppState {
             has_cover: sb.cover_cached,
             modified: chrono::DateTime::from_timestamp(sb.mtime, 0)
                 .unwrap_or_else(chrono::Utc::now),
+            file_hash: sb.file_hash.clone(),
+            library_id: sb.library_id.clone(),
+            reading_direction: sb
+                .reading_direction
+                .as_deref()
+                .map(ReadingDirection::parse)
+                .unwrap_or_default(),
         })
     }
 
     /// Convert Book to StoredBook.
-    fn book_to_stored(book: &Book, library_id: &str) -> StoredBook {
+    fn book_to_stored(book: &Book) -> StoredBook {
         let now = db::now_timestamp();
         let mtime = book.modified.timestamp();
 
         StoredBook {
             id: book.id.clone(),
-            library_id: library_id.to_string(),
-            file_hash: None,
+            library_id: book.library_id.clone(),
+            file_hash: book.file_hash.clone(),
             title: book.title.clone(),
             author: book.authors.first().cloned(),
             authors_json: Some(serde_json::to_string(&book.authors).unwrap_or_default()),
+            contributors_json: if book.contributors.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&book.contributors).unwrap_or_default())
+            },
             description: book.description.clone(),
             publisher: book.publisher.clone(),
             published: book.published.clone(),
             language: book.language.clone(),
             isbn: book.isbn.clone(),
+            identifiers_json: if book.identifiers.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&book.identifiers).unwrap_or_default())
+            },
             series: book.series.clone(),
             series_index: book.series_index,
             tags_json: if book.tags.is_empty() {
@@ -136,11 +209,38 @@ impl AppState {
             mtime,
             page_count: book.page_count.map(|p| p as i64),
             cover_cached: book.has_cover,
+            reading_direction: Some(book.reading_direction.as_str().to_string()),
             created_at: now,
             updated_at: now,
         }
     }
 
+    /// Collect the searchable text of a book.
+    fn book_to_search_entry(book: &Book) -> SearchEntry {
+        let isbns: Vec<String> = book
+            .isbn
+            .iter()
+            .chain(
+                book.identifiers
+                    .iter()
+                    .filter(|i| i.scheme == "isbn")
+                    .map(|i| &i.value),
+            )
+            .map(|isbn| isbn.chars().filter(char::is_ascii_alphanumeric).collect())
+            .collect();
+
+        SearchEntry {
+            book_id: book.id.clone(),
+            title: book.title.clone(),
+            authors: book.authors.join("\n"),
+            series: book.series.clone().unwrap_or_default(),
+            tags: book.tags.join("\n"),
+            publisher: book.publisher.clone().unwrap_or_default(),
+            description: book.description.clone().unwrap_or_default(),
+            isbn: isbns.join("\n"),
+        }
+    }
+
     /// Scan all libraries incrementally (only changed files).
     pub fn scan_all_libraries(&self) -> Result<()> {
         // Prevent concurrent scans
@@ -149,9 +249,16 @@ impl AppState {
             return Ok(());
         }
 
-        let result = self.do_incremental_scan();
-        self.scanning.store(false, Ordering::SeqCst);
-        result
+        // Clear the flag even if the scan unwinds
+        struct Reset<'a>(&'a AtomicBool);
+        impl Drop for Reset<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::SeqCst);
+            }
+        }
+        let _reset = Reset(&self.scanning);
+
+        self.do_incremental_scan()
     }
 
     /// Perform the actual incremental scan.
@@ -177,9 +284,17 @@ impl AppState {
             let existing_map: HashMap<String, StoredBook> =
                 existing.into_iter().map(|b| (b.id.clone(), b)).collect();
 
+            if !library.content_index {
+                self.db.clear_library_content(&library.id)?;
+            }
+
             // Scan filesystem
-            let (new, updated, unchanged, scanned_ids) =
-                self.scan_directory_incremental(&lib_path, &library.id, &existing_map)?;
+            let (new, updated, unchanged, scanned_ids) = self.scan_directory_incremental(
+                &lib_path,
+                &library.id,
+                &existing_map,
+                library.content_index,
+            )?;
 
             total_new += new;
             total_updated += updated;
@@ -227,11 +342,15 @@ impl AppState {
     }
 
     /// Scan a directory incrementally, comparing with existing DB entries.
+    ///
+    /// With `content_index`, the text of new and updated books is indexed too,
+    /// along with unchanged books whose text has not been indexed yet.
     fn scan_directory_incremental(
         &self,
         path: &PathBuf,
         library_id: &str,
         existing: &HashMap<String, StoredBook>,
+        content_index: bool,
     ) -> Result<(usize, usize, usize, Vec<String>)> {
         // Collect files first
         let files: Vec<_> = walkdir::WalkDir::new(path)
@@ -241,8 +360,7 @@ impl AppState {
             .filter(|e| e.path().is_file())
             .filter_map(|e| {
                 let file_path = e.path().to_path_buf();
-                let extension = file_path.extension()?.to_str()?;
-                let format = BookFormat::from_extension(extension)?;
+                let format = BookFormat::from_path(&file_path)?;
                 let metadata = std::fs::metadata(&file_path).ok()?;
                 Some((file_path, format, metadata))
             })
@@ -254,6 +372,12 @@ impl AppState {
         let mut scanned_ids = Vec::with_capacity(files.len());
         let mut to_process = Vec::new();
         let mut unchanged_count = 0;
+        let content_indexed = if content_index {
+            self.db.content_indexed_books(library_id)?
+        } else {
+            HashSet::new()
+        };
+        let mut content_only = Vec::new();
 
         for (file_path, format, metadata) in files {
             let id = uuid::Uuid::new_v5(
@@ -277,7 +401,15 @@ impl AppState {
                 && existing_book.mtime == mtime
                 && existing_book.file_size == file_size
             {
-                // Unchanged - skip
+                // Unchanged - backfill the document digest for books scanned before it existed
+                if existing_book.file_hash.is_none()
+                    && let Ok(hash) = crate::library::book::partial_md5(&file_path)
+                {
+                    let _ = self.db.update_book_hash(&id, &hash);
+                }
+                if content_index && !content_indexed.contains(&id) {
+                    content_only.push((file_path, format, id));
+                }
                 unchanged_count += 1;
                 continue;
             }
@@ -286,8 +418,12 @@ impl AppState {
             to_process.push((file_path, format, metadata, id));
         }
 
+        if !content_only.is_empty() {
+            tracing::info!(books = content_only.len(), "Indexing book content");
+        }
+
         let to_process_count = to_process.len();
-        if to_process_count == 0 {
+        if to_process_count == 0 && content_only.is_empty() {
             return Ok((0, 0, unchanged_count, scanned_ids));
         }
 
@@ -323,10 +459,21 @@ impl AppState {
                     }
 
                     // Extract metadata
-                    if let Ok(book) = self.extract_book_metadata(file_path, id, *format, metadata) {
-                        let stored = Self::book_to_stored(&book, &library_id_owned);
+                    if let Ok(book) = self.extract_book_metadata(
+                        file_path,
+                        id,
+                        &library_id_owned,
+                        *format,
+                        metadata,
+                    ) {
+                        let stored = Self::book_to_stored(&book);
                         // Save immediately (SQLite handles locking via parking_lot::Mutex)
-                        let _ = self.db.save_book(&stored);
+                        if self.db.save_book(&stored).is_ok() {
+                            let _ = self.db.index_book(&Self::book_to_search_entry(&book));
+                            if content_index {
+                                self.index_content(&book.id, &book.path, book.format);
+                            }
+                        }
                     }
 
                     // Progress logging every 100 files
@@ -341,6 +488,10 @@ impl AppState {
                         );
                     }
                 });
+
+            content_only
+                .par_iter()
+                .for_each(|(file_path, format, id)| self.index_content(id, file_path, *format));
         });
 
         Ok((
@@ -351,19 +502,32 @@ impl AppState {
         ))
     }
 
+    /// Extract the text of a book into the content index.
+    ///
+    /// Books whose text cannot be read are indexed empty rather than retried
+    /// on every scan.
+    fn index_content(&self, id: &str, path: &std::path::Path, format: BookFormat) {
+        let sections = formats::get_handler(format)
+            .extract_content(path)
+            .unwrap_or_else(|e| {
+                tracing::debug!(path = %path.display(), error = %e, "Failed to extract book text");
+                Vec::new()
+            });
+        if let Err(e) = self.db.index_book_content(id, &sections) {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to index book text");
+        }
+    }
+
     /// Extract metadata from a single book file.
     fn extract_book_metadata(
         &self,
         file_path: &std::path::Path,
         id: &str,
+        library_id: &str,
         format: BookFormat,
         metadata: &std::fs::Metadata,
     ) -> Result<Book> {
-        let title = file_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("Unknown")
-            .to_string();
+        let title = file_stem(file_path).unwrap_or("Unknown").to_string();
 
         let mtime = metadata
             .modified()
@@ -378,11 +542,13 @@ impl AppState {
             id: id.to_string(),
             title,
             authors: Vec::new(),
+            contributors: Vec::new(),
             description: None,
             publisher: None,
             published: None,
             language: None,
             isbn: None,
+            identifiers: Vec::new(),
             series: None,
             series_index: None,
             tags: Vec::new(),
@@ -392,6 +558,9 @@ impl AppState {
             page_count: None,
             has_cover: false,
             modified: chrono::DateTime::from_timestamp(mtime, 0).unwrap_or_else(chrono::Utc::now),
+            file_hash: crate::library::book::partial_md5(file_path).ok(),
+            library_id: library_id.to_string(),
+            reading_direction: ReadingDirection::Ltr,
         };
 
         // Extract metadata
@@ -399,9 +568,10 @@ impl AppState {
             tracing::debug!(path = %file_path.display(), error = %e, "Failed to extract metadata");
         }
 
-        // Extract and cache cover
+        // Extract and cache cover; has_cover only holds once one was produced
         let cache_path = self.cover_cache_path(id);
         if !cache_path.exists() {
+            book.has_cover = false;
             if let Ok(Some(cover_data)) = handler.extract_cover(file_path) {
                 if let Some(parent) = cache_path.parent() {
                     let _ = std::fs::create_dir_all(parent);
@@ -437,48 +607,212 @@ impl AppState {
         self.books.read().iter().find(|b| b.id == id).cloned()
     }
 
-    /// Get recent books.
-    pub fn get_recent(&self, limit: usize) -> Vec<Book> {
-        let mut books = self.books.read().clone();
-        books.sort_by(|a, b| b.modified.cmp(&a.modified));
+    /// Get books by ID, preserving the order of `ids`.
+    pub fn get_books(&self, ids: &[String]) -> Vec<Book> {
+        let books = self.books.read();
+        let by_id: HashMap<&str, &Book> = books.iter().map(|b| (b.id.as_str(), b)).collect();
+        ids.iter()
+            .filter_map(|id| by_id.get(id.as_str()).map(|b| (*b).clone()))
+            .collect()
+    }
+
+    /// Get the folder category tree.
+    pub fn categories(&self) -> parking_lot::RwLockReadGuard<'_, CategoryTree> {
+        self.categories.read()
+    }
+
+    /// Find a book in visible libraries by KOReader document digest
+    /// (partial MD5 or filename MD5).
+    pub fn get_book_by_digest(&self, digest: &str, library_ids: &HashSet<String>) -> Option<Book> {
+        let ids = self.digests.read().get(&digest.to_lowercase()).cloned()?;
+        self.get_books(&ids)
+            .into_iter()
+            .find(|b| library_ids.contains(&b.library_id))
+    }
+
+    /// Libraries visible to a user.
+    ///
+    /// Anonymous users see public libraries, users also see libraries they
+    /// own or were granted access to, and admins see every library.
+    pub fn visible_libraries(&self, user: Option<&User>) -> Result<Vec<Library>> {
+        match user {
+            Some(user) if self.auth.is_admin(user) => self.db.list_libraries(),
+            Some(user) => self.db.get_user_libraries(&user.id),
+            None => Ok(self
+                .db
+                .list_libraries()?
+                .into_iter()
+                .filter(|l| l.is_public)
+                .collect()),
+        }
+    }
+
+    /// IDs of the libraries visible to a user.
+    pub fn visible_library_ids(&self, user: Option<&User>) -> Result<HashSet<String>> {
+        Ok(self
+            .visible_libraries(user)?
+            .into_iter()
+            .map(|l| l.id)
+            .collect())
+    }
+
+    /// Get all books in the given libraries.
+    pub fn get_visible_books(&self, library_ids: &HashSet<String>) -> Vec<Book> {
+        self.books
+            .read()
+            .iter()
+            .filter(|b| library_ids.contains(&b.library_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Get book by ID if it belongs to one of the given libraries.
+    pub fn get_visible_book(&self, id: &str, library_ids: &HashSet<String>) -> Option<Book> {
+        self.get_book(id)
+            .filter(|b| library_ids.contains(&b.library_id))
+    }
+
+    /// Get recent books from the given libraries.
+    pub fn get_recent(&self, limit: usize, library_ids: &HashSet<String>) -> Vec<Book> {
+        let mut books = self.get_visible_books(library_ids);
+        books.sort_by_key(|b| std::cmp::Reverse(b.modified));
         books.truncate(limit);
         books
     }
 
-    /// Search books.
-    pub fn search(&self, query: &str) -> Vec<Book> {
-        let query = query.to_lowercase();
+    /// Distinct values of a field in the given libraries, with book counts.
+    pub fn browse(
+        &self,
+        field: BrowseField,
+        library_ids: &HashSet<String>,
+    ) -> Vec<(String, usize)> {
+        field.distinct(&self.get_visible_books(library_ids))
+    }
+
+    /// Books from the given libraries having a value of a field.
+    pub fn get_books_by(
+        &self,
+        field: BrowseField,
+        value: &str,
+        library_ids: &HashSet<String>,
+    ) -> Vec<Book> {
         self.books
             .read()
             .iter()
-            .filter(|b| {
-                b.title.to_lowercase().contains(&query)
-                    || b.authors.iter().any(|a| a.to_lowercase().contains(&query))
-            })
+            .filter(|b| library_ids.contains(&b.library_id) && field.matches(b, value))
             .cloned()
             .collect()
     }
 
+    /// Search books in the given libraries, best matches first.
+    ///
+    /// See [`SearchQuery`] for the query syntax. A query with only filters
+    /// lists the matching books by title.
+    pub fn search(&self, query: &str, library_ids: &HashSet<String>) -> Vec<Book> {
+        let query = SearchQuery::parse(query);
+        let ranks: Option<HashMap<String, usize>> = match query.fts_expression() {
+            Some(expression) => match self.db.search_books(&expression) {
+                Ok(ids) => Some(ids.into_iter().enumerate().map(|(i, id)| (id, i)).collect()),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Search failed");
+                    return Vec::new();
+                }
+            },
+            None => None,
+        };
+
+        let mut books: Vec<Book> = self
+            .books
+            .read()
+            .iter()
+            .filter(|b| library_ids.contains(&b.library_id))
+            .filter(|b| ranks.as_ref().is_none_or(|ranks| ranks.contains_key(&b.id)))
+            .filter(|b| query.matches(b))
+            .cloned()
+            .collect();
+        if let Some(ranks) = ranks {
+            books.sort_by_key(|b| ranks[&b.id]);
+        }
+        books
+    }
+
+    /// Search the text of books in the given libraries.
+    ///
+    /// Returns up to `limit` books, best matches first, each with its best
+    /// passages. Words without a field are looked up in the text; `field:`
+    /// terms and the `lang:`/`format:` filters narrow the books.
+    pub fn search_content(
+        &self,
+        query: &str,
+        library_ids: &HashSet<String>,
+        limit: usize,
+    ) -> Result<Vec<(Book, Vec<ContentMatch>)>> {
+        let query = SearchQuery::parse(query);
+        let Some(expression) = query.text_expression() else {
+            return Ok(Vec::new());
+        };
+        let allowed: Option<HashSet<String>> = match query.field_expression() {
+            Some(fields) => Some(self.db.search_books(&fields)?.into_iter().collect()),
+            None => None,
+        };
+
+        let library_ids: Vec<String> = library_ids.iter().cloned().collect();
+        let matches = self
+            .db
+            .search_content(&expression, &library_ids, MAX_CONTENT_MATCHES)?;
+
+        let books = self.books.read();
+        let mut results: Vec<(Book, Vec<ContentMatch>)> = Vec::new();
+        for content_match in matches {
+            if let Some((_, passages)) = results
+                .iter_mut()
+                .find(|(book, _)| book.id == content_match.book_id)
+            {
+                if passages.len() < PASSAGES_PER_BOOK {
+                    passages.push(content_match);
+                }
+                continue;
+            }
+            if results.len() == limit {
+                continue;
+            }
+            let book = books.iter().find(|b| {
+                b.id == content_match.book_id
+                    && allowed.as_ref().is_none_or(|ids| ids.contains(&b.id))
+                    && query.matches(b)
+            });
+            if let Some(book) = book {
+                results.push((book.clone(), vec![content_match]));
+            }
+        }
+        Ok(results)
+    }
+
     /// Get book count.
     pub fn book_count(&self) -> usize {
         self.books.read().len()
     }
 
-    /// Get all books with their relative paths for sync API.
-    pub fn get_books_with_paths(&self) -> Vec<(Book, String)> {
+    /// Get books with their relative paths for sync API.
+    pub fn get_books_with_paths(&self, library_ids: &HashSet<String>) -> Vec<(Book, String)> {
+        let books = self.get_visible_books(library_ids);
+        self.with_relative_paths(books)
+    }
+
+    /// Pair books with their path relative to their library, keeping their order.
+    pub fn with_relative_paths(&self, books: Vec<Book>) -> Vec<(Book, String)> {
         let libraries = match self.db.list_libraries() {
             Ok(libs) => libs,
             Err(_) => return Vec::new(),
         };
 
-        let books = self.books.read();
         let mut result = Vec::new();
-
-        for book in books.iter() {
+        for book in books {
             for lib in &libraries {
                 let lib_path = PathBuf::from(&lib.path);
                 if let Some(rel) = book.relative_path(&lib_path) {
-                    result.push((book.clone(), rel.to_string_lossy().to_string()));
+                    let rel = rel.to_string_lossy().to_string();
+                    result.push((book, rel));
                     break;
                 }
             }
@@ -523,4 +857,143 @@ impl AppState {
     pub fn has_cached_cover(&self, book_id: &str) -> bool {
         self.cover_cache_path(book_id).exists()
     }
+
+    /// Get path to a cached conversion.
+    fn converted_cache_path(&self, book_id: &str, options: &ComicOptions, ext: &str) -> PathBuf {
+        let name = match options.cache_key() {
+            Some(key) => format!("{}-{}.{}", book_id, key, ext),
+            None => format!("{}.{}", book_id, ext),
+        };
+        self.config.cache.converted_dir.join(name)
+    }
+
+    /// Convert a book to EPUB, reusing the cached file unless the book changed since.
+    ///
+    /// Comics become fixed-layout books, with pages re-encoded according to `options`.
+    pub fn get_converted_epub(&self, book: &Book, options: &ComicOptions) -> Result<PathBuf> {
+        let cache_path = self.converted_cache_path(&book.id, options, "epub");
+        self.cached_conversion(book, cache_path, |file| {
+            if convert::is_fixed_layout(book.format) {
+                convert::comic_to_epub(book, options, file)
+            } else {
+                let cover = self.get_cover(book);
+                convert::to_epub(book, cover.as_deref(), file)
+            }
+        })
+    }
+
+    /// Re-encode a comic archive into a CBZ for a device, reusing the cached
+    /// file unless the book changed since.
+    pub fn get_optimized_comic(&self, book: &Book, options: &ComicOptions) -> Result<PathBuf> {
+        let cache_path = self.converted_cache_path(&book.id, options, "cbz");
+        self.cached_conversion(book, cache_path, |file| {
+            convert::optimize_comic(book, options, file)
+        })
+    }
+
+    /// Run a conversion unless its cached output is newer than the book.
+    ///
+    /// Cache hits are touched, so eviction removes the least recently used
+    /// conversions first.
+    fn cached_conversion(
+        &self,
+        book: &Book,
+        cache_path: PathBuf,
+        convert: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+    ) -> Result<PathBuf> {
+        let fresh = std::fs::metadata(&cache_path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| chrono::DateTime::<chrono::Utc>::from(modified) >= book.modified);
+        if fresh {
+            if let Err(e) = File::options()
+                .write(true)
+                .open(&cache_path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+            {
+                tracing::debug!(error = %e, path = ?cache_path, "Failed to touch cached conversion");
+            }
+            return Ok(cache_path);
+        }
+
+        if let Some(parent) = cache_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Concurrent requests must never serve a partially written file
+        let tmp_path = cache_path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        if let Err(e) = convert(&mut file).and_then(|()| Ok(file.flush()?)) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        std::fs::rename(&tmp_path, &cache_path)?;
+
+        let max_bytes = self.config.cache.converted_max_mb * 1024 * 1024;
+        if max_bytes > 0
+            && let Err(e) =
+                evict_conversions(&self.config.cache.converted_dir, max_bytes, &cache_path)
+        {
+            tracing::warn!(error = %e, "Failed to evict cached conversions");
+        }
+
+        Ok(cache_path)
+    }
+}
+
+/// Remove the least recently used files of the conversion cache until it
+/// fits in `max_bytes`, keeping `keep` (the file being served).
+fn evict_conversions(dir: &Path, max_bytes: u64, keep: &Path) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let path = entry.path();
+        // Conversions in progress are renamed into place once written
+        if !metadata.is_file() || path.extension().is_some_and(|ext| ext == "tmp") {
+            continue;
+        }
+        total += metadata.len();
+        if path != keep {
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+
+    files.sort();
+    for (_, size, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        std::fs::remove_file(&path)?;
+        total -= size;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_evict_conversions() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("old.epub", 30), ("used.epub", 10), ("idle.epub", 20)] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, [0; 100]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+        std::fs::write(dir.path().join("idle.epub.1234.tmp"), [0; 100]).unwrap();
+
+        evict_conversions(dir.path(), 250, &dir.path().join("old.epub")).unwrap();
+        assert!(dir.path().join("old.epub").exists());
+        assert!(!dir.path().join("idle.epub").exists());
+        assert!(dir.path().join("used.epub").exists());
+        assert!(dir.path().join("idle.epub.1234.tmp").exists());
+    }
 }
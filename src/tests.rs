@@ -18,6 +18,7 @@ fn create_user(db: &Database, id: &str, username: &str) {
         role: "user".to_string(),
         created_at: now_timestamp(),
         last_login: None,
+        device_profile: None,
     };
     db.create_user(&user).unwrap();
 }
@@ -30,6 +31,7 @@ fn create_library(db: &Database) {
         is_public: true,
         owner_id: None,
         created_at: now_timestamp(),
+        content_index: false,
     };
     db.create_library(&lib).unwrap();
 }
@@ -42,11 +44,13 @@ fn create_book(db: &Database, id: &str, title: &str) {
         title: title.to_string(),
         author: None,
         authors_json: None,
+        contributors_json: None,
         description: None,
         publisher: None,
         published: None,
         language: None,
         isbn: None,
+        identifiers_json: None,
         series: None,
         series_index: None,
         tags_json: None,
@@ -56,6 +60,7 @@ fn create_book(db: &Database, id: &str, title: &str) {
         mtime: now_timestamp(),
         page_count: None,
         cover_cached: false,
+        reading_direction: None,
         created_at: now_timestamp(),
         updated_at: now_timestamp(),
     };
@@ -79,6 +84,7 @@ fn db_create_and_get_user() {
         role: "user".to_string(),
         created_at: now_timestamp(),
         last_login: None,
+        device_profile: None,
     };
 
     db.create_user(&user).unwrap();
@@ -102,6 +108,7 @@ fn db_duplicate_username_fails() {
         role: "user".to_string(),
         created_at: now_timestamp(),
         last_login: None,
+        device_profile: None,
     };
     let user2 = User {
         id: "user-2".to_string(),
@@ -111,6 +118,7 @@ fn db_duplicate_username_fails() {
         role: "user".to_string(),
         created_at: now_timestamp(),
         last_login: None,
+        device_profile: None,
     };
 
     db.create_user(&user1).unwrap();
@@ -128,6 +136,7 @@ fn db_delete_user() {
         role: "user".to_string(),
         created_at: now_timestamp(),
         last_login: None,
+        device_profile: None,
     };
 
     db.create_user(&user).unwrap();
@@ -135,6 +144,21 @@ fn db_delete_user() {
     assert!(db.get_user_by_username("bob").unwrap().is_none());
 }
 
+#[test]
+fn db_user_device_profile() {
+    let db = test_db();
+    create_user(&db, "user-1", "testuser");
+
+    db.set_user_device_profile("user-1", Some("kindle"))
+        .unwrap();
+    let user = db.get_user_by_id("user-1").unwrap().unwrap();
+    assert_eq!(user.device_profile.as_deref(), Some("kindle"));
+
+    db.set_user_device_profile("user-1", None).unwrap();
+    let user = db.get_user_by_username("testuser").unwrap().unwrap();
+    assert_eq!(user.device_profile, None);
+}
+
 #[test]
 fn db_create_and_get_session() {
     let db = test_db();
@@ -181,6 +205,7 @@ fn db_create_and_list_libraries() {
         is_public: true,
         owner_id: None,
         created_at: now_timestamp(),
+        content_index: false,
     };
 
     db.create_library(&lib).unwrap();
@@ -202,6 +227,7 @@ fn db_get_library_by_name() {
         is_public: false,
         owner_id: Some("user-1".to_string()),
         created_at: now_timestamp(),
+        content_index: false,
     };
 
     db.create_library(&lib).unwrap();
@@ -223,11 +249,13 @@ fn db_save_and_get_book() {
         title: "Test Book".to_string(),
         author: Some("Author".to_string()),
         authors_json: Some(r#"["Author"]"#.to_string()),
+        contributors_json: None,
         description: None,
         publisher: None,
         published: None,
         language: None,
         isbn: None,
+        identifiers_json: None,
         series: None,
         series_index: None,
         tags_json: None,
@@ -237,6 +265,7 @@ fn db_save_and_get_book() {
         mtime: now_timestamp(),
         page_count: Some(100),
         cover_cached: true,
+        reading_direction: Some("rtl".to_string()),
         created_at: now_timestamp(),
         updated_at: now_timestamp(),
     };
@@ -246,6 +275,40 @@ fn db_save_and_get_book() {
     let found = db.get_book("book-1").unwrap().unwrap();
     assert_eq!(found.title, "Test Book");
     assert_eq!(found.format, "epub");
+    assert_eq!(found.reading_direction.as_deref(), Some("rtl"));
+}
+
+#[test]
+fn db_migrates_books_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("old.db");
+
+    // Books table as created before reading_direction existed
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE books (
+            id TEXT PRIMARY KEY, library_id TEXT NOT NULL, file_hash TEXT,
+            title TEXT NOT NULL, author TEXT, authors_json TEXT, description TEXT,
+            publisher TEXT, published TEXT, language TEXT, isbn TEXT, series TEXT,
+            series_index REAL, tags_json TEXT, path TEXT NOT NULL, format TEXT NOT NULL,
+            file_size INTEGER NOT NULL, mtime INTEGER NOT NULL DEFAULT 0, page_count INTEGER,
+            cover_cached INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        INSERT INTO books (id, library_id, title, path, format, file_size, created_at, updated_at)
+        VALUES ('old', 'lib-1', 'Old Book', '/old.cbz', 'cbz', 1, 0, 0);",
+    )
+    .unwrap();
+    drop(conn);
+
+    let db = Database::open(&path).unwrap();
+    let old = db.get_book("old").unwrap().unwrap();
+    assert_eq!(old.title, "Old Book");
+    assert_eq!(old.reading_direction, None);
+
+    // Opening again must not try to add the column twice
+    drop(db);
+    Database::open(&path).unwrap();
 }
 
 #[test]
@@ -271,6 +334,131 @@ fn db_delete_book() {
     assert!(db.get_book("book-del").unwrap().is_none());
 }
 
+#[test]
+fn db_search_index() {
+    use crate::db::SearchEntry;
+
+    let db = test_db();
+    create_library(&db);
+    create_book(&db, "book-1", "Les Misérables");
+    create_book(&db, "book-2", "Notre-Dame de Paris");
+    for (id, title, description) in [
+        ("book-1", "Les Misérables", "Paris, 1832"),
+        ("book-2", "Notre-Dame de Paris", "Quasimodo"),
+    ] {
+        db.index_book(&SearchEntry {
+            book_id: id.to_string(),
+            title: title.to_string(),
+            authors: "Victor Hugo".to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+    }
+    // Reindexing replaces the previous entry
+    db.index_book(&SearchEntry {
+        book_id: "book-1".to_string(),
+        title: "Les Misérables".to_string(),
+        authors: "Victor Hugo".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(db.search_index_len().unwrap(), 2);
+
+    // Diacritics are folded and matches in the title rank first
+    assert_eq!(db.search_books("\"miserables\"").unwrap(), vec!["book-1"]);
+    assert_eq!(db.search_books("\"paris\"").unwrap(), vec!["book-2"]);
+    assert_eq!(db.search_books("authors : \"hug\"*").unwrap().len(), 2);
+
+    assert!(db.delete_book("book-2").unwrap());
+    assert_eq!(db.search_index_len().unwrap(), 1);
+    assert!(db.search_books("\"quasimodo\"").unwrap().is_empty());
+}
+
+#[test]
+fn db_content_index() {
+    use crate::db::{MATCH_END, MATCH_START};
+    use crate::formats::{TextLocation, TextSection};
+
+    let db = test_db();
+    create_library(&db);
+    create_book(&db, "book-1", "Moby Dick");
+    create_book(&db, "book-2", "Other");
+    let section = |location, text: &str| TextSection {
+        location,
+        title: Some("Loomings".to_string()),
+        text: text.to_string(),
+    };
+    db.index_book_content(
+        "book-1",
+        &[
+            section(
+                TextLocation::Chapter(1),
+                "Call me Ishmael. Some years ago...",
+            ),
+            section(TextLocation::Chapter(2), "The whale, the whale!"),
+        ],
+    )
+    .unwrap();
+    db.index_book_content("book-2", &[]).unwrap();
+    assert_eq!(db.content_indexed_books("lib-1").unwrap().len(), 2);
+
+    let libraries = vec!["lib-1".to_string()];
+    let matches = db.search_content("\"ishmael\"", &libraries, 10).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].book_id, "book-1");
+    assert_eq!(matches[0].location, TextLocation::Chapter(1));
+    assert_eq!(matches[0].title.as_deref(), Some("Loomings"));
+    assert!(
+        matches[0]
+            .snippet
+            .contains(&format!("{}Ishmael{}", MATCH_START, MATCH_END))
+    );
+    assert!(
+        db.search_content("\"ishmael\"", &["lib-2".to_string()], 10)
+            .unwrap()
+            .is_empty()
+    );
+
+    // Reindexing replaces the text
+    db.index_book_content("book-1", &[section(TextLocation::Page(3), "Queequeg")])
+        .unwrap();
+    assert!(
+        db.search_content("\"whale\"", &libraries, 10)
+            .unwrap()
+            .is_empty()
+    );
+
+    assert!(db.delete_book("book-1").unwrap());
+    assert!(
+        db.search_content("\"queequeg\"", &libraries, 10)
+            .unwrap()
+            .is_empty()
+    );
+
+    assert_eq!(db.clear_library_content("lib-1").unwrap(), 1);
+    assert!(db.content_indexed_books("lib-1").unwrap().is_empty());
+}
+
+#[test]
+fn db_reading_statuses() {
+    let db = test_db();
+    setup_user_and_book(&db);
+    create_book(&db, "book-2", "Second Book");
+
+    let mut progress = device_progress("kobo", 40.0, 100);
+    db.save_progress(&progress).unwrap();
+    progress.device_id = Some("phone".to_string());
+    progress.status = "finished".to_string();
+    progress.updated_at = 200;
+    db.save_progress(&progress).unwrap();
+
+    let statuses = db.reading_statuses("user-1").unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses.get("book-1").map(String::as_str), Some("finished"));
+    assert!(db.reading_statuses("nobody").unwrap().is_empty());
+}
+
 #[test]
 fn db_save_and_get_progress() {
     let db = test_db();
@@ -602,6 +790,34 @@ fn book_format_from_extension() {
     assert_eq!(BookFormat::from_extension("unknown"), None);
 }
 
+#[test]
+fn book_format_from_path() {
+    use crate::library::Book;
+    use std::path::{Path, PathBuf};
+
+    assert_eq!(
+        BookFormat::from_path(Path::new("/books/novel.fb2.zip")),
+        Some(BookFormat::Fb2)
+    );
+    assert_eq!(
+        BookFormat::from_path(Path::new("/books/NOVEL.FB2")),
+        Some(BookFormat::Fb2)
+    );
+    assert_eq!(
+        BookFormat::from_path(Path::new("/books/comic.cbz")),
+        Some(BookFormat::Cbz)
+    );
+    // A plain zip is not a book
+    assert_eq!(BookFormat::from_path(Path::new("/books/archive.zip")), None);
+
+    let mut book = Book::new(PathBuf::from("/books/novel.fb2.zip"), BookFormat::Fb2);
+    assert_eq!(book.title, "novel");
+    assert_eq!(book.stem(), "novel");
+    assert_eq!(book.mime_type(), "application/x-zip-compressed-fb2");
+    book.path = PathBuf::from("/books/novel.fb2");
+    assert_eq!(book.mime_type(), "application/x-fictionbook+xml");
+}
+
 #[test]
 fn book_format_mime_type() {
     assert_eq!(BookFormat::Epub.mime_type(), "application/epub+zip");
@@ -709,3 +925,1035 @@ fn auth_is_admin() {
     assert!(auth.is_admin(&admin));
     assert!(!auth.is_admin(&user));
 }
+
+#[test]
+fn partial_md5_small_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("small.epub");
+    std::fs::write(&path, b"hello world").unwrap();
+
+    let digest = crate::library::book::partial_md5(&path).unwrap();
+    assert_eq!(digest, format!("{:x}", md5::compute(b"hello world")));
+}
+
+#[test]
+fn partial_md5_samples_offsets() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.pdf");
+    let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, &data).unwrap();
+
+    // Samples at 0, 1024 and 4096 (partial); 16384 is past the end
+    let mut expected = md5::Context::new();
+    expected.consume(&data[0..1024]);
+    expected.consume(&data[1024..2048]);
+    expected.consume(&data[4096..5000]);
+
+    let digest = crate::library::book::partial_md5(&path).unwrap();
+    assert_eq!(digest, format!("{:x}", expected.finalize()));
+}
+
+#[test]
+fn db_update_book_hash() {
+    let db = test_db();
+    create_library(&db);
+    create_book(&db, "book-1", "Hashed");
+
+    assert!(db.update_book_hash("book-1", "abc123").unwrap());
+
+    let found = db.get_book_by_hash("abc123").unwrap().unwrap();
+    assert_eq!(found.id, "book-1");
+}
+
+#[test]
+fn state_book_by_digest() {
+    use crate::server::AppState;
+
+    let db = test_db();
+    setup_user_and_book(&db);
+    create_book(&db, "book-2", "Other Book");
+    db.update_book_hash("book-1", "ABC123").unwrap();
+
+    let auth = AuthService::new(db.clone(), 30, true);
+    let state = AppState::new_with_db(Config::default(), db, auth);
+    state.load_from_db().unwrap();
+    let libraries = state.visible_library_ids(None).unwrap();
+
+    let by_hash = state.get_book_by_digest("abc123", &libraries).unwrap();
+    assert_eq!(by_hash.id, "book-1");
+    let filename = format!("{:x}", md5::compute("book-2.pdf"));
+    let by_filename = state.get_book_by_digest(&filename, &libraries).unwrap();
+    assert_eq!(by_filename.id, "book-2");
+
+    // Books of other libraries are never matched
+    let hidden = std::collections::HashSet::new();
+    assert!(state.get_book_by_digest("abc123", &hidden).is_none());
+    assert!(state.get_book_by_digest("unknown", &libraries).is_none());
+}
+
+#[test]
+fn state_book_by_digest_across_libraries() {
+    use crate::server::AppState;
+
+    let db = test_db();
+    create_library(&db);
+    db.create_library(&Library {
+        id: "lib-private".to_string(),
+        name: "Private".to_string(),
+        path: "/private".to_string(),
+        is_public: false,
+        owner_id: None,
+        created_at: now_timestamp(),
+        content_index: false,
+    })
+    .unwrap();
+    // The same file in a hidden library and in a visible one
+    create_book(&db, "template", "Shared");
+    let template = db.get_book("template").unwrap().unwrap();
+    for (id, library_id, dir) in [
+        ("hidden", "lib-private", "/private"),
+        ("visible", "lib-1", "/test"),
+    ] {
+        db.save_book(&StoredBook {
+            id: id.to_string(),
+            library_id: library_id.to_string(),
+            path: format!("{}/shared.pdf", dir),
+            ..template.clone()
+        })
+        .unwrap();
+    }
+
+    let auth = AuthService::new(db.clone(), 30, true);
+    let state = AppState::new_with_db(Config::default(), db, auth);
+    state.load_from_db().unwrap();
+    let libraries = state.visible_library_ids(None).unwrap();
+    assert!(!libraries.contains("lib-private"));
+
+    let digest = format!("{:x}", md5::compute("shared.pdf"));
+    let book = state.get_book_by_digest(&digest, &libraries).unwrap();
+    assert_eq!(book.id, "visible");
+}
+
+#[test]
+fn auth_kosync_key_from_password() {
+    let db = test_db();
+    let auth = AuthService::new(db, 30, true);
+
+    auth.create_user("reader", "secret", "user").unwrap();
+
+    let key = crate::auth::kosync_key("secret");
+    assert_eq!(key, "5ebe2294ecd0e0f08eab7690d2a6ee69");
+    assert!(auth.authenticate_kosync("reader", &key).unwrap().is_some());
+    assert!(
+        auth.authenticate_kosync("reader", "secret")
+            .unwrap()
+            .is_none()
+    );
+    assert!(auth.authenticate_kosync("nobody", &key).unwrap().is_none());
+}
+
+#[test]
+fn auth_kosync_register() {
+    let db = test_db();
+    let auth = AuthService::new(db, 30, true);
+
+    let key = crate::auth::kosync_key("secret");
+    auth.register_kosync("koreader", &key).unwrap();
+
+    assert!(
+        auth.authenticate_kosync("koreader", &key)
+            .unwrap()
+            .is_some()
+    );
+    // The MD5 key doubles as the account password
+    assert!(auth.login("koreader", &key, None).is_ok());
+}
+
+#[test]
+fn auth_kosync_follows_password_change() {
+    let db = test_db();
+    let auth = AuthService::new(db, 30, true);
+
+    auth.create_user("reader", "oldpass", "user").unwrap();
+    auth.change_password("reader", "newpass").unwrap();
+
+    let old_key = crate::auth::kosync_key("oldpass");
+    let new_key = crate::auth::kosync_key("newpass");
+    assert!(
+        auth.authenticate_kosync("reader", &old_key)
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        auth.authenticate_kosync("reader", &new_key)
+            .unwrap()
+            .is_some()
+    );
+}
+
+fn device_progress(device: &str, percentage: f64, updated_at: i64) -> ReadingProgress {
+    ReadingProgress {
+        id: 0,
+        user_id: "user-1".to_string(),
+        book_id: "book-1".to_string(),
+        device_id: Some(device.to_string()),
+        current_page: None,
+        total_pages: None,
+        percentage: Some(percentage),
+        current_chapter: None,
+        position_data: None,
+        status: "reading".to_string(),
+        started_at: Some(updated_at),
+        finished_at: None,
+        updated_at,
+    }
+}
+
+#[test]
+fn db_merged_progress_strategies() {
+    use crate::config::MergeStrategy;
+
+    let db = test_db();
+    setup_user_and_book(&db);
+
+    let ts = now_timestamp();
+    db.save_progress(&device_progress("kobo", 60.0, ts))
+        .unwrap();
+    db.save_progress(&device_progress("phone", 20.0, ts + 10))
+        .unwrap();
+
+    let latest = db
+        .get_merged_progress("user-1", "book-1", MergeStrategy::Latest, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest.device_id.as_deref(), Some("phone"));
+
+    // Going back on another device must not move the merged position backwards
+    let furthest = db
+        .get_merged_progress("user-1", "book-1", MergeStrategy::Furthest, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(furthest.device_id.as_deref(), Some("kobo"));
+    assert_eq!(furthest.percentage, Some(60.0));
+
+    let own = db
+        .get_merged_progress("user-1", "book-1", MergeStrategy::PerDevice, Some("kobo"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(own.percentage, Some(60.0));
+
+    let rows = db.get_device_progress("user-1", "book-1").unwrap();
+    assert_eq!(rows.len(), 2);
+}
+
+#[test]
+fn db_furthest_progress_same_device() {
+    use crate::config::MergeStrategy;
+
+    let db = test_db();
+    setup_user_and_book(&db);
+
+    let ts = now_timestamp();
+    assert!(
+        db.save_merged_progress(&device_progress("kobo", 60.0, ts), MergeStrategy::Furthest)
+            .unwrap()
+    );
+    // The only device going back must not move the merged position backwards
+    assert!(
+        !db.save_merged_progress(
+            &device_progress("kobo", 20.0, ts + 10),
+            MergeStrategy::Furthest
+        )
+        .unwrap()
+    );
+    let furthest = db
+        .get_merged_progress("user-1", "book-1", MergeStrategy::Furthest, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(furthest.percentage, Some(60.0));
+
+    // Other strategies follow the device
+    assert!(
+        db.save_merged_progress(
+            &device_progress("kobo", 20.0, ts + 20),
+            MergeStrategy::Latest
+        )
+        .unwrap()
+    );
+    let rows = db.get_device_progress("user-1", "book-1").unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].percentage, Some(20.0));
+}
+
+#[test]
+fn progress_position_fallbacks() {
+    let mut progress = device_progress("kobo", 0.0, now_timestamp());
+    progress.percentage = None;
+    progress.current_page = Some(25);
+    progress.total_pages = Some(50);
+    assert_eq!(progress.position(), 50.0);
+
+    progress.total_pages = None;
+    assert_eq!(progress.position(), 25.0);
+}
+
+#[test]
+fn config_parse_merge_strategy() {
+    use crate::config::MergeStrategy;
+
+    let config: Config = toml::from_str("[sync]\nmerge_strategy = \"per_device\"\n").unwrap();
+    assert_eq!(config.sync.merge_strategy, MergeStrategy::PerDevice);
+    assert_eq!(
+        Config::default().sync.merge_strategy,
+        MergeStrategy::Furthest
+    );
+    assert!(toml::from_str::<Config>("[sync]\nmerge_strategy = \"oldest\"\n").is_err());
+}
+
+#[test]
+fn config_parse_device_profiles() {
+    use crate::config::PageFormat;
+    use crate::formats::convert::ComicOptions;
+
+    let config: Config = toml::from_str(
+        r#"
+[[devices]]
+name = "Kindle"
+width = 1072
+height = 1448
+grayscale = true
+max_file_size_mb = 50
+
+[[devices]]
+name = "tablet"
+width = 1600
+height = 2560
+format = "png"
+"#,
+    )
+    .unwrap();
+
+    let kindle = config.device_profile("kindle").unwrap();
+    assert!(kindle.grayscale);
+    assert_eq!(kindle.format, PageFormat::Jpeg);
+    assert_eq!(kindle.quality, 85);
+    assert!(config.device_profile("phone").is_none());
+
+    let options = ComicOptions::from(kindle);
+    assert_eq!(options.max_size, Some((1072, 1448)));
+    assert_eq!(options.max_file_size, Some(50 * 1024 * 1024));
+    assert!(options.cache_key().is_some());
+    assert_ne!(
+        options.cache_key(),
+        ComicOptions::from(config.device_profile("tablet").unwrap()).cache_key()
+    );
+    assert_eq!(ComicOptions::default().cache_key(), None);
+
+    assert!(
+        toml::from_str::<Config>(
+            "[[devices]]\nname = \"x\"\nwidth = 1\nheight = 1\nformat = \"gif\"\n"
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn pagination_offsets() {
+    use crate::opds::Pagination;
+
+    let first = Pagination::new(None, None, 10, 25);
+    assert_eq!(first.offset, 0);
+    assert_eq!(first.previous_offset(), None);
+    assert_eq!(first.next_offset(), Some(10));
+    assert_eq!(first.last_offset(), 20);
+
+    let last = Pagination::new(Some(3), None, 10, 25);
+    assert_eq!(last.offset, 20);
+    assert_eq!(last.next_offset(), None);
+    assert_eq!(last.slice((0..25).collect()), vec![20, 21, 22, 23, 24]);
+
+    let unaligned = Pagination::new(Some(2), Some(5), 10, 25);
+    assert_eq!(unaligned.offset, 5);
+    assert_eq!(unaligned.previous_offset(), Some(0));
+    assert_eq!(unaligned.href("/catalog/all", 15), "/catalog/all?offset=15");
+    assert_eq!(
+        unaligned.href("/catalog/search?q=a", 10),
+        "/catalog/search?q=a&page=2"
+    );
+
+    let empty = Pagination::new(None, None, 10, 0);
+    assert_eq!(empty.last_offset(), 0);
+    assert_eq!(empty.next_offset(), None);
+
+    // Out-of-range values from the query string stay within the results
+    for huge in [
+        Pagination::new(Some(usize::MAX), None, 10, 25),
+        Pagination::new(None, Some(usize::MAX), 10, 25),
+    ] {
+        assert_eq!(huge.offset, 25);
+        assert_eq!(huge.next_offset(), None);
+        assert_eq!(huge.previous_offset(), Some(15));
+        assert!(huge.slice((0..25).collect::<Vec<_>>()).is_empty());
+    }
+}
+
+#[test]
+fn feed_pagination_links() {
+    use crate::opds::{FeedBuilder, Pagination};
+
+    let xml = FeedBuilder::new("urn:uuid:all", "All Books")
+        .paginate(Pagination::new(Some(2), None, 10, 35), "/catalog/all")
+        .build();
+
+    assert!(xml.contains(r#"rel="first" href="/catalog/all?page=1""#));
+    assert!(xml.contains(r#"rel="previous" href="/catalog/all?page=1""#));
+    assert!(xml.contains(r#"rel="next" href="/catalog/all?page=3""#));
+    assert!(xml.contains(r#"rel="last" href="/catalog/all?page=4""#));
+    assert!(xml.contains("<opensearch:totalResults>35</opensearch:totalResults>"));
+    assert!(xml.contains("<opensearch:itemsPerPage>10</opensearch:itemsPerPage>"));
+    assert!(xml.contains("<opensearch:startIndex>11</opensearch:startIndex>"));
+}
+
+#[test]
+fn feed_entry_contributors_and_identifiers() {
+    use crate::library::{Book, Contributor, Identifier};
+    use crate::opds::FeedBuilder;
+
+    let book = Book {
+        id: "b1".to_string(),
+        title: "Translated".to_string(),
+        authors: vec!["Author".to_string()],
+        contributors: vec![Contributor {
+            name: "Tina Translator".to_string(),
+            role: "trl".to_string(),
+        }],
+        identifiers: vec![Identifier {
+            scheme: "isbn".to_string(),
+            value: "9780306406157".to_string(),
+        }],
+        ..Default::default()
+    };
+
+    let xml = FeedBuilder::new("urn:uuid:all", "All Books")
+        .book_entry(&book, "")
+        .build();
+
+    assert!(xml.contains("<contributor><name>Tina Translator</name></contributor>"));
+    assert!(xml.contains("<dc:identifier>urn:isbn:9780306406157</dc:identifier>"));
+}
+
+#[test]
+fn feed_entry_epub_conversion_link() {
+    use crate::library::Book;
+    use crate::opds::FeedBuilder;
+
+    let markdown = Book {
+        id: "md".to_string(),
+        title: "Notes".to_string(),
+        format: BookFormat::Md,
+        ..Default::default()
+    };
+    let epub = Book {
+        id: "ep".to_string(),
+        title: "Novel".to_string(),
+        format: BookFormat::Epub,
+        ..Default::default()
+    };
+    let comic = Book {
+        id: "cb".to_string(),
+        title: "Comic".to_string(),
+        format: BookFormat::Cbz,
+        ..Default::default()
+    };
+
+    let xml = FeedBuilder::new("urn:uuid:all", "All Books")
+        .book_entry(&markdown, "")
+        .book_entry(&epub, "")
+        .book_entry(&comic, "")
+        .build();
+
+    assert!(xml.contains("/books/md/convert/epub"));
+    assert!(!xml.contains("/books/ep/convert/epub"));
+    assert!(xml.contains("href=\"/books/cb/convert/epub\" type=\"application/epub+zip\" title=\"EPUB (fixed layout)\""));
+}
+
+#[test]
+fn feed_entry_series_link() {
+    use crate::library::Book;
+    use crate::opds::FeedBuilder;
+
+    let book = Book {
+        id: "b1".to_string(),
+        title: "Guards! Guards!".to_string(),
+        series: Some("Discworld / City Watch".to_string()),
+        ..Default::default()
+    };
+
+    let xml = FeedBuilder::new("urn:uuid:all", "All Books")
+        .book_entry(&book, "")
+        .build();
+
+    assert!(xml.contains(
+        r#"<link rel="related" href="/catalog/series/Discworld%20%2F%20City%20Watch" type="application/atom+xml;profile=opds-catalog;kind=acquisition" title="Series: Discworld / City Watch"/>"#
+    ));
+    assert!(!xml.contains("/catalog/search"));
+}
+
+#[test]
+fn feed_facet_links() {
+    use crate::opds::{Facet, FeedBuilder};
+
+    let xml = FeedBuilder::new("urn:uuid:all", "All Books")
+        .facets(vec![
+            Facet {
+                group: "Format".to_string(),
+                title: "EPUB".to_string(),
+                href: "/catalog/all?format=epub".to_string(),
+                count: Some(3),
+                active: true,
+            },
+            Facet {
+                group: "Sort by".to_string(),
+                title: "Author".to_string(),
+                href: "/catalog/all?sort=author".to_string(),
+                count: None,
+                active: false,
+            },
+        ])
+        .build();
+
+    assert!(xml.contains(
+        r#"<link rel="http://opds-spec.org/facet" href="/catalog/all?format=epub" type="application/atom+xml;profile=opds-catalog;kind=acquisition" title="EPUB" opds:facetGroup="Format" opds:activeFacet="true" thr:count="3"/>"#
+    ));
+    assert!(xml.contains(r#"title="Author" opds:facetGroup="Sort by"/>"#));
+}
+
+#[test]
+fn opds2_publication() {
+    use crate::library::Book;
+    use crate::opds::v2::{PUBLICATION_MIME, Publication};
+
+    let book = Book {
+        id: "dune".to_string(),
+        title: "Dune".to_string(),
+        authors: vec!["Frank Herbert".to_string()],
+        series: Some("Dune".to_string()),
+        series_index: Some(1.0),
+        format: BookFormat::Cbz,
+        ..Default::default()
+    };
+
+    let json: serde_json::Value =
+        serde_json::from_str(&Publication::new(&book, "").build()).unwrap();
+    assert_eq!(json["metadata"]["@type"], "http://schema.org/Book");
+    assert_eq!(json["metadata"]["author"][0]["name"], "Frank Herbert");
+    assert_eq!(json["metadata"]["belongsTo"]["series"][0]["position"], 1.0);
+    assert_eq!(
+        json["metadata"]["belongsTo"]["series"][0]["links"][0]["href"],
+        "/opds/v2/series/Dune"
+    );
+    assert!(json["metadata"].get("publisher").is_none());
+
+    let links = json["links"].as_array().unwrap();
+    assert_eq!(links[0]["href"], "/opds/v2/publications/dune");
+    assert_eq!(links[0]["type"], PUBLICATION_MIME);
+    // Download and the fixed-layout conversion
+    assert_eq!(links.len(), 3);
+    assert_eq!(links[2]["href"], "/books/dune/convert/epub");
+    assert_eq!(json["images"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn opds2_feed_pagination_and_facets() {
+    use crate::opds::v2::{Feed, FeedBuilder};
+    use crate::opds::{Facet, Pagination};
+
+    let facet = |title: &str, active| Facet {
+        group: "Format".to_string(),
+        title: title.to_string(),
+        href: format!("/opds/v2/all?format={}", title),
+        count: Some(5),
+        active,
+    };
+    let json = FeedBuilder::new("All Books")
+        .self_link("/opds/v2/all?page=2")
+        .search_link("/opds/v2/search{?query}")
+        .paginate(Pagination::new(Some(2), None, 10, 25), "/opds/v2/all")
+        .facets(vec![facet("epub", true), facet("pdf", false)])
+        .build();
+
+    let feed: Feed = serde_json::from_str(&json).unwrap();
+    assert_eq!(feed.metadata.number_of_items, Some(25));
+    assert_eq!(feed.metadata.items_per_page, Some(10));
+    assert_eq!(feed.metadata.current_page, Some(2));
+    let links: Vec<_> = feed
+        .links
+        .iter()
+        .map(|l| (l.rel.as_deref().unwrap(), l.href.as_str()))
+        .collect();
+    assert_eq!(
+        links,
+        vec![
+            ("self", "/opds/v2/all?page=2"),
+            ("search", "/opds/v2/search{?query}"),
+            ("first", "/opds/v2/all?page=1"),
+            ("previous", "/opds/v2/all?page=1"),
+            ("next", "/opds/v2/all?page=3"),
+            ("last", "/opds/v2/all?page=3"),
+        ]
+    );
+    assert!(feed.links[1].templated);
+
+    assert_eq!(feed.facets.len(), 1);
+    let facets = &feed.facets[0].links;
+    assert_eq!(facets[0].rel.as_deref(), Some("self"));
+    assert_eq!(facets[1].rel, None);
+    assert_eq!(facets[1].properties.number_of_items, Some(5));
+    assert!(!json.contains("publications"));
+}
+
+#[test]
+fn category_tree_by_folder() {
+    use crate::library::{Book, CategoryTree};
+    use std::path::PathBuf;
+
+    let library = |id: &str, name: &str, path: &str| Library {
+        id: id.to_string(),
+        name: name.to_string(),
+        path: path.to_string(),
+        is_public: true,
+        owner_id: None,
+        created_at: 0,
+        content_index: false,
+    };
+    let book = |library_id: &str, path: &str| {
+        let mut book = Book::new(PathBuf::from(path), BookFormat::Cbz);
+        book.library_id = library_id.to_string();
+        book
+    };
+
+    let libraries = vec![
+        library("lib-1", "Comics", "/comics"),
+        library("lib-2", "Manga", "/manga"),
+    ];
+    let books = vec![
+        book("lib-1", "/comics/loose.cbz"),
+        book("lib-1", "/comics/Series/Vol 1/ch1.cbz"),
+        book("lib-1", "/comics/Series/Vol 1/ch2.cbz"),
+        book("lib-1", "/comics/Series/Vol 2/ch3.cbz"),
+        book("lib-2", "/manga/Series/a.cbz"),
+    ];
+
+    let tree = CategoryTree::build(&libraries, &books);
+    let roots = tree.roots();
+    assert_eq!(roots.len(), 2);
+    assert_eq!(roots[0].category.name, "Comics");
+    assert_eq!(roots[0].category.book_count, 4);
+    assert_eq!(roots[0].category.subcategory_count, 1);
+    assert_eq!(roots[0].books.len(), 1);
+
+    let series = tree.children(&roots[0].category.id);
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0].category.book_count, 3);
+    assert_eq!(
+        series[0].parent.as_deref(),
+        Some(roots[0].category.id.as_str())
+    );
+
+    let volumes = tree.children(&series[0].category.id);
+    let names: Vec<_> = volumes.iter().map(|n| n.category.name.as_str()).collect();
+    assert_eq!(names, vec!["Vol 1", "Vol 2"]);
+    assert_eq!(volumes[0].books.len(), 2);
+
+    // Same relative folder in another library gets a distinct ID
+    let manga_series = tree.children(&roots[1].category.id);
+    assert_ne!(manga_series[0].category.id, series[0].category.id);
+    assert_eq!(manga_series[0].library_id, "lib-2");
+}
+
+#[test]
+fn state_library_visibility() {
+    use crate::server::AppState;
+
+    let db = test_db();
+    setup_user_and_book(&db);
+    create_user(&db, "user-2", "granted");
+    db.create_library(&Library {
+        id: "lib-2".to_string(),
+        name: "Private".to_string(),
+        path: "/private".to_string(),
+        is_public: false,
+        owner_id: None,
+        created_at: now_timestamp(),
+        content_index: false,
+    })
+    .unwrap();
+    let mut private = db.get_book("book-1").unwrap().unwrap();
+    private.id = "book-2".to_string();
+    private.library_id = "lib-2".to_string();
+    private.path = "/private/book-2.pdf".to_string();
+    db.save_book(&private).unwrap();
+    db.grant_library_access("user-2", "lib-2", false).unwrap();
+
+    let auth = AuthService::new(db.clone(), 30, true);
+    let admin = auth.create_user("admin", "password123", "admin").unwrap();
+    let state = AppState::new_with_db(Config::default(), db.clone(), auth);
+    state.load_from_db().unwrap();
+
+    let user = db.get_user_by_id("user-1").unwrap().unwrap();
+    let granted = db.get_user_by_id("user-2").unwrap().unwrap();
+
+    let anonymous = state.visible_library_ids(None).unwrap();
+    assert!(anonymous.contains("lib-1") && !anonymous.contains("lib-2"));
+    assert!(state.get_visible_book("book-2", &anonymous).is_none());
+    assert_eq!(state.search("test", &anonymous).len(), 1);
+
+    let ids = state.visible_library_ids(Some(&user)).unwrap();
+    assert!(!ids.contains("lib-2"));
+
+    let ids = state.visible_library_ids(Some(&granted)).unwrap();
+    assert!(ids.contains("lib-2"));
+    assert!(state.get_visible_book("book-2", &ids).is_some());
+    assert_eq!(state.get_visible_books(&ids).len(), 2);
+
+    let ids = state.visible_library_ids(Some(&admin)).unwrap();
+    assert_eq!(ids.len(), 2);
+
+    assert!(db.revoke_library_access("user-2", "lib-2").unwrap());
+    let ids = state.visible_library_ids(Some(&granted)).unwrap();
+    assert!(!ids.contains("lib-2"));
+}
+
+#[test]
+fn state_search_ranked_with_filters() {
+    use crate::server::AppState;
+
+    let db = test_db();
+    create_library(&db);
+    let books = [
+        (
+            "hobbit",
+            "The Hobbit",
+            "J. R. R. Tolkien",
+            None,
+            "en",
+            "epub",
+        ),
+        (
+            "guards",
+            "Guards! Guards!",
+            "Terry Pratchett",
+            Some("Discworld"),
+            "en-GB",
+            "epub",
+        ),
+        (
+            "mort",
+            "Mort",
+            "Terry Pratchett",
+            Some("Discworld"),
+            "fr",
+            "pdf",
+        ),
+        ("essay", "On Tolkien", "Élise Durand", None, "fr", "epub"),
+    ];
+    for (id, title, author, series, language, format) in books {
+        create_book(&db, id, title);
+        let mut book = db.get_book(id).unwrap().unwrap();
+        // The format of a saved book never changes
+        db.delete_book(id).unwrap();
+        book.authors_json = Some(serde_json::to_string(&[author]).unwrap());
+        book.series = series.map(str::to_string);
+        book.language = Some(language.to_string());
+        book.format = format.to_string();
+        book.path = format!("/test/{}.{}", id, format);
+        db.save_book(&book).unwrap();
+    }
+
+    let auth = AuthService::new(db.clone(), 30, true);
+    let state = AppState::new_with_db(Config::default(), db.clone(), auth);
+    // The index is filled from the books already in the database
+    state.load_from_db().unwrap();
+    let libraries = state.visible_library_ids(None).unwrap();
+    let ids = |query: &str| -> Vec<String> {
+        state
+            .search(query, &libraries)
+            .into_iter()
+            .map(|b| b.id)
+            .collect()
+    };
+
+    assert_eq!(ids("tolkien"), vec!["essay", "hobbit"]);
+    assert_eq!(ids("author:tolkien"), vec!["hobbit"]);
+    assert_eq!(ids("author:elise"), vec!["essay"]);
+    assert_eq!(ids("hob"), vec!["hobbit"]);
+    assert_eq!(ids(r#"series:"Discworld" lang:fr"#), vec!["mort"]);
+    assert_eq!(ids("pratchett lang:en format:epub"), vec!["guards"]);
+    assert_eq!(ids("format:pdf"), vec!["mort"]);
+    assert!(ids("author:pratchett format:cbz").is_empty());
+    assert_eq!(ids("").len(), 4);
+}
+
+#[test]
+fn state_browse_by_field() {
+    use crate::library::BrowseField;
+    use crate::server::AppState;
+
+    let db = test_db();
+    create_library(&db);
+    for (id, title, author, series, index) in [
+        ("mort", "Mort", "Terry Pratchett", Some("Discworld"), 4.0),
+        (
+            "colour",
+            "The Colour of Magic",
+            "Terry Pratchett",
+            Some("discworld"),
+            1.0,
+        ),
+        ("hobbit", "The Hobbit", "J. R. R. Tolkien", None, 0.0),
+    ] {
+        create_book(&db, id, title);
+        let mut book = db.get_book(id).unwrap().unwrap();
+        book.authors_json = Some(serde_json::to_string(&[author]).unwrap());
+        book.series = series.map(str::to_string);
+        book.series_index = Some(index);
+        db.save_book(&book).unwrap();
+    }
+
+    let auth = AuthService::new(db.clone(), 30, true);
+    let state = AppState::new_with_db(Config::default(), db.clone(), auth);
+    state.load_from_db().unwrap();
+    let libraries = state.visible_library_ids(None).unwrap();
+
+    assert_eq!(
+        state.browse(BrowseField::Author, &libraries),
+        vec![
+            ("J. R. R. Tolkien".to_string(), 1),
+            ("Terry Pratchett".to_string(), 2),
+        ]
+    );
+    assert_eq!(state.browse(BrowseField::Series, &libraries).len(), 1);
+    let mut series: Vec<String> = state
+        .get_books_by(BrowseField::Series, "DISCWORLD", &libraries)
+        .into_iter()
+        .map(|b| b.id)
+        .collect();
+    series.sort();
+    assert_eq!(series, vec!["colour", "mort"]);
+    assert!(
+        state
+            .get_books_by(
+                BrowseField::Series,
+                "Discworld",
+                &std::collections::HashSet::new()
+            )
+            .is_empty()
+    );
+
+    // Browse routes sit next to the fixed catalog routes without conflicts
+    let _ = crate::server::create_router(state);
+}
+
+#[test]
+fn state_content_search_after_scan() {
+    use crate::server::AppState;
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let indexed = dir.path().join("indexed");
+    let plain = dir.path().join("plain");
+    std::fs::create_dir_all(&indexed).unwrap();
+    std::fs::create_dir_all(&plain).unwrap();
+
+    let write_epub = |path: &std::path::Path, author: &str| {
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let files = [
+            (
+                "META-INF/container.xml",
+                r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#.to_string(),
+            ),
+            (
+                "OEBPS/content.opf",
+                format!(
+                    r#"<package xmlns="http://www.idpf.org/2007/opf" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<metadata><dc:title>Sea Stories</dc:title><dc:creator>{}</dc:creator></metadata>
+<manifest><item id="c1" href="text/one.xhtml"/><item id="c2" href="text/two.xhtml"/></manifest>
+<spine><itemref idref="c1"/><itemref idref="c2"/></spine>
+</package>"#,
+                    author
+                ),
+            ),
+            (
+                "OEBPS/text/one.xhtml",
+                "<html><body><h1>Loomings</h1><p>Call me Ishmael.</p></body></html>".to_string(),
+            ),
+            (
+                "OEBPS/text/two.xhtml",
+                "<html><head><title>The Carpet-Bag</title></head><body><p>A whale &amp; a ship.</p></body></html>"
+                    .to_string(),
+            ),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    };
+    write_epub(&indexed.join("moby.epub"), "Herman Melville");
+    write_epub(&indexed.join("copy.epub"), "Someone Else");
+    write_epub(&plain.join("moby.epub"), "Herman Melville");
+
+    let db = test_db();
+    for (id, path, content_index) in [("lib-1", &indexed, true), ("lib-2", &plain, false)] {
+        db.create_library(&Library {
+            id: id.to_string(),
+            name: id.to_string(),
+            path: path.to_string_lossy().to_string(),
+            is_public: true,
+            owner_id: None,
+            created_at: now_timestamp(),
+            content_index,
+        })
+        .unwrap();
+    }
+
+    let auth = AuthService::new(db.clone(), 30, true);
+    let state = AppState::new_with_db(Config::default(), db.clone(), auth);
+    state.scan_all_libraries().unwrap();
+    let libraries = state.visible_library_ids(None).unwrap();
+
+    // Only the library that opted in is searched
+    let results = state.search_content("whale", &libraries, 10).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(book, _)| book.library_id == "lib-1"));
+    let passage = &results[0].1[0];
+    assert_eq!(passage.location, crate::formats::TextLocation::Chapter(2));
+    assert_eq!(passage.title.as_deref(), Some("The Carpet-Bag"));
+
+    let results = state
+        .search_content("\"call me ishmael\" author:melville", &libraries, 10)
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].1[0].title.as_deref(), Some("Loomings"));
+
+    assert_eq!(
+        state.search_content("whale", &libraries, 1).unwrap().len(),
+        1
+    );
+    assert!(
+        state
+            .search_content("author:melville", &libraries, 10)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn auth_basic_credentials() {
+    let db = test_db();
+    let auth = AuthService::new(db, 30, true);
+    auth.create_user("reader", "secret123", "user").unwrap();
+
+    let user = auth.authenticate_basic("reader", "secret123").unwrap();
+    assert_eq!(user.unwrap().username, "reader");
+    // Served from the cache on the second call
+    assert!(
+        auth.authenticate_basic("reader", "secret123")
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        auth.authenticate_basic("reader", "wrong")
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        auth.authenticate_basic("nobody", "secret123")
+            .unwrap()
+            .is_none()
+    );
+
+    auth.change_password("reader", "newsecret").unwrap();
+    assert!(
+        auth.authenticate_basic("reader", "secret123")
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        auth.authenticate_basic("reader", "newsecret")
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[test]
+fn unauthorized_response_challenges_basic() {
+    use crate::error::AppError;
+    use axum::http::{StatusCode, header};
+    use axum::response::IntoResponse;
+
+    let response = AppError::Unauthorized("Authentication required".to_string()).into_response();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let challenge = response.headers()[header::WWW_AUTHENTICATE]
+        .to_str()
+        .unwrap();
+    assert!(challenge.starts_with("Basic realm="));
+
+    let config: Config = toml::from_str("[auth]\nrequire_auth = true\n").unwrap();
+    assert!(config.auth.require_auth);
+    assert!(!Config::default().auth.require_auth);
+}
+
+#[tokio::test]
+async fn extract_admin_guard() {
+    use crate::error::AppError;
+    use crate::server::{AdminUser, AppState, AuthUser};
+    use axum::extract::FromRequestParts;
+    use axum::http::{Request, header};
+
+    let db = test_db();
+    let auth = AuthService::new(db.clone(), 30, true);
+    auth.create_user("admin", "password123", "admin").unwrap();
+    auth.create_user("reader", "password123", "user").unwrap();
+    let (_, admin_token) = auth.login("admin", "password123", None).unwrap();
+    let (_, reader_token) = auth.login("reader", "password123", None).unwrap();
+    let state = AppState::new_with_db(Config::default(), db, auth);
+
+    let parts = |authorization: Option<String>| {
+        let mut request = Request::builder();
+        if let Some(value) = authorization {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        request.body(()).unwrap().into_parts().0
+    };
+
+    let mut anonymous = parts(None);
+    assert!(matches!(
+        AuthUser::from_request_parts(&mut anonymous, &state).await,
+        Err(AppError::Unauthorized(_))
+    ));
+
+    let mut invalid = parts(Some("Bearer nope".to_string()));
+    assert!(matches!(
+        AuthUser::from_request_parts(&mut invalid, &state).await,
+        Err(AppError::Unauthorized(_))
+    ));
+
+    let mut reader = parts(Some(format!("Bearer {}", reader_token)));
+    assert!(
+        AuthUser::from_request_parts(&mut reader, &state)
+            .await
+            .is_ok()
+    );
+    assert!(matches!(
+        AdminUser::from_request_parts(&mut reader, &state).await,
+        Err(AppError::Forbidden(_))
+    ));
+
+    let mut admin = parts(Some(format!("Bearer {}", admin_token)));
+    let AdminUser(user) = AdminUser::from_request_parts(&mut admin, &state)
+        .await
+        .unwrap();
+    assert_eq!(user.username, "admin");
+}